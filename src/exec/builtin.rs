@@ -0,0 +1,174 @@
+use std::fmt::Display;
+
+use super::{EnvFrame, NodeValue, StateNode};
+
+/// A native function that can be called from the running code, see
+/// [`register_fn`](super::VirtualEnv::register_fn).
+pub type BuiltinFn = fn(&[NodeValue]) -> Result<NodeValue, String>;
+
+/// How many arguments a function takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+    /// From the first to the second count, inclusive
+    Between(usize, usize),
+}
+
+impl Arity {
+    /// Check if the function can be called with `count` arguments.
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(exact) => count == exact,
+            Arity::AtLeast(min) => count >= min,
+            Arity::Between(min, max) => (min..=max).contains(&count),
+        }
+    }
+}
+
+/// Display the arity as the number of arguments expected, like `at least 1 argument`.
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        match *self {
+            Arity::Exact(exact) => write!(f, "{exact} argument{}", plural(exact)),
+            Arity::AtLeast(min) => write!(f, "at least {min} argument{}", plural(min)),
+            Arity::Between(min, max) => write!(f, "{min} to {max} arguments"),
+        }
+    }
+}
+
+/// Typed access to the arguments of a [`BuiltinFn`], the errors name the argument by
+/// its 1-based position so they can be returned as is.
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::exec::{Args, NodeValue};
+/// let args = [NodeValue::Integer(4), NodeValue::String("x".into())];
+/// assert_eq!(args.f64(0), Ok(4.0));
+/// assert_eq!(args.f64(1), Err("argument 2 must be a Number, got `x`".into()));
+/// ```
+pub trait Args {
+    /// Get the argument at the 0-based `index`.
+    fn value(&self, index: usize) -> Result<&NodeValue, String>;
+    /// Get a number argument as a float.
+    fn f64(&self, index: usize) -> Result<f64, String>;
+    /// Get an integer argument.
+    fn int(&self, index: usize) -> Result<i128, String>;
+    /// Get a list argument.
+    fn list(&self, index: usize) -> Result<&[NodeValue], String>;
+}
+
+impl Args for [NodeValue] {
+    fn value(&self, index: usize) -> Result<&NodeValue, String> {
+        self.get(index).ok_or_else(|| format!("argument {} is missing", index + 1))
+    }
+
+    fn f64(&self, index: usize) -> Result<f64, String> {
+        let value = self.value(index)?;
+        value.as_float().ok_or_else(|| type_error(index, "a Number", value))
+    }
+
+    fn int(&self, index: usize) -> Result<i128, String> {
+        let value = self.value(index)?;
+        value.as_int().ok_or_else(|| type_error(index, "an Integer", value))
+    }
+
+    fn list(&self, index: usize) -> Result<&[NodeValue], String> {
+        match self.value(index)? {
+            NodeValue::List(list) => Ok(list),
+            value => Err(type_error(index, "a List", value)),
+        }
+    }
+}
+
+/// The error for an argument of the wrong type.
+fn type_error(index: usize, expected: &str, value: &NodeValue) -> String {
+    let value = value.to_string().unwrap_or_else(|_| format!("{value:?}"));
+    format!("argument {} must be {expected}, got `{value}`", index + 1)
+}
+
+/// A `CALL` lambda for calling functions by name, the node's first branch is the name and
+/// its third branch holds the arguments - like `SubExpr(&[Expr("IDENT"), Token("op", "("),
+/// SepBy(..), Token("op", ")")])`. Functions stored in variables are called first, then
+/// the [registered](super::VirtualEnv::register_fn) builtins.
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::exec::{Arity, Executor};
+/// use interpreter::lang::math;
+/// // the math env already defines `CALL` as `builtin::call`
+/// let mut env = math::env();
+/// env.register_fn("double", Arity::Exact(1), |args| Ok(args[0].clone() + args[0].clone()));
+/// let mut executor = Executor::new(math::lexer(), math::parser(), env);
+/// assert_eq!(executor.exec_str("double(21)"), Ok("42".into()));
+/// assert_eq!(executor.exec_str("double(1, 2)"), Err("double expects 1 argument, got 2".into()));
+/// ```
+pub fn call(mut frame: EnvFrame) -> StateNode {
+    let node = frame.node();
+    let name = node.nodes.first().and_then(|name| name.leaf.as_ref()).map(|name| name.value.clone());
+    let (Some(name), Some(args)) = (name, node.nodes.get(2)) else {
+        return StateNode::RuntimeErr(format!("Could not call `{node}`").into());
+    };
+    let function = match frame.get_ident(&name) {
+        StateNode::Value(NodeValue::Function(function)) => {
+            let arity = Arity::Exact(function.params.len());
+            if !arity.accepts(args.nodes.len()) {
+                return StateNode::RuntimeErr(format!("{name} expects {arity}, got {}", args.nodes.len()).into());
+            }
+            Some(function)
+        },
+        StateNode::Value(_) => return StateNode::RuntimeErr(format!("`{name}` is not a function").into()),
+        _ => None,
+    };
+    let mut values = vec![];
+    for arg in &args.nodes {
+        match frame.eval_tree(arg) {
+            err @ StateNode::RuntimeErr(_) => return err,
+            value => values.push(value.as_node_value()),
+        }
+    }
+    match function {
+        Some(function) => frame.call_last(function, values),
+        None => frame.call_builtin(&name, &values),
+    }
+}
+
+/// The `PRINT` lambda, it displays each branch of the node and writes them joined by
+/// spaces as one line to the env's [output](super::VirtualEnv::set_output), giving no value.
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use interpreter::exec::Executor;
+/// // the math and calc envs already define `PRINT` as `builtin::print`
+/// let lines = Arc::new(Mutex::new(vec![]));
+/// let sink = lines.clone();
+/// let mut executor = Executor::math();
+/// executor.env_mut().set_output(Box::new(move |line| sink.lock().unwrap().push(line.to_owned())));
+/// assert_eq!(executor.exec_str("print(1 + 1, 2.5)"), Ok("None".into()));
+/// assert_eq!(*lines.lock().unwrap(), ["2 2.5"]);
+/// ```
+pub fn print(mut frame: EnvFrame) -> StateNode {
+    let mut values = vec![];
+    for value in frame.eval_nodes() {
+        match value {
+            err @ StateNode::RuntimeErr(_) => return err,
+            value => match frame.display(&value.as_node_value()) {
+                Ok(value) => values.push(value),
+                Err(err) => return StateNode::RuntimeErr(err.into()),
+            },
+        }
+    }
+    frame.write_line(&values.join(" "));
+    StateNode::Value(NodeValue::None)
+}
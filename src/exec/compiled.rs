@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::parser::syntax::AbstractSyntaxTree;
+
+/// A parsed expression that can be run any number of times with
+/// [`exec_compiled`](super::Executor::exec_compiled) without lexing or parsing it again.
+///
+/// The tree is shared, so cloning is cheap and the clones can be sent to other threads
+/// to run in their own executors.
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::exec::Executor;
+/// let executor = Executor::math();
+/// let compiled = executor.compile("x * 2")?;
+/// assert_eq!(compiled.src(), "x * 2");
+/// Ok::<(), interpreter::parser::ParseError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    src: Arc<str>,
+    ast: Arc<AbstractSyntaxTree>,
+}
+
+impl CompiledExpr {
+    pub fn new(src: &str, ast: AbstractSyntaxTree) -> CompiledExpr {
+        CompiledExpr { src: src.into(), ast: Arc::new(ast) }
+    }
+
+    /// Get the source the expression was parsed from.
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    /// Get the parsed tree.
+    pub fn ast(&self) -> &AbstractSyntaxTree {
+        &self.ast
+    }
+}
+
+// -=-=- Parse Cache -=-=- //
+
+/// The most recently used expressions keyed by their source, the least recently used one
+/// is dropped once it is full.
+pub(crate) struct ParseCache {
+    capacity: usize,
+    // the most recently used is at the back
+    entries: VecDeque<CompiledExpr>,
+}
+
+impl ParseCache {
+    pub(crate) fn new(capacity: usize) -> ParseCache {
+        ParseCache { capacity, entries: VecDeque::with_capacity(capacity) }
+    }
+
+    /// Get the expression parsed from `src` and mark it as the most recently used.
+    pub(crate) fn get(&mut self, src: &str) -> Option<CompiledExpr> {
+        let index = self.entries.iter().position(|entry| entry.src() == src)?;
+        let entry = self.entries.remove(index)?;
+        self.entries.push_back(entry.clone());
+        Some(entry)
+    }
+
+    pub(crate) fn insert(&mut self, compiled: CompiledExpr) {
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(compiled);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::syntax::TreeNode;
+
+    fn compiled(src: &str) -> CompiledExpr {
+        CompiledExpr::new(src, AbstractSyntaxTree { root: TreeNode::from_nodes(vec![]) })
+    }
+
+    /// the least recently used expression is dropped first.
+    #[test]
+    fn parse_cache_lru() {
+        let mut cache = ParseCache::new(2);
+        cache.insert(compiled("a"));
+        cache.insert(compiled("b"));
+        assert!(cache.get("a").is_some());
+        cache.insert(compiled("c"));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+    }
+
+    /// compiled expressions can be sent to other threads.
+    #[test]
+    fn compiled_is_send() {
+        fn assert_send<T: Send + Clone>() {}
+        assert_send::<CompiledExpr>();
+    }
+}
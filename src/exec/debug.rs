@@ -0,0 +1,179 @@
+//! # Debugger Hooks
+//!
+//! Contains the [`DebugEvent`] a [`VirtualEnv`](super::VirtualEnv) gives its
+//! [debugger](super::VirtualEnv::set_debugger) once each lambda has run its branches, and the
+//! [`DebugAction`] the debugger answers with.
+//!
+
+use std::collections::HashMap;
+
+use crate::lexer::ReadPointer;
+use crate::parser::syntax::TreeNode;
+use super::NodeValue;
+
+/// Called once each lambda has run its branches, see [`VirtualEnv::set_debugger`](super::VirtualEnv::set_debugger).
+pub type Debugger = Box<dyn FnMut(DebugEvent<'_>) -> DebugAction + Send + Sync>;
+
+/// What the debugger wants to happen after an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Go on, the debugger is called again for the next lambda
+    Continue,
+    /// Go on without calling the debugger until the lambda this one is a branch of finishes
+    StepOver,
+    /// Don't give the value of the lambda, the evaluation fails instead and the variables
+    /// are put back to how they were before it
+    Abort,
+}
+
+/// A lambda that has run its branches, with the variables it can see.
+pub struct DebugEvent<'a> {
+    /// The name of the lambda, like `ADD`
+    pub lambda: &'a str,
+    /// The node the lambda runs on
+    pub node: &'a TreeNode,
+    /// The global variables
+    pub variables: &'a HashMap<String, NodeValue>,
+    /// The variables of the blocks being run, innermost last
+    pub scopes: &'a [HashMap<String, NodeValue>],
+    /// The output of the environment
+    pub(super) output: &'a mut (dyn FnMut(&str) + Send + Sync),
+}
+
+impl DebugEvent<'_> {
+    /// Get where the node was read from, if it was read.
+    pub fn span(&self) -> Option<ReadPointer> {
+        self.node.span()
+    }
+
+    /// Get the node over multiple indented lines, like `{:#}`.
+    pub fn pretty(&self) -> String {
+        format!("{:#}", self.node)
+    }
+
+    /// Get the value a variable has where the lambda runs, from the nearest scope it is
+    /// declared in.
+    pub fn binding(&self, name: &str) -> Option<&NodeValue> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.variables.get(name))
+    }
+
+    /// Write a line to the [output](super::VirtualEnv::set_output) of the environment,
+    /// the way `print` writes its lines.
+    pub fn write_line(&mut self, line: &str) {
+        (self.output)(line);
+    }
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::exec::Executor;
+
+    /// install a debugger that records each lambda and answers with `action`.
+    fn recording(executor: &mut Executor, action: fn(&str) -> DebugAction) -> Arc<Mutex<Vec<String>>> {
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = events.clone();
+        executor.env_mut().set_debugger(Box::new(move |event| {
+            sink.lock().unwrap().push(event.lambda.to_owned());
+            action(event.lambda)
+        }));
+        events
+    }
+
+    /// each lambda is given to the debugger once its branches have run, so the lambdas
+    /// of the branches come first.
+    #[test]
+    fn debugger_events() -> Result<(), String> {
+        let mut executor = Executor::math();
+        let events = recording(&mut executor, |_| DebugAction::Continue);
+        assert_eq!(executor.exec_str("1+2*3")?, "7");
+        assert_eq!(*events.lock().unwrap(), ["INTEGER", "INTEGER", "INTEGER", "MULT", "ADD"]);
+        Ok(())
+    }
+
+    /// stepping over a lambda skips the events until the lambda it is a branch of finishes.
+    #[test]
+    fn debugger_step_over() -> Result<(), String> {
+        let mut executor = Executor::math();
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = events.clone();
+        executor.env_mut().set_debugger(Box::new(move |event| {
+            let mut events = sink.lock().unwrap();
+            events.push(event.lambda.to_owned());
+            // step over the first lambda of each evaluation
+            match events.len() {
+                1 => DebugAction::StepOver,
+                _ => DebugAction::Continue,
+            }
+        }));
+        assert_eq!(executor.exec_str("1+2*3")?, "7");
+        // the `1` was stepped over, so the rest of the `ADD` is skipped
+        assert_eq!(*events.lock().unwrap(), ["INTEGER", "ADD"]);
+        events.lock().unwrap().clear();
+        executor.exec_str("2*3")?;
+        assert_eq!(*events.lock().unwrap(), ["INTEGER", "MULT"]);
+        Ok(())
+    }
+
+    /// aborting fails the evaluation at the node the lambda was about to run on.
+    #[test]
+    fn debugger_abort() {
+        let mut executor = Executor::math();
+        let events = recording(&mut executor, |lambda| match lambda {
+            "MULT" => DebugAction::Abort,
+            _ => DebugAction::Continue,
+        });
+        let err = executor.exec_value("1 + 2 * 3").expect_err("the debugger aborts at `*`");
+        assert_eq!(err.message, "Aborted by the debugger");
+        assert_eq!(err.position.map(|pos| pos.user_position()), Some((1, 5)));
+        assert_eq!(events.lock().unwrap().last().map(String::as_str), Some("MULT"));
+        // without a debugger it runs again
+        executor.env_mut().clear_debugger();
+        assert_eq!(executor.exec_str("1 + 2 * 3"), Ok("7".into()));
+    }
+
+    /// the variables an aborted evaluation set are put back, even the ones set before the
+    /// lambda it aborted at.
+    #[test]
+    fn debugger_abort_rolls_back() -> Result<(), String> {
+        let mut executor = Executor::math();
+        executor.exec_str("y := 1")?;
+        recording(&mut executor, |lambda| match lambda {
+            "DECLARE" => DebugAction::Abort,
+            _ => DebugAction::Continue,
+        });
+        assert!(executor.exec_str("x := 5").is_err());
+        assert!(executor.get_var("x").is_none());
+        assert!(executor.exec_str("{ y = 2; z := 3 }").is_err());
+        assert_eq!(executor.get_var("y"), Some(&NodeValue::Integer(1)));
+        assert!(executor.get_var("z").is_none());
+        Ok(())
+    }
+
+    /// the event sees the variables, the node and where it was read from.
+    #[test]
+    fn debugger_state() -> Result<(), String> {
+        let mut executor = Executor::math();
+        executor.exec_str("x := 4")?;
+        let seen = Arc::new(Mutex::new(vec![]));
+        let sink = seen.clone();
+        executor.env_mut().set_debugger(Box::new(move |event| {
+            if event.lambda == "MULT" {
+                let span = event.span().map(|span| span.user_position());
+                sink.lock().unwrap().push((event.binding("x").cloned(), event.binding("y").cloned(), span, event.pretty()));
+            }
+            DebugAction::Continue
+        }));
+        executor.exec_str("{ y := 2; x * y }")?;
+        let seen = seen.lock().unwrap();
+        let (x, y, span, pretty) = seen.first().ok_or("MULT was not run")?;
+        assert_eq!((x, y, *span), (&Some(NodeValue::Integer(4)), &Some(NodeValue::Integer(2)), Some((1, 11))));
+        assert!(pretty.starts_with("{ MULT $1 $3 }\n"), "{pretty}");
+        Ok(())
+    }
+}
@@ -0,0 +1,412 @@
+//! # Decimal Numbers
+//!
+//! Contains the [`Decimal`] held by [`NodeValue::Decimal`](super::NodeValue::Decimal), an
+//! exact base 10 number for when binary floats aren't good enough - like money, where
+//! `0.1 + 0.2` has to be `0.3`. Only built with the `decimal` feature.
+//!
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// The most digits a [`Decimal`] keeps after the point, results with more are rounded.
+pub const MAX_SCALE: u32 = 28;
+
+/// An exact decimal number, a whole `mantissa` divided by `10` to the power of `scale`.
+///
+/// ---
+///
+/// The number is always kept without trailing zeros after the point, so equal numbers
+/// have the same parts and display the same. Results that would need more than
+/// [`MAX_SCALE`] digits after the point are rounded half to even.
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::exec::decimal::Decimal;
+/// let sum = "0.1".parse::<Decimal>()?.checked_add("0.2".parse()?)?;
+/// assert_eq!(sum, "0.3".parse()?);
+/// assert_eq!(sum.to_string(), "0.3");
+/// Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Zero, with nothing after the point.
+    pub const ZERO: Decimal = Decimal { mantissa: 0, scale: 0 };
+
+    /// Make the number `mantissa / 10^scale`, a `scale` above [`MAX_SCALE`] is an error.
+    ///
+    /// ---
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use interpreter::exec::decimal::Decimal;
+    /// assert_eq!(Decimal::new(1250, 3)?.to_string(), "1.25");
+    /// assert!(Decimal::new(1, 29).is_err());
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn new(mantissa: i128, scale: u32) -> Result<Decimal, String> {
+        if scale > MAX_SCALE {
+            return Err(format!("A Decimal can't have more than {MAX_SCALE} digits after the point"));
+        }
+        Ok(Decimal { mantissa, scale }.normalize())
+    }
+
+    /// Get the whole number the decimal is made from, see [`new`](Decimal::new).
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// Get how many digits are after the point.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Check if the number is zero.
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    /// Remove the trailing zeros after the point.
+    fn normalize(mut self) -> Decimal {
+        while self.scale > 0 && self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.scale -= 1;
+        }
+        if self.mantissa == 0 {
+            self.scale = 0;
+        }
+        self
+    }
+
+    /// Get the mantissas of both numbers with the same scale, along with the scale.
+    fn align(self, other: Decimal, op: &str) -> Result<(i128, i128, u32), String> {
+        let scale = self.scale.max(other.scale);
+        let rescale = |value: Decimal| pow10(scale - value.scale)
+            .and_then(|factor| value.mantissa.checked_mul(factor))
+            .ok_or_else(|| overflow(op));
+        Ok((rescale(self)?, rescale(other)?, scale))
+    }
+
+    /// Add the numbers, a result too big for a Decimal is an error.
+    pub fn checked_add(self, other: Decimal) -> Result<Decimal, String> {
+        let (lhs, rhs, scale) = self.align(other, "+")?;
+        let mantissa = lhs.checked_add(rhs).ok_or_else(|| overflow("+"))?;
+        Ok(Decimal { mantissa, scale }.normalize())
+    }
+
+    /// Subtract the `other` number, a result too big for a Decimal is an error.
+    pub fn checked_sub(self, other: Decimal) -> Result<Decimal, String> {
+        let (lhs, rhs, scale) = self.align(other, "-")?;
+        let mantissa = lhs.checked_sub(rhs).ok_or_else(|| overflow("-"))?;
+        Ok(Decimal { mantissa, scale }.normalize())
+    }
+
+    /// Multiply the numbers, digits past [`MAX_SCALE`] are rounded.
+    pub fn checked_mul(self, other: Decimal) -> Result<Decimal, String> {
+        let mantissa = self.mantissa.checked_mul(other.mantissa).ok_or_else(|| overflow("*"))?;
+        let scale = self.scale + other.scale;
+        match scale.checked_sub(MAX_SCALE) {
+            Some(extra) if extra > 0 => Ok(Decimal { mantissa: round_div(mantissa, 10i128.pow(extra)), scale: MAX_SCALE }.normalize()),
+            _ => Ok(Decimal { mantissa, scale }.normalize()),
+        }
+    }
+
+    /// Divide by the `other` number, a result that doesn't end within [`MAX_SCALE`]
+    /// digits is rounded half to even.
+    ///
+    /// ---
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use interpreter::exec::decimal::Decimal;
+    /// let third = Decimal::from(2).checked_div(Decimal::from(3))?;
+    /// assert_eq!(third.to_string(), "0.6666666666666666666666666667");
+    /// assert!(Decimal::from(1).checked_div(Decimal::ZERO).is_err());
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn checked_div(self, other: Decimal) -> Result<Decimal, String> {
+        if other.is_zero() {
+            return Err("Cannot divide by zero".into());
+        }
+        let negative = (self.mantissa < 0) != (other.mantissa < 0);
+        let (numerator, denominator) = (self.mantissa.unsigned_abs(), other.mantissa.unsigned_abs());
+        // the digits of the quotient are worked out one at a time, like long division
+        let mut quotient = numerator / denominator;
+        let mut remainder = numerator % denominator;
+        // the scale of the quotient is the digits worked out past the point plus the
+        // difference in the scales, which can start out negative
+        let mut digits: i64 = 0;
+        let scale_of = |digits: i64| digits + self.scale as i64 - other.scale as i64;
+        while remainder != 0 && scale_of(digits) < MAX_SCALE as i64 {
+            let next = remainder.checked_mul(10).zip(quotient.checked_mul(10));
+            let Some((shifted, base)) = next else { break };
+            let Some(with_digit) = base.checked_add(shifted / denominator) else { break };
+            quotient = with_digit;
+            remainder = shifted % denominator;
+            digits += 1;
+        }
+        // round half to even with the next digit and what is left after it
+        if remainder != 0 {
+            let next = remainder.checked_mul(10).map(|shifted| (shifted / denominator, shifted % denominator));
+            let round_up = match next {
+                Some((digit, rest)) => digit > 5 || (digit == 5 && (rest != 0 || quotient % 2 == 1)),
+                // too big to shift, compare with half of the denominator instead
+                None => remainder > denominator / 2 || (remainder == denominator - remainder && quotient % 2 == 1),
+            };
+            if round_up {
+                quotient = quotient.checked_add(1).ok_or_else(|| overflow("/"))?;
+            }
+        }
+        let mut mantissa = i128::try_from(quotient).map_err(|_| overflow("/"))?;
+        if negative {
+            mantissa = -mantissa;
+        }
+        match u32::try_from(scale_of(digits)) {
+            Ok(scale) => Ok(Decimal { mantissa, scale }.normalize()),
+            // fewer digits after the point than none, so the mantissa grows instead
+            Err(_) => {
+                let factor = u32::try_from(-scale_of(digits)).ok().and_then(pow10).ok_or_else(|| overflow("/"))?;
+                let mantissa = mantissa.checked_mul(factor).ok_or_else(|| overflow("/"))?;
+                Ok(Decimal { mantissa, scale: 0 })
+            }
+        }
+    }
+
+    /// Divide and round down towards negative infinity to a whole number.
+    pub fn floor_div(self, other: Decimal) -> Result<Decimal, String> {
+        if other.is_zero() {
+            return Err("Cannot divide by zero".into());
+        }
+        let (lhs, rhs, _) = self.align(other, "//")?;
+        let div = lhs.checked_div(rhs).ok_or_else(|| overflow("//"))?;
+        let mantissa = match lhs % rhs != 0 && (lhs < 0) != (rhs < 0) {
+            true => div - 1,
+            false => div,
+        };
+        Ok(Decimal { mantissa, scale: 0 })
+    }
+
+    /// The remainder of [`floor_div`](Decimal::floor_div), it has the same sign as the divisor.
+    pub fn modulo(self, other: Decimal) -> Result<Decimal, String> {
+        if other.is_zero() {
+            return Err("Cannot divide by zero".into());
+        }
+        let (lhs, rhs, scale) = self.align(other, "%")?;
+        let rem = lhs.checked_rem(rhs).ok_or_else(|| overflow("%"))?;
+        let mantissa = match rem != 0 && (rem < 0) != (rhs < 0) {
+            true => rem + rhs,
+            false => rem,
+        };
+        Ok(Decimal { mantissa, scale }.normalize())
+    }
+
+    /// Negate the number, the smallest mantissa has no positive and is an error.
+    pub fn checked_neg(self) -> Result<Decimal, String> {
+        let mantissa = self.mantissa.checked_neg().ok_or_else(|| overflow("-"))?;
+        Ok(Decimal { mantissa, scale: self.scale })
+    }
+
+    /// Get the whole part of the number, dropping the digits after the point.
+    pub fn trunc(&self) -> i128 {
+        self.mantissa / 10i128.pow(self.scale)
+    }
+
+    /// Get the closest `f64` to the number.
+    pub fn to_f64(&self) -> f64 {
+        // parsing the digits rounds correctly, dividing the parts would round twice
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Get the number closest to a float from the digits it is displayed with, so `0.1`
+    /// gives exactly `0.1` - infinity and NaN have no Decimal.
+    pub fn from_f64(float: f64) -> Result<Decimal, String> {
+        if !float.is_finite() {
+            return Err(format!("Cannot convert {float} to a Decimal"));
+        }
+        format!("{float}").parse()
+    }
+}
+
+/// Get `10^exp` if it fits in an `i128`.
+fn pow10(exp: u32) -> Option<i128> {
+    10i128.checked_pow(exp)
+}
+
+/// Divide and round half to even.
+fn round_div(value: i128, divisor: i128) -> i128 {
+    let (div, rem) = (value / divisor, value % divisor);
+    let twice = rem.unsigned_abs() * 2;
+    let away = twice > divisor.unsigned_abs() || (twice == divisor.unsigned_abs() && div % 2 != 0);
+    match (away, value < 0) {
+        (true, true) => div - 1,
+        (true, false) => div + 1,
+        (false, _) => div,
+    }
+}
+
+/// The error for a result that doesn't fit in a Decimal.
+fn overflow(op: &str) -> String {
+    format!("Decimal overflow in `{op}`")
+}
+
+impl From<i128> for Decimal {
+    fn from(int: i128) -> Self {
+        Decimal { mantissa: int, scale: 0 }
+    }
+}
+
+impl From<i32> for Decimal {
+    fn from(int: i32) -> Self {
+        Decimal { mantissa: int as i128, scale: 0 }
+    }
+}
+
+/// Parses an optional `-` or `+`, digits and optionally a `.` followed by more digits.
+impl FromStr for Decimal {
+    type Err = String;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Failed to parse '{src}' as Decimal");
+        let (negative, digits) = match src.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, src.strip_prefix('+').unwrap_or(src)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+            return Err(invalid());
+        }
+        // trailing zeros don't count towards the scale
+        let fraction = fraction.trim_end_matches('0');
+        let scale = u32::try_from(fraction.len()).map_err(|_| invalid())?;
+        let mantissa = format!("{whole}{fraction}").trim_start_matches('0').parse::<i128>()
+            .or_else(|err| match err.kind() {
+                std::num::IntErrorKind::Empty => Ok(0),
+                _ => Err(format!("'{src}' is too big for a Decimal")),
+            })?;
+        Decimal::new(if negative { -mantissa } else { mantissa }, scale)
+    }
+}
+
+/// Displays every digit of the number without trailing zeros, like `-12.05`.
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{sign}{digits}");
+        }
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{sign}{whole}.{fraction}")
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares the whole parts and then the digits after the point, so numbers too big to
+/// give the same scale are still ordered.
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let parts = |value: &Decimal| {
+            let factor = 10i128.pow(value.scale);
+            let fraction = (value.mantissa % factor) * 10i128.pow(MAX_SCALE - value.scale);
+            (value.mantissa / factor, fraction)
+        };
+        parts(self).cmp(&parts(other))
+    }
+}
+
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(src: &str) -> Decimal {
+        src.parse().expect("the test decimal should parse")
+    }
+
+    /// numbers are parsed and displayed with every digit but no trailing zeros.
+    #[test]
+    fn parse_and_display() {
+        for (src, expected) in [("0.1", "0.1"), ("1.50", "1.5"), ("-0.05", "-0.05"), ("007", "7"), ("+2.", "2"), (".25", "0.25"), ("-0.0", "0")] {
+            assert_eq!(dec(src).to_string(), expected, "{src}");
+        }
+        assert_eq!(dec("1.2300").scale(), 2);
+        for src in ["", ".", "-", "1.2.3", "1e5", "abc", " 1"] {
+            assert!(src.parse::<Decimal>().is_err(), "{src:?} should not parse");
+        }
+        assert!("0.00000000000000000000000000001".parse::<Decimal>().is_err());
+        assert_eq!("1".repeat(40).parse::<Decimal>(), Err(format!("'{}' is too big for a Decimal", "1".repeat(40))));
+    }
+
+    /// sums and products are exact.
+    #[test]
+    fn arithmetic() -> Result<(), String> {
+        assert_eq!(dec("0.1").checked_add(dec("0.2"))?, dec("0.3"));
+        assert_eq!(dec("1.05").checked_sub(dec("2"))?.to_string(), "-0.95");
+        assert_eq!(dec("1.5").checked_mul(dec("-0.2"))?.to_string(), "-0.3");
+        assert_eq!(dec("7.5").floor_div(dec("2"))?.to_string(), "3");
+        assert_eq!(dec("-7.5").floor_div(dec("2"))?.to_string(), "-4");
+        assert_eq!(dec("7.5").modulo(dec("-2"))?.to_string(), "-0.5");
+        assert_eq!(dec("0.1").checked_neg()?.to_string(), "-0.1");
+        assert_eq!(dec("-3.99").trunc(), -3);
+        assert_eq!(dec("0.1").to_f64(), 0.1);
+        assert_eq!(Decimal::from_f64(0.1)?, dec("0.1"));
+        Ok(())
+    }
+
+    /// quotients are rounded half to even after the last digit that fits.
+    #[test]
+    fn division_rounding() -> Result<(), String> {
+        assert_eq!(dec("1").checked_div(dec("4"))?.to_string(), "0.25");
+        assert_eq!(dec("1").checked_div(dec("3"))?.to_string(), "0.3333333333333333333333333333");
+        assert_eq!(dec("-2").checked_div(dec("3"))?.to_string(), "-0.6666666666666666666666666667");
+        assert_eq!(dec("10").checked_div(dec("0.5"))?.to_string(), "20");
+        assert_eq!(dec("1.2").checked_div(dec("0.004"))?.to_string(), "300");
+        // a tie rounds to the even digit
+        let tiny = Decimal::new(5, MAX_SCALE)?;
+        assert_eq!(tiny.checked_div(dec("2"))?, Decimal::new(2, MAX_SCALE)?);
+        assert_eq!(Decimal::new(15, MAX_SCALE)?.checked_div(dec("2"))?, Decimal::new(8, MAX_SCALE)?);
+        assert_eq!(tiny.checked_mul(dec("0.1"))?, Decimal::ZERO);
+        assert_eq!(dec("1").checked_div(Decimal::ZERO), Err("Cannot divide by zero".into()));
+        Ok(())
+    }
+
+    /// results too big for a Decimal are errors instead of wrapping.
+    #[test]
+    fn overflow_errors() {
+        let max = Decimal::from(i128::MAX);
+        assert_eq!(max.checked_add(dec("1")), Err("Decimal overflow in `+`".into()));
+        assert_eq!(Decimal::from(i128::MIN).checked_sub(dec("1")), Err("Decimal overflow in `-`".into()));
+        assert_eq!(max.checked_mul(dec("2")), Err("Decimal overflow in `*`".into()));
+        assert_eq!(max.checked_div(dec("0.5")), Err("Decimal overflow in `/`".into()));
+        assert_eq!(Decimal::from(i128::MIN).checked_neg(), Err("Decimal overflow in `-`".into()));
+        // the scales can't be lined up
+        assert_eq!(max.checked_add(dec("0.1")), Err("Decimal overflow in `+`".into()));
+    }
+
+    /// numbers are ordered by value whatever their scale.
+    #[test]
+    fn ordering() {
+        assert!(dec("0.3") > dec("0.25"));
+        assert!(dec("-1.5") < dec("-1.25"));
+        assert!(Decimal::from(i128::MAX) > dec("0.5"));
+        assert_eq!(dec("2.50").cmp(&dec("2.5")), Ordering::Equal);
+    }
+}
@@ -0,0 +1,208 @@
+//! # Execution Errors
+//! 
+//! Contains the [`ExecError`] returned when running source code fails at any stage
+//! and the [`RuntimeError`] held by a failed [`StateNode`](super::StateNode).
+//! 
+
+use std::path::{Path, PathBuf};
+
+use crate::lexer::ReadPointer;
+use crate::parser::ParseError;
+
+/// The stage of execution an [`ExecError`] came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecErrorKind {
+    Io,
+    Parse,
+    Runtime,
+}
+
+impl std::fmt::Display for ExecErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecErrorKind::Io => write!(f, "IO Error"),
+            ExecErrorKind::Parse => write!(f, "Parse Error"),
+            ExecErrorKind::Runtime => write!(f, "Runtime Error"),
+        }
+    }
+}
+
+/// An error from executing source code with where it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecError {
+    /// The stage of execution the error came from
+    pub kind: ExecErrorKind,
+    /// Message describing what went wrong
+    pub message: String,
+    /// Where in the source the error happened
+    pub position: Option<ReadPointer>,
+    /// The file being executed
+    pub file: Option<PathBuf>,
+}
+
+/// Displays the error as `file:line:col: Kind Error: message` with 1-based line and column.
+impl std::fmt::Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        if let Some(position) = &self.position {
+            let (line, col) = position.user_position();
+            write!(f, "{line}:{col}:")?;
+        }
+        if self.file.is_some() || self.position.is_some() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+impl From<ParseError> for ExecError {
+    fn from(err: ParseError) -> Self {
+        ExecError { kind: ExecErrorKind::Parse, message: err.message, position: Some(err.position), file: None }
+    }
+}
+
+/// Keeps exec errors usable where a `String` error is expected.
+impl From<ExecError> for String {
+    fn from(err: ExecError) -> Self {
+        err.to_string()
+    }
+}
+
+impl ExecError {
+    /// Make an error for when the source cannot be read.
+    pub fn io(message: &str) -> ExecError {
+        ExecError { kind: ExecErrorKind::Io, message: message.to_owned(), position: None, file: None }
+    }
+
+    /// Make an error for when running the code fails.
+    pub fn runtime(message: &str, position: Option<ReadPointer>) -> ExecError {
+        ExecError { kind: ExecErrorKind::Runtime, message: message.to_owned(), position, file: None }
+    }
+
+    /// Set the file the error happened in.
+    pub fn in_file(mut self, path: &Path) -> ExecError {
+        self.file = Some(path.to_owned());
+        self
+    }
+}
+
+// -=-=- Runtime Error -=-=- //
+
+/// An error from evaluating a syntax tree in a [`VirtualEnv`](super::VirtualEnv).
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// The code could not be evaluated
+    Error(String),
+    /// The host stopped the evaluation with the cancel token
+    Interrupted,
+    /// The evaluation went over the step limit or timeout
+    LimitExceeded(String),
+    /// The code could not be evaluated because of the source at the position
+    At(String, ReadPointer),
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::Error(message) => write!(f, "{message}"),
+            RuntimeError::Interrupted => write!(f, "Execution interrupted"),
+            RuntimeError::LimitExceeded(message) => write!(f, "Limit exceeded: {message}"),
+            RuntimeError::At(message, _) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl RuntimeError {
+    /// Get where in the source the error happened, if it is known.
+    pub fn position(&self) -> Option<&ReadPointer> {
+        match self {
+            RuntimeError::At(_, position) => Some(position),
+            _ => None,
+        }
+    }
+
+    /// Render the error for people to read like a [report](crate::report): the message,
+    /// the line of `src` it happened on and a caret under the columns of the position.
+    /// An error without a position is only the message.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::RuntimeError;
+    /// use interpreter::lexer::ReadPointer;
+    /// let err = RuntimeError::At("undefined variable 'y'".into(), ReadPointer::from_pos((0,4, 0,5), (4, 5)));
+    /// assert_eq!(err.render_with_source("x + y"), [
+    ///     "Runtime Error: undefined variable 'y'",
+    ///     " --> 1:5",
+    ///     "  |",
+    ///     "1 | x + y",
+    ///     "  |     ^",
+    ///     "",
+    /// ].join("\n"));
+    /// ```
+    pub fn render_with_source(&self, src: &str) -> String {
+        let err = ExecError::runtime(&self.to_string(), self.position().cloned());
+        crate::report::format_error_with(&err, src, false)
+    }
+}
+
+impl From<String> for RuntimeError {
+    fn from(message: String) -> Self {
+        RuntimeError::Error(message)
+    }
+}
+
+impl From<&str> for RuntimeError {
+    fn from(message: &str) -> Self {
+        RuntimeError::Error(message.to_owned())
+    }
+}
+
+/// Keeps runtime errors usable where a `String` error is expected.
+impl From<RuntimeError> for String {
+    fn from(err: RuntimeError) -> Self {
+        err.to_string()
+    }
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::StateNode;
+    use crate::lang::math;
+    use crate::lexer::LineReader;
+
+    /// the caret is under the token the error happened at, on its own line.
+    #[test]
+    fn render_runtime_error() -> Result<(), String> {
+        let src = "a := 2
+1+b * a";
+        let mut env = math::env();
+        let mut reader = LineReader::new(src);
+        env.exec(math::parser().parse_tree(&math::lexer(), &mut reader)?);
+        let StateNode::RuntimeErr(err) = env.exec(math::parser().parse_tree(&math::lexer(), &mut reader)?) else {
+            return Err("`b` is not defined".into());
+        };
+        assert_eq!(err.render_with_source(src), [
+            "Runtime Error: undefined variable 'b', did you mean 'a'?",
+            " --> 2:3",
+            "  |",
+            "2 | 1+b * a",
+            "  |   ^",
+            "",
+        ].join("\n"));
+        // the caret is under the third character
+        let caret = err.render_with_source(src).lines().nth(4).map(|line| line.find('^'));
+        assert_eq!(caret, Some(Some("2 | ".len() + 2)));
+        // without a position there is only the message
+        let err = RuntimeError::from("Cannot divide by zero");
+        assert_eq!(err.render_with_source("1/0"), "Runtime Error: Cannot divide by zero\n");
+        Ok(())
+    }
+}
@@ -1,226 +1,2115 @@
 
 
-use std::collections::HashMap;
-use std::ops::{Add, Div, Mul, Sub};
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Shl, Shr, Sub};
 use std::str::FromStr;
-use std::{error, u32};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use crate::lexer::Token;
 use crate::parser::syntax::{AbstractSyntaxTree, TreeNode};
 use crate::exec::syntax::OwnedLambda;
-
-use super::syntax::Lambda;
+#[cfg(feature = "decimal")]
+use crate::exec::decimal::Decimal;
+use super::{Arity, BuiltinFn, DebugAction, DebugEvent, Debugger, EnvSnapshot, ExecStats, LambdaFn, Module, RuntimeError, Signature};
+use super::ratio::{self, Ratio, Rational};
+use super::types::TypeRule;
 
 
 pub struct VirtualEnv {
-    definitions: HashMap<String, fn(EnvFrame) -> StateNode>,
+    definitions: HashMap<String, LambdaFn>,
+    /// the lambdas of each module by their unqualified names
+    modules: HashMap<String, HashMap<String, LambdaFn>>,
+    /// the lambdas that only work out a value from their branches, see [`define_pure`](VirtualEnv::define_pure)
+    pure: HashSet<String>,
+    /// the types the lambdas give, see [`check_types`](VirtualEnv::check_types)
+    signatures: HashMap<String, TypeRule>,
+    builtins: HashMap<String, (Arity, BuiltinFn)>,
     pub variables: HashMap<String, NodeValue>, // Change the key type to String
+    /// the variables of the blocks being run, innermost last - see [`push_scope`](VirtualEnv::push_scope)
+    scopes: Vec<HashMap<String, NodeValue>>,
+    budget: Budget,
+    division: DivisionMode,
+    float_format: FloatFormat,
+    /// how many nodes are being evaluated inside each other
+    depth: usize,
+    /// how many evaluations are running on the stack inside lambdas, and where the stack
+    /// was when the outermost one started
+    nesting: usize,
+    stack_base: Option<usize>,
+    output: Box<dyn FnMut(&str) + Send + Sync>,
+    /// only collect the stats when enabled, so the lambdas aren't timed otherwise
+    stats_enabled: bool,
+    stats: ExecStats,
+    debugger: Option<Debugger>,
+    /// how many lambdas are running, so a step over knows which lambda it is inside
+    lambda_depth: usize,
+    /// the depth a lambda was stepped over at, the debugger isn't called again until
+    /// a lambda above it finishes
+    stepping_over: Option<usize>,
+    /// if the debugger aborted the evaluation, so its variable changes are put back
+    aborted: bool,
+}
+
+/// How many steps a subtree can take to be folded.
+const FOLD_STEP_LIMIT: u64 = 10_000;
+
+/// How many nodes can be evaluated inside each other by default, see
+/// [`set_depth_limit`](VirtualEnv::set_depth_limit).
+const DEFAULT_DEPTH_LIMIT: usize = 100_000;
+
+/// How much of the stack the lambdas evaluating their branches on it can use, half the
+/// 2 MiB stack of a spawned thread.
+const STACK_LIMIT: usize = 1024 * 1024;
+
+/// Get roughly where the stack is, to tell how much of it the nested evaluations use.
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::ptr::addr_of!(marker) as usize
+}
+
+// -=-=- Evaluation Work List -=-=- //
+
+/// What starting or resuming the evaluation of a node gave.
+enum Started<'n> {
+    /// The node has its value
+    Done(StateNode),
+    /// The node is waiting on the value of another node
+    Needs(&'n TreeNode),
+}
+
+/// A node waiting on the value of the node evaluated after it.
+enum Waiting<'n> {
+    /// The node evaluates one of its children with the child's lambda, the child's value is its value
+    Forward,
+    /// The node's lambda asked for the value of a branch
+    Lambda(Suspended<'n>),
+    /// The node's lambda called a function, the value of the body is its value once the
+    /// scopes are back to how many there were
+    Call(Suspended<'n>, usize),
+}
+
+/// The nodes waiting on values while a tree is worked through.
+struct WorkList<'n> {
+    waiting: Vec<Waiting<'n>>,
+    /// the last body added to the bodies, and the bodies by where their node is
+    last: &'n Bodies,
+    bodies: HashMap<usize, &'n TreeNode>,
+}
+
+impl<'n> WorkList<'n> {
+    /// Keep the body of a function until the tree is done, so the nodes in it can wait.
+    fn body(&mut self, body: Arc<TreeNode>) -> &'n TreeNode {
+        let key = Arc::as_ptr(&body) as usize;
+        if let Some(node) = self.bodies.get(&key) {
+            return node;
+        }
+        let last = self.last.next.get_or_init(|| Box::new(Bodies { body: Some(body), next: OnceCell::new() }));
+        self.last = last;
+        let node = last.body.as_deref().expect("a body was just added");
+        self.bodies.insert(key, node);
+        node
+    }
+}
+
+/// The bodies of the functions called while working through a tree, each added after the
+/// last so the ones before it stay borrowed.
+#[derive(Default)]
+struct Bodies {
+    body: Option<Arc<TreeNode>>,
+    next: OnceCell<Box<Bodies>>,
+}
+
+/// Drop the bodies one after another instead of each dropping the rest.
+impl Drop for Bodies {
+    fn drop(&mut self) {
+        let mut next = self.next.take();
+        while let Some(mut bodies) = next {
+            next = bodies.next.take();
+        }
+    }
+}
+
+/// What the lambda of a node does, see [`target`](VirtualEnv::target).
+enum Target<'n> {
+    Node(&'n TreeNode),
+    Token(&'n TreeNode),
+    Lambda(&'n str, &'n TreeNode, Cow<'n, [u32]>),
+    Err(RuntimeError),
+}
+
+/// An env lambda being run on a node.
+struct Suspended<'n> {
+    name: &'n str,
+    node: &'n TreeNode,
+    args: Cow<'n, [u32]>,
+    replay: Replay,
+    /// how long the runs of the lambda took, for the stats
+    elapsed: Duration,
+}
+
+/// The values of the branches a pure lambda asked for so far, it is run again from the
+/// start with them once the next branches it asks for have values.
+#[derive(Default)]
+struct Replay {
+    /// if the lambda is pure, so its branches can be evaluated outside of it
+    suspends: bool,
+    values: Vec<StateNode>,
+    /// how many of the values the current run has taken
+    taken: usize,
+    /// the 1-based indexes of the branches asked for that don't have a value yet
+    wants: VecDeque<usize>,
+    /// the function the lambda gave as its value, see [`EnvFrame::call_last`]
+    call: Option<(Function, Vec<NodeValue>)>,
+}
+
+impl Replay {
+    /// Get the values of the branches in order, once one of them has no value it is asked
+    /// for with the rest of them and a stand in value is given back. Nothing more is asked
+    /// for in a run after that, since what the lambda asks next may depend on the value.
+    fn take(&mut self, indexes: &[usize]) -> Vec<StateNode> {
+        let asked = !self.wants.is_empty();
+        indexes.iter().map(|index| {
+            if let (false, Some(value)) = (asked || !self.wants.is_empty(), self.values.get(self.taken)) {
+                self.taken += 1;
+                return value.clone();
+            }
+            if !asked {
+                self.wants.push_back(*index);
+            }
+            StateNode::RuntimeErr(RuntimeError::Interrupted)
+        }).collect()
+    }
+
+    /// Get the branch to evaluate next.
+    fn wanted(&self) -> Option<usize> {
+        self.wants.front().copied()
+    }
+
+    /// Give the value of the branch that was wanted next.
+    fn give(&mut self, value: StateNode) {
+        self.wants.pop_front();
+        self.values.push(value);
+    }
+
+    /// Take the values from the first one again for the next run.
+    fn restart(&mut self) {
+        self.taken = 0;
+    }
+}
+
+// -=-=- Execution Budget -=-=- //
+
+/// How often the timeout is checked, in steps.
+const TIMEOUT_CHECK_STEPS: u64 = 64;
+
+/// Limits on how long a single [`exec`](VirtualEnv::exec) can run for.
+struct Budget {
+    step_limit: Option<u64>,
+    timeout: Option<Duration>,
+    depth_limit: usize,
+    cancel: Arc<AtomicBool>,
+    // -=- state of the current exec -=- //
+    steps: u64,
+    started: Instant,
+    halted: Option<RuntimeError>,
+}
+
+impl Budget {
+    fn new() -> Budget {
+        Budget {
+            step_limit: None,
+            timeout: None,
+            depth_limit: DEFAULT_DEPTH_LIMIT,
+            cancel: Arc::new(AtomicBool::new(false)),
+            steps: 0,
+            started: Instant::now(),
+            halted: None,
+        }
+    }
+
+    /// Reset the state for a new exec.
+    fn start(&mut self) {
+        // a cancel that came after the last exec finished is for that one
+        self.cancel.store(false, Ordering::SeqCst);
+        self.steps = 0;
+        self.started = Instant::now();
+        self.halted = None;
+    }
+
+    /// Count a step, once the budget is used up every step after fails with the same error.
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        if let Some(err) = &self.halted {
+            return Err(err.clone());
+        }
+        self.steps += 1;
+        let err = if self.cancel.swap(false, Ordering::SeqCst) {
+            RuntimeError::Interrupted
+        } else if let Some(limit) = self.step_limit.filter(|limit| self.steps > *limit) {
+            RuntimeError::LimitExceeded(format!("more than {limit} steps"))
+        } else if let Some(timeout) = self.timeout.filter(|timeout| {
+            self.steps.is_multiple_of(TIMEOUT_CHECK_STEPS) && self.started.elapsed() > *timeout
+        }) {
+            RuntimeError::LimitExceeded(format!("ran longer than {timeout:?}"))
+        } else {
+            return Ok(());
+        };
+        self.halted = Some(err.clone());
+        Err(err)
+    }
+}
+
+impl Default for VirtualEnv {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VirtualEnv {
     pub fn new() -> VirtualEnv {
-        VirtualEnv { definitions: HashMap::new(), variables: HashMap::new() }
+        VirtualEnv {
+            definitions: HashMap::new(),
+            modules: HashMap::new(),
+            pure: HashSet::new(),
+            signatures: HashMap::new(),
+            builtins: HashMap::new(),
+            variables: HashMap::new(),
+            scopes: vec![],
+            budget: Budget::new(),
+            division: DivisionMode::default(),
+            float_format: FloatFormat::default(),
+            depth: 0,
+            nesting: 0,
+            stack_base: None,
+            output: Box::new(|line| println!("{line}")),
+            stats_enabled: false,
+            stats: ExecStats::default(),
+            debugger: None,
+            lambda_depth: 0,
+            stepping_over: None,
+            aborted: false,
+        }
+    }
+
+    /// Make a new environment with the same lambdas, functions and settings but none of the
+    /// variables, the output goes to stdout and it has its own
+    /// [cancel token](VirtualEnv::cancel_token).
+    pub fn clone_stateless(&self) -> VirtualEnv {
+        let mut env = VirtualEnv {
+            definitions: self.definitions.clone(),
+            modules: self.modules.clone(),
+            pure: self.pure.clone(),
+            signatures: self.signatures.clone(),
+            builtins: self.builtins.clone(),
+            division: self.division,
+            float_format: self.float_format,
+            ..VirtualEnv::new()
+        };
+        env.budget.step_limit = self.budget.step_limit;
+        env.budget.timeout = self.budget.timeout;
+        env.budget.depth_limit = self.budget.depth_limit;
+        env
+    }
+
+    /// Set where lines written by the running code go, by default they are printed to stdout.
+    /// The output is `Send + Sync` so the env can be moved or shared between threads.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use interpreter::exec::Executor;
+    /// let lines = Arc::new(Mutex::new(vec![]));
+    /// let sink = lines.clone();
+    /// let mut executor = Executor::calc();
+    /// executor.env_mut().set_output(Box::new(move |line| sink.lock().unwrap().push(line.to_owned())));
+    /// 
+    /// executor.exec_str("print(1 + 1)")?;
+    /// assert_eq!(*lines.lock().unwrap(), ["2"]);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn set_output(&mut self, output: Box<dyn FnMut(&str) + Send + Sync>) {
+        self.output = output;
+    }
+
+    /// Write a line to the [output](VirtualEnv::set_output).
+    pub fn write_line(&mut self, line: &str) {
+        (self.output)(line);
+    }
+
+    /// Call a [`Function`] with the `args` bound to its parameters in a new scope. The
+    /// variables declared while it runs belong to that scope and are dropped when it
+    /// returns, while [assigning](VirtualEnv::assign_ident) a variable of the caller
+    /// changes it.
+    pub fn call(&mut self, function: &Function, args: Vec<NodeValue>) -> StateNode {
+        let arity = Arity::Exact(function.params.len());
+        if !arity.accepts(args.len()) {
+            return StateNode::RuntimeErr(format!("Function expects {arity}, got {}", args.len()).into());
+        }
+        let depth = self.scopes.len();
+        self.push_scope();
+        for (param, arg) in function.params.iter().zip(args) {
+            self.set_ident(param, arg);
+        }
+        let result = self.eval_node(&function.body);
+        // an error can leave the scopes the body pushed behind
+        self.scopes.truncate(depth);
+        result
+    }
+
+    /// Register a native function that the [`CALL`](super::builtin::call) lambda can call
+    /// by name, it is only called with a number of arguments the `arity` accepts.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Args, Arity, NodeValue, VirtualEnv};
+    /// let mut env = VirtualEnv::new();
+    /// env.register_fn("sqrt", Arity::Exact(1), |args| Ok(NodeValue::BigFloat(args.f64(0)?.sqrt())));
+    /// assert_eq!(env.call_builtin("sqrt", &[NodeValue::Integer(9)]).as_node_value(), NodeValue::BigFloat(3.0));
+    /// ```
+    pub fn register_fn(&mut self, name: &str, arity: Arity, function: BuiltinFn) {
+        self.builtins.insert(name.to_owned(), (arity, function));
+    }
+
+    /// Call a [registered](VirtualEnv::register_fn) function, the errors are prefixed with
+    /// its name.
+    pub fn call_builtin(&self, name: &str, args: &[NodeValue]) -> StateNode {
+        let Some((arity, function)) = self.builtins.get(name) else {
+            return StateNode::RuntimeErr(format!("Unknown function `{name}`").into());
+        };
+        if !arity.accepts(args.len()) {
+            return StateNode::RuntimeErr(format!("{name} expects {arity}, got {}", args.len()).into());
+        }
+        match function(args) {
+            Ok(value) => StateNode::Value(value),
+            Err(err) => StateNode::RuntimeErr(format!("{name}: {err}").into()),
+        }
+    }
+
+    /// Set how `/` divides numbers.
+    pub fn set_division_mode(&mut self, mode: DivisionMode) {
+        self.division = mode;
+    }
+
+    /// Get how `/` divides numbers.
+    pub fn division_mode(&self) -> DivisionMode {
+        self.division
+    }
+
+    /// Set how floats are displayed, this only changes how the values are shown and not
+    /// the values themselves.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, FloatFormat, Precision};
+    /// let mut executor = Executor::math();
+    /// assert_eq!(executor.exec_str("1 / 3"), Ok("0.3333333".into()));
+    /// executor.env_mut().set_float_format(FloatFormat { precision: Precision::Shortest, trim_zeros: true });
+    /// assert_eq!(executor.exec_str("1 / 3"), Ok("0.33333334".into()));
+    /// ```
+    pub fn set_float_format(&mut self, format: FloatFormat) {
+        self.float_format = format;
+    }
+
+    /// Get how floats are displayed.
+    pub fn float_format(&self) -> FloatFormat {
+        self.float_format
+    }
+
+    /// Display a value with the env's [float format](VirtualEnv::set_float_format).
+    pub fn display(&self, value: &NodeValue) -> Result<String, String> {
+        value.display(&self.float_format)
+    }
+
+    /// Limit how many nodes a single [`exec`](VirtualEnv::exec) can evaluate before it
+    /// fails with [`RuntimeError::LimitExceeded`].
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.budget.step_limit = Some(limit);
+    }
+
+    /// Limit how long a single [`exec`](VirtualEnv::exec) can run before it fails with
+    /// [`RuntimeError::LimitExceeded`] - the time is checked every few steps.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.budget.timeout = Some(timeout);
+    }
+
+    /// Limit how many nodes can be evaluated inside each other before the evaluation fails
+    /// with [`RuntimeError::LimitExceeded`]. The nodes are worked through on a list instead
+    /// of the stack, so the default of 100 000 only stops a runaway recursion.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// let mut executor = Executor::math();
+    /// executor.env_mut().set_depth_limit(10);
+    /// assert_eq!(executor.exec_str("1+1+1"), Ok("3".into()));
+    /// assert!(executor.exec_str(&vec!["1"; 20].join("+")).is_err());
+    /// ```
+    pub fn set_depth_limit(&mut self, limit: usize) {
+        self.budget.depth_limit = limit;
     }
 
+    /// Get the flag that stops the current [`exec`](VirtualEnv::exec) with
+    /// [`RuntimeError::Interrupted`] when set, it can be set from another thread and is
+    /// cleared once the exec is stopped and when each exec starts.
+    pub fn cancel_token(&self) -> Arc<AtomicBool> {
+        self.budget.cancel.clone()
+    }
+
+    /// Declare a variable in the innermost scope, it replaces a variable of the same name
+    /// in that scope and hides the ones in the scopes around it.
     pub fn set_ident(&mut self, ident: &str, value: NodeValue) {
-        self.variables.insert(ident.to_owned(), value);
+        match self.scopes.last_mut() {
+            Some(scope) => scope.insert(ident.to_owned(), value),
+            None => self.variables.insert(ident.to_owned(), value),
+        };
     }
-    
+
+    /// Change the variable in the nearest scope it is declared in, a variable that isn't
+    /// declared in any scope is an error.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{NodeValue, VirtualEnv};
+    /// let mut env = VirtualEnv::new();
+    /// env.set_ident("x", NodeValue::Integer(1));
+    /// env.push_scope();
+    /// env.assign_ident("x", NodeValue::Integer(2))?;
+    /// assert!(env.assign_ident("y", NodeValue::Integer(3)).is_err());
+    /// env.pop_scope();
+    /// assert_eq!(env.get_ident("x"), NodeValue::Integer(2));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn assign_ident(&mut self, ident: &str, value: NodeValue) -> Result<(), String> {
+        let variable = match self.scopes.iter_mut().rev().find(|scope| scope.contains_key(ident)) {
+            Some(scope) => scope.get_mut(ident),
+            None => self.variables.get_mut(ident),
+        };
+        match variable {
+            Some(variable) => {
+                *variable = value;
+                Ok(())
+            },
+            None => Err(self.undefined(ident)),
+        }
+    }
+
+    /// Start a scope for a block, the variables [declared](VirtualEnv::set_ident) until it
+    /// is [popped](VirtualEnv::pop_scope) belong to it.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// End the innermost scope and drop its variables, the variables outside of every
+    /// scope are never popped.
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Remove all the variables from the environment.
+    pub fn clear_idents(&mut self) {
+        self.variables.clear();
+        self.scopes.clear();
+    }
+
+    /// Check if a lambda is defined with the `name`, which can be qualified with its
+    /// module like `math:ADD`.
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.get_lambda(name).is_some()
+    }
+
+    /// Get the lambda defined with the `name`, a name that isn't defined on its own is
+    /// looked up as `module:name`.
+    fn get_lambda(&self, name: &str) -> Option<LambdaFn> {
+        self.definitions.get(name).or_else(|| {
+            let (module, name) = name.split_once(':')?;
+            self.modules.get(module)?.get(name)
+        }).copied()
+    }
+
+    /// Get the sorted names of every lambda defined in the environment without their
+    /// modules.
+    pub fn lambda_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.definitions.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Capture the variables so they can be [restored](VirtualEnv::restore) later, values
+    /// that are not kept are listed in the snapshot's warnings.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// let mut executor = Executor::math();
+    /// executor.exec_str("x := 41")?;
+    /// let snapshot = executor.env().snapshot();
+    /// 
+    /// let mut executor = Executor::math();
+    /// executor.env_mut().restore(snapshot);
+    /// assert_eq!(executor.exec_str("x + 1"), Ok("42".into()));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn snapshot(&self) -> EnvSnapshot {
+        EnvSnapshot::from_variables(&self.variables)
+    }
+
+    /// Replace the variables with the ones from a [`snapshot`](VirtualEnv::snapshot), so
+    /// any change to them since is undone - the lambdas are not part of a snapshot.
+    pub fn restore(&mut self, snapshot: EnvSnapshot) {
+        self.variables = snapshot.variables;
+    }
+
+    /// Get the value of the variable from the nearest scope it is declared in.
     pub fn get_ident(&self, ident: &str) -> NodeValue {
-        match self.variables.get(ident) {
+        let variable = self.scopes.iter().rev()
+            .find_map(|scope| scope.get(ident))
+            .or_else(|| self.variables.get(ident));
+        match variable {
             Some(val) => val.clone(),
-            None => NodeValue::ValueError(format!("Could Not find variable `{}`", ident)), // Use `ident` instead of `{ident}`
+            None => NodeValue::ValueError(self.undefined(ident)),
         }
     }
 
+    /// The error for a variable that isn't declared, naming a close variable.
+    fn undefined(&self, ident: &str) -> String {
+        match self.suggest_variable(ident) {
+            Some(name) => format!("undefined variable '{ident}', did you mean '{name}'?"),
+            None => format!("undefined variable '{ident}'"),
+        }
+    }
+
+    /// Get the variables of the environment by name, to look at the state between runs.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{NodeValue, VirtualEnv};
+    /// let mut env = VirtualEnv::new();
+    /// env.set_ident("x", NodeValue::Integer(1));
+    /// assert_eq!(env.bindings().get("x"), Some(&NodeValue::Integer(1)));
+    /// ```
+    pub fn bindings(&self) -> &HashMap<String, NodeValue> {
+        &self.variables
+    }
+
+    /// Get the sorted names of the variables in the environment.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{NodeValue, VirtualEnv};
+    /// let mut env = VirtualEnv::new();
+    /// env.set_ident("y", NodeValue::Integer(2));
+    /// env.set_ident("x", NodeValue::Integer(1));
+    /// assert_eq!(env.variable_names(), ["x", "y"]);
+    /// ```
+    pub fn variable_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.variables.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Get the variable closest to `ident` when it is at most 2 edits away.
+    fn suggest_variable(&self, ident: &str) -> Option<&str> {
+        let scoped = self.scopes.iter().flat_map(|scope| scope.keys().map(|name| name.as_str()));
+        scoped.chain(self.variable_names())
+            .map(|name| (edit_distance(ident, name), name))
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, name)| name)
+    }
+
     pub fn exec(&mut self, ast: AbstractSyntaxTree) -> StateNode {
+        self.exec_tree(&ast)
+    }
+
+    /// Replace the subtrees that always give the same value with a node holding the value,
+    /// so they aren't worked out again every time the tree is run. Only subtrees where
+    /// every lambda is [pure](VirtualEnv::define_pure) are folded, ones that fail are left
+    /// as they are - a failing subtree still fails when the tree is run.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, NodeValue};
+    /// let mut executor = Executor::math();
+    /// let compiled = executor.compile("2 * (3 + 4) + x")?;
+    /// let mut ast = compiled.ast().clone();
+    /// executor.env().fold_constants(&mut ast);
+    /// assert_eq!(ast.root.nodes[0].value, Some(NodeValue::Integer(14)));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn fold_constants(&self, ast: &mut AbstractSyntaxTree) {
+        let written = Arc::new(AtomicBool::new(false));
+        let mut scratch = self.scratch(&written);
+        scratch.fold_node(&mut ast.root, &written);
+    }
+
+    /// Work out as much of the tree as the variables that are set allow. Subtrees that
+    /// give a value are replaced with it, like [`fold_constants`](VirtualEnv::fold_constants),
+    /// while the ones reading unset variables are kept so they can be run once those are
+    /// set. Subtrees that set variables, print, or fail are kept as well.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, NodeValue, PartialResult};
+    /// let mut executor = Executor::math();
+    /// let ast = executor.compile("a * 2 + b")?.ast().clone();
+    /// executor.env_mut().set_ident("a", NodeValue::Integer(3));
+    /// 
+    /// let PartialResult::Residual(residual) = executor.env().partial_eval(&ast) else {
+    ///     return Err("`b` is not set".into());
+    /// };
+    /// assert_eq!(residual.to_string(), "( 6 op:+ ( ident:b ) )");
+    /// 
+    /// executor.env_mut().set_ident("b", NodeValue::Integer(1));
+    /// assert_eq!(executor.env().partial_eval(&residual), PartialResult::Value(NodeValue::Integer(7)));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn partial_eval(&self, ast: &AbstractSyntaxTree) -> PartialResult {
+        let written = Arc::new(AtomicBool::new(false));
+        let mut scratch = self.scratch(&written);
+        scratch.variables = self.variables.clone();
+        let mut residual = ast.clone();
+        scratch.partial_node(&mut residual.root, &written, &self.variables);
+        match residual.root.value.take() {
+            Some(value) => PartialResult::Value(value),
+            None => PartialResult::Residual(residual),
+        }
+    }
+
+    /// An environment with the same lambdas and no variables to work out subtrees in,
+    /// anything it prints sets `written`.
+    fn scratch(&self, written: &Arc<AtomicBool>) -> VirtualEnv {
+        let mut scratch = VirtualEnv {
+            definitions: self.definitions.clone(),
+            modules: self.modules.clone(),
+            pure: self.pure.clone(),
+            signatures: self.signatures.clone(),
+            builtins: self.builtins.clone(),
+            division: self.division,
+            output: {
+                let written = written.clone();
+                Box::new(move |_| written.store(true, Ordering::SeqCst))
+            },
+            ..VirtualEnv::new()
+        };
+        // a constant loop that never ends isn't folded
+        scratch.set_step_limit(FOLD_STEP_LIMIT);
+        scratch
+    }
+
+    /// Replace the node with its value if it gives one without changing the `variables`
+    /// or printing, otherwise try its branches.
+    fn partial_node(&mut self, node: &mut TreeNode, written: &AtomicBool, variables: &HashMap<String, NodeValue>) {
+        if node.value.is_some() || node.lambda == OwnedLambda::EvalToken {
+            return;
+        }
+        self.budget.start();
+        let result = self.eval_node(node);
+        let side_effect = written.swap(false, Ordering::SeqCst) || self.variables != *variables;
+        if side_effect {
+            self.variables = variables.clone();
+            self.scopes.clear();
+        }
+        if let (StateNode::Value(value), false) = (result, side_effect) {
+            let position = node.position.take();
+            *node = TreeNode::from_value(value);
+            node.position = position;
+            return;
+        }
+        node.nodes.iter_mut().for_each(|node| self.partial_node(node, written, variables));
+    }
+
+    /// Fold the node if it is constant, otherwise fold its branches.
+    fn fold_node(&mut self, node: &mut TreeNode, written: &AtomicBool) {
+        // tokens are read by the lambdas above them
+        if node.value.is_some() || node.lambda == OwnedLambda::EvalToken {
+            return;
+        }
+        if self.is_constant(node) {
+            self.budget.start();
+            let result = self.eval_node(node);
+            let side_effect = written.swap(false, Ordering::SeqCst) || !self.variables.is_empty();
+            self.variables.clear();
+            if let (StateNode::Value(value), false) = (result, side_effect) {
+                let position = node.position.take();
+                *node = TreeNode::from_value(value);
+                node.position = position;
+                return;
+            }
+        }
+        node.nodes.iter_mut().for_each(|node| self.fold_node(node, written));
+    }
+
+    /// Check that every lambda in the tree is [pure](VirtualEnv::define_pure).
+    fn is_constant(&self, node: &TreeNode) -> bool {
+        let mut names = vec![];
+        node.lambda.collect_names(&mut names);
+        names.into_iter().all(|name| self.pure.contains(name))
+            && node.nodes.iter().all(|node| self.is_constant(node))
+    }
+
+    /// Run a borrowed tree, so the same tree can be run again.
+    pub fn exec_tree(&mut self, ast: &AbstractSyntaxTree) -> StateNode {
         // println!("exec: {ast}");
+        self.budget.start();
         self.eval_node(&ast.root)
     }
 
+    /// Evaluate a node. The nodes are worked through on a list instead of the stack - a
+    /// [pure](VirtualEnv::define_pure) lambda that asks for the value of a branch is put
+    /// aside until the branch has its value and then run again, so a deep tree of them
+    /// doesn't go deeper on the stack. The other lambdas evaluate their branches on the
+    /// stack, once that uses more than [`STACK_LIMIT`] the evaluation fails with
+    /// [`RuntimeError::LimitExceeded`] instead of overflowing it.
     fn eval_node(&mut self, node: &TreeNode) -> StateNode {
-        let lambda = &node.lambda;
-        // println!("EVAL: {node} {lambda}");
-        // println!("{lambda:?}");
+        let position = stack_position();
+        let base = *self.stack_base.get_or_insert(position);
+        if base.abs_diff(position) > STACK_LIMIT {
+            return StateNode::RuntimeErr(RuntimeError::LimitExceeded(format!("more than {} KiB of stack", STACK_LIMIT / 1024)));
+        }
+        // the variables before the outermost evaluation, for the debugger to abort it
+        let snapshot = (self.nesting == 0 && self.debugger.is_some())
+            .then(|| (self.variables.clone(), self.scopes.clone()));
+        self.nesting += 1;
+        let result = self.run(node);
+        self.nesting -= 1;
+        if self.nesting == 0 {
+            self.stack_base = None;
+            let aborted = std::mem::take(&mut self.aborted);
+            if let (true, Some((variables, scopes)), StateNode::RuntimeErr(_)) = (aborted, snapshot, &result) {
+                self.variables = variables;
+                self.scopes = scopes;
+            }
+        }
+        result
+    }
+
+    /// Work through the node and the nodes it needs until it has its value.
+    fn run(&mut self, root: &TreeNode) -> StateNode {
+        let bodies = Bodies::default();
+        let mut work = WorkList { waiting: vec![], last: &bodies, bodies: HashMap::new() };
+        let mut started = self.start(root, &mut work);
+        loop {
+            let mut value = match started {
+                Started::Needs(node) => {
+                    started = self.start(node, &mut work);
+                    continue;
+                }
+                Started::Done(value) => value,
+            };
+            // give the value to the nodes waiting on it, until one needs another node
+            started = loop {
+                self.depth -= 1;
+                match work.waiting.pop() {
+                    None => return value,
+                    Some(Waiting::Forward) => continue,
+                    Some(Waiting::Lambda(mut lambda)) => {
+                        lambda.replay.give(value);
+                        match self.resume(lambda, &mut work) {
+                            Started::Done(next) => value = next,
+                            needs => break needs,
+                        }
+                    }
+                    Some(Waiting::Call(lambda, depth)) => {
+                        // an error can leave the scopes the body pushed behind
+                        self.scopes.truncate(depth);
+                        value = self.finish(lambda, value);
+                    }
+                }
+            };
+        }
+    }
 
-        self.eval_lambda(node, lambda)
+    /// Start evaluating a node, a tree deeper than the [depth limit](VirtualEnv::set_depth_limit)
+    /// fails with [`RuntimeError::LimitExceeded`].
+    fn start<'n>(&mut self, node: &'n TreeNode, work: &mut WorkList<'n>) -> Started<'n> {
+        self.depth += 1;
+        let limit = self.budget.depth_limit;
+        if self.depth > limit {
+            return Started::Done(StateNode::RuntimeErr(RuntimeError::LimitExceeded(format!("more than {limit} nested nodes"))));
+        }
+        if let Err(err) = self.budget.step() {
+            return Started::Done(StateNode::RuntimeErr(err));
+        }
+        if let Some(value) = &node.value {
+            return Started::Done(StateNode::Value(value.clone()));
+        }
+        // println!("EVAL: {node} {}", node.lambda);
+        match Self::target(node, &node.lambda) {
+            Target::Node(node) => {
+                work.waiting.push(Waiting::Forward);
+                Started::Needs(node)
+            }
+            Target::Token(node) => Started::Done(EnvFrame::build_frame(self, "TOKEN", node, &[]).eval_token()),
+            Target::Lambda(name, node, args) => {
+                self.lambda_depth += 1;
+                let replay = Replay { suspends: self.pure.contains(name), ..Replay::default() };
+                self.resume(Suspended { name, node, args, replay, elapsed: Duration::ZERO }, work)
+            }
+            Target::Err(err) => Started::Done(StateNode::RuntimeErr(err)),
+        }
     }
 
-    fn eval_lambda(&mut self, node: &TreeNode, lambda: &OwnedLambda) -> StateNode {
+    /// Follow the lambda of a node through the branches it picks to the env lambda it runs
+    /// or the node it evaluates.
+    fn target<'n>(mut node: &'n TreeNode, mut lambda: &'n OwnedLambda) -> Target<'n> {
         use OwnedLambda::*;
-        match lambda {
-            Eval => self.eval(node),
-            EvalToken => self.eval_token(node),
-            Lambda(name, args) => self.lambda(name, node, args),
-            EvalAs(name) => self.lambda(name, node, &[]),
-            GetExpr(arg, sublambda) => match node.nodes.get(*arg as usize - 1) {
-                Some(subnode) => self.eval_lambda(subnode, sublambda),
-                None => StateNode::RuntimeErr(format!("No node found for index {arg} on node `{node}`")),
+        // the 1-based indexes of the children the lambda was picked from
+        let mut picked: Option<Cow<'n, [u32]>> = None;
+        loop {
+            let Some(args) = &picked else {
+                match lambda {
+                    Eval if node.lambda == Eval => return Target::Err(format!("Recursion Error: Cannot EVAL on node with EVAL lambda `{node}`").into()),
+                    Eval => return Target::Node(node),
+                    EvalToken => return Target::Token(node),
+                    Lambda(name, args) => return Target::Lambda(name, node, Cow::Borrowed(args)),
+                    EvalAs(name) => return Target::Lambda(name, node, Cow::Borrowed(&[])),
+                    GetExpr(arg, sublambda) => match Self::get_child(node, *arg) {
+                        Ok(subnode) => (node, lambda) = (subnode, sublambda),
+                        Err(err) => return Target::Err(err),
+                    },
+                    GetExprs(args, sublambda) => (picked, lambda) = (Some(Cow::Borrowed(args.as_slice())), sublambda),
+                    _ => return Target::Err(format!("No lambda eval found for `{lambda:?}`").into()),
+                }
+                continue;
+            };
+            // the picked children keep their place on the node instead of being copied to a new node
+            if let Err(err) = args.iter().try_for_each(|arg| Self::get_child(node, *arg).map(|_| ())) {
+                return Target::Err(err);
+            }
+            // the index on the node of a 1-based index into the picked children
+            let pick = |arg: u32| match arg {
+                0 => Err(RuntimeError::from("GetExpr index is 1-based; 0 is invalid".to_owned())),
+                arg => args.get(arg as usize - 1).copied()
+                    .ok_or_else(|| format!("No node found for index {arg} on node `{node}`").into()),
+            };
+            let pick_all = |sub_args: &[u32]| sub_args.iter().map(|arg| pick(*arg)).collect::<Result<Vec<u32>, _>>();
+            match lambda {
+                Lambda(name, sub_args) => return match pick_all(sub_args) {
+                    Ok(sub_args) => Target::Lambda(name, node, Cow::Owned(sub_args)),
+                    Err(err) => Target::Err(err),
+                },
+                GetExpr(arg, sublambda) => match pick(*arg).and_then(|arg| Self::get_child(node, arg)) {
+                    Ok(subnode) => (node, lambda, picked) = (subnode, sublambda, None),
+                    Err(err) => return Target::Err(err),
+                },
+                GetExprs(sub_args, sublambda) => match pick_all(sub_args) {
+                    Ok(sub_args) => (picked, lambda) = (Some(Cow::Owned(sub_args)), sublambda),
+                    Err(err) => return Target::Err(err),
+                },
+                EvalAs(name) => return Target::Lambda(name, node, picked.unwrap_or_default()),
+                _ => return Target::Err(format!("No lambda eval found for `{lambda:?}` on the picked nodes of `{node}`").into()),
             }
-            _ => StateNode::RuntimeErr(format!("No lambda eval found for `{lambda:?}`")),
         }
+    }
 
-        
+    /// Get the child of the node by its 1-based index.
+    fn get_child(node: &TreeNode, arg: u32) -> Result<&TreeNode, RuntimeError> {
+        if arg == 0 {
+            return Err("GetExpr index is 1-based; 0 is invalid".into());
+        }
+        node.nodes.get(arg as usize - 1)
+            .ok_or_else(|| format!("No node found for index {arg} on node `{node}`").into())
+    }
+
+    /// Run a lambda from the start with the values it has been given, a pure lambda that
+    /// asks for a branch without a value is put aside until the branch is evaluated.
+    fn resume<'n>(&mut self, mut lambda: Suspended<'n>, work: &mut WorkList<'n>) -> Started<'n> {
+        if let Some(index) = lambda.replay.wanted() {
+            let branch = &lambda.node.nodes[index - 1];
+            work.waiting.push(Waiting::Lambda(lambda));
+            return Started::Needs(branch);
+        }
+        let Some(cb) = self.get_lambda(lambda.name) else {
+            let name = lambda.name;
+            return Started::Done(self.finish(lambda, StateNode::RuntimeErr(format!("No lambda found for `{name}`").into())));
+        };
+        let started = self.stats_enabled.then(Instant::now);
+        let frame = EnvFrame { env: self, name: lambda.name, node: lambda.node, args: &lambda.args, replay: Some(&mut lambda.replay) };
+        let result = cb(frame);
+        if let Some(started) = started {
+            lambda.elapsed += started.elapsed();
+        }
+        if let Some(index) = lambda.replay.wanted() {
+            lambda.replay.restart();
+            let branch = &lambda.node.nodes[index - 1];
+            work.waiting.push(Waiting::Lambda(lambda));
+            return Started::Needs(branch);
+        }
+        match lambda.replay.call.take() {
+            Some((function, args)) => self.call_body(lambda, function, args, work),
+            None => Started::Done(self.finish(lambda, result)),
+        }
     }
 
-    fn eval(&mut self, node: &TreeNode) -> StateNode {
-        if let OwnedLambda::Eval = &node.lambda {
-            return StateNode::RuntimeErr(format!("Recursion Error: Cannot EVAL on node with EVAL lambda `{node}`"));
+    /// Call a function the lambda gave as its value, see [`call`](VirtualEnv::call) - the
+    /// body is worked through after the lambda, so calls inside each other don't go deeper
+    /// on the stack.
+    fn call_body<'n>(&mut self, lambda: Suspended<'n>, function: Function, args: Vec<NodeValue>, work: &mut WorkList<'n>) -> Started<'n> {
+        let arity = Arity::Exact(function.params.len());
+        if !arity.accepts(args.len()) {
+            let err = StateNode::RuntimeErr(format!("Function expects {arity}, got {}", args.len()).into());
+            return Started::Done(self.finish(lambda, err));
         }
-        self.eval_node(node)
+        let depth = self.scopes.len();
+        self.push_scope();
+        for (param, arg) in function.params.iter().zip(args) {
+            self.set_ident(param, arg);
+        }
+        let body = work.body(function.body);
+        work.waiting.push(Waiting::Call(lambda, depth));
+        Started::Needs(body)
     }
 
-    fn eval_token(&mut self, node: &TreeNode) -> StateNode {
-        // println!("EVAL TOKEN: {node}");
-        EnvFrame::build_frame(self, node, &[]).eval_token()
+    /// Finish running a lambda with its value, giving it to the debugger.
+    fn finish(&mut self, lambda: Suspended, result: StateNode) -> StateNode {
+        self.lambda_depth -= 1;
+        if self.stats_enabled {
+            self.stats.record(lambda.name, lambda.elapsed);
+        }
+        // a failed lambda is already stopping the evaluation
+        if let StateNode::RuntimeErr(_) = result {
+            return result;
+        }
+        match self.debug(lambda.name, lambda.node) {
+            Ok(()) => result,
+            Err(err) => StateNode::RuntimeErr(err),
+        }
     }
-    
-    fn lambda(&mut self, name: &str, node: &TreeNode, args: &[u32]) -> StateNode {
-        let lambda = match self.definitions.get(name) {
-            Some(lambda) => lambda,
-            None => return StateNode::RuntimeErr(format!("No lambda found for `{}`", name)),
+
+    /// Give the lambda that has run its branches to the debugger.
+    fn debug(&mut self, name: &str, node: &TreeNode) -> Result<(), RuntimeError> {
+        let Some(debugger) = self.debugger.as_mut() else {
+            return Ok(());
+        };
+        match self.stepping_over {
+            Some(depth) if self.lambda_depth >= depth => return Ok(()),
+            Some(_) => self.stepping_over = None,
+            None => (),
+        }
+        let event = DebugEvent {
+            lambda: name, node, variables: &self.variables, scopes: &self.scopes, output: &mut self.output,
         };
-        lambda(EnvFrame::build_frame(self, node, args))
+        match debugger(event) {
+            DebugAction::Continue => Ok(()),
+            DebugAction::StepOver => {
+                // the outermost lambda has nothing left to step over
+                self.stepping_over = (self.lambda_depth > 0).then_some(self.lambda_depth);
+                Ok(())
+            }
+            DebugAction::Abort => {
+                self.aborted = true;
+                Err(match node.span() {
+                    Some(span) => RuntimeError::At("Aborted by the debugger".into(), span),
+                    None => RuntimeError::from("Aborted by the debugger".to_owned()),
+                })
+            }
+        }
+    }
+
+    /// Call the `debugger` once each lambda has run its branches, with the [`DebugEvent`]
+    /// of the lambda - so `1 + 2 * 3` steps through `MULT` before `ADD`. What it answers
+    /// decides how the evaluation goes on, see [`DebugAction`] - an abort puts the variables
+    /// back to how they were before the evaluation, so nothing it set is kept.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{DebugAction, Executor};
+    /// let mut executor = Executor::math();
+    /// executor.env_mut().set_debugger(Box::new(|event| match event.lambda {
+    ///     "DIV" => DebugAction::Abort,
+    ///     _ => DebugAction::Continue,
+    /// }));
+    /// assert_eq!(executor.exec_str("1 + 2"), Ok("3".into()));
+    /// assert_eq!(executor.exec_str("1 / 2"), Err("Aborted by the debugger".into()));
+    /// ```
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+        self.stepping_over = None;
+    }
+
+    /// Stop calling the [debugger](VirtualEnv::set_debugger).
+    pub fn clear_debugger(&mut self) {
+        self.debugger = None;
+        self.stepping_over = None;
+    }
+
+    /// Check if a [debugger](VirtualEnv::set_debugger) is set.
+    pub fn has_debugger(&self) -> bool {
+        self.debugger.is_some()
+    }
+
+    /// Start or stop counting how many times each lambda runs and how long it takes,
+    /// the stats collected so far are kept - see [`take_stats`](VirtualEnv::take_stats).
+    pub fn enable_stats(&mut self, enabled: bool) {
+        self.stats_enabled = enabled;
+    }
+
+    /// Get the [`ExecStats`] collected since they were last taken, starting them over.
+    pub fn take_stats(&mut self) -> ExecStats {
+        std::mem::take(&mut self.stats)
     }
 
-    pub fn define(&mut self, lambda_type: &str, cb: fn(EnvFrame) -> StateNode) {
+    pub fn define(&mut self, lambda_type: &str, cb: LambdaFn) {
+        self.pure.remove(lambda_type);
         self.definitions.insert(lambda_type.into(), cb);
     }
+
+    /// Define a lambda that only works out a value from its branches - it doesn't read or
+    /// set variables, call functions or print - so [`fold_constants`](VirtualEnv::fold_constants)
+    /// can replace a subtree using it with its value. The lambda is run again from the start
+    /// each time it asks for a branch without a value, so it mustn't push scopes either.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{NodeValue, StateNode, VirtualEnv};
+    /// let mut env = VirtualEnv::new();
+    /// env.define_pure("ONE", |_| StateNode::Value(NodeValue::Integer(1)));
+    /// assert!(env.is_pure("ONE"));
+    /// env.define("ONE", |_| StateNode::Value(NodeValue::Integer(1)));
+    /// assert!(!env.is_pure("ONE"));
+    /// ```
+    pub fn define_pure(&mut self, lambda_type: &str, cb: LambdaFn) {
+        self.definitions.insert(lambda_type.into(), cb);
+        self.pure.insert(lambda_type.into());
+    }
+
+    /// Check if the lambda with the `name` was defined as [pure](VirtualEnv::define_pure).
+    pub fn is_pure(&self, name: &str) -> bool {
+        self.pure.contains(name)
+    }
+
+    /// Define a lambda in a `module` so it can be called as `module:name`, and as `name`
+    /// when nothing else is defined with that name. A name already defined in the module
+    /// is an error instead of being replaced.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{StateNode, VirtualEnv};
+    /// let mut env = VirtualEnv::new();
+    /// env.define_in("host", "PING", |_| StateNode::None)?;
+    /// assert!(env.is_defined("host:PING"));
+    /// assert!(env.define_in("host", "PING", |_| StateNode::None).is_err());
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_in(&mut self, module: &str, lambda_type: &str, cb: LambdaFn) -> Result<(), String> {
+        let lambdas = self.modules.entry(module.to_owned()).or_default();
+        if lambdas.contains_key(lambda_type) {
+            return Err(format!("Lambda `{module}:{lambda_type}` is already defined"));
+        }
+        lambdas.insert(lambda_type.to_owned(), cb);
+        self.definitions.entry(lambda_type.to_owned()).or_insert(cb);
+        Ok(())
+    }
+
+    /// Define every lambda of the [`Module`] with [`define_in`](VirtualEnv::define_in),
+    /// nothing is defined when any of them is already defined in the module.
+    pub fn register_module(&mut self, module: Module) -> Result<(), String> {
+        let name = module.name().to_owned();
+        if let Some(lambdas) = self.modules.get(&name) {
+            let taken: Vec<_> = module.lambda_names().into_iter()
+                .filter(|lambda| lambdas.contains_key(*lambda))
+                .map(|lambda| format!("`{name}:{lambda}`"))
+                .collect();
+            if !taken.is_empty() {
+                return Err(format!("Lambdas {} are already defined", taken.join(", ")));
+            }
+        }
+        module.into_definitions().try_for_each(|(lambda, cb, pure)| {
+            // the plain name only calls this lambda when nothing else has it
+            let plain = !self.definitions.contains_key(&lambda);
+            self.define_in(&name, &lambda, cb)?;
+            if pure {
+                self.pure.insert(format!("{name}:{lambda}"));
+                if plain {
+                    self.pure.insert(lambda);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Give the lambda with the `name` a [`Signature`] that works out the type it gives
+    /// from the types of its args, for [`check_types`](VirtualEnv::check_types).
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{types, VirtualEnv};
+    /// let mut env = VirtualEnv::new();
+    /// env.define_signature("ADD", |args| types::arithmetic("add", args));
+    /// assert!(env.signature("ADD").is_some());
+    /// ```
+    pub fn define_signature(&mut self, lambda_type: &str, signature: Signature) {
+        self.signatures.insert(lambda_type.to_owned(), TypeRule::Signature(signature));
+    }
+
+    /// Mark the lambda with the `name` as reading the variable named by the first token of
+    /// its node, so [`check_types`](VirtualEnv::check_types) gives it the type of the
+    /// variable instead of working it out from its args.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use std::collections::HashMap;
+    /// use interpreter::exec::ValueType;
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("x"))?;
+    /// let mut env = math::env();
+    /// env.define_variable_signature("GET_IDENT");
+    /// assert_eq!(env.infer_type(&ast, &HashMap::from([("x".into(), ValueType::Float)])), ValueType::Float);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_variable_signature(&mut self, lambda_type: &str) {
+        self.signatures.insert(lambda_type.to_owned(), TypeRule::Variable);
+    }
+
+    /// Get the [`Signature`] of the lambda with the `name`, a qualified name like `math:ADD`
+    /// uses the signature of `ADD` when it has none of its own.
+    pub fn signature(&self, name: &str) -> Option<Signature> {
+        match self.type_rule(name)? {
+            TypeRule::Signature(signature) => Some(signature),
+            TypeRule::Variable => None,
+        }
+    }
+
+    /// Get how the type of the lambda with the `name` is worked out, with the same
+    /// fallback as [`signature`](VirtualEnv::signature).
+    pub(crate) fn type_rule(&self, name: &str) -> Option<TypeRule> {
+        self.signatures.get(name)
+            .or_else(|| self.signatures.get(name.split_once(':')?.1))
+            .copied()
+    }
+
+    /// Get the sorted names of the registered modules.
+    pub fn module_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.modules.keys().map(|name| name.as_str()).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Get the sorted names of the lambdas defined in the `module`, without the module.
+    pub fn module_lambdas(&self, module: &str) -> Vec<&str> {
+        let mut names: Vec<_> = self.modules.get(module).into_iter()
+            .flat_map(|lambdas| lambdas.keys().map(|name| name.as_str()))
+            .collect();
+        names.sort_unstable();
+        names
+    }
 }
 
 
 #[derive(Debug)]
-pub enum Exec {
+#[allow(clippy::large_enum_variant)]
+pub enum Exec<'a> {
     NoOp(),
     UniExpr( StateNode ),
     BinExpr( StateNode, StateNode ),
     TriExpr( StateNode, StateNode, StateNode ),
-    Root( StateNode ),
-    RuntimeErr(String)
+    /// The node itself when the lambda doesn't take 1 to 3 args, it is borrowed from the
+    /// tree so evaluating never copies it
+    Root( &'a TreeNode ),
+    RuntimeErr(RuntimeError)
 }
 
-impl Exec {
-    pub fn new(node: TreeNode) -> Exec {
-        Self::Root( StateNode::new(node) )
+impl<'a> Exec<'a> {
+    pub fn new(node: &'a TreeNode) -> Exec<'a> {
+        Self::Root( node )
     }
+
+    /// Take the evaluated args in order, for lambdas that handle any number of them.
+    /// A [`Root`](Exec::Root) has no evaluated args and an error is the only arg.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Exec, NodeValue, StateNode};
+    /// let int = |int| StateNode::Value(NodeValue::Integer(int));
+    /// assert_eq!(Exec::BinExpr(int(1), int(2)).args(), [int(1), int(2)]);
+    /// assert!(Exec::NoOp().args().is_empty());
+    /// ```
+    pub fn args(self) -> Vec<StateNode> {
+        match self {
+            Self::NoOp() | Self::Root(_) => vec![],
+            Self::UniExpr(arg) => vec![arg],
+            Self::BinExpr(lhs, rhs) => vec![lhs, rhs],
+            Self::TriExpr(first, second, third) => vec![first, second, third],
+            Self::RuntimeErr(err) => vec![StateNode::RuntimeErr(err)],
+        }
+    }
+}
+
+/// What [`partial_eval`](VirtualEnv::partial_eval) could work out of a tree.
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum PartialResult {
+    /// The whole tree gave a value.
+    Value(NodeValue),
+    /// The tree with every subtree that could be worked out replaced by its value.
+    Residual(AbstractSyntaxTree),
 }
 
 #[derive(Debug, Clone)]
 pub enum StateNode {
     None,
-    Node(TreeNode),
+    Node(Arc<TreeNode>),
     Value(NodeValue),
-    RuntimeErr(String)
+    RuntimeErr(RuntimeError)
+}
+
+/// States are equal when they hold equal values, errors with the same message - wherever
+/// they happened - or nodes with the same [structure](TreeNode#impl-PartialEq-for-TreeNode).
+impl PartialEq for StateNode {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::None, Self::None) => true,
+            (Self::Node(lhs), Self::Node(rhs)) => lhs == rhs,
+            (Self::Value(lhs), Self::Value(rhs)) => lhs == rhs,
+            (Self::RuntimeErr(lhs), Self::RuntimeErr(rhs)) => lhs.to_string() == rhs.to_string(),
+            _ => false,
+        }
+    }
 }
 
 impl StateNode {
     pub fn new(node: TreeNode) -> StateNode {
-        Self::Node(node)
+        Self::Node(Arc::new(node))
+    }
+
+    pub fn as_value(self) -> StateNode {
+        match self {
+            Self::Node(_) => {
+                Self::RuntimeErr("Cannot convert Node to Value".into())
+            },
+            _ => self
+        }
+    }
+
+    pub fn as_ident(&self) -> NodeValue {
+        match self {
+            Self::Value(value) => match value {
+                NodeValue::Ident(ident) => NodeValue::Ident(ident.to_string()),
+                NodeValue::Token(token) => NodeValue::Ident(token.value.clone()),
+                NodeValue::String(string) => NodeValue::Ident(string.into()),
+                _ => NodeValue::ValueError(format!("Cannot convert `{value:?}` to Identifier"))
+            },
+            _ => NodeValue::ValueError(format!("Cannot convert `{self:?}` to Identifier")),
+        }
+    }
+
+    /// Take the name of an [`Ident`](NodeValue::Ident), any other value is an error - a
+    /// runtime error is given as is.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{NodeValue, StateNode};
+    /// assert_eq!(StateNode::Value(NodeValue::Ident("x".into())).into_ident(), Ok("x".into()));
+    /// assert!(StateNode::Value(NodeValue::Integer(3)).into_ident().is_err());
+    /// assert!(StateNode::Value(NodeValue::String("x".into())).into_ident().is_err());
+    /// ```
+    pub fn into_ident(self) -> Result<String, RuntimeError> {
+        match self {
+            Self::Value(NodeValue::Ident(ident)) => Ok(ident),
+            Self::Value(value) => Err(format!("Cannot use {} as an Identifier", value.describe()).into()),
+            Self::RuntimeErr(err) => Err(err),
+            state => Err(format!("Cannot use `{state:?}` as an Identifier").into()),
+        }
+    }
+
+    pub fn as_node_value(self) -> NodeValue {
+        match self {
+            Self::RuntimeErr(_) => unreachable!(), // should not be trying to convert an error
+            Self::Value(val) => val,
+            _ => NodeValue::ValueError(format!("Cannot convert `{self:?}` to NodeValue")),
+        }
+        // NodeValue::ValueError(format!("Cannot convert `{self:?}` to NodeValue"))
+    }
+
+    /// Apply a binary operator to the values, the first runtime error is given as is and a
+    /// value error from either side or the operator becomes a runtime error.
+    fn operator(lhs: StateNode, rhs: StateNode, op: fn(a: NodeValue, b: NodeValue) -> NodeValue) -> StateNode {
+        let (lhs, rhs) = match (lhs, rhs) {
+            (err @ Self::RuntimeErr(_), _) | (_, err @ Self::RuntimeErr(_)) => return err,
+            (lhs, rhs) => (lhs.as_node_value(), rhs.as_node_value()),
+        };
+        let value = match (lhs, rhs) {
+            (NodeValue::ValueError(err), _) | (_, NodeValue::ValueError(err)) => return Self::RuntimeErr(err.into()),
+            (lhs, rhs) => op(lhs, rhs),
+        };
+        match value {
+            NodeValue::ValueError(err) => Self::RuntimeErr(err.into()),
+            value => Self::Value(value),
+        }
+    }
+}
+
+impl Add for StateNode {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs + rhs )
+    }
+}
+impl Sub for StateNode {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs - rhs )
+    }
+}
+impl Mul for StateNode {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs * rhs )
+    }
+}
+impl Div for StateNode {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs / rhs )
+    }
+}
+impl BitAnd for StateNode {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs & rhs )
+    }
+}
+impl BitOr for StateNode {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs | rhs )
+    }
+}
+impl BitXor for StateNode {
+    type Output = Self;
+
+    fn bitxor(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs ^ rhs )
+    }
+}
+impl Shl for StateNode {
+    type Output = Self;
+
+    fn shl(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs << rhs )
+    }
+}
+impl Shr for StateNode {
+    type Output = Self;
+
+    fn shr(self, other: Self) -> Self::Output {
+        Self::operator(self, other, |lhs, rhs| lhs >> rhs )
+    }
+}
+impl Not for StateNode {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        self.convert(|value| !value.clone())
+    }
+}
+
+impl Neg for StateNode {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        self.convert(|value| -value.clone())
+    }
+}
+
+impl StateNode {
+    /// Divide the values with the [`DivisionMode`], see [`NodeValue::true_div`].
+    pub fn div_with(self, other: Self, mode: DivisionMode) -> Self {
+        match mode {
+            DivisionMode::Legacy => self / other,
+            DivisionMode::True => Self::operator(self, other, |lhs, rhs| lhs.true_div(rhs, false)),
+            DivisionMode::TrueInf => Self::operator(self, other, |lhs, rhs| lhs.true_div(rhs, true)),
+            DivisionMode::Rational => Self::operator(self, other, NodeValue::rational_div),
+        }
+    }
+
+    /// Floor divide the values, see [`NodeValue::floor_div`].
+    pub fn floor_div(self, other: Self) -> Self {
+        Self::operator(self, other, NodeValue::floor_div)
+    }
+
+    /// Get the remainder of the values, see [`NodeValue::modulo`].
+    pub fn modulo(self, other: Self) -> Self {
+        Self::operator(self, other, NodeValue::modulo)
+    }
+
+    /// Convert the value to an integer, see [`NodeValue::to_int`].
+    pub fn to_int(self) -> Self {
+        self.convert(NodeValue::to_int)
+    }
+
+    /// Convert the value to a float, see [`NodeValue::to_float`].
+    pub fn to_float(self) -> Self {
+        self.convert(NodeValue::to_float)
+    }
+
+    fn convert(self, op: fn(&NodeValue) -> NodeValue) -> StateNode {
+        if let Self::RuntimeErr(_) = self { return self; }
+        match op(&self.as_node_value()) {
+            NodeValue::ValueError(err) => Self::RuntimeErr(err.into()),
+            value => Self::Value(value),
+        }
+    }
+}
+
+/// How `/` divides numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DivisionMode {
+    /// Integers stay integers when they divide exactly, otherwise the result is a float
+    Legacy,
+    /// The result is always a float and dividing by zero is an error
+    #[default]
+    True,
+    /// The result is always a float and dividing by zero gives infinity or NaN
+    TrueInf,
+    /// Integers divide into an exact [`Rational`](NodeValue::Rational), floats still give a float
+    Rational,
+}
+
+/// How floats are displayed, see [`set_float_format`](VirtualEnv::set_float_format).
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::exec::{FloatFormat, NodeValue, Precision};
+/// let format = FloatFormat { precision: Precision::Decimals(2), trim_zeros: false };
+/// assert_eq!(NodeValue::BigFloat(2.5).display(&format), Ok("2.50".into()));
+/// assert_eq!(NodeValue::BigFloat(2.5).display(&FloatFormat::default()), Ok("2.5".into()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloatFormat {
+    /// How many digits are kept
+    pub precision: Precision,
+    /// Drop the zeros at the end of the decimals, and the point if no decimals are left
+    pub trim_zeros: bool,
+}
+
+/// How many digits of a float are displayed, the last digit is rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Precision {
+    /// The significant digits the float type holds - 7 for a `Float` and 15 for a `BigFloat`
+    #[default]
+    Native,
+    /// The fewest digits that read back as the same float
+    Shortest,
+    /// At most this many significant digits
+    Significant(usize),
+    /// At most this many decimal places
+    Decimals(usize),
+}
+
+/// The significant digits a `f32` holds.
+const F32_DIGITS: usize = 7;
+/// The significant digits a `f64` holds.
+const F64_DIGITS: usize = 15;
+
+/// The [native](Precision::Native) digits without trailing zeros.
+impl Default for FloatFormat {
+    fn default() -> Self {
+        FloatFormat { precision: Precision::Native, trim_zeros: true }
+    }
+}
+
+impl FloatFormat {
+    /// Display with Rust's float formatting, the way [`NodeValue::to_string`] does.
+    pub const SHORTEST: FloatFormat = FloatFormat { precision: Precision::Shortest, trim_zeros: true };
+
+    /// Display a float that holds `digits` significant digits, `shortest` is its shortest form.
+    fn format(&self, float: f64, digits: usize, shortest: String) -> String {
+        if !float.is_finite() {
+            return shortest;
+        }
+        let decimals = match self.precision {
+            Precision::Shortest => return shortest,
+            Precision::Native => Self::decimals_for(float, digits),
+            Precision::Significant(significant) => Self::decimals_for(float, significant),
+            Precision::Decimals(decimals) => decimals,
+        };
+        let string = format!("{float:.decimals$}");
+        if !self.trim_zeros || !string.contains('.') {
+            return string;
+        }
+        match string.trim_end_matches('0').trim_end_matches('.') {
+            "-0" => "0".into(),
+            trimmed => trimmed.into(),
+        }
+    }
+
+    /// Get how many decimal places keep `significant` digits of the float.
+    fn decimals_for(float: f64, significant: usize) -> usize {
+        let significant = significant.max(1);
+        // the exponent after rounding, so `9.99` to 2 digits is `1.0e1`
+        let exponent = format!("{float:.*e}", significant - 1);
+        let exponent: i64 = exponent.split_once('e').and_then(|(_, exp)| exp.parse().ok()).unwrap_or(0);
+        (significant as i64 - 1 - exponent).max(0) as usize
+    }
+}
+
+/// A function defined by the running code, see [`VirtualEnv::call`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// The names the arguments are bound to
+    pub params: Vec<String>,
+    /// The tree evaluated for the result
+    pub body: Arc<TreeNode>,
+}
+
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NodeValue {
+    // Hidden Types
+    Token(Token),
+    Ident(String),
+    // Types
+    BigFloat(f64),
+    Float(f32),
+    BigInteger(i128),
+    Integer(i32),
+    /// An exact decimal number, only with the `decimal` feature
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
+    /// An exact fraction, see [`NodeValue::rational`]
+    Rational(Rational),
+    String(String),
+    /// A single character, like `'a'`
+    Char(char),
+    Boolean(bool),
+    List(Vec<NodeValue>),
+    Map(BTreeMap<String, NodeValue>),
+    /// A function defined by the running code, it can't be serialized
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Function(Function),
+    /// The absence of a value
+    None,
+    /// The `null` literal, a value that can be stored and compared unlike the result of a
+    /// statement that gave no value
+    Null,
+    // Errors
+    ValueError(String),
+}
+
+impl NodeValue {
+
+    /// Get the name of the value's type for messages, like `Integer` or `List`.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert_eq!(NodeValue::BigFloat(0.5).type_name(), "Float");
+    /// assert_eq!(NodeValue::String("x".into()).type_name(), "String");
+    /// ```
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Token(_) => "Token",
+            Self::Ident(_) => "Identifier",
+            Self::BigFloat(_) | Self::Float(_) => "Float",
+            Self::BigInteger(_) | Self::Integer(_) => "Integer",
+            #[cfg(feature = "decimal")]
+            Self::Decimal(_) => "Decimal",
+            Self::Rational(..) => "Rational",
+            Self::String(_) => "String",
+            Self::Char(_) => "Char",
+            Self::Boolean(_) => "Boolean",
+            Self::List(_) => "List",
+            Self::Map(_) => "Map",
+            Self::Function(_) => "Function",
+            Self::None => "None",
+            Self::Null => "Null",
+            Self::ValueError(_) => "Error",
+        }
+    }
+
+    /// Describe the value by its type and how it is displayed, like ``String `x` ``.
+    pub(crate) fn describe(&self) -> String {
+        match self.to_string() {
+            Ok(value) if !matches!(self, Self::None | Self::Null) => format!("{} `{value}`", self.type_name()),
+            _ => self.type_name().into(),
+        }
+    }
+
+    /// The error for values an arithmetic operator can't be used on.
+    fn mismatch(op: &str, lhs: &Self, rhs: &Self) -> Self {
+        let (lhs, rhs) = (lhs.describe(), rhs.describe());
+        Self::ValueError(match op {
+            "+" => format!("Cannot add {rhs} to {lhs}"),
+            "-" => format!("Cannot subtract {rhs} from {lhs}"),
+            "*" => format!("Cannot multiply {lhs} by {rhs}"),
+            "//" => format!("Cannot floor divide {lhs} by {rhs}"),
+            "%" => format!("Cannot take the remainder of {lhs} by {rhs}"),
+            _ => format!("Cannot divide {lhs} by {rhs}"),
+        })
+    }
+
+    /// Convert both values to the same type before applying the operator, a value that
+    /// can't be converted is a [`mismatch`](NodeValue::mismatch).
+    fn promote(&self, other: &Self, convert: fn(&Self) -> Self, op: &str, apply: fn(Self, Self) -> Self) -> Self {
+        match (convert(self), convert(other)) {
+            (Self::ValueError(_), _) | (_, Self::ValueError(_)) => Self::mismatch(op, self, other),
+            (lhs, rhs) => apply(lhs, rhs),
+        }
+    }
+
+    /// Check if the value is one of the number types.
+    pub fn is_number(&self) -> bool {
+        match self {
+            Self::BigFloat(_) | Self::Float(_) | Self::BigInteger(_) | Self::Integer(_) | Self::Rational(..) => true,
+            #[cfg(feature = "decimal")]
+            Self::Decimal(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Get an integer value as an `i128`.
+    pub(crate) fn as_int(&self) -> Option<i128> {
+        match self {
+            Self::BigInteger(int) => Some(*int),
+            Self::Integer(int) => Some(*int as i128),
+            _ => None,
+        }
+    }
+
+    /// Get any number value as an `f64`.
+    pub(crate) fn as_float(&self) -> Option<f64> {
+        match self {
+            Self::BigFloat(float) => Some(*float),
+            Self::Float(float) => Some(*float as f64),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(decimal) => Some(decimal.to_f64()),
+            Self::Rational(ratio) => Some(ratio.to_f64()),
+            value => value.as_int().map(|int| int as f64),
+        }
+    }
+
+    /// Make a [`Rational`](NodeValue::Rational) from a fraction, it is reduced and the
+    /// sign is moved to the numerator.
+    /// 
+    /// ---
+    /// 
+    /// A zero denominator is a [`ValueError`](NodeValue::ValueError).
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert_eq!(NodeValue::rational(2, -4).to_string(), Ok("-1/2".into()));
+    /// assert_eq!(NodeValue::rational(1, 3).to_string(), Ok("1/3".into()));
+    /// assert_eq!(NodeValue::rational(6, 3).to_string(), Ok("2".into()));
+    /// ```
+    pub fn rational(num: i128, den: i128) -> Self {
+        Self::reduce((num, den), "/")
+    }
+
+    /// Reduce a fraction to a [`Rational`](NodeValue::Rational), a fraction with a sign
+    /// that can't be moved to the numerator is an overflow in `op`.
+    fn reduce((num, den): Ratio, op: &str) -> Self {
+        if den == 0 {
+            return Self::ValueError("Cannot divide by zero".into());
+        }
+        match Rational::new(num, den) {
+            Some(ratio) => Self::Rational(ratio),
+            None => Self::ValueError(format!("Rational overflow in `{op}`")),
+        }
     }
 
-    pub fn as_value(self) -> StateNode {
+    /// Get an integer or rational value as a fraction.
+    fn as_ratio(&self) -> Option<Ratio> {
         match self {
-            Self::Node(node) => {
-                Self::RuntimeErr("Cannot convert Node to Value".into())
-            },
-            _ => self
+            Self::Rational(ratio) => Some(ratio.ratio()),
+            value => value.as_int().map(|int| (int, 1)),
         }
     }
 
-    pub fn as_ident(&self) -> NodeValue {
-        match self {
-            Self::Value(value) => match value {
-                NodeValue::Ident(ident) => NodeValue::Ident(ident.to_string()),
-                NodeValue::Token(token) => NodeValue::Ident(token.value.clone()),
-                NodeValue::String(string) => NodeValue::Ident(string.into()),
-                _ => NodeValue::ValueError(format!("Cannot convert `{value:?}` to Identifier"))
+    /// Apply a fraction operator when either value is a rational - integers are promoted
+    /// and floats are mixed as floats. The operator gives [`None`] when it overflows.
+    fn rational_op(&self, other: &Self, op: &str, apply: fn(Ratio, Ratio) -> Option<Ratio>) -> Option<Self> {
+        if !matches!((self, other), (Self::Rational(..), _) | (_, Self::Rational(..))) {
+            return None;
+        }
+        let (lhs, rhs) = (self.as_ratio()?, other.as_ratio()?);
+        Some(match apply(lhs, rhs) {
+            Some(ratio) => Self::reduce(ratio, op),
+            None => Self::ValueError(format!("Rational overflow in `{op}`")),
+        })
+    }
+
+    /// Divide exactly, integers and rationals give a [`Rational`](NodeValue::Rational)
+    /// and anything else is a [`true_div`](NodeValue::true_div).
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert_eq!(NodeValue::Integer(2).rational_div(NodeValue::Integer(6)).to_string(), Ok("1/3".into()));
+    /// assert_eq!(NodeValue::Float(1.0).rational_div(NodeValue::Integer(4)), NodeValue::Float(0.25));
+    /// ```
+    pub fn rational_div(self, other: Self) -> Self {
+        match (self.as_ratio(), other.as_ratio()) {
+            (Some(lhs), Some(rhs)) => match ratio::div(lhs, rhs) {
+                Some(ratio) => Self::reduce(ratio, "/"),
+                None => Self::ValueError("Rational overflow in `/`".into()),
             },
-            _ => NodeValue::ValueError(format!("Cannot convert `{self:?}` to Identifier")),
+            _ => self.true_div(other, false),
         }
     }
 
-    pub fn as_node_value(self) -> NodeValue {
+    /// Get an integer or decimal value as a [`Decimal`], floats have to be converted
+    /// with [`to_decimal`](NodeValue::to_decimal) first.
+    #[cfg(feature = "decimal")]
+    pub(crate) fn as_decimal(&self) -> Option<Decimal> {
         match self {
-            Self::RuntimeErr(_) => unreachable!(), // should not be trying to convert an error
-            Self::Value(val) => val,
-            _ => NodeValue::ValueError(format!("Cannot convert `{self:?}` to NodeValue")),
+            Self::Decimal(decimal) => Some(*decimal),
+            value => value.as_int().map(Decimal::from),
         }
-        // NodeValue::ValueError(format!("Cannot convert `{self:?}` to NodeValue"))
     }
 
-    fn operator(lhs: StateNode, rhs: StateNode, op: fn(a: NodeValue, b: NodeValue) -> NodeValue) -> StateNode {
-        // println!("OPERATOR\nLHS: {lhs:?}\nRHS: {rhs:?}");
-        if let Self::RuntimeErr(_) = lhs { return lhs; }
-        if let Self::RuntimeErr(_) = rhs { return rhs; }
-        // -=-=- //
-        match (lhs.as_node_value(), rhs.as_node_value()) {
-            (NodeValue::ValueError(err), _) |(_, NodeValue::ValueError(err)) => StateNode::RuntimeErr(err),
-            (lhv, rhv) => match op(lhv, rhv) {
-                NodeValue::ValueError(err) => Self::RuntimeErr(err),
-                val => Self::Value(val),
+    /// Apply a [`Decimal`] operator when either value is a decimal - integers are
+    /// promoted and floats are a mismatch, they have to be converted to mix.
+    #[cfg(feature = "decimal")]
+    fn decimal_op(&self, other: &Self, op: &str, apply: fn(Decimal, Decimal) -> Result<Decimal, String>) -> Self {
+        match (self.as_decimal(), other.as_decimal()) {
+            (Some(lhs), Some(rhs)) => apply(lhs, rhs).map(Self::Decimal).unwrap_or_else(Self::ValueError),
+            _ => Self::mismatch(op, self, other),
+        }
+    }
+
+    /// Compare two numbers as floats if either is a float - [`None`] if either isn't a
+    /// number or is NaN.
+    fn cmp_numbers(&self, other: &Self) -> Option<CmpOrdering> {
+        if let (Some(lhs), Some(rhs)) = (self.as_int(), other.as_int()) {
+            return Some(lhs.cmp(&rhs));
+        }
+        // decimals are compared exactly with integers and other decimals
+        #[cfg(feature = "decimal")]
+        if let (Some(lhs), Some(rhs)) = (self.as_decimal(), other.as_decimal()) {
+            return Some(lhs.cmp(&rhs));
+        }
+        // fractions are compared exactly, the denominators are positive
+        if let (Some((lhs_num, lhs_den)), Some((rhs_num, rhs_den))) = (self.as_ratio(), other.as_ratio()) {
+            if let (Some(lhs), Some(rhs)) = (lhs_num.checked_mul(rhs_den), rhs_num.checked_mul(lhs_den)) {
+                return Some(lhs.cmp(&rhs));
             }
-            _ => unreachable!(), // Should not happen if as_node_value is implemented correctly
         }
+        self.as_float()?.partial_cmp(&other.as_float()?)
     }
-}
 
-impl Add for StateNode {
-    type Output = Self;
+    /// Check if either value is one of the big types.
+    fn is_big(&self, other: &Self) -> bool {
+        [self, other].iter().any(|value| matches!(value, Self::BigFloat(_) | Self::BigInteger(_)))
+    }
 
-    fn add(self, other: Self) -> Self::Output {
-        Self::operator(self, other, |lhs, rhs| lhs + rhs )
+    /// Make a float value - a [`BigFloat`](NodeValue::BigFloat) if either value is big.
+    fn float_like(&self, other: &Self, float: f64) -> Self {
+        match self.is_big(other) {
+            true => Self::BigFloat(float),
+            false => Self::Float(float as f32),
+        }
     }
-}
-impl Sub for StateNode {
-    type Output = Self;
 
-    fn sub(self, other: Self) -> Self::Output {
-        Self::operator(self, other, |lhs, rhs| lhs - rhs )
+    /// Make an integer value - a [`BigInteger`](NodeValue::BigInteger) if either value is big.
+    fn int_like(&self, other: &Self, int: i128) -> Self {
+        match self.is_big(other) {
+            true => Self::BigInteger(int),
+            false => i32::try_from(int)
+                .map(Self::Integer)
+                .unwrap_or_else(|_| Self::ValueError(format!("{int} is too big for an Integer"))),
+        }
     }
-}
-impl Mul for StateNode {
-    type Output = Self;
 
-    fn mul(self, other: Self) -> Self::Output {
-        Self::operator(self, other, |lhs, rhs| lhs * rhs )
+    /// Get the numbers for an operation, or the error to give instead.
+    #[allow(clippy::result_large_err)]
+    fn number_operands(&self, other: &Self, op: &str) -> Result<(), Self> {
+        match (self, other) {
+            (Self::ValueError(err), _) | (_, Self::ValueError(err)) => Err(Self::ValueError(err.into())),
+            (lhs, rhs) if lhs.is_number() && rhs.is_number() => Ok(()),
+            (lhs, rhs) => Err(Self::ValueError(format!("Cannot use `{op}` on {} and {}", lhs.describe(), rhs.describe()))),
+        }
     }
-}
-impl Div for StateNode {
-    type Output = Self;
 
-    fn div(self, other: Self) -> Self::Output {
-        Self::operator(self, other, |lhs, rhs| lhs / rhs )
+    /// Divide as floats no matter the types, dividing by zero is an error unless
+    /// `zero_is_inf` where it gives infinity or NaN.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert_eq!(NodeValue::Integer(8).true_div(NodeValue::Integer(2), false), NodeValue::Float(4.0));
+    /// assert_eq!(NodeValue::Integer(1).true_div(NodeValue::Integer(0), true), NodeValue::Float(f32::INFINITY));
+    /// ```
+    pub fn true_div(self, other: Self, zero_is_inf: bool) -> Self {
+        if let Err(err) = self.number_operands(&other, "/") { return err; }
+        // decimals are exact, so they stay decimals and have no infinity
+        #[cfg(feature = "decimal")]
+        if matches!((&self, &other), (Self::Decimal(_), _) | (_, Self::Decimal(_))) {
+            return self.decimal_op(&other, "/", Decimal::checked_div);
+        }
+        // so do rationals
+        if let Some(ratio) = self.rational_op(&other, "/", ratio::div) {
+            return ratio;
+        }
+        let (lhs, rhs) = (self.as_float().unwrap_or_default(), other.as_float().unwrap_or_default());
+        if rhs == 0.0 && !zero_is_inf {
+            return Self::ValueError("Cannot divide by zero".into());
+        }
+        self.float_like(&other, lhs / rhs)
     }
-}
 
+    /// Divide and round down towards negative infinity, integers stay integers.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert_eq!(NodeValue::Integer(-7).floor_div(NodeValue::Integer(2)), NodeValue::Integer(-4));
+    /// ```
+    pub fn floor_div(self, other: Self) -> Self {
+        if let Err(err) = self.number_operands(&other, "//") { return err; }
+        #[cfg(feature = "decimal")]
+        if matches!((&self, &other), (Self::Decimal(_), _) | (_, Self::Decimal(_))) {
+            return self.decimal_op(&other, "//", Decimal::floor_div);
+        }
+        if let Some(ratio) = self.rational_op(&other, "//", ratio::floor_div) {
+            return ratio;
+        }
+        match (self.as_int(), other.as_int()) {
+            (_, Some(0)) => Self::ValueError("Cannot divide by zero".into()),
+            (Some(lhs), Some(rhs)) => match lhs.checked_div(rhs) {
+                Some(div) if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) => self.int_like(&other, div - 1),
+                Some(div) => self.int_like(&other, div),
+                None => Self::ValueError("Integer overflow in `//`".into()),
+            },
+            _ => {
+                let (lhs, rhs) = (self.as_float().unwrap_or_default(), other.as_float().unwrap_or_default());
+                if rhs == 0.0 { return Self::ValueError("Cannot divide by zero".into()); }
+                self.float_like(&other, (lhs / rhs).floor())
+            }
+        }
+    }
 
-#[derive(Debug, Clone)]
-pub enum NodeValue {
-    // Hidden Types
-    Token(Token),
-    Ident(String),
-    // Types
-    BigFloat(f64),
-    Float(f32),
-    BigInteger(i128),
-    Integer(i32),
-    String(String),
-    // Errors
-    ValueError(String),
-}
+    /// The remainder of [`floor_div`](NodeValue::floor_div), it has the same sign as the divisor.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert_eq!(NodeValue::Integer(7).modulo(NodeValue::Integer(-2)), NodeValue::Integer(-1));
+    /// ```
+    pub fn modulo(self, other: Self) -> Self {
+        if let Err(err) = self.number_operands(&other, "%") { return err; }
+        #[cfg(feature = "decimal")]
+        if matches!((&self, &other), (Self::Decimal(_), _) | (_, Self::Decimal(_))) {
+            return self.decimal_op(&other, "%", Decimal::modulo);
+        }
+        if let Some(ratio) = self.rational_op(&other, "%", ratio::modulo) {
+            return ratio;
+        }
+        match (self.as_int(), other.as_int()) {
+            (_, Some(0)) => Self::ValueError("Cannot divide by zero".into()),
+            (Some(lhs), Some(rhs)) => match lhs.checked_rem(rhs) {
+                Some(rem) if rem != 0 && (rem < 0) != (rhs < 0) => self.int_like(&other, rem + rhs),
+                Some(rem) => self.int_like(&other, rem),
+                None => Self::ValueError("Integer overflow in `%`".into()),
+            },
+            _ => {
+                let (lhs, rhs) = (self.as_float().unwrap_or_default(), other.as_float().unwrap_or_default());
+                if rhs == 0.0 { return Self::ValueError("Cannot divide by zero".into()); }
+                self.float_like(&other, lhs - rhs * (lhs / rhs).floor())
+            }
+        }
+    }
 
-impl NodeValue {
+    /// Convert the value to an integer, floats are truncated towards zero and strings are
+    /// parsed - a [`BigFloat`](NodeValue::BigFloat) gives a [`BigInteger`](NodeValue::BigInteger).
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert_eq!(NodeValue::Float(-3.9).to_int(), NodeValue::Integer(-3));
+    /// assert_eq!(NodeValue::String("42".into()).to_int(), NodeValue::Integer(42));
+    /// assert!(matches!(NodeValue::String("x".into()).to_int(), NodeValue::ValueError(_)));
+    /// ```
+    pub fn to_int(&self) -> NodeValue {
+        match self {
+            Self::Integer(_) | Self::BigInteger(_) => self.clone(),
+            Self::Float(_) | Self::BigFloat(_) => match self.as_float().unwrap_or_default() {
+                float if float.is_finite() => self.int_like(self, float.trunc() as i128),
+                float => Self::ValueError(format!("Cannot convert {float} to an Integer")),
+            },
+            #[cfg(feature = "decimal")]
+            Self::Decimal(decimal) => self.int_like(self, decimal.trunc()),
+            Self::Rational(ratio) => self.int_like(self, ratio.numer() / ratio.denom()),
+            Self::String(string) => Self::parse_value::<i32>(string.trim()).unwrap_or_else(Self::ValueError),
+            Self::Boolean(boolean) => Self::Integer(*boolean as i32),
+            Self::ValueError(_) => self.clone(),
+            value => Self::ValueError(format!("Cannot convert `{}` to an Integer", value.to_string().unwrap_or_default())),
+        }
+    }
+
+    /// Convert the value to a float, strings are parsed - big numbers give a
+    /// [`BigFloat`](NodeValue::BigFloat).
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert!(matches!(NodeValue::Integer(2).to_float(), NodeValue::Float(2.0)));
+    /// assert!(matches!(NodeValue::BigInteger(2).to_float(), NodeValue::BigFloat(2.0)));
+    /// assert_eq!(NodeValue::String("0.5".into()).to_float(), NodeValue::Float(0.5));
+    /// ```
+    pub fn to_float(&self) -> NodeValue {
+        match self {
+            Self::Float(_) | Self::BigFloat(_) => self.clone(),
+            Self::Integer(_) | Self::BigInteger(_) => self.float_like(self, self.as_float().unwrap_or_default()),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(decimal) => Self::Float(decimal.to_f64() as f32),
+            Self::Rational(..) => Self::Float(self.as_float().unwrap_or_default() as f32),
+            Self::String(string) => Self::parse_value::<f32>(string.trim()).unwrap_or_else(Self::ValueError),
+            Self::Boolean(boolean) => Self::Float(*boolean as i32 as f32),
+            Self::ValueError(_) => self.clone(),
+            value => Self::ValueError(format!("Cannot convert `{}` to a Float", value.to_string().unwrap_or_default())),
+        }
+    }
+
+    /// Convert the value to a [`Decimal`], floats give the decimal they are displayed as
+    /// and strings are parsed.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// assert_eq!(NodeValue::Float(0.1).to_decimal().to_string(), Ok("0.1".into()));
+    /// assert_eq!(NodeValue::String("2.50".into()).to_decimal().to_string(), Ok("2.5".into()));
+    /// assert_eq!(NodeValue::Integer(3).to_decimal().type_name(), "Decimal");
+    /// ```
+    #[cfg(feature = "decimal")]
+    pub fn to_decimal(&self) -> NodeValue {
+        let decimal = match self {
+            Self::Decimal(_) | Self::ValueError(_) => return self.clone(),
+            Self::Integer(_) | Self::BigInteger(_) => Ok(self.as_int().map(Decimal::from).unwrap_or_default()),
+            Self::Rational(ratio) => Decimal::from(ratio.numer()).checked_div(Decimal::from(ratio.denom())),
+            Self::Float(float) => format!("{float}").parse(),
+            Self::BigFloat(float) => Decimal::from_f64(*float),
+            Self::String(string) => string.trim().parse(),
+            Self::Boolean(boolean) => Ok(Decimal::from(*boolean as i32)),
+            value => Err(format!("Cannot convert `{}` to a Decimal", value.to_string().unwrap_or_default())),
+        };
+        decimal.map(Self::Decimal).unwrap_or_else(Self::ValueError)
+    }
 
     pub fn to_string(&self) -> Result<String, String> {
+        self.display(&FloatFormat::SHORTEST)
+    }
+
+    /// Display the value with floats in the given `format`, the errors are the same as
+    /// [`to_string`](NodeValue::to_string).
+    pub fn display(&self, format: &FloatFormat) -> Result<String, String> {
         match self {
             Self::Token(token) => Ok(token.to_string()),
             Self::Ident(ident) => Ok(ident.to_string()),
 
-            Self::BigFloat(float) => Ok(float.to_string()),
-            Self::Float(float) => Ok(float.to_string()),
+            Self::BigFloat(float) => Ok(format.format(*float, F64_DIGITS, float.to_string())),
+            Self::Float(float) => Ok(format.format(*float as f64, F32_DIGITS, float.to_string())),
             Self::BigInteger(int) => Ok(int.to_string()),
             Self::Integer(int) => Ok(int.to_string()),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(decimal) => Ok(decimal.to_string()),
+            Self::Rational(ratio) => Ok(ratio.to_string()),
             Self::String(string) => Ok(string.into()),
+            Self::Char(char) => Ok(char.to_string()),
+            Self::Boolean(boolean) => Ok(boolean.to_string()),
+            Self::List(list) => Ok(format!("[{}]", list.iter()
+                .map(|value| value.display(format))
+                .collect::<Result<Vec<String>, String>>()?
+                .join(", "))),
+            Self::Map(map) => Ok(format!("{{{}}}", map.iter()
+                .map(|(key, value)| Ok(format!("{key}: {}", value.display(format)?)))
+                .collect::<Result<Vec<String>, String>>()?
+                .join(", "))),
+            Self::Function(function) => Ok(format!("fn({})", function.params.join(", "))),
+            Self::None => Ok("None".into()),
+            Self::Null => Ok("null".into()),
 
             Self::ValueError(err) => Err(err.into()),
         }
@@ -249,6 +2138,12 @@ impl NodeValue {
                 .map(NodeValue::BigFloat)
                 .map_err(|_| format!("Failed to parse '{value}' as Float")),
             NodeType::String => Ok(NodeValue::String(value.to_string())),
+            NodeType::Char => value
+                .parse::<char>()
+                .map(NodeValue::Char)
+                .map_err(|_| format!("Failed to parse '{value}' as Char")),
+            #[cfg(feature = "decimal")]
+            NodeType::Decimal => value.parse::<Decimal>().map(NodeValue::Decimal),
         }
     }
 
@@ -267,6 +2162,37 @@ impl NodeValue {
     }
 }
 
+/// Numbers are equal across types (`Integer(2) == Float(2.0)`), other values are equal
+/// to values of the same type and a [`ValueError`](NodeValue::ValueError) is never equal.
+impl PartialEq for NodeValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::ValueError(_), _) | (_, Self::ValueError(_)) => false,
+            (Self::Token(lhs), Self::Token(rhs)) => lhs == rhs,
+            (Self::Ident(lhs), Self::Ident(rhs)) => lhs == rhs,
+            (Self::String(lhs), Self::String(rhs)) => lhs == rhs,
+            (Self::Char(lhs), Self::Char(rhs)) => lhs == rhs,
+            (Self::Boolean(lhs), Self::Boolean(rhs)) => lhs == rhs,
+            (Self::None, Self::None) => true,
+            (Self::Null, Self::Null) => true,
+            (Self::Function(lhs), Self::Function(rhs)) => lhs == rhs,
+            (Self::List(lhs), Self::List(rhs)) => lhs == rhs,
+            (Self::Map(lhs), Self::Map(rhs)) => lhs == rhs,
+            _ => self.cmp_numbers(other) == Some(CmpOrdering::Equal),
+        }
+    }
+}
+
+/// Only numbers are ordered, other values are only compared for equality.
+impl PartialOrd for NodeValue {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        match self.is_number() && other.is_number() {
+            true => self.cmp_numbers(other),
+            false => (self == other).then_some(CmpOrdering::Equal),
+        }
+    }
+}
+
 impl Add for NodeValue {
     type Output = Self;
 
@@ -277,18 +2203,26 @@ impl Add for NodeValue {
         match (&self, &other) {
             (Self::ValueError(err), _) |
             (_, Self::ValueError(err)) => Self::ValueError(err.into()),
+            (Self::List(_), _) | (_, Self::List(_)) => Self::mismatch("+", &self, &other),
+            // a char is never read as the number it shows
+            (Self::Char(_), _) | (_, Self::Char(_)) => Self::mismatch("+", &self, &other),
+            (Self::Map(_), _) | (_, Self::Map(_)) => Self::mismatch("+", &self, &other),
+            #[cfg(feature = "decimal")]
+            (Self::Decimal(_), _) | (_, Self::Decimal(_)) => self.decimal_op(&other, "+", Decimal::checked_add),
+            (Self::Rational(..), _) | (_, Self::Rational(..)) => self.rational_op(&other, "+", ratio::add)
+                .unwrap_or_else(|| self.promote(&other, Self::to_float, "+", |lhs, rhs| lhs + rhs)),
 
             (Self::BigFloat(f1), Self::BigFloat(f2)) => Self::BigFloat(f1 + f2),
             (Self::Float(f1), Self::Float(f2)) => Self::Float(f1 + f2),
             (Self::BigInteger(i1), Self::BigInteger(i2)) => Self::BigInteger(i1 + i2),
             (Self::Integer(i1), Self::Integer(i2)) => Self::Integer(i1 + i2),
 
-            (Self::BigFloat(_), _) | (_, Self::BigFloat(_)) => self.as_type::<f64>() + other.as_type::<f64>(),
-            (Self::Float(_), _) | (_, Self::Float(_)) => self.as_type::<f32>() + other.as_type::<f32>(),
-            (Self::BigInteger(_), _) | (_, Self::BigInteger(_)) => self.as_type::<i128>() + other.as_type::<i128>(),
-            (Self::Integer(_), _) | (_, Self::Integer(_)) => self.as_type::<i32>() + other.as_type::<i32>(),
+            (Self::BigFloat(_), _) | (_, Self::BigFloat(_)) => self.promote(&other, Self::as_type::<f64>, "+", |lhs, rhs| lhs + rhs),
+            (Self::Float(_), _) | (_, Self::Float(_)) => self.promote(&other, Self::as_type::<f32>, "+", |lhs, rhs| lhs + rhs),
+            (Self::BigInteger(_), _) | (_, Self::BigInteger(_)) => self.promote(&other, Self::as_type::<i128>, "+", |lhs, rhs| lhs + rhs),
+            (Self::Integer(_), _) | (_, Self::Integer(_)) => self.promote(&other, Self::as_type::<i32>, "+", |lhs, rhs| lhs + rhs),
 
-            (lhs, rhs) => Self::ValueError(format!("Cannot add {lhs:?} to {rhs:?}."))
+            (lhs, rhs) => Self::mismatch("+", lhs, rhs)
         }
     }
 }
@@ -302,18 +2236,26 @@ impl Sub for NodeValue {
         match (&self, &other) {
             (Self::ValueError(err), _) |
             (_, Self::ValueError(err)) => Self::ValueError(err.into()),
+            (Self::List(_), _) | (_, Self::List(_)) => Self::mismatch("-", &self, &other),
+            // a char is never read as the number it shows
+            (Self::Char(_), _) | (_, Self::Char(_)) => Self::mismatch("-", &self, &other),
+            (Self::Map(_), _) | (_, Self::Map(_)) => Self::mismatch("-", &self, &other),
+            #[cfg(feature = "decimal")]
+            (Self::Decimal(_), _) | (_, Self::Decimal(_)) => self.decimal_op(&other, "-", Decimal::checked_sub),
+            (Self::Rational(..), _) | (_, Self::Rational(..)) => self.rational_op(&other, "-", ratio::sub)
+                .unwrap_or_else(|| self.promote(&other, Self::to_float, "-", |lhs, rhs| lhs - rhs)),
 
             (Self::BigFloat(f1), Self::BigFloat(f2)) => Self::BigFloat(f1 - f2),
             (Self::Float(f1), Self::Float(f2)) => Self::Float(f1 - f2),
             (Self::BigInteger(i1), Self::BigInteger(i2)) => Self::BigInteger(i1 - i2),
             (Self::Integer(i1), Self::Integer(i2)) => Self::Integer(i1 - i2),
 
-            (Self::BigFloat(_), _) | (_, Self::BigFloat(_)) => self.as_type::<f64>() - other.as_type::<f64>(),
-            (Self::Float(_), _) | (_, Self::Float(_)) => self.as_type::<f32>() - other.as_type::<f32>(),
-            (Self::BigInteger(_), _) | (_, Self::BigInteger(_)) => self.as_type::<i128>() - other.as_type::<i128>(),
-            (Self::Integer(_), _) | (_, Self::Integer(_)) => self.as_type::<i32>() - other.as_type::<i32>(),
+            (Self::BigFloat(_), _) | (_, Self::BigFloat(_)) => self.promote(&other, Self::as_type::<f64>, "-", |lhs, rhs| lhs - rhs),
+            (Self::Float(_), _) | (_, Self::Float(_)) => self.promote(&other, Self::as_type::<f32>, "-", |lhs, rhs| lhs - rhs),
+            (Self::BigInteger(_), _) | (_, Self::BigInteger(_)) => self.promote(&other, Self::as_type::<i128>, "-", |lhs, rhs| lhs - rhs),
+            (Self::Integer(_), _) | (_, Self::Integer(_)) => self.promote(&other, Self::as_type::<i32>, "-", |lhs, rhs| lhs - rhs),
 
-            (lhs, rhs) => Self::ValueError(format!("Cannot add {lhs:?} to {rhs:?}."))
+            (lhs, rhs) => Self::mismatch("-", lhs, rhs)
         }
     }
 }
@@ -327,18 +2269,26 @@ impl Mul for NodeValue {
         match (&self, &other) {
             (Self::ValueError(err), _) |
             (_, Self::ValueError(err)) => Self::ValueError(err.into()),
+            (Self::List(_), _) | (_, Self::List(_)) => Self::mismatch("*", &self, &other),
+            // a char is never read as the number it shows
+            (Self::Char(_), _) | (_, Self::Char(_)) => Self::mismatch("*", &self, &other),
+            (Self::Map(_), _) | (_, Self::Map(_)) => Self::mismatch("*", &self, &other),
+            #[cfg(feature = "decimal")]
+            (Self::Decimal(_), _) | (_, Self::Decimal(_)) => self.decimal_op(&other, "*", Decimal::checked_mul),
+            (Self::Rational(..), _) | (_, Self::Rational(..)) => self.rational_op(&other, "*", ratio::mul)
+                .unwrap_or_else(|| self.promote(&other, Self::to_float, "*", |lhs, rhs| lhs * rhs)),
 
             (Self::BigFloat(f1), Self::BigFloat(f2)) => Self::BigFloat(f1 * f2),
             (Self::Float(f1), Self::Float(f2)) => Self::Float(f1 * f2),
             (Self::BigInteger(i1), Self::BigInteger(i2)) => Self::BigInteger(i1 * i2),
             (Self::Integer(i1), Self::Integer(i2)) => Self::Integer(i1 * i2),
 
-            (Self::BigFloat(_), _) | (_, Self::BigFloat(_)) => self.as_type::<f64>() * other.as_type::<f64>(),
-            (Self::Float(_), _) | (_, Self::Float(_)) => self.as_type::<f32>() * other.as_type::<f32>(),
-            (Self::BigInteger(_), _) | (_, Self::BigInteger(_)) => self.as_type::<i128>() * other.as_type::<i128>(),
-            (Self::Integer(_), _) | (_, Self::Integer(_)) => self.as_type::<i32>() * other.as_type::<i32>(),
+            (Self::BigFloat(_), _) | (_, Self::BigFloat(_)) => self.promote(&other, Self::as_type::<f64>, "*", |lhs, rhs| lhs * rhs),
+            (Self::Float(_), _) | (_, Self::Float(_)) => self.promote(&other, Self::as_type::<f32>, "*", |lhs, rhs| lhs * rhs),
+            (Self::BigInteger(_), _) | (_, Self::BigInteger(_)) => self.promote(&other, Self::as_type::<i128>, "*", |lhs, rhs| lhs * rhs),
+            (Self::Integer(_), _) | (_, Self::Integer(_)) => self.promote(&other, Self::as_type::<i32>, "*", |lhs, rhs| lhs * rhs),
 
-            (lhs, rhs) => Self::ValueError(format!("Cannot add {lhs:?} to {rhs:?}."))
+            (lhs, rhs) => Self::mismatch("*", lhs, rhs)
         }
     }
 }
@@ -348,12 +2298,13 @@ impl Div for NodeValue {
     fn div(self, other: Self) -> Self::Output {
         // println!("{self:?} / {other:?}");
 
-        if let Some(_) = match other {
-            Self::BigFloat(float) if float == 0.0 => Some(()),
-            Self::Float(float) if float == 0.0 => Some(()),
-            Self::BigInteger(int) if int == 0 => Some(()),
-            Self::Integer(int) if int == 0 => Some(()),
-            _ => None,
+        if match other {
+            Self::BigFloat(float) => float == 0.0,
+            Self::Float(float) => float == 0.0,
+            Self::BigInteger(int) => int == 0,
+            Self::Integer(int) => int == 0,
+            Self::Rational(ratio) => ratio.numer() == 0,
+            _ => false,
         }{
             return Self::ValueError("Cannot divide by zero".into())
         };
@@ -363,6 +2314,14 @@ impl Div for NodeValue {
             // error check
             (Self::ValueError(err), _) |
             (_, Self::ValueError(err)) => Self::ValueError(err.into()),
+            (Self::List(_), _) | (_, Self::List(_)) => Self::mismatch("/", &self, &other),
+            // a char is never read as the number it shows
+            (Self::Char(_), _) | (_, Self::Char(_)) => Self::mismatch("/", &self, &other),
+            (Self::Map(_), _) | (_, Self::Map(_)) => Self::mismatch("/", &self, &other),
+            #[cfg(feature = "decimal")]
+            (Self::Decimal(_), _) | (_, Self::Decimal(_)) => self.decimal_op(&other, "/", Decimal::checked_div),
+            (Self::Rational(..), _) | (_, Self::Rational(..)) => self.rational_op(&other, "/", ratio::div)
+                .unwrap_or_else(|| self.promote(&other, Self::to_float, "/", |lhs, rhs| lhs / rhs)),
 
             // division
             (Self::BigFloat(f1), Self::BigFloat(f2)) => Self::BigFloat(f1 / f2),
@@ -377,13 +2336,124 @@ impl Div for NodeValue {
             }
 
             // conversion
-            (Self::BigFloat(_), _) | (_, Self::BigFloat(_)) => self.as_type::<f64>() / other.as_type::<f64>(),
-            (Self::Float(_), _) | (_, Self::Float(_)) => self.as_type::<f32>() / other.as_type::<f32>(),
-            (Self::BigInteger(_), _) | (_, Self::BigInteger(_)) => self.as_type::<i128>() / other.as_type::<i128>(),
-            (Self::Integer(_), _) | (_, Self::Integer(_)) => self.as_type::<i32>() / other.as_type::<i32>(),
+            (Self::BigFloat(_), _) | (_, Self::BigFloat(_)) => self.promote(&other, Self::as_type::<f64>, "/", |lhs, rhs| lhs / rhs),
+            (Self::Float(_), _) | (_, Self::Float(_)) => self.promote(&other, Self::as_type::<f32>, "/", |lhs, rhs| lhs / rhs),
+            (Self::BigInteger(_), _) | (_, Self::BigInteger(_)) => self.promote(&other, Self::as_type::<i128>, "/", |lhs, rhs| lhs / rhs),
+            (Self::Integer(_), _) | (_, Self::Integer(_)) => self.promote(&other, Self::as_type::<i32>, "/", |lhs, rhs| lhs / rhs),
 
             // value error
-            (lhs, rhs) => Self::ValueError(format!("Cannot add {lhs:?} to {rhs:?}."))
+            (lhs, rhs) => Self::mismatch("/", lhs, rhs)
+        }
+    }
+}
+
+impl NodeValue {
+    /// Get both values as integers for a bitwise operator, or the error to give instead.
+    fn int_operands(&self, other: &Self, op: &str) -> Result<(i128, i128), String> {
+        match (self, other) {
+            (Self::ValueError(err), _) | (_, Self::ValueError(err)) => Err(err.into()),
+            (lhs, rhs) => match (lhs.as_int(), rhs.as_int()) {
+                (Some(lhs), Some(rhs)) => Ok((lhs, rhs)),
+                _ => Err(format!("Cannot use `{op}` on {} and {}", lhs.describe(), rhs.describe())),
+            },
+        }
+    }
+
+    /// Shift the integer by the other value, shifting by a negative amount or by more
+    /// bits than the integer has is an error.
+    fn shift(self, other: Self, op: &str) -> Self {
+        let (lhs, rhs) = match self.int_operands(&other, op) {
+            Ok(ints) => ints,
+            Err(err) => return Self::ValueError(err),
+        };
+        let bits = if self.is_big(&other) { i128::BITS } else { i32::BITS };
+        let amount = match u32::try_from(rhs) {
+            Ok(amount) if amount < bits => amount,
+            _ => return Self::ValueError(format!("Cannot shift by {rhs}, it must be from 0 to {}", bits - 1)),
+        };
+        match op {
+            "<<" if (lhs << amount) >> amount != lhs => Self::ValueError("Integer overflow in `<<`".into()),
+            "<<" => self.int_like(&other, lhs << amount),
+            _ => self.int_like(&other, lhs >> amount),
+        }
+    }
+}
+
+impl BitAnd for NodeValue {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self::Output {
+        match self.int_operands(&other, "&") {
+            Ok((lhs, rhs)) => self.int_like(&other, lhs & rhs),
+            Err(err) => Self::ValueError(err),
+        }
+    }
+}
+impl BitOr for NodeValue {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self::Output {
+        match self.int_operands(&other, "|") {
+            Ok((lhs, rhs)) => self.int_like(&other, lhs | rhs),
+            Err(err) => Self::ValueError(err),
+        }
+    }
+}
+impl BitXor for NodeValue {
+    type Output = Self;
+
+    fn bitxor(self, other: Self) -> Self::Output {
+        match self.int_operands(&other, "^") {
+            Ok((lhs, rhs)) => self.int_like(&other, lhs ^ rhs),
+            Err(err) => Self::ValueError(err),
+        }
+    }
+}
+impl Shl for NodeValue {
+    type Output = Self;
+
+    fn shl(self, other: Self) -> Self::Output {
+        self.shift(other, "<<")
+    }
+}
+impl Shr for NodeValue {
+    type Output = Self;
+
+    fn shr(self, other: Self) -> Self::Output {
+        self.shift(other, ">>")
+    }
+}
+impl Not for NodeValue {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Self::Integer(int) => Self::Integer(!int),
+            Self::BigInteger(int) => Self::BigInteger(!int),
+            Self::ValueError(_) => self,
+            value => Self::ValueError(format!("Cannot use `~` on {}", value.describe())),
+        }
+    }
+}
+impl Neg for NodeValue {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Self::Integer(int) => int.checked_neg().map(Self::Integer)
+                .unwrap_or_else(|| Self::ValueError("Integer overflow in `-`".into())),
+            Self::BigInteger(int) => int.checked_neg().map(Self::BigInteger)
+                .unwrap_or_else(|| Self::ValueError("Integer overflow in `-`".into())),
+            Self::Float(float) => Self::Float(-float),
+            Self::BigFloat(float) => Self::BigFloat(-float),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(decimal) => decimal.checked_neg().map(Self::Decimal).unwrap_or_else(Self::ValueError),
+            Self::Rational(ratio) => match ratio.numer().checked_neg() {
+                Some(num) => Self::reduce((num, ratio.denom()), "-"),
+                None => Self::ValueError("Rational overflow in `-`".into()),
+            },
+            Self::ValueError(_) => self,
+            value => Self::ValueError(format!("Cannot use `-` on {}", value.describe())),
         }
     }
 }
@@ -396,6 +2466,9 @@ pub enum NodeType {
     BigFloat,
     Float,
     String,
+    Char,
+    #[cfg(feature = "decimal")]
+    Decimal,
 }
 
 pub trait NodeTypeTrait {
@@ -419,72 +2492,696 @@ impl NodeTypeTrait for String {
 impl NodeTypeTrait for str {
     const VARIANT: NodeType = NodeType::String;
 }
+impl NodeTypeTrait for char {
+    const VARIANT: NodeType = NodeType::Char;
+}
+#[cfg(feature = "decimal")]
+impl NodeTypeTrait for Decimal {
+    const VARIANT: NodeType = NodeType::Decimal;
+}
 
 pub struct EnvFrame<'a> {
     env: &'a mut VirtualEnv,
+    name: &'a str,
     node: &'a TreeNode,
     args: &'a [u32],
+    /// the values a pure lambda was given, the branches are evaluated outside of it
+    replay: Option<&'a mut Replay>,
 }
 
-impl EnvFrame<'_> {
-    pub fn build_frame<'a>(env: &'a mut VirtualEnv, node: &'a TreeNode, args: &'a [u32]) -> EnvFrame<'a> {
-        EnvFrame { env, node, args }
+impl<'a> EnvFrame<'a> {
+    pub fn build_frame<'b>(env: &'b mut VirtualEnv, name: &'b str, node: &'b TreeNode, args: &'b [u32]) -> EnvFrame<'b> {
+        EnvFrame { env, name, node, args, replay: None }
     }
     
-    pub fn eval(&mut self) -> Exec {
-        match self.args.len() {
-            1 => Exec::UniExpr(self.eval_branch(0)),
-            2 => Exec::BinExpr(self.eval_branch(0), self.eval_branch(1)),
-            // 3 => Exec::TriOp(),
-            _ => Exec::Root(StateNode::Node(self.node.clone())),
+    /// Evaluate the branches of the lambda's args - 1, 2 and 3 args give a
+    /// [`UniExpr`](Exec::UniExpr), [`BinExpr`](Exec::BinExpr) and [`TriExpr`](Exec::TriExpr)
+    /// with the values in order, any other number gives the [`Root`](Exec::Root) node
+    /// without evaluating it.
+    /// 
+    /// ---
+    /// 
+    /// An arg that isn't a branch of the node is a runtime error in its place.
+    pub fn eval(&mut self) -> Exec<'a> {
+        let count = self.args.len();
+        if !(1..=3).contains(&count) {
+            return Exec::Root(self.node);
+        }
+        let mut values = self.eval_args(1..=count).into_iter();
+        let mut next = || values.next().unwrap_or(StateNode::None);
+        match count {
+            1 => Exec::UniExpr(next()),
+            2 => Exec::BinExpr(next(), next()),
+            _ => Exec::TriExpr(next(), next(), next()),
+        }
+    }
+
+    /// Get how `/` divides numbers in the environment.
+    pub fn division_mode(&self) -> DivisionMode {
+        self.env.division_mode()
+    }
+
+    /// Get how many arguments the lambda was called with.
+    pub fn arg_count(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Evaluate only the branch of one of the lambda's arguments, `arg` is the 1-based
+    /// position in the arguments - for lambdas that choose which branches to evaluate
+    /// instead of using [`eval`](EnvFrame::eval). An arg that isn't a branch of the node
+    /// is an error in the grammar.
+    pub fn eval_arg(&mut self, arg: usize) -> StateNode {
+        self.eval_args(arg..=arg).pop().unwrap_or(StateNode::None)
+    }
+
+    /// Evaluate the branches of a range of 1-based args in order, an arg that isn't a
+    /// branch of the node is an error in its place.
+    fn eval_args(&mut self, args: std::ops::RangeInclusive<usize>) -> Vec<StateNode> {
+        let indexes: Vec<_> = args.map(|arg| {
+            let Some(index) = arg.checked_sub(1).and_then(|arg| self.args.get(arg)) else {
+                return Err(RuntimeError::from(format!("No argument {arg} for node `{}`", self.node)));
+            };
+            match self.branch(*index as usize) {
+                Some(_) => Ok(*index as usize),
+                None => Err(RuntimeError::from(format!(
+                    "Lambda `{}` has an invalid arg index {index}, the node has {} branches",
+                    self.name, self.node.nodes.len(),
+                ))),
+            }
+        }).collect();
+        let mut values = self.eval_branches(&indexes.iter().filter_map(|index| index.as_ref().ok().copied()).collect::<Vec<_>>()).into_iter();
+        indexes.into_iter().map(|index| match index {
+            Ok(_) => values.next().unwrap_or(StateNode::None),
+            Err(err) => StateNode::RuntimeErr(err),
+        }).collect()
+    }
+
+    /// Evaluate branches of the node by their 1-based indexes in order, a pure lambda
+    /// takes them from its replay instead.
+    fn eval_branches(&mut self, indexes: &[usize]) -> Vec<StateNode> {
+        match &mut self.replay {
+            Some(replay) if replay.suspends => replay.take(indexes),
+            _ => indexes.iter()
+                .map(|index| self.env.eval_node(&self.node.nodes[index - 1]))
+                .collect(),
+        }
+    }
+
+    /// Get the node the lambda was called on, for lambdas that need its branches without
+    /// evaluating them.
+    pub fn node(&self) -> &'a TreeNode {
+        self.node
+    }
+
+    /// Get a branch of the node without evaluating it, `index` is 1-based like the
+    /// lambda's args.
+    pub fn branch(&self, index: usize) -> Option<&'a TreeNode> {
+        index.checked_sub(1).and_then(|index| self.node.nodes.get(index))
+    }
+
+    /// Get the token of a branch of the node if it is a leaf, `index` is 1-based like the
+    /// lambda's args - for lambdas that depend on which token was matched, like an operator.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, StateNode, NodeValue};
+    /// use interpreter::lang::math;
+    /// use interpreter::parser::syntax::Expression::*;
+    /// use interpreter::exec::syntax::Lambda::*;
+    /// let mut parser = math::parser();
+    /// parser.define("EXPR", SubExpr(&[Token("int", ""), Token("op", "")]), Lambda("OP", &[1]));
+    /// let mut env = math::env();
+    /// env.define("OP", |frame| match frame.token(2) {
+    ///     Some(token) => StateNode::Value(NodeValue::String(token.value.clone())),
+    ///     None => StateNode::RuntimeErr("no operator".into()),
+    /// });
+    /// let mut executor = Executor::new(math::lexer(), parser, env);
+    /// assert_eq!(executor.exec_str("1 +"), Ok("+".into()));
+    /// ```
+    pub fn token(&self, index: usize) -> Option<&'a Token> {
+        self.branch(index).and_then(|node| node.leaf.as_ref())
+    }
+
+    /// Evaluate any node in the environment, such as a branch of [`node`](EnvFrame::node).
+    pub fn eval_tree(&mut self, node: &TreeNode) -> StateNode {
+        match self.node.nodes.iter().position(|branch| std::ptr::eq(branch, node)) {
+            Some(index) => self.eval_branches(&[index + 1]).pop().unwrap_or(StateNode::None),
+            None => self.env.eval_node(node),
+        }
+    }
+
+    /// Call a [`Function`], see [`VirtualEnv::call`].
+    pub fn call(&mut self, function: &Function, args: Vec<NodeValue>) -> StateNode {
+        self.env.call(function, args)
+    }
+
+    /// Call a [`Function`] as the last thing the lambda does, the lambda must give the
+    /// value this gives as its own. The body is evaluated once the lambda returns when it
+    /// can be, so a function calling itself doesn't go deeper on the stack.
+    pub(crate) fn call_last(&mut self, function: Function, args: Vec<NodeValue>) -> StateNode {
+        match &mut self.replay {
+            Some(replay) => {
+                replay.call = Some((function, args));
+                StateNode::None
+            }
+            None => self.env.call(&function, args),
         }
-        // Exec::RuntimeErr("EVAL Not Imp[lemsdkjfsdkj".into())
     }
 
-    fn eval_branch(&mut self, branch: usize) -> StateNode {
-        let node = &self.node.nodes[self.args[branch] as usize - 1];
-        self.eval_node(node)
+    /// Call a registered function, see [`VirtualEnv::call_builtin`].
+    pub fn call_builtin(&self, name: &str, args: &[NodeValue]) -> StateNode {
+        self.env.call_builtin(name, args)
+    }
+
+    /// Display a value with the env's [float format](VirtualEnv::set_float_format).
+    pub fn display(&self, value: &NodeValue) -> Result<String, String> {
+        self.env.display(value)
+    }
+
+    /// Write a line to the environment's [output](VirtualEnv::set_output).
+    pub fn write_line(&mut self, line: &str) {
+        self.env.write_line(line);
+    }
+
+    /// Evaluate every branch of the node in order.
+    pub fn eval_nodes(&mut self) -> Vec<StateNode> {
+        let indexes: Vec<_> = (1..=self.node.nodes.len()).collect();
+        self.eval_branches(&indexes)
     }
 
+    /// An `ident` token is an [`Ident`](NodeValue::Ident), any other token is kept as a
+    /// [`Token`](NodeValue::Token).
     fn eval_token(&self) -> StateNode {
         match &self.node.leaf {
+            Some(token) if token.token_type == "ident" => StateNode::Value(NodeValue::Ident(token.value.clone())),
             Some(token) => StateNode::Value(NodeValue::Token(token.clone())),
-            None => StateNode::RuntimeErr(format!("Cannot EVAL TOKEN for `{}`", self.node)),
+            None => StateNode::RuntimeErr(format!("Cannot EVAL TOKEN for `{}`", self.node).into()),
         }
     }
 
-    fn eval_node(&mut self, node: &TreeNode) -> StateNode {
-        self.env.eval_node(node)
-    }
-    
-    pub fn eval_as<T>(&self) -> StateNode
+    /// Parse the node's token as the type `T`, a branch node is evaluated first - its arg,
+    /// or its only branch - and the value is converted to `T`.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, NodeValue};
+    /// use interpreter::lang::math;
+    /// use interpreter::parser::syntax::Expression::*;
+    /// use interpreter::exec::syntax::Lambda::*;
+    /// let mut parser = math::parser();
+    /// // `FLOAT` is `frame.eval_as::<f32>()` in the math env
+    /// parser.define("EXPR", SubExpr(&[Token("op", "("), Expr("MATH:EXPR"), Token("op", ")")]), Lambda("FLOAT", &[2]));
+    /// let mut executor = Executor::new(math::lexer(), parser, math::env());
+    /// let value = executor.exec_compiled(&executor.compile("(1 + 2)")?).map_err(|err| err.to_string())?;
+    /// assert!(matches!(value, NodeValue::Float(3.0)));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn eval_as<T>(&mut self) -> StateNode
     where
         T: FromStr + NodeTypeTrait,
         <T as FromStr>::Err: std::fmt::Debug,
     {
-        let value = match &self.node.leaf {
-            Some(token) => &token.value,
-            None => return StateNode::RuntimeErr("EVAL_AS called on branch node, leaf node expected.".into()),
+        if let Some(token) = &self.node.leaf {
+            return match NodeValue::parse_value::<T>(&token.value) {
+                Ok(parsed_value) => StateNode::Value(parsed_value),
+                Err(err) => StateNode::RuntimeErr(err.into()),
+            };
+        }
+        let value = match self.eval() {
+            Exec::UniExpr(value) => value,
+            Exec::Root(node) if node.nodes.len() == 1 => self.eval_branches(&[1]).pop().unwrap_or(StateNode::None),
+            _ => return StateNode::RuntimeErr("EVAL_AS called on branch node, a leaf or one branch expected.".into()),
         };
-
-        match NodeValue::parse_value::<T>(value) {
-            Ok(parsed_value) => StateNode::Value(parsed_value),
-            Err(err) => StateNode::RuntimeErr(err),
+        match value {
+            err @ StateNode::RuntimeErr(_) => err,
+            value => match value.as_node_value().as_type::<T>() {
+                NodeValue::ValueError(err) => StateNode::RuntimeErr(err.into()),
+                value => StateNode::Value(value),
+            },
         }
     }
     
+    /// Declare a variable in the innermost scope, see [`VirtualEnv::set_ident`].
     pub fn set_ident(&mut self, ident: &str, value: NodeValue) {
         // println!("FRAME :: SET IDENT: {ident:?} {value:?}");
         self.env.set_ident(ident, value);
     }
+
+    /// Change a variable in the nearest scope it is declared in, an undefined variable is
+    /// an error at the identifier's token in the node - see [`VirtualEnv::assign_ident`].
+    pub fn assign_ident(&mut self, ident: &str, value: NodeValue) -> Result<(), RuntimeError> {
+        self.env.assign_ident(ident, value).map_err(|err| self.error_at(ident, err))
+    }
+
+    /// Start a scope for a block, see [`VirtualEnv::push_scope`].
+    pub fn push_scope(&mut self) {
+        self.env.push_scope();
+    }
+
+    /// End the innermost scope, see [`VirtualEnv::pop_scope`].
+    pub fn pop_scope(&mut self) {
+        self.env.pop_scope();
+    }
     
-    
+    /// Get the value of a variable, an undefined variable is an error at the identifier's
+    /// token in the node.
     pub fn get_ident(&self, ident: &str) -> StateNode {
         // println!("FRAME :: GET IDENT: {ident:?}");
         match self.env.get_ident(ident) {
-            NodeValue::ValueError(err) => StateNode::RuntimeErr(err),
+            NodeValue::ValueError(err) => StateNode::RuntimeErr(self.error_at(ident, err)),
             value => StateNode::Value(value),
         }
     }
 
-}
\ No newline at end of file
+    /// Place an error about a variable at the identifier's token in the node.
+    fn error_at(&self, ident: &str, err: String) -> RuntimeError {
+        match self.node.leaves().find(|token| token.value == ident) {
+            Some(token) => RuntimeError::At(err, token.position.clone()),
+            None => err.into(),
+        }
+    }
+
+}
+
+/// The Levenshtein distance between two strings, in chars.
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs: Vec<char> = rhs.chars().collect();
+    let mut row: Vec<usize> = (0..=rhs.len()).collect();
+    for (i, lc) in lhs.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, rc) in rhs.iter().enumerate() {
+            let substitute = diagonal + usize::from(lc != *rc);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[rhs.len()]
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// numbers are equal across types, other values only to the same type.
+    #[test]
+    fn node_value_eq() {
+        assert_eq!(NodeValue::Integer(2), NodeValue::Float(2.0));
+        assert_eq!(NodeValue::BigInteger(2), NodeValue::Integer(2));
+        assert_eq!(NodeValue::BigFloat(0.5), NodeValue::Float(0.5));
+        assert_ne!(NodeValue::Integer(2), NodeValue::Float(2.5));
+        assert_ne!(NodeValue::Integer(2), NodeValue::String("2".into()));
+        assert_eq!(NodeValue::String("a".into()), NodeValue::String("a".into()));
+        assert_ne!(NodeValue::String("a".into()), NodeValue::Ident("a".into()));
+        assert_eq!(
+            NodeValue::List(vec![NodeValue::Integer(1), NodeValue::Float(2.0)]),
+            NodeValue::List(vec![NodeValue::Float(1.0), NodeValue::BigInteger(2)]),
+        );
+        // errors are never equal
+        let err = NodeValue::ValueError("bad".into());
+        assert_ne!(err, err.clone());
+    }
+
+    /// floats are rounded and trimmed only when displayed.
+    #[test]
+    fn float_format() -> Result<(), String> {
+        let mut executor = crate::exec::Executor::math();
+        executor.env_mut().set_float_format(FloatFormat { precision: Precision::Decimals(4), trim_zeros: true });
+        assert_eq!(executor.exec_str("1.0/3.0")?, "0.3333");
+        assert_eq!(executor.exec_str("1.0/4.0")?, "0.25");
+        executor.exec_str("x := 0 - 1/100000")?;
+        assert_eq!(executor.exec_str("x")?, "0");
+        assert_eq!(executor.env().variables.get("x"), Some(&NodeValue::Float(-0.00001)));
+        executor.env_mut().set_float_format(FloatFormat { precision: Precision::Decimals(4), trim_zeros: false });
+        assert_eq!(executor.exec_str("[1.0/4.0, 2]")?, "[0.2500, 2]");
+        // the default keeps the digits the type holds
+        let format = FloatFormat::default();
+        assert_eq!(NodeValue::BigFloat(0.1 + 0.2).display(&format)?, "0.3");
+        assert_eq!(NodeValue::BigFloat(123456.5).display(&format)?, "123456.5");
+        assert_eq!(NodeValue::Float(f32::INFINITY).display(&format)?, "inf");
+        let format = FloatFormat { precision: Precision::Significant(2), trim_zeros: true };
+        assert_eq!(NodeValue::BigFloat(9.99).display(&format)?, "10");
+        assert_eq!(NodeValue::BigFloat(0.012345).display(&format)?, "0.012");
+        Ok(())
+    }
+
+    /// the errors name the operation and the types it was used on.
+    #[test]
+    fn arithmetic_mismatch() {
+        let text = || NodeValue::String("x".into());
+        let error = |value: NodeValue| match value {
+            NodeValue::ValueError(err) => err,
+            value => format!("no error: {value:?}"),
+        };
+        assert_eq!(error(text() - NodeValue::Integer(1)), "Cannot subtract Integer `1` from String `x`");
+        assert_eq!(error(text() + NodeValue::Float(0.5)), "Cannot add Float `0.5` to String `x`");
+        assert_eq!(error(NodeValue::BigInteger(2) * text()), "Cannot multiply Integer `2` by String `x`");
+        assert_eq!(error(text() / NodeValue::Integer(2)), "Cannot divide String `x` by Integer `2`");
+        assert_eq!(error(NodeValue::Boolean(true) - NodeValue::None), "Cannot subtract None from Boolean `true`");
+        assert_eq!(error(text().floor_div(NodeValue::Integer(2))), "Cannot use `//` on String `x` and Integer `2`");
+        // strings of numbers are still converted
+        assert_eq!(NodeValue::String("3".into()) - NodeValue::Integer(1), NodeValue::Integer(2));
+    }
+
+    /// bitwise operators work on integers of either size and nothing else.
+    #[test]
+    fn bitwise_operators() {
+        let int = NodeValue::Integer;
+        let error = |value: NodeValue| match value {
+            NodeValue::ValueError(err) => err,
+            value => format!("no error: {value:?}"),
+        };
+        assert_eq!(int(6) & int(3), int(2));
+        assert_eq!(int(6) | int(3), int(7));
+        assert_eq!(int(6) ^ int(3), int(5));
+        assert_eq!(int(1) << int(4), int(16));
+        assert_eq!(int(-16) >> int(2), int(-4));
+        assert_eq!(!int(0), int(-1));
+        assert_eq!(!NodeValue::BigInteger(5), NodeValue::BigInteger(-6));
+        assert!(matches!(NodeValue::BigInteger(1) << int(100), NodeValue::BigInteger(int) if int == 1 << 100));
+        // only integers
+        assert_eq!(error(int(1) & NodeValue::Float(1.0)), "Cannot use `&` on Integer `1` and Float `1`");
+        assert_eq!(error(!NodeValue::String("x".into())), "Cannot use `~` on String `x`");
+        // shifts that don't fit are errors
+        assert_eq!(error(int(1) << int(-1)), "Cannot shift by -1, it must be from 0 to 31");
+        assert_eq!(error(int(1) >> int(i32::MAX)), "Cannot shift by 2147483647, it must be from 0 to 31");
+        assert_eq!(error(NodeValue::BigInteger(1) << int(128)), "Cannot shift by 128, it must be from 0 to 127");
+        assert_eq!(error(int(1) << int(31)), "2147483648 is too big for an Integer");
+        assert_eq!(error(NodeValue::BigInteger(3) << int(127)), "Integer overflow in `<<`");
+        // the states keep their errors
+        let value = |int| StateNode::Value(NodeValue::Integer(int));
+        assert_eq!(value(12) & value(10), value(8));
+        assert_eq!(!value(0), value(-1));
+        assert!(matches!(!StateNode::Value(NodeValue::Float(1.0)), StateNode::RuntimeErr(_)));
+    }
+
+    /// the first error of an operator is kept and value errors become runtime errors.
+    #[test]
+    fn operator_errors() {
+        let value = |int| StateNode::Value(NodeValue::Integer(int));
+        let error = |state: StateNode| match state {
+            StateNode::RuntimeErr(err) => err.to_string(),
+            state => format!("no error: {state:?}"),
+        };
+        let left = || StateNode::RuntimeErr("left".into());
+        let right = || StateNode::RuntimeErr("right".into());
+        assert_eq!(error(left() + value(1)), "left");
+        assert_eq!(error(value(1) - right()), "right");
+        assert_eq!(error(left() * right()), "left");
+        // from a value
+        assert_eq!(error(StateNode::Value(NodeValue::ValueError("bad".into())) + value(1)), "bad");
+        // from the operator
+        assert_eq!(error(value(1) / value(0)), "Cannot divide by zero");
+        assert_eq!(error(value(1).modulo(StateNode::Value(NodeValue::String("x".into())))), "Cannot use `%` on Integer `1` and String `x`");
+        assert!(matches!(value(1) + value(2), StateNode::Value(NodeValue::Integer(3))));
+    }
+
+    /// a cast on a branch evaluates it and converts the value.
+    #[test]
+    fn eval_as_branch() -> Result<(), String> {
+        use crate::exec::Executor;
+        use crate::lang::math;
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[Token("ident", "f"), Token("op", "("), Expr("MATH:EXPR"), Token("op", ")")]),
+            SubExpr(&[Token("ident", "i"), Token("op", "("), Expr("MATH:EXPR"), Token("op", ")")]),
+            SubExpr(&[Token("op", "("), Expr("MATH:EXPR"), Token("op", ")")]),
+        ]), LambdaOr(&[
+            Lambda("FLOAT", &[3]),
+            Lambda("INTEGER", &[3]),
+            EvalAs("FLOAT"),
+        ]));
+        let mut executor = Executor::new(math::lexer(), parser, math::env());
+        let mut eval = |src: &str| -> Result<NodeValue, String> {
+            let compiled = executor.compile(src)?;
+            executor.exec_compiled(&compiled).map_err(|err| err.to_string())
+        };
+        assert!(matches!(eval("f(1+2)")?, NodeValue::Float(3.0)));
+        assert!(matches!(eval("i(2*3)")?, NodeValue::Integer(6)));
+        // not coercible
+        assert_eq!(eval("i(1/2)"), Err("Runtime Error: Failed to parse '0.5' as Integer".into()));
+        // more than one branch and no arg
+        let err = eval("(1)").expect_err("three branches");
+        assert!(err.contains("a leaf or one branch expected"), "got {err}");
+        Ok(())
+    }
+
+    /// rationals are reduced with the sign on the numerator and stay exact with integers.
+    #[test]
+    fn rational_arithmetic() {
+        let ratio = NodeValue::rational;
+        let parts = |value: NodeValue| match value {
+            NodeValue::Rational(ratio) => Some((ratio.numer(), ratio.denom())),
+            _ => None,
+        };
+        let error = |value: NodeValue| match value {
+            NodeValue::ValueError(err) => err,
+            value => format!("no error: {value:?}"),
+        };
+        // reduced
+        assert_eq!(parts(ratio(6, 8)), Some((3, 4)));
+        assert_eq!(parts(ratio(0, 5)), Some((0, 1)));
+        assert_eq!(parts(ratio(3, -6)), Some((-1, 2)));
+        assert_eq!(parts(ratio(-3, -6)), Some((1, 2)));
+        assert_eq!(ratio(4, 2).to_string(), Ok("2".into()));
+        assert_eq!(ratio(-1, 3).to_string(), Ok("-1/3".into()));
+        // different denominators
+        assert_eq!(parts(ratio(1, 3) + ratio(1, 6)), Some((1, 2)));
+        assert_eq!(parts(ratio(1, 4) - ratio(1, 3)), Some((-1, 12)));
+        assert_eq!(parts(ratio(2, 3) * ratio(9, 4)), Some((3, 2)));
+        assert_eq!(parts(ratio(1, 2) / ratio(1, 4)), Some((2, 1)));
+        // integers are promoted, floats make a float
+        assert_eq!(parts(ratio(1, 2) + NodeValue::Integer(1)), Some((3, 2)));
+        assert_eq!(parts(NodeValue::BigInteger(3) / ratio(3, 4)), Some((4, 1)));
+        assert_eq!(ratio(1, 4) + NodeValue::Float(0.5), NodeValue::Float(0.75));
+        assert_eq!(parts(-ratio(1, 2)), Some((-1, 2)));
+        assert_eq!(parts(ratio(-7, 2).floor_div(NodeValue::Integer(1))), Some((-4, 1)));
+        assert_eq!(parts(ratio(7, 2).modulo(NodeValue::Integer(-1))), Some((-1, 2)));
+        // compared exactly
+        assert!(ratio(1, 3) < ratio(1, 2));
+        assert_eq!(ratio(4, 2), NodeValue::Integer(2));
+        let max = i64::MAX as i128;
+        assert_eq!(ratio(max - 1, max).partial_cmp(&ratio(max - 2, max - 1)), Some(CmpOrdering::Greater));
+        // dividing by zero
+        assert_eq!(error(ratio(1, 0)), "Cannot divide by zero");
+        assert_eq!(error(ratio(1, 2) / ratio(0, 3)), "Cannot divide by zero");
+        assert_eq!(error(ratio(1, 2).true_div(ratio(0, 1), true)), "Cannot divide by zero");
+        assert_eq!(error(ratio(1, 2).modulo(ratio(0, 1))), "Cannot divide by zero");
+        // fractions past an `i64` keep going as big as a `BigInteger`
+        assert_eq!(parts(ratio(max, 1) * NodeValue::Integer(4)), Some((max * 4, 1)));
+        assert_eq!(parts(ratio(max, 3) * ratio(4, 5)), Some((max * 4, 15)));
+        assert_eq!(parts(-ratio(i64::MIN as i128, 3)), Some((-(i64::MIN as i128), 3)));
+        assert_eq!(error(ratio(i128::MAX, 3) * ratio(4, 5)), "Rational overflow in `*`");
+        assert_eq!(error(-ratio(i128::MIN, 3)), "Rational overflow in `-`");
+        // a whole rational converts like a whole number
+        assert_eq!(ratio(7, 2).to_int(), NodeValue::Integer(3));
+    }
+
+    /// numbers are ordered across types and NaN is not ordered.
+    #[test]
+    fn node_value_ord() {
+        assert!(NodeValue::Integer(1) < NodeValue::Float(1.5));
+        assert!(NodeValue::BigFloat(-3.0) < NodeValue::BigInteger(-2));
+        assert!(NodeValue::BigInteger(i128::MAX) > NodeValue::BigInteger(i128::MAX - 1));
+        let nan = NodeValue::BigFloat(f64::NAN);
+        assert_ne!(nan, nan.clone());
+        assert_eq!(nan.partial_cmp(&NodeValue::Integer(1)), None);
+        assert_eq!(NodeValue::Float(f32::NAN).partial_cmp(&NodeValue::Float(f32::NAN)), None);
+        // only numbers are ordered
+        let text = NodeValue::String("a".into());
+        assert_eq!(text.partial_cmp(&NodeValue::String("b".into())), None);
+        assert_eq!(text.partial_cmp(&text.clone()), Some(CmpOrdering::Equal));
+    }
+
+    /// evaluating borrows the nodes of the tree, so no node of a 10k node tree is cloned
+    /// going through `Exec::Root` and picked children.
+    #[test]
+    fn eval_without_cloning() -> Result<(), String> {
+        use crate::exec::syntax::Lambda;
+        use crate::parser::syntax::CLONED_NODES;
+        let mut env = VirtualEnv::new();
+        env.define("SUM", |mut frame| match frame.eval() {
+            Exec::Root(node) => node.nodes.iter()
+                .fold(StateNode::Value(NodeValue::Integer(0)), |sum, node| sum + frame.eval_tree(node)),
+            _ => StateNode::RuntimeErr("Something sum".into()),
+        });
+        env.define("ONE", |_| StateNode::Value(NodeValue::Integer(1)));
+        // each branch picks its children twice before counting the first one
+        let branches = (0..5_000).map(|_| {
+            let mut branch = TreeNode::from_nodes(vec![TreeNode::from_value(NodeValue::None), TreeNode::from_value(NodeValue::None)]);
+            branch.set_lambda(&Lambda::GetExprs(&[2, 1], &Lambda::GetExprs(&[2], &Lambda::GetExpr(1, &Lambda::EvalAs("ONE")))));
+            branch
+        });
+        let mut root = TreeNode::from_nodes(branches.collect());
+        root.set_lambda(&Lambda::EvalAs("SUM"));
+        let ast = AbstractSyntaxTree { root };
+
+        CLONED_NODES.with(|cloned| cloned.set(0));
+        assert_eq!(env.exec_tree(&ast), StateNode::Value(NodeValue::Integer(5_000)));
+        assert_eq!(CLONED_NODES.with(|cloned| cloned.get()), 0);
+        Ok(())
+    }
+
+    /// constant subtrees are replaced with their value and the result doesn't change.
+    #[test]
+    fn fold_constants() -> Result<(), String> {
+        use crate::lang::math;
+        use crate::lexer::LineReader;
+        let parse = |src: &str| math::parser().parse_tree(&math::lexer(), &mut LineReader::new(src));
+        let mut env = math::env();
+        env.set_ident("x", NodeValue::Integer(1));
+
+        let ast = parse("2*(3+4)+x")?;
+        let mut folded = ast.clone();
+        env.fold_constants(&mut folded);
+        assert_eq!(folded.root.nodes[0].value, Some(NodeValue::Integer(14)));
+        assert_eq!(folded.root.nodes[2].value, None);
+        let (StateNode::Value(before), StateNode::Value(after)) = (env.exec_tree(&ast), env.exec_tree(&folded)) else {
+            return Err("`2*(3+4)+x` should give a value".into());
+        };
+        assert_eq!(before, after);
+        assert_eq!(after, NodeValue::Integer(15));
+
+        // a failing subtree still fails when it runs
+        let ast = parse("1/0 + x")?;
+        let mut folded = ast.clone();
+        env.fold_constants(&mut folded);
+        assert!(folded.root.nodes[0].value.is_none());
+        assert_eq!(folded.root.nodes[0].lambda, ast.root.nodes[0].lambda);
+        assert!(matches!(env.exec_tree(&folded), StateNode::RuntimeErr(_)));
+
+        // assignments and prints are left to run
+        for src in ["y := 1 + 2", "print(1 + 2)"] {
+            let ast = parse(src)?;
+            let mut folded = ast.clone();
+            env.fold_constants(&mut folded);
+            assert!(folded.root.value.is_none(), "{src} was folded");
+        }
+        Ok(())
+    }
+
+    /// only subtrees of pure lambdas are folded, a lambda defined without its purity is kept.
+    #[test]
+    fn fold_only_pure() -> Result<(), String> {
+        use crate::lang::math;
+        use crate::lexer::LineReader;
+        let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("-(1 + 2)"))?;
+        let mut env = math::env();
+        assert!(env.is_pure("NEG") && env.is_pure("math:NEG"));
+        assert!(!env.is_pure("PRINT"));
+        env.define("NEG", |_| StateNode::Value(NodeValue::Integer(0)));
+        let mut folded = ast.clone();
+        env.fold_constants(&mut folded);
+        assert!(folded.root.value.is_none());
+        env.define_pure("NEG", |_| StateNode::Value(NodeValue::Integer(0)));
+        let mut folded = ast.clone();
+        env.fold_constants(&mut folded);
+        assert_eq!(folded.root.value, Some(NodeValue::Integer(0)));
+        Ok(())
+    }
+
+    /// the parts that only read set variables are worked out and the rest is kept to run later.
+    #[test]
+    fn partial_eval() -> Result<(), String> {
+        use crate::lang::math;
+        use crate::lexer::LineReader;
+        let parse = |src: &str| math::parser().parse_tree(&math::lexer(), &mut LineReader::new(src));
+        let mut env = math::env();
+        env.set_ident("a", NodeValue::Integer(3));
+
+        // every variable is set
+        assert_eq!(env.partial_eval(&parse("a * 2 + 1")?), PartialResult::Value(NodeValue::Integer(7)));
+        // `b` isn't set
+        let PartialResult::Residual(residual) = env.partial_eval(&parse("a*2 + b")?) else {
+            return Err("`b` is not set".into());
+        };
+        assert_eq!(residual.root.nodes[0].value, Some(NodeValue::Integer(6)));
+        assert_eq!(residual.to_string(), "( 6 op:+ ( ident:b ) )");
+        assert!(matches!(env.exec_tree(&residual), StateNode::RuntimeErr(_)));
+        // the residual runs once `b` is set
+        env.set_ident("b", NodeValue::Integer(10));
+        assert!(matches!(env.exec_tree(&residual), StateNode::Value(NodeValue::Integer(16))));
+        assert_eq!(env.partial_eval(&residual), PartialResult::Value(NodeValue::Integer(16)));
+
+        // assignments and prints are kept, but what they are given is worked out
+        for (src, expected) in [
+            ("c := a + 1", "( ident:c declare::= 4 )"),
+            ("a += 1", "( ident:a op_assign:+= 1 )"),
+            ("print(a + 1)", "( ident:print op:( ( 4 ) op:) )"),
+        ] {
+            let PartialResult::Residual(residual) = env.partial_eval(&parse(src)?) else {
+                return Err(format!("{src} was worked out"));
+            };
+            assert_eq!(residual.to_string(), expected);
+        }
+        assert!(!env.variables.contains_key("c"));
+        assert_eq!(env.get_ident("a"), NodeValue::Integer(3));
+        Ok(())
+    }
+
+    /// a name can't be defined twice in a module, and a module is registered whole or not at all.
+    #[test]
+    fn module_duplicates() {
+        let mut env = VirtualEnv::new();
+        assert_eq!(env.define_in("host", "PING", |_| StateNode::None), Ok(()));
+        assert_eq!(env.define_in("host", "PING", |_| StateNode::None), Err("Lambda `host:PING` is already defined".into()));
+        // the same name in another module is fine
+        assert_eq!(env.define_in("other", "PING", |_| StateNode::None), Ok(()));
+        let mut module = Module::new("host");
+        module.define("PONG", |_| StateNode::None);
+        module.define("PING", |_| StateNode::None);
+        assert_eq!(env.register_module(module), Err("Lambdas `host:PING` are already defined".into()));
+        assert!(!env.is_defined("PONG"));
+        assert!(!env.is_defined("host:PONG"));
+        assert!(crate::lang::math::env().register_module(crate::lang::math::module()).is_err());
+    }
+
+    /// a qualified lambda name reaches its module when the plain name is taken.
+    #[test]
+    fn module_qualified_lambda() {
+        use crate::exec::Executor;
+        use crate::lang::math;
+        use crate::parser::Assoc;
+        let executor = |ops: &'static [(&'static str, &'static str)]| {
+            let mut env = math::env();
+            let mut host = Module::new("host");
+            host.define("ADD", |_| StateNode::Value(NodeValue::Integer(42)));
+            env.register_module(host).expect("the host module is new");
+            let mut parser = math::parser();
+            parser.define_binop("MATH:EXPR", "MATH:EXPR:1", ops, Assoc::Left);
+            Executor::new(math::lexer(), parser, env)
+        };
+        assert_eq!(executor(&[("+", "host:ADD")]).exec_str("1 + 2"), Ok("42".into()));
+        assert_eq!(executor(&[("+", "math:ADD")]).exec_str("1 + 2"), Ok("3".into()));
+        // the plain name is the module that defined it first
+        assert_eq!(executor(&[("+", "ADD")]).exec_str("1 + 2"), Ok("3".into()));
+        assert!(executor(&[("+", "nope:ADD")]).exec_str("1 + 2").is_err());
+        assert!(executor(&[("+", "host:ADD")]).validate().is_ok());
+    }
+
+    /// the lambdas are listed per module.
+    #[test]
+    fn module_lambdas() {
+        let mut env = crate::lang::math::env();
+        env.define_in("host", "B", |_| StateNode::None).expect("the name is new");
+        env.define_in("host", "A", |_| StateNode::None).expect("the name is new");
+        env.define("PLAIN", |_| StateNode::None);
+        assert_eq!(env.module_names(), ["host", "math"]);
+        assert_eq!(env.module_lambdas("host"), ["A", "B"]);
+        let math = env.module_lambdas("math");
+        assert!(math.contains(&"ADD") && math.contains(&"DECLARE"), "{math:?}");
+        assert!(!math.contains(&"PLAIN"));
+        assert!(env.module_lambdas("missing").is_empty());
+        assert_eq!(env.lambda_names().len(), math.len() + 3);
+    }
+}
@@ -0,0 +1,102 @@
+//! # Value Assertion Macros
+//! 
+//! This is where the [`assert_value`] macro is defined and tested.
+//! 
+
+
+// -=-=-=-=- Testing Macros -=-=-=-=- //
+
+/// Asserts that a [`StateNode`](crate::exec::StateNode) is the expected
+/// [`NodeValue`](crate::exec::NodeValue) - unlike `==` on values, the types have to
+/// match too, so `Integer(2)` is not `Float(2.0)`.
+///
+/// Like [`assert`], this macro has a second form, where a custom panic message can be provided
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::assert_value;
+/// use interpreter::exec::NodeValue;
+/// use interpreter::lang::math;
+/// use interpreter::lexer::LineReader;
+/// let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("1 + 2"))?;
+/// assert_value!(math::env().exec(ast), NodeValue::Integer(3));
+/// Ok::<(), String>(())
+/// ```
+#[macro_export]
+macro_rules! assert_value {
+    ( $result:expr, $expected:expr $(, $($arg:tt)+)? ) => {
+        {
+            // matched so temporaries live until the end of the assertion
+            match (&$result, &$expected) { (result, expected) => {
+            let result: &$crate::exec::StateNode = result;
+            let expected: &$crate::exec::NodeValue = expected;
+            let same = match result {
+                $crate::exec::StateNode::Value(value) => value == expected
+                    && ::std::mem::discriminant(value) == ::std::mem::discriminant(expected),
+                _ => false,
+            };
+            if !same {
+                let msg = ::std::string::String::new() $(+ &format!($($arg)+))?;
+                panic!("When trying to match values: {msg}\n  result: {result:?}\nexpected: {expected:?}");
+            }
+            }}
+        }
+    };
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+/// Test that [`assert_value`] and the equality of [`StateNode`] are working properly.
+#[cfg(test)]
+mod tests {
+    use crate::exec::{NodeValue, RuntimeError, StateNode};
+    use crate::lang::math;
+    use crate::lexer::{LineReader, ReadPointer};
+    use crate::parser::syntax::TreeNode;
+
+    /// Run the math source in a new environment.
+    fn exec(src: &str) -> Result<StateNode, String> {
+        let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new(src))?;
+        Ok(math::env().exec(ast))
+    }
+
+    /// values of the right type are matched.
+    #[test]
+    fn assert_value() -> Result<(), String> {
+        assert_value!(exec("1 + 2")?, NodeValue::Integer(3));
+        assert_value!(exec("3 / 2")?, NodeValue::Float(1.5), "dividing gives a float");
+        assert_value!(exec("str(12)")?, NodeValue::String("12".into()));
+        Ok(())
+    }
+
+    /// equal numbers of another type are not matched.
+    #[test]
+    #[should_panic(expected = "When trying to match values: the type matters")]
+    fn assert_value_type() {
+        assert_value!(StateNode::Value(NodeValue::Integer(2)), NodeValue::Float(2.0), "the type matters");
+    }
+
+    /// errors are not values.
+    #[test]
+    #[should_panic(expected = "When trying to match values")]
+    fn assert_value_error() {
+        assert_value!(exec("1 / 0").unwrap(), NodeValue::Integer(0));
+    }
+
+    /// errors compare by message and nodes by structure.
+    #[test]
+    fn state_node_eq() {
+        let position = ReadPointer::from_pos((0, 0, 0, 1), (0, 1));
+        let at = StateNode::RuntimeErr(RuntimeError::At("bad".into(), position));
+        assert_eq!(at, StateNode::RuntimeErr("bad".into()));
+        assert_ne!(at, StateNode::RuntimeErr("worse".into()));
+        let node = |value| StateNode::new(TreeNode::from_nodes(vec![TreeNode::from_value(NodeValue::Integer(value))]));
+        assert_eq!(node(1), node(1));
+        assert_ne!(node(1), node(2));
+        assert_ne!(node(1), StateNode::None);
+        assert_eq!(StateNode::Value(NodeValue::Integer(2)), StateNode::Value(NodeValue::Float(2.0)));
+    }
+}
@@ -1,44 +1,1061 @@
-
+#[allow(clippy::module_inception)]
 mod exec;
+mod error;
+mod snapshot;
+mod compiled;
+mod macros;
+mod module;
+mod ratio;
+mod stats;
+mod debug;
+pub mod builtin;
 pub mod syntax;
+pub mod types;
+#[cfg(feature = "decimal")]
+pub mod decimal;
 
-
-use std::ops::Deref;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
 pub use exec::*;
-use once_cell::sync::Lazy;
+pub use error::*;
+pub use snapshot::*;
+pub use stats::{ExecStats, LambdaStats};
+pub use debug::{DebugAction, DebugEvent, Debugger};
+pub use compiled::CompiledExpr;
+pub use ratio::Rational;
+pub use module::{LambdaFn, Module};
+pub use types::{Signature, TypeDiagnostic, ValueType};
+pub use builtin::{Args, Arity, BuiltinFn};
 
-use crate::{lexer::{Lexer, Reader}, parser:: Parser};
+use compiled::ParseCache;
+use crate::{lexer::{CachingReader, LexError, Lexer, LineReader, ReadPointer, Reader, Token}, parser::{ParseError, Parser, syntax::AbstractSyntaxTree}};
 
 pub struct Executor<'a> {
-    lexer: Lexer,
-    parser: Parser<'a>,
+    lexer: Arc<Lexer>,
+    parser: Arc<Parser<'a>>,
     env: VirtualEnv,
+    cache: Option<ParseCache>,
 }
 
-impl Executor<'_> {
-    pub fn new(lexer: Lexer, parser: Parser, env: VirtualEnv) -> Executor {
-        Executor { lexer, parser, env }
+impl<'a> Executor<'a> {
+    pub fn new(lexer: Lexer, parser: Parser<'a>, env: VirtualEnv) -> Executor<'a> {
+        Executor { lexer: Arc::new(lexer), parser: Arc::new(parser), env, cache: None }
+    }
+
+    /// Make an executor from a lexer and parser that are already compiled, they are shared
+    /// with every other executor made from them - only the env is its own.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use std::sync::Arc;
+    /// use interpreter::exec::Executor;
+    /// use interpreter::lang::math;
+    /// let (lexer, parser) = (Arc::new(math::lexer()), Arc::new(math::parser()));
+    /// let mut first = Executor::from_shared(lexer.clone(), parser.clone(), math::env());
+    /// let mut second = Executor::from_shared(lexer, parser, math::env());
+    /// first.exec_str("x := 1")?;
+    /// assert!(second.exec_str("x").is_err());
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn from_shared(lexer: Arc<Lexer>, parser: Arc<Parser<'a>>, env: VirtualEnv) -> Executor<'a> {
+        Executor { lexer, parser, env, cache: None }
+    }
+
+    /// Start an [`ExecutorBuilder`] for an executor made from shared parts.
+    pub fn builder() -> ExecutorBuilder<'a> {
+        ExecutorBuilder::new()
     }
 
+    /// Make an executor for the [`math`](crate::lang::math) language, the grammar is only
+    /// compiled once and shared by every math executor.
     pub fn math() -> Executor<'static> {
         crate::lang::math::exec()
     }
 
+    /// Make an executor for the [`calc`](crate::lang::calc) scripting language, the grammar
+    /// is only compiled once and shared by every calc executor.
+    pub fn calc() -> Executor<'static> {
+        crate::lang::calc::exec()
+    }
+
+    /// Get the [`Lexer`] used to read tokens.
+    pub fn lexer(&self) -> &Lexer {
+        &self.lexer
+    }
+
+    /// Get the [`Parser`] used to build the syntax trees.
+    pub fn parser(&self) -> &Parser<'a> {
+        &self.parser
+    }
+
+    /// Get the [`VirtualEnv`] the code is run in.
+    pub fn env(&self) -> &VirtualEnv {
+        &self.env
+    }
+
+    /// Make an independent executor that shares the lexer and parser, with a
+    /// [stateless copy](VirtualEnv::clone_stateless) of the environment - so every thread
+    /// can run code in its own variables without parsing the grammar again.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// let mut executor = Executor::math();
+    /// executor.exec_str("x := 1")?;
+    /// let mut copy = executor.clone_stateless();
+    /// assert!(copy.exec_str("x").is_err());
+    /// assert_eq!(copy.exec_str("2 * 3"), Ok("6".into()));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn clone_stateless(&self) -> Executor<'a> {
+        Executor {
+            lexer: self.lexer.clone(),
+            parser: self.parser.clone(),
+            env: self.env.clone_stateless(),
+            cache: None,
+        }
+    }
+
+    /// Get the [`VirtualEnv`] the code is run in as mutable.
+    pub fn env_mut(&mut self) -> &mut VirtualEnv {
+        &mut self.env
+    }
+
+    /// Execute the next expression in the reader, displaying its result. Input that is
+    /// empty or only has skipped content (like whitespace) gives `None`.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// use interpreter::lexer::LineReader;
+    /// let mut executor = Executor::math();
+    /// assert_eq!(executor.exec(&mut LineReader::new("2 * 4")), Ok("8".into()));
+    /// assert_eq!(executor.exec(&mut LineReader::new("   ")), Ok("None".into()));
+    /// ```
     pub fn exec<T>(&mut self, reader: &mut T) -> Result<String, String> where T: Reader{
+        if self.lexer.at_end(reader) {
+            return Ok("None".into());
+        }
         let ast = self.parser.parse_tree(&self.lexer, reader)?;
         // println!("AST:\n{ast:}");
+        self.run(&ast)
+    }
 
-        // -=- interpreter -=- //
-        self.env.set_ident("thing", exec::NodeValue::Integer(-1));
-        let result = self.env.exec(ast);
+    /// Execute the next expression in the reader like [`exec`](Executor::exec), along with
+    /// the span of the source from its first token to its last - so an editor can highlight
+    /// what was run. A runtime error without a position of its own is at the span.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// use interpreter::lexer::LineReader;
+    /// let mut executor = Executor::math();
+    /// let (result, span) = executor.exec_spanned(&mut LineReader::new("  1 + 2"))?;
+    /// assert_eq!(result, "3");
+    /// assert_eq!((span.start_offset(), span.end_offset()), (2, 7));
+    /// Ok::<(), interpreter::exec::ExecError>(())
+    /// ```
+    pub fn exec_spanned<T>(&mut self, reader: &mut T) -> Result<(String, ReadPointer), ExecError> where T: Reader {
+        let start = self.lexer.next_position(reader);
+        let ast = self.parser.parse_tree(&self.lexer, reader)?;
+        // the span comes from the tokens, the reader was committed past them
+        let span = ast.root.span().unwrap_or(start);
+        let value = self.value(&ast).map_err(|err| {
+            let position = err.position().cloned().unwrap_or_else(|| span.clone());
+            ExecError::runtime(&err.to_string(), Some(position))
+        })?;
+        Ok((self.env.display(&value).unwrap_or_default(), span))
+    }
 
-        match result {
-            StateNode::None => Ok("None".into()),
-            StateNode::Value(val) => Ok(val.to_string().unwrap_or_default()),
-            
+    /// Execute every expression in the reader until the end of its content, returning
+    /// the result of the last one.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// use interpreter::lexer::LineReader;
+    /// let mut executor = Executor::math();
+    /// let mut reader = LineReader::new("x := 2\ny := x * 3\ny + 1");
+    /// assert_eq!(executor.exec_all(&mut reader), Ok("7".into()));
+    /// ```
+    pub fn exec_all<T>(&mut self, reader: &mut T) -> Result<String, ExecError> where T: Reader {
+        let value = self.run_all(reader)?;
+        Ok(self.env.display(&value).unwrap_or_default())
+    }
+
+    /// Execute every expression in a string of source code, returning the value of the
+    /// last one instead of displaying it.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, NodeValue};
+    /// let mut executor = Executor::math();
+    /// assert_eq!(executor.exec_value("x := 3\nx * 2"), Ok(NodeValue::Integer(6)));
+    /// ```
+    pub fn exec_value(&mut self, src: &str) -> Result<NodeValue, ExecError> {
+        self.run_all(&mut CachingReader::new(LineReader::new(src)))
+    }
+
+    /// Run every expression in the reader, giving the value of the last one.
+    fn run_all<T>(&mut self, reader: &mut T) -> Result<NodeValue, ExecError> where T: Reader {
+        let mut value = NodeValue::None;
+        while !self.lexer.at_end(reader) {
+            let position = self.lexer.next_position(reader);
+            let ast = self.parser.parse_tree(&self.lexer, reader)?;
+            value = self.value(&ast).map_err(|err| {
+                let position = err.position().cloned().unwrap_or(position);
+                ExecError::runtime(&err.to_string(), Some(position))
+            })?;
+        }
+        Ok(value)
+    }
+
+    /// Get the value of a variable in the environment.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// let mut executor = Executor::math();
+    /// executor.exec_str("x := 2 * 3")?;
+    /// assert_eq!(executor.get_var("x").and_then(|x| x.to_string().ok()), Some("6".into()));
+    /// assert!(executor.get_var("y").is_none());
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn get_var(&self, name: &str) -> Option<&NodeValue> {
+        self.env.variables.get(name)
+    }
+
+    /// Get every variable in the environment by name, see [`VirtualEnv::bindings`].
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, NodeValue};
+    /// let mut executor = Executor::math();
+    /// executor.exec_str("x := 2 * 3")?;
+    /// let snapshot = executor.variables().clone();
+    /// assert_eq!(snapshot.get("x"), Some(&NodeValue::Integer(6)));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn variables(&self) -> &HashMap<String, NodeValue> {
+        self.env.bindings()
+    }
+
+    /// Check that every lambda the parser calls is defined in the environment, each missing
+    /// lambda is listed with the rule that calls it.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, VirtualEnv};
+    /// use interpreter::lang::math;
+    /// assert!(Executor::math().validate().is_ok());
+    /// 
+    /// let executor = Executor::new(math::lexer(), math::parser(), VirtualEnv::new());
+    /// let missing = executor.validate().unwrap_err();
+    /// assert!(missing.contains(&"Lambda `ADD` used by rule `MATH:EXPR` is not defined".to_string()));
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let missing: Vec<_> = self.parser.lambda_uses().into_iter()
+            .filter(|(_, name)| !self.env.is_defined(name))
+            .map(|(rule, name)| format!("Lambda `{name}` used by rule `{rule}` is not defined"))
+            .collect();
+        match missing.is_empty() {
+            true => Ok(()),
+            false => Err(missing),
+        }
+    }
+
+    /// Get the sorted names of the lambdas defined in the environment that no rule of the
+    /// parser calls, these are only reachable from other lambdas - or are a mistake.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, StateNode};
+    /// let mut executor = Executor::math();
+    /// executor.env_mut().define("NEVER", |_| StateNode::None);
+    /// assert!(executor.unused_lambdas().contains(&"NEVER"));
+    /// ```
+    pub fn unused_lambdas(&self) -> Vec<&str> {
+        let used = self.parser.lambda_names();
+        self.env.lambda_names().into_iter()
+            .filter(|name| !used.contains(name))
+            .collect()
+    }
+
+    /// Get the flag that stops the running code when set, see [`VirtualEnv::cancel_token`].
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use std::sync::atomic::Ordering;
+    /// use std::time::Duration;
+    /// use interpreter::lang::calc;
+    /// let mut executor = calc::exec();
+    /// let cancel = executor.cancel_token();
+    /// let handle = std::thread::spawn(move || {
+    ///     std::thread::sleep(Duration::from_millis(50));
+    ///     cancel.store(true, Ordering::SeqCst);
+    /// });
+    /// assert_eq!(executor.exec_str("while true { }"), Err("Execution interrupted".into()));
+    /// handle.join().unwrap();
+    /// // the flag is cleared once the code is stopped
+    /// assert_eq!(executor.exec_str("1+2"), Ok("3".into()));
+    /// ```
+    pub fn cancel_token(&self) -> Arc<AtomicBool> {
+        self.env.cancel_token()
+    }
+
+    /// Parse the reader as a single expression without running it, so the environment
+    /// is left unchanged. Anything left in the reader after the expression is an error.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// use interpreter::lexer::LineReader;
+    /// let executor = Executor::math();
+    /// let ast = executor.check(&mut LineReader::new("x = 1 + 2"))?;
+    /// assert!(format!("{ast:#}").contains("ASSIGN"));
+    /// assert!(!executor.env().variables.contains_key("x"));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn check<T>(&self, reader: &mut T) -> Result<AbstractSyntaxTree, ParseError> where T: Reader {
+        self.parser.parse_complete(&self.lexer, reader)
+    }
+
+    /// Read all the tokens left in the reader without parsing or running them.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// use interpreter::lexer::LineReader;
+    /// let executor = Executor::math();
+    /// let tokens = executor.lex(&mut LineReader::new("x = 2"))?;
+    /// assert_eq!(tokens.len(), 3);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn lex<T>(&self, reader: &mut T) -> Result<Vec<Token>, LexError> where T: Reader {
+        self.lexer.tokenize(reader)
+    }
+
+    /// Parse a string of source code as a single expression to run later with
+    /// [`exec_compiled`](Executor::exec_compiled), anything after the expression is an error.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, NodeValue};
+    /// let mut executor = Executor::math();
+    /// let compiled = executor.compile("x + 1")?;
+    /// for x in 1..=3 {
+    ///     executor.env_mut().set_ident("x", NodeValue::Integer(x));
+    ///     assert_eq!(executor.exec_compiled(&compiled)?, NodeValue::Integer(x + 1));
+    /// }
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn compile(&self, src: &str) -> Result<CompiledExpr, ParseError> {
+        let ast = self.check(&mut CachingReader::new(LineReader::new(src)))?;
+        Ok(CompiledExpr::new(src, ast))
+    }
+
+    /// Run a [`CompiledExpr`] in the executor's environment, giving the value it results in.
+    pub fn exec_compiled(&mut self, compiled: &CompiledExpr) -> Result<NodeValue, ExecError> {
+        self.value(compiled.ast()).map_err(|err| ExecError::runtime(&err.to_string(), err.position().cloned()))
+    }
+
+    /// Keep the trees parsed by [`exec_str`](Executor::exec_str) for the last `capacity`
+    /// different sources, so running the same source again skips parsing - `0` turns the
+    /// cache off.
+    pub fn set_parse_cache(&mut self, capacity: usize) {
+        self.cache = (capacity > 0).then(|| ParseCache::new(capacity));
+    }
+
+    /// Get how many parsed trees are in the [parse cache](Executor::set_parse_cache).
+    pub fn cached_parses(&self) -> usize {
+        self.cache.as_ref().map_or(0, |cache| cache.len())
+    }
+
+    /// Run a parsed [`AbstractSyntaxTree`] in the executor's environment.
+    fn run_tree(&mut self, ast: &AbstractSyntaxTree) -> StateNode {
+        self.env.exec_tree(ast)
+    }
+
+    /// Run a parsed [`AbstractSyntaxTree`] in the executor's environment, giving the value it results in.
+    fn value(&mut self, ast: &AbstractSyntaxTree) -> Result<NodeValue, RuntimeError> {
+        match self.run_tree(ast) {
+            StateNode::None => Ok(NodeValue::None),
+            StateNode::Value(val) => Ok(val),
             StateNode::RuntimeErr(err) => Err(err),
+            StateNode::Node(node) => Err(format!("Node Result: {node}").into()),
+        }
+    }
+
+    /// Run a parsed [`AbstractSyntaxTree`] in the executor's environment, displaying the result.
+    fn run(&mut self, ast: &AbstractSyntaxTree) -> Result<String, String> {
+        match self.run_tree(ast) {
+            StateNode::None => Ok("None".into()),
+            StateNode::Value(val) => Ok(self.env.display(&val).unwrap_or_default()),
+
+            StateNode::RuntimeErr(err) => Err(err.into()),
             StateNode::Node(node) => Err(format!("Node Result: {node}")),
         }
     }
-}
\ No newline at end of file
+
+    /// Execute a string of source code as a single expression, anything after it other than
+    /// a separator is a parse error. The tree is parsed from a [`LineReader`] wrapped in a
+    /// [`CachingReader`] - unless it is in the [parse cache](Executor::set_parse_cache).
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// let mut executor = Executor::math();
+    /// assert_eq!(executor.exec_str("1+2"), Ok("3".into()));
+    /// ```
+    pub fn exec_str(&mut self, src: &str) -> Result<String, String> {
+        let mut reader = CachingReader::new(LineReader::new(src));
+        if self.lexer.at_end(&reader) {
+            return Ok("None".into());
+        }
+        let Some(cache) = &mut self.cache else {
+            let ast = self.parser.parse_complete(&self.lexer, &mut reader)?;
+            return self.run(&ast);
+        };
+        let compiled = match cache.get(src) {
+            Some(compiled) => compiled,
+            None => {
+                let ast = self.parser.parse_complete(&self.lexer, &mut reader)?;
+                let compiled = CompiledExpr::new(src, ast);
+                cache.insert(compiled.clone());
+                compiled
+            }
+        };
+        self.run(compiled.ast())
+    }
+
+    /// Execute each line with [`exec_str`](Executor::exec_str) as the iterator is read, the
+    /// lines share the executor's environment and an error on one line doesn't stop the rest.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// let mut executor = Executor::math();
+    /// let lines = ["x := 2", "x / 0", "x * 3"].map(String::from);
+    /// let results: Vec<_> = executor.exec_lines(lines).collect();
+    /// assert!(results[1].is_err());
+    /// assert_eq!(results[2], Ok("6".into()));
+    /// ```
+    pub fn exec_lines<'s, I>(&'s mut self, lines: I) -> impl Iterator<Item = Result<String, String>> + use<'s, 'a, I>
+    where I: IntoIterator<Item = String> {
+        lines.into_iter().map(move |line| self.exec_str(&line))
+    }
+}
+
+// -=-=- Executor Builder -=-=- //
+
+/// Builds an [`Executor`] from a shared [`Lexer`] and [`Parser`] with its own [`VirtualEnv`].
+///
+/// The lexer and parser are immutable once defined so they can be built once and shared
+/// between any number of executors, while every executor keeps its own variables.
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use interpreter::exec::Executor;
+/// use interpreter::lang::math;
+///
+/// let lexer = Arc::new(math::lexer());
+/// let parser = Arc::new(math::parser());
+///
+/// let mut executor = Executor::builder()
+///     .lexer(lexer.clone())
+///     .parser(parser.clone())
+///     .env(math::env())
+///     .build()?;
+/// Ok::<(), String>(())
+/// ```
+pub struct ExecutorBuilder<'a> {
+    lexer: Option<Arc<Lexer>>,
+    parser: Option<Arc<Parser<'a>>>,
+    env: Option<VirtualEnv>,
+    validate: bool,
+}
+
+impl Default for ExecutorBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> ExecutorBuilder<'a> {
+    /// Make a new builder with no parts set.
+    pub fn new() -> ExecutorBuilder<'a> {
+        ExecutorBuilder { lexer: None, parser: None, env: None, validate: true }
+    }
+
+    /// Set the shared [`Lexer`] used to read tokens.
+    pub fn lexer(mut self, lexer: Arc<Lexer>) -> Self {
+        self.lexer = Some(lexer);
+        self
+    }
+
+    /// Set the shared [`Parser`] used to build the syntax trees.
+    pub fn parser(mut self, parser: Arc<Parser<'a>>) -> Self {
+        self.parser = Some(parser);
+        self
+    }
+
+    /// Set the [`VirtualEnv`] owned by the executor - if it is not set an empty
+    /// environment is used.
+    pub fn env(mut self, env: VirtualEnv) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Set if [`build`](ExecutorBuilder::build) checks the lambdas with
+    /// [`Executor::validate`], it does by default.
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// Build the [`Executor`], the lexer and parser are required and every lambda the
+    /// parser calls must be defined in the env unless [validating](ExecutorBuilder::validate)
+    /// is turned off - see [`Executor::validate`].
+    pub fn build(self) -> Result<Executor<'a>, String> {
+        let executor = Executor {
+            lexer: self.lexer.ok_or("ExecutorBuilder requires a Lexer")?,
+            parser: self.parser.ok_or("ExecutorBuilder requires a Parser")?,
+            env: self.env.unwrap_or_default(),
+            cache: None,
+        };
+        if self.validate {
+            executor.validate().map_err(|missing| missing.join("\n"))?;
+        }
+        Ok(executor)
+    }
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::math;
+
+    /// executors from one shared grammar don't compile it again or share variables.
+    #[test]
+    fn shared_grammar() -> Result<(), String> {
+        let (lexer, parser) = math::grammar();
+        let mut first = Executor::from_shared(lexer.clone(), parser.clone(), math::env());
+        let mut second = Executor::from_shared(lexer.clone(), parser.clone(), math::env());
+        assert!(Arc::ptr_eq(&first.lexer, &second.lexer));
+        assert!(Arc::ptr_eq(&first.parser, &second.parser));
+        first.exec_str("x := 1")?;
+        second.exec_str("x := 2")?;
+        assert_eq!(first.exec_str("x * 10")?, "10");
+        assert_eq!(second.exec_str("x * 10")?, "20");
+        // the language executors use the same grammar
+        assert!(Arc::ptr_eq(&Executor::math().parser, &parser));
+        assert!(Arc::ptr_eq(&Executor::calc().lexer, &Executor::calc().lexer));
+        Ok(())
+    }
+
+    /// executors built from the same parts should not share variables.
+    #[test]
+    fn builder_shared_parts() -> Result<(), String> {
+        let lexer = Arc::new(math::lexer());
+        let parser = Arc::new(math::parser());
+
+        let handles: Vec<_> = (1..=8).map(|n| {
+            let lexer = lexer.clone();
+            let parser = parser.clone();
+            std::thread::spawn(move || -> Result<String, String> {
+                let mut executor = Executor::builder()
+                    .lexer(lexer)
+                    .parser(parser)
+                    .env(math::env())
+                    .build()?;
+                executor.exec(&mut LineReader::new(&format!("x:={n}")))?;
+                executor.exec(&mut LineReader::new("x*2"))
+            })
+        }).collect();
+
+        for (n, handle) in (1..=8).zip(handles) {
+            let result = handle.join().map_err(|_| "thread panicked")??;
+            assert_eq!(result, (n * 2).to_string());
+        }
+        Ok(())
+    }
+
+    /// the lexer and parser are required to build an executor.
+    #[test]
+    fn builder_missing_parts() {
+        assert!(Executor::builder().env(math::env()).build().is_err());
+        assert!(Executor::builder().lexer(Arc::new(math::lexer())).build().is_err());
+    }
+
+    /// children picked from a node can be passed together to a lambda.
+    #[test]
+    fn get_exprs_to_lambda() -> Result<(), String> {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", SubExpr(&[
+            Token("op", "("), Expr("NUM"), Token("op", "-"), Expr("NUM"), Token("op", ")"),
+        ]), GetExprs(&[2, 4], &Lambda("ADD", &[1, 2])));
+        let mut executor = Executor::new(math::lexer(), parser, math::env());
+        assert_eq!(executor.exec_str("(1 - 2)")?, "3");
+        Ok(())
+    }
+
+    /// a grammar calling lambdas the env doesn't define is rejected when built.
+    #[test]
+    fn builder_missing_lambdas() {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", Expr("NUM"), LambdaOr(&[Lambda("POW", &[1]), EvalAs("NEGATE")]));
+        let result = Executor::builder()
+            .lexer(Arc::new(math::lexer()))
+            .parser(Arc::new(parser))
+            .env(math::env())
+            .build();
+        assert_eq!(result.err(), Some("Lambda `NEGATE` used by rule `EXPR` is not defined\nLambda `POW` used by rule `EXPR` is not defined".into()));
+    }
+
+    /// a step limit stops a deeply nested expression.
+    #[test]
+    fn step_limit_exceeded() -> Result<(), String> {
+        let mut executor = Executor::math();
+        executor.env_mut().set_step_limit(10);
+        let ast = executor.check(&mut LineReader::new("1+2+3+4+5+6+7"))?;
+        let result = executor.env_mut().exec(ast);
+        assert!(matches!(result, StateNode::RuntimeErr(RuntimeError::LimitExceeded(_))), "got {result:?}");
+        // small expressions still run
+        assert_eq!(executor.exec_str("1+2")?, "3");
+        Ok(())
+    }
+
+    /// setting the cancel token from another thread stops a long evaluation.
+    #[test]
+    fn cancel_from_thread() -> Result<(), String> {
+        use std::sync::atomic::Ordering;
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", Expr("NUM"), Lambda("SPIN", &[1]));
+        let mut env = math::env();
+        // evaluate the number forever
+        env.define("SPIN", |mut frame| loop {
+            if let Exec::UniExpr(err @ StateNode::RuntimeErr(_)) = frame.eval() {
+                return err;
+            }
+        });
+        let mut executor = Executor::new(math::lexer(), parser, env);
+        let cancel = executor.cancel_token();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            cancel.store(true, Ordering::SeqCst);
+        });
+        let ast = executor.check(&mut LineReader::new("1"))?;
+        let result = executor.env_mut().exec(ast);
+        handle.join().map_err(|_| "thread panicked")?;
+        assert!(matches!(result, StateNode::RuntimeErr(RuntimeError::Interrupted)), "got {result:?}");
+        Ok(())
+    }
+
+    /// a cancel set while nothing runs doesn't stop the next exec.
+    #[test]
+    fn stale_cancel() {
+        use std::sync::atomic::Ordering;
+        let mut executor = Executor::math();
+        executor.cancel_token().store(true, Ordering::SeqCst);
+        assert_eq!(executor.exec_str("1+2"), Ok("3".into()));
+        assert!(!executor.cancel_token().load(Ordering::SeqCst));
+    }
+
+    /// out of range child indexes are runtime errors instead of panics.
+    #[test]
+    fn get_expr_index_errors() -> Result<(), String> {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[ Token("op", "+"), Expr("NUM") ]),
+            SubExpr(&[ Token("op", "-"), Expr("NUM") ]),
+            SubExpr(&[ Token("op", "*"), Expr("NUM") ]),
+        ]), LambdaOr(&[
+            GetExpr(0, &Eval),
+            GetExpr(3, &Eval),
+            GetExprs(&[2, 0], &Lambda("ADD", &[1, 2])),
+        ]));
+        let mut executor = Executor::new(math::lexer(), parser, math::env());
+        let err = "GetExpr index is 1-based; 0 is invalid".to_string();
+        assert_eq!(executor.exec_str("+1"), Err(err.clone()));
+        assert_eq!(executor.exec_str("*1"), Err(err));
+        let err = executor.exec_str("-1").expect_err("index 3 is out of range");
+        assert!(err.starts_with("No node found for index 3"), "got {err}");
+        Ok(())
+    }
+
+    /// stateless copies share the grammar and run on their own threads in their own variables.
+    #[test]
+    fn clone_stateless_threads() -> Result<(), String> {
+        let mut base = Executor::math();
+        base.env_mut().set_step_limit(1_000);
+        base.exec_str("shared := 100")?;
+        let handles: Vec<_> = (1..=8).map(|n| {
+            let mut executor = base.clone_stateless();
+            std::thread::spawn(move || -> Result<Vec<String>, String> {
+                let mut results = vec![executor.exec_str("shared").unwrap_or_else(|err| err)];
+                executor.exec_str(&format!("x := {n}"))?;
+                for _ in 0..100 {
+                    executor.exec_str("x = x + 1")?;
+                }
+                results.push(executor.exec_str("x")?);
+                Ok(results)
+            })
+        }).collect();
+        for (n, handle) in (1..=8).zip(handles) {
+            let results = handle.join().map_err(|_| "thread panicked")??;
+            assert_eq!(results, ["undefined variable 'shared'".to_string(), format!("{}", n + 100)]);
+        }
+        // the copies keep the settings but not the variables
+        assert!(Arc::ptr_eq(&base.parser, &base.clone_stateless().parser));
+        let long = vec!["1"; 1_000].join("+");
+        let err = base.clone_stateless().exec_str(&long).expect_err("the step limit is kept");
+        assert!(err.contains("more than 1000 steps"), "got {err}");
+        assert_eq!(base.exec_str("shared")?, "100");
+        assert!(base.get_var("x").is_none());
+        Ok(())
+    }
+
+    /// a misspelled lambda is reported with the rule it is used in.
+    #[test]
+    fn validate_missing_lambda() -> Result<(), String> {
+        use crate::parser::{Assoc, syntax::Expression::*};
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", Expr("TERM"), Eval);
+        parser.define_binop("TERM", "VALUE", &[("*", "MUTL")], Assoc::Left);
+        let (lexer, parser) = (Arc::new(math::lexer()), Arc::new(parser));
+        let builder = || Executor::builder().lexer(lexer.clone()).parser(parser.clone()).env(math::env());
+        let err = builder().build().err().ok_or("the builder should validate")?;
+        assert_eq!(err, "Lambda `MUTL` used by rule `TERM` is not defined");
+        let broken = builder().validate(false).build()?;
+        assert_eq!(broken.validate(), Err(vec!["Lambda `MUTL` used by rule `TERM` is not defined".to_string()]));
+        // env lambdas that no rule calls are only a warning
+        assert!(broken.unused_lambdas().is_empty(), "{:?}", broken.unused_lambdas());
+        let mut env = math::env();
+        env.define("MUTL", |_| StateNode::None);
+        assert_eq!(Executor::new(math::lexer(), math::parser(), env).unused_lambdas(), ["MUTL"]);
+        Ok(())
+    }
+
+    /// lambda arg indexes outside of the node are runtime errors instead of panics.
+    #[test]
+    fn lambda_arg_index_errors() {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[ Expr("NUM"), Expr("NUM") ]),
+            SubExpr(&[ Token("op", "-"), Expr("NUM") ]),
+        ]), LambdaOr(&[
+            Lambda("ADD", &[1, 5]),
+            Lambda("ADD", &[0, 2]),
+        ]));
+        let mut executor = Executor::new(math::lexer(), parser, math::env());
+        let err = "Lambda `ADD` has an invalid arg index 5, the node has 2 branches".to_string();
+        assert_eq!(executor.exec_str("1 2"), Err(err));
+        let err = "Lambda `ADD` has an invalid arg index 0, the node has 2 branches".to_string();
+        assert_eq!(executor.exec_str("-1"), Err(err));
+    }
+
+    /// checking and lexing report errors without changing the environment.
+    #[test]
+    fn check_and_lex_no_side_effects() -> Result<(), String> {
+        let executor = Executor::math();
+        // parses even though running it would fail
+        executor.check(&mut LineReader::new("x = 1/0"))?;
+        // fails where the expression stopped matching
+        let err = executor.check(&mut LineReader::new("x = )")).expect_err("`x = )` should not parse");
+        assert_eq!(err.position.line_pos.1, 2);
+        // lexing finds the tokens and the unknown ones
+        assert_eq!(executor.lex(&mut LineReader::new("x = 1/0"))?.len(), 5);
+        let err = executor.lex(&mut LineReader::new("x = #")).expect_err("`#` is not a token");
+        assert_eq!(err.position.line_pos.1, 4);
+        assert!(!executor.env().variables.contains_key("x"));
+        Ok(())
+    }
+
+    /// errors show where they happened counting lines and columns from 1.
+    #[test]
+    fn user_positions() {
+        let mut executor = Executor::math();
+        let err = executor.check(&mut LineReader::new(")")).expect_err("`)` should not parse");
+        assert_eq!(err.position.user_position(), (1, 1));
+        assert!(err.to_string().ends_with("at line 1 col 1"), "got {err}");
+        let err = executor.lex(&mut LineReader::new("#")).expect_err("`#` is not a token");
+        assert!(err.to_string().ends_with("at line 1 col 1"), "got {err}");
+        let err = executor.exec_all(&mut LineReader::new("1\n2 / 0")).expect_err("dividing by zero");
+        assert_eq!(err.position.as_ref().map(|position| position.user_position().0), Some(2));
+        assert!(err.to_string().starts_with("2:1: "), "got {err}");
+    }
+
+    /// a compiled expression is parsed once and run with different variables.
+    #[test]
+    fn compiled_expr() -> Result<(), String> {
+        let mut executor = Executor::math();
+        let compiled = executor.compile("a*b + c")?;
+        for (a, b, c, expected) in [(1, 2, 3, 5), (4, 5, 6, 26), (0, 9, -1, -1)] {
+            executor.env_mut().set_ident("a", NodeValue::Integer(a));
+            executor.env_mut().set_ident("b", NodeValue::Integer(b));
+            executor.env_mut().set_ident("c", NodeValue::Integer(c));
+            assert_eq!(executor.exec_compiled(&compiled)?, NodeValue::Integer(expected));
+        }
+        // the same compiled expression runs in other threads with their own envs
+        let handles: Vec<_> = (1..=4).map(|n| {
+            let compiled = compiled.clone();
+            std::thread::spawn(move || -> Result<NodeValue, String> {
+                let mut executor = Executor::math();
+                executor.exec_str(&format!("a := {n}"))?;
+                executor.exec_str("b := 10")?;
+                executor.exec_str("c := 1")?;
+                Ok(executor.exec_compiled(&compiled)?)
+            })
+        }).collect();
+        for (n, handle) in (1..=4).zip(handles) {
+            assert_eq!(handle.join().map_err(|_| "thread panicked")??, NodeValue::Integer(n * 10 + 1));
+        }
+        // trailing tokens aren't part of the expression
+        assert!(executor.compile("a b").is_err());
+        Ok(())
+    }
+
+    /// the core types can be shared between threads.
+    #[test]
+    fn thread_safe_types() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+        assert_send::<crate::lexer::Lexer>();
+        assert_sync::<crate::lexer::Lexer>();
+        assert_send::<Parser<'static>>();
+        assert_sync::<Parser<'static>>();
+        assert_send::<VirtualEnv>();
+        assert_sync::<VirtualEnv>();
+        assert_send::<Executor<'static>>();
+        assert_send::<std::sync::Mutex<Executor<'static>>>();
+    }
+
+    /// an executor can be moved to another thread and run there.
+    #[test]
+    fn exec_on_thread() -> Result<(), String> {
+        let mut executor = Executor::math();
+        executor.exec_str("x := 6 * 7")?;
+        let handle = std::thread::spawn(move || executor.exec_str("x + 1"));
+        assert_eq!(handle.join().map_err(|_| "thread panicked")??, "43");
+        // or shared behind a mutex
+        let executor = Arc::new(std::sync::Mutex::new(Executor::math()));
+        let handles: Vec<_> = (1..=4).map(|n| {
+            let executor = executor.clone();
+            std::thread::spawn(move || executor.lock().unwrap().exec_str(&format!("{n} * 2")))
+        }).collect();
+        for (n, handle) in (1..=4).zip(handles) {
+            assert_eq!(handle.join().map_err(|_| "thread panicked")??, (n * 2).to_string());
+        }
+        Ok(())
+    }
+
+    /// tokens left after the expression are a parse error instead of being dropped.
+    #[test]
+    fn exec_str_trailing_input() {
+        let mut executor = Executor::math();
+        for src in ["1 2", "1 +", "2^3", "a := 1; b := 2"] {
+            let err = executor.exec_str(src).expect_err(src);
+            assert!(err.contains("Unexpected token after expression"), "{src}: {err}");
+        }
+        executor.set_parse_cache(4);
+        assert!(executor.exec_str("1 2").is_err());
+        assert_eq!(executor.cached_parses(), 0);
+        assert_eq!(executor.exec_str("  1 + 2  "), Ok("3".into()));
+    }
+
+    /// the parse cache gives the same results as parsing every time.
+    #[test]
+    fn parse_cache() -> Result<(), String> {
+        let mut executor = Executor::math();
+        executor.set_parse_cache(2);
+        executor.exec_str("x := 1")?;
+        for _ in 0..3 {
+            executor.exec_str("x = x * 2")?;
+        }
+        assert_eq!(executor.exec_str("x")?, "8");
+        assert_eq!(executor.cached_parses(), 2);
+        assert!(executor.exec_str(")").is_err());
+        executor.set_parse_cache(0);
+        assert_eq!(executor.cached_parses(), 0);
+        assert_eq!(executor.exec_str("x + 1")?, "9");
+        Ok(())
+    }
+
+    /// the span covers the expression from its first token to its last.
+    #[test]
+    fn exec_spanned() -> Result<(), ExecError> {
+        let mut executor = Executor::math();
+        let mut reader = LineReader::new("1 + 2\n x := 4 * 2");
+        let (result, span) = executor.exec_spanned(&mut reader)?;
+        assert_eq!(result, "3");
+        assert_eq!(span.read_pos, (0, 5));
+        assert_eq!((span.start_line_col(), span.end_line_col()), ((0, 0), (0, 5)));
+        let (_, span) = executor.exec_spanned(&mut reader)?;
+        assert_eq!((span.start_line_col(), span.end_line_col()), ((1, 1), (1, 11)));
+        let err = executor.exec_spanned(&mut LineReader::new("(1 + 2) / 0")).expect_err("dividing by zero is an error");
+        assert_eq!(err.position.map(|position| position.read_pos), Some((0, 11)));
+        Ok(())
+    }
+
+    /// empty input and input with only whitespace run nothing.
+    #[test]
+    fn empty_input() -> Result<(), String> {
+        for mut executor in [Executor::math(), Executor::calc()] {
+            assert_eq!(executor.exec_str(""), Ok("None".into()));
+            assert_eq!(executor.exec_str("   "), Ok("None".into()));
+            executor.set_parse_cache(4);
+            assert_eq!(executor.exec_str("   "), Ok("None".into()));
+            assert_eq!(executor.cached_parses(), 0);
+        }
+        assert_eq!(Executor::math().exec(&mut LineReader::new(" \n ")), Ok("None".into()));
+        assert_eq!(Executor::math().exec_all(&mut LineReader::new("  ")), Ok("None".into()));
+        Ok(())
+    }
+
+    /// lines are run as they are read and share the variables.
+    #[test]
+    fn exec_lines() {
+        let mut executor = Executor::math();
+        let lines = ["a := 4", "b := a / 0", "a * 2"].map(String::from);
+        let mut results = executor.exec_lines(lines);
+        assert_eq!(results.next(), Some(Ok("4".into())));
+        assert!(results.next().is_some_and(|result| result.is_err()));
+        assert_eq!(results.next(), Some(Ok("8".into())));
+        assert_eq!(results.next(), None);
+    }
+
+    /// the bindings hold every variable that was assigned.
+    #[test]
+    fn variable_bindings() -> Result<(), String> {
+        let mut lexer = math::lexer();
+        lexer.define_literal("sep", ";")?;
+        let mut parser = math::parser();
+        parser.set_separator(crate::parser::syntax::Expression::Token("sep", ";"));
+        let mut executor = Executor::new(lexer, parser, math::env());
+        assert!(executor.variables().is_empty());
+        let trees = executor.parser().parse_all(executor.lexer(), &mut LineReader::new("a := 1; b := 2"))?;
+        for (ast, value) in trees.iter().zip([1, 2]) {
+            assert_eq!(executor.env_mut().exec_tree(ast), StateNode::Value(NodeValue::Integer(value)));
+        }
+        let bindings = executor.variables();
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(bindings.get("a"), Some(&NodeValue::Integer(1)));
+        assert_eq!(bindings.get("b"), Some(&NodeValue::Integer(2)));
+        Ok(())
+    }
+
+    /// each number of args gets its own variant, with the args in order.
+    #[test]
+    fn exec_arities() {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[ Token("ident", "one"), Expr("NUM") ]),
+            SubExpr(&[ Token("ident", "two"), Expr("NUM"), Expr("NUM") ]),
+            SubExpr(&[ Token("ident", "three"), Expr("NUM"), Expr("NUM"), Expr("NUM") ]),
+            SubExpr(&[ Token("ident", "bad"), Expr("NUM") ]),
+            Token("ident", ""),
+        ]), LambdaOr(&[
+            Lambda("ARGS", &[2]),
+            Lambda("ARGS", &[2, 3]),
+            Lambda("ARGS", &[3, 2, 4]),
+            Lambda("ARGS", &[2, 3]),
+            EvalAs("ARGS"),
+        ]));
+        let mut env = math::env();
+        env.define("ARGS", |mut frame| {
+            let exec = frame.eval();
+            let variant = match &exec {
+                Exec::UniExpr(..) => "uni",
+                Exec::BinExpr(..) => "bin",
+                Exec::TriExpr(..) => "tri",
+                Exec::Root(_) => "root",
+                Exec::NoOp() | Exec::RuntimeErr(_) => "other",
+            };
+            let mut args = vec![];
+            for arg in exec.args() {
+                match arg {
+                    StateNode::Value(value) => args.push(value.to_string().unwrap_or_default()),
+                    err => return err,
+                }
+            }
+            StateNode::Value(NodeValue::String(format!("{variant} [{}]", args.join(", "))))
+        });
+        let mut executor = Executor::new(math::lexer(), parser, env);
+        assert_eq!(executor.exec_str("one 1"), Ok("uni [1]".into()));
+        assert_eq!(executor.exec_str("two 1 2"), Ok("bin [1, 2]".into()));
+        assert_eq!(executor.exec_str("three 1 2 3"), Ok("tri [2, 1, 3]".into()));
+        assert_eq!(executor.exec_str("zero"), Ok("root []".into()));
+        // two args on a node with one branch after the keyword
+        let err = "Lambda `ARGS` has an invalid arg index 3, the node has 2 branches".to_string();
+        assert_eq!(executor.exec_str("bad 1"), Err(err));
+    }
+}
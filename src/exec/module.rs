@@ -0,0 +1,72 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{EnvFrame, StateNode};
+
+/// A lambda that can be defined in a [`VirtualEnv`](super::VirtualEnv).
+pub type LambdaFn = fn(EnvFrame) -> StateNode;
+
+/// A named group of related lambdas that can be
+/// [registered](super::VirtualEnv::register_module) in a [`VirtualEnv`](super::VirtualEnv)
+/// all at once. Each lambda can be called by its qualified name like `math:ADD`, or by
+/// its own name when no other module defined it first.
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::exec::{Module, StateNode, VirtualEnv};
+/// let mut module = Module::new("host");
+/// module.define("NOTHING", |_| StateNode::None);
+/// assert_eq!(module.lambda_names(), ["NOTHING"]);
+///
+/// let mut env = VirtualEnv::new();
+/// env.register_module(module)?;
+/// assert!(env.is_defined("host:NOTHING"));
+/// assert!(env.is_defined("NOTHING"));
+/// Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Module {
+    name: String,
+    definitions: BTreeMap<String, LambdaFn>,
+    pure: BTreeSet<String>,
+}
+
+impl Module {
+    pub fn new(name: &str) -> Module {
+        Module { name: name.to_owned(), definitions: BTreeMap::new(), pure: BTreeSet::new() }
+    }
+
+    /// Get the name the lambdas are qualified with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Define a lambda in the module, replacing one with the same name.
+    pub fn define(&mut self, lambda_type: &str, cb: LambdaFn) {
+        self.pure.remove(lambda_type);
+        self.definitions.insert(lambda_type.to_owned(), cb);
+    }
+
+    /// Define a lambda that only works out a value from its branches, see
+    /// [`VirtualEnv::define_pure`](super::VirtualEnv::define_pure).
+    pub fn define_pure(&mut self, lambda_type: &str, cb: LambdaFn) {
+        self.definitions.insert(lambda_type.to_owned(), cb);
+        self.pure.insert(lambda_type.to_owned());
+    }
+
+    /// Get the sorted names of the lambdas in the module.
+    pub fn lambda_names(&self) -> Vec<&str> {
+        self.definitions.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Take the lambdas of the module in the order of their names, with whether they are pure.
+    pub(super) fn into_definitions(self) -> impl Iterator<Item = (String, LambdaFn, bool)> {
+        let pure = self.pure;
+        self.definitions.into_iter().map(move |(lambda, cb)| {
+            let is_pure = pure.contains(&lambda);
+            (lambda, cb, is_pure)
+        })
+    }
+}
@@ -0,0 +1,210 @@
+//! # Fractions
+//!
+//! The [`Rational`] held by [`NodeValue::Rational`](super::NodeValue::Rational), and the
+//! arithmetic behind it on fractions held as a numerator and denominator pair. The results
+//! of the arithmetic aren't reduced, that is left to the [`Rational`] they are made into.
+//! Every operator gives [`None`] when it overflows.
+//!
+
+/// A numerator and a denominator.
+pub(crate) type Ratio = (i128, i128);
+
+/// An exact fraction, always reduced with a positive denominator. The numerator and
+/// denominator are as big as a [`BigInteger`](super::NodeValue::BigInteger), so a fraction
+/// of two `i64`s never overflows when they are multiplied.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::exec::Rational;
+/// let half = Rational::new(2, -4).ok_or("the denominator is not zero")?;
+/// assert_eq!((half.numer(), half.denom()), (-1, 2));
+/// assert_eq!(half.to_string(), "-1/2");
+/// assert!(Rational::new(1, 0).is_none());
+/// Ok::<(), &str>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "(i128, i128)", into = "(i128, i128)"))]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    /// Reduce the fraction and move its sign to the numerator, [`None`] when the
+    /// denominator is zero or the sign can't be moved without overflowing.
+    pub fn new(num: i128, den: i128) -> Option<Rational> {
+        if den == 0 {
+            return None;
+        }
+        let divisor = gcd(num, den) as i128;
+        let (num, den) = (num / divisor, den / divisor);
+        match den < 0 {
+            true => Some(Rational { num: num.checked_neg()?, den: den.checked_neg()? }),
+            false => Some(Rational { num, den }),
+        }
+    }
+
+    /// Get the numerator, it has the sign of the fraction.
+    pub fn numer(&self) -> i128 {
+        self.num
+    }
+
+    /// Get the denominator, it is always positive.
+    pub fn denom(&self) -> i128 {
+        self.den
+    }
+
+    /// Check if the fraction is a whole number.
+    pub fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+
+    /// Get the fraction as the closest `f64`.
+    pub fn to_f64(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    /// Get the fraction as a numerator and denominator pair for the arithmetic.
+    pub(crate) fn ratio(&self) -> Ratio {
+        (self.num, self.den)
+    }
+}
+
+/// A whole number is displayed as one, anything else as `p/q`.
+impl std::fmt::Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.den {
+            1 => write!(f, "{}", self.num),
+            den => write!(f, "{}/{den}", self.num),
+        }
+    }
+}
+
+/// Reduce a numerator and denominator pair, see [`Rational::new`].
+impl TryFrom<(i128, i128)> for Rational {
+    type Error = String;
+
+    fn try_from((num, den): (i128, i128)) -> Result<Self, Self::Error> {
+        Rational::new(num, den).ok_or_else(|| format!("{num}/{den} is not a valid fraction"))
+    }
+}
+
+impl From<Rational> for (i128, i128) {
+    fn from(rational: Rational) -> Self {
+        rational.ratio()
+    }
+}
+
+/// The greatest common divisor, `1` when both are zero so it can always divide.
+pub(crate) fn gcd(lhs: i128, rhs: i128) -> u128 {
+    let (mut lhs, mut rhs) = (lhs.unsigned_abs(), rhs.unsigned_abs());
+    while rhs != 0 {
+        (lhs, rhs) = (rhs, lhs % rhs);
+    }
+    lhs.max(1)
+}
+
+pub(crate) fn add((lhs_num, lhs_den): Ratio, (rhs_num, rhs_den): Ratio) -> Option<Ratio> {
+    let num = lhs_num.checked_mul(rhs_den)?.checked_add(rhs_num.checked_mul(lhs_den)?)?;
+    Some((num, lhs_den.checked_mul(rhs_den)?))
+}
+
+pub(crate) fn sub(lhs: Ratio, (rhs_num, rhs_den): Ratio) -> Option<Ratio> {
+    add(lhs, (rhs_num.checked_neg()?, rhs_den))
+}
+
+pub(crate) fn mul((lhs_num, lhs_den): Ratio, (rhs_num, rhs_den): Ratio) -> Option<Ratio> {
+    Some((lhs_num.checked_mul(rhs_num)?, lhs_den.checked_mul(rhs_den)?))
+}
+
+/// Divide by flipping the divisor, dividing by zero gives a zero denominator.
+pub(crate) fn div(lhs: Ratio, (rhs_num, rhs_den): Ratio) -> Option<Ratio> {
+    mul(lhs, (rhs_den, rhs_num))
+}
+
+/// Divide and round down towards negative infinity to a whole number.
+pub(crate) fn floor_div(lhs: Ratio, rhs: Ratio) -> Option<Ratio> {
+    let (num, den) = div(lhs, rhs)?;
+    let floor = match den {
+        0 => return Some((0, 0)),
+        den if den < 0 => num.checked_neg()?.div_euclid(den.checked_neg()?),
+        den => num.div_euclid(den),
+    };
+    Some((floor, 1))
+}
+
+/// The remainder of [`floor_div`], it has the same sign as the divisor.
+pub(crate) fn modulo(lhs: Ratio, rhs: Ratio) -> Option<Ratio> {
+    match floor_div(lhs, rhs)? {
+        (_, 0) => Some((0, 0)),
+        floor => sub(lhs, mul(rhs, floor)?),
+    }
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// reduce a fraction the way `NodeValue::rational` does, for comparing.
+    fn reduced((num, den): Ratio) -> Ratio {
+        let divisor = gcd(num, den) as i128;
+        match den < 0 {
+            true => (-num / divisor, -den / divisor),
+            false => (num / divisor, den / divisor),
+        }
+    }
+
+    /// fractions are always reduced with a positive denominator.
+    #[test]
+    fn rational_new() {
+        let parts = |num, den| Rational::new(num, den).map(|ratio| ratio.ratio());
+        assert_eq!(parts(6, 8), Some((3, 4)));
+        assert_eq!(parts(0, -5), Some((0, 1)));
+        assert_eq!(parts(3, -6), Some((-1, 2)));
+        assert_eq!(parts(1, 0), None);
+        assert_eq!(parts(1, i128::MIN), None);
+        assert_eq!(Rational::try_from((2, 4)).map(|ratio| ratio.to_string()), Ok("1/2".into()));
+        assert!(Rational::try_from((1, 0)).is_err());
+    }
+
+    /// a fraction that isn't reduced or has a zero denominator can't be read back.
+    #[cfg(feature = "serde")]
+    #[test]
+    fn rational_serde() -> Result<(), String> {
+        let half = Rational::new(-2, 4).ok_or("the denominator is not zero")?;
+        let json = serde_json::to_string(&half).map_err(|err| err.to_string())?;
+        assert_eq!(json, "[-1,2]");
+        assert_eq!(serde_json::from_str::<Rational>("[2,-4]").map(|ratio| ratio.ratio()).ok(), Some((-1, 2)));
+        assert!(serde_json::from_str::<Rational>("[1,0]").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ratio_arithmetic() {
+        assert_eq!(gcd(12, -18), 6);
+        assert_eq!(gcd(0, 0), 1);
+        assert_eq!(add((1, 3), (1, 6)).map(reduced), Some((1, 2)));
+        assert_eq!(sub((1, 4), (3, 4)).map(reduced), Some((-1, 2)));
+        assert_eq!(mul((2, 3), (3, 4)).map(reduced), Some((1, 2)));
+        assert_eq!(div((1, 2), (-1, 4)).map(reduced), Some((-2, 1)));
+        assert_eq!(div((1, 2), (0, 1)), Some((1, 0)));
+        assert_eq!(mul((i128::MAX, 1), (2, 1)), None);
+    }
+
+    /// flooring rounds down and the remainder takes the sign of the divisor.
+    #[test]
+    fn ratio_floor_div() {
+        assert_eq!(floor_div((7, 2), (1, 1)), Some((3, 1)));
+        assert_eq!(floor_div((-7, 2), (1, 1)), Some((-4, 1)));
+        assert_eq!(floor_div((7, 2), (-1, 1)), Some((-4, 1)));
+        assert_eq!(modulo((7, 2), (1, 1)).map(reduced), Some((1, 2)));
+        assert_eq!(modulo((7, 2), (-1, 1)).map(reduced), Some((-1, 2)));
+        assert_eq!(modulo((1, 2), (0, 1)), Some((0, 0)));
+    }
+}
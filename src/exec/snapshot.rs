@@ -0,0 +1,138 @@
+//! # Environment Snapshots
+//! 
+//! Contains the [`EnvSnapshot`] used to save the variables of a [`VirtualEnv`](super::VirtualEnv)
+//! and restore them later. With the `serde` feature a snapshot can be serialized to keep
+//! a session between runs.
+//! 
+
+use std::collections::HashMap;
+
+use super::NodeValue;
+
+/// The variables of a [`VirtualEnv`](super::VirtualEnv) at a point in time.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EnvSnapshot {
+    /// The saved variables by name
+    pub variables: HashMap<String, NodeValue>,
+    /// Why any variables were left out of the snapshot
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub warnings: Vec<String>,
+}
+
+impl EnvSnapshot {
+    /// Make a snapshot of the `variables`, tokens, errors and functions are not state that
+    /// can be restored so they are skipped with a warning.
+    pub fn from_variables(variables: &HashMap<String, NodeValue>) -> EnvSnapshot {
+        let mut snapshot = EnvSnapshot::default();
+        for (name, value) in variables {
+            match value {
+                NodeValue::Token(_) => snapshot.warnings.push(format!("Skipped `{name}`: tokens cannot be saved")),
+                NodeValue::ValueError(_) => snapshot.warnings.push(format!("Skipped `{name}`: errors cannot be saved")),
+                NodeValue::Function(_) => snapshot.warnings.push(format!("Skipped `{name}`: functions cannot be saved")),
+                _ => { snapshot.variables.insert(name.clone(), value.clone()); }
+            }
+        }
+        snapshot.warnings.sort();
+        snapshot
+    }
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::Executor;
+
+    /// variables are carried to a new executor, values that can't be saved are skipped.
+    #[test]
+    fn snapshot_restore() -> Result<(), String> {
+        let mut executor = Executor::math();
+        executor.exec_str("x := 41")?;
+        executor.env_mut().set_ident("err", NodeValue::ValueError("bad".into()));
+        let snapshot = executor.env().snapshot();
+        assert_eq!(snapshot.warnings, ["Skipped `err`: errors cannot be saved"]);
+
+        let mut executor = Executor::math();
+        executor.env_mut().set_ident("y", NodeValue::Integer(1));
+        executor.env_mut().restore(snapshot);
+        assert_eq!(executor.exec_str("x + 1")?, "42");
+        assert!(!executor.env().variables.contains_key("y"));
+        assert!(!executor.env().variables.contains_key("err"));
+        Ok(())
+    }
+
+    /// restoring a snapshot undoes every change made to the variables after it.
+    #[test]
+    fn snapshot_undo() -> Result<(), String> {
+        let mut executor = Executor::math();
+        executor.exec_str("a := 1")?;
+        executor.exec_str("b := [1, 2]")?;
+        let snapshot = executor.env().snapshot();
+        executor.exec_str("a = 10")?;
+        executor.exec_str("b = 3.5")?;
+        executor.exec_str("c := a + b")?;
+        assert_eq!(executor.exec_str("c")?, "13.5");
+        executor.env_mut().restore(snapshot.clone());
+        assert_eq!(executor.get_var("a"), Some(&NodeValue::Integer(1)));
+        assert_eq!(executor.exec_str("b")?, "[1, 2]");
+        assert_eq!(executor.get_var("c"), None);
+        // the same snapshot can be restored again
+        executor.env_mut().clear_idents();
+        executor.env_mut().restore(snapshot);
+        assert_eq!(executor.env().variable_names(), ["a", "b"]);
+        Ok(())
+    }
+
+    /// every value can be serialized and read back.
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() -> Result<(), String> {
+        use crate::lexer::{ReadPointer, Token};
+        use crate::parser::syntax::TreeNode;
+        #[allow(unused_mut)]
+        let mut values = vec![
+            NodeValue::Token(Token::new("int", "1", ReadPointer::from_pos((0,2, 0,3), (2,3)))),
+            NodeValue::Ident("x".into()),
+            NodeValue::BigFloat(1.5e300),
+            NodeValue::Float(2.5),
+            NodeValue::BigInteger(170141183460469231731687303715884105727),
+            NodeValue::Integer(-7),
+            NodeValue::rational(-2, 6),
+            NodeValue::String("text".into()),
+            NodeValue::Char('é'),
+            NodeValue::Boolean(true),
+            NodeValue::None,
+            NodeValue::Null,
+            NodeValue::List(vec![NodeValue::Integer(1), NodeValue::List(vec![])]),
+            NodeValue::Map([("x".to_string(), NodeValue::Float(0.5))].into()),
+            NodeValue::ValueError("bad".into()),
+        ];
+        #[cfg(feature = "decimal")]
+        values.push(NodeValue::Decimal("-12.05".parse()?));
+        for value in values {
+            let json = serde_json::to_string(&value).map_err(|err| err.to_string())?;
+            let read: NodeValue = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+            assert_eq!(format!("{read:?}"), format!("{value:?}"));
+        }
+        // functions are skipped by snapshots, so they can't be serialized on their own
+        let function = NodeValue::Function(crate::exec::Function { params: vec![], body: std::sync::Arc::new(TreeNode::from_value(NodeValue::Null)) });
+        assert!(serde_json::to_string(&function).is_err());
+        // a map pulled from a variable
+        let mut executor = Executor::math();
+        executor.exec_str("p := {x: 1, y: [2, 3]}")?;
+        let json = serde_json::to_string(executor.get_var("p").ok_or("no variable `p`")?).map_err(|err| err.to_string())?;
+        let read: NodeValue = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+        assert_eq!(read.to_string()?, "{x: 1, y: [2, 3]}");
+        // a whole snapshot
+        let mut executor = Executor::math();
+        executor.exec_str("x := 41")?;
+        let json = serde_json::to_string(&executor.env().snapshot()).map_err(|err| err.to_string())?;
+        let snapshot: EnvSnapshot = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+        let mut executor = Executor::math();
+        executor.env_mut().restore(snapshot);
+        assert_eq!(executor.exec_str("x + 1")?, "42");
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! # Execution Statistics
+//!
+//! Contains the [`ExecStats`] collected by a [`VirtualEnv`](super::VirtualEnv) while
+//! [stats are enabled](super::VirtualEnv::enable_stats), to find which lambdas a script
+//! spends its time in.
+//!
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How many times a lambda ran and how long it took in total.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LambdaStats {
+    /// How many times the lambda ran
+    pub count: u64,
+    /// The time spent in the lambda, including the lambdas it ran
+    pub time: Duration,
+}
+
+/// The [`LambdaStats`] of each lambda that ran, by name.
+///
+/// ---
+///
+/// Displaying the stats gives a table with the lambdas that took the longest first.
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::exec::Executor;
+/// let mut executor = Executor::math();
+/// executor.env_mut().enable_stats(true);
+/// executor.exec_str("1 + 2 + 3")?;
+///
+/// let stats = executor.env_mut().take_stats();
+/// assert_eq!(stats.count("ADD"), 2);
+/// assert!(stats.to_string().starts_with("lambda"));
+/// Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecStats {
+    lambdas: HashMap<String, LambdaStats>,
+}
+
+impl ExecStats {
+    /// Count a run of the lambda that took `time`.
+    pub(crate) fn record(&mut self, name: &str, time: Duration) {
+        let stats = match self.lambdas.get_mut(name) {
+            Some(stats) => stats,
+            None => self.lambdas.entry(name.to_owned()).or_default(),
+        };
+        stats.count += 1;
+        stats.time += time;
+    }
+
+    /// Get the stats of a lambda, `None` when it never ran.
+    pub fn get(&self, name: &str) -> Option<&LambdaStats> {
+        self.lambdas.get(name)
+    }
+
+    /// Get how many times a lambda ran.
+    pub fn count(&self, name: &str) -> u64 {
+        self.get(name).map_or(0, |stats| stats.count)
+    }
+
+    /// Check if no lambda ran.
+    pub fn is_empty(&self) -> bool {
+        self.lambdas.is_empty()
+    }
+
+    /// Get the stats of every lambda that ran, the longest total time first and then by name.
+    pub fn sorted(&self) -> Vec<(&str, &LambdaStats)> {
+        let mut lambdas: Vec<_> = self.lambdas.iter().map(|(name, stats)| (name.as_str(), stats)).collect();
+        lambdas.sort_by(|(lhs_name, lhs), (rhs_name, rhs)| rhs.time.cmp(&lhs.time).then(lhs_name.cmp(rhs_name)));
+        lambdas
+    }
+}
+
+impl std::fmt::Display for ExecStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lambdas = self.sorted();
+        let width = lambdas.iter().map(|(name, _)| name.len()).max().unwrap_or_default().max("lambda".len());
+        writeln!(f, "{:<width$}  {:>10}  {:>12}", "lambda", "count", "time")?;
+        for (name, stats) in lambdas {
+            writeln!(f, "{name:<width$}  {:>10}  {:>12}", stats.count, format!("{:?}", stats.time))?;
+        }
+        Ok(())
+    }
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::Executor;
+
+    /// every lambda run in a loop is counted, and taking the stats starts them over.
+    #[test]
+    fn loop_stats() -> Result<(), String> {
+        let mut executor = Executor::calc();
+        executor.env_mut().enable_stats(true);
+        executor.exec_str("i = 0")?;
+        executor.exec_str("while i < 50 { i = i + 1 }")?;
+        let stats = executor.env_mut().take_stats();
+        assert_eq!(stats.count("ADD"), 50);
+        assert_eq!(stats.count("CMP"), 51);
+        assert_eq!(stats.count("SUB"), 0);
+        // the loop holds the time of everything inside it
+        let time = |name| stats.get(name).map(|stats| stats.time).unwrap_or_default();
+        assert!(time("WHILE") >= time("ADD"));
+        assert_eq!(stats.sorted().first().map(|(name, _)| *name), Some("WHILE"));
+
+        assert!(executor.env_mut().take_stats().is_empty());
+        executor.exec_str("i + 1")?;
+        assert_eq!(executor.env_mut().take_stats().count("ADD"), 1);
+        // nothing is counted once they are turned off
+        executor.env_mut().enable_stats(false);
+        executor.exec_str("i + 1")?;
+        assert!(executor.env_mut().take_stats().is_empty());
+        Ok(())
+    }
+
+    /// the table has a row for each lambda, the longest first.
+    #[test]
+    fn display_stats() {
+        let mut stats = ExecStats::default();
+        stats.record("ADD", Duration::from_millis(2));
+        stats.record("GET_IDENT", Duration::from_millis(5));
+        stats.record("ADD", Duration::from_millis(1));
+        assert_eq!(stats.to_string(), [
+            "lambda          count          time",
+            "GET_IDENT           1           5ms",
+            "ADD                 2           3ms",
+            "",
+        ].join("\n"));
+    }
+}
@@ -5,9 +5,11 @@ pub enum Lambda<'a> {
     Lambda(&'a str, &'a[u32]),
 
     GetExpr(u32, &'a Self),
+    GetExprs(&'a[u32], &'a Self),
     
     Eval,
     EvalAs(&'a str),
+    /// The node's token as a value, an `ident` token is an [`Ident`](crate::exec::NodeValue::Ident)
     EvalToken,
 }
 
@@ -28,6 +30,9 @@ impl std::fmt::Display for Lambda<'_> {
                 write!(f, "{} ", args.iter().map(|arg| format!("${arg}")).collect::<Vec<String>>().join(" "))
             }, 
             Lambda::GetExpr(arg, lambda) => write!(f, "with &{arg} {lambda} "),
+            Lambda::GetExprs(args, lambda) => {
+                write!(f, "with {} {lambda} ", args.iter().map(|arg| format!("&{arg}")).collect::<Vec<String>>().join(" "))
+            },
             Lambda::Eval => write!(f, "EVAL "),
             Lambda::EvalAs(lambda) => write!(f, "{lambda} "), 
             Lambda::EvalToken => write!(f, "EVAL_TOKEN "),
@@ -37,15 +42,28 @@ impl std::fmt::Display for Lambda<'_> {
     }
 }
 
-impl<'a> Into<OwnedLambda> for Lambda<'a> {
-    fn into(self) -> OwnedLambda {
+impl<'a> Lambda<'a> {
+    /// Add the names of the env lambdas this lambda calls to `names`.
+    pub fn collect_names(&self, names: &mut Vec<&'a str>) {
         match self {
+            Lambda::LambdaOr(lambdas) => lambdas.iter().for_each(|lambda| lambda.collect_names(names)),
+            Lambda::Lambda(name, _) | Lambda::EvalAs(name) => names.push(name),
+            Lambda::GetExpr(_, lambda) | Lambda::GetExprs(_, lambda) => lambda.collect_names(names),
+            Lambda::Eval | Lambda::EvalToken => {}
+        }
+    }
+}
+
+impl<'a> From<Lambda<'a>> for OwnedLambda {
+    fn from(lambda: Lambda<'a>) -> Self {
+        match lambda {
             Lambda::LambdaOr(lambdas) => {
                 let lambdas: Vec<OwnedLambda> = lambdas.iter().map(|l| l.into()).collect();
                 OwnedLambda::LambdaOr(lambdas)
             }
             Lambda::Lambda(name, args) => OwnedLambda::Lambda(name.to_string(), args.to_vec()),
             Lambda::GetExpr(id, lambda) => OwnedLambda::GetExpr(id, Box::new(lambda.into())),
+            Lambda::GetExprs(ids, lambda) => OwnedLambda::GetExprs(ids.to_vec(), Box::new(lambda.into())),
             Lambda::Eval => OwnedLambda::Eval,
             Lambda::EvalAs(name) => OwnedLambda::EvalAs(name.to_string()),
             Lambda::EvalToken => OwnedLambda::EvalToken,
@@ -59,12 +77,25 @@ pub enum OwnedLambda {
     LambdaOr(Vec<OwnedLambda>),
     Lambda(String, Vec<u32>),
     GetExpr(u32, Box<OwnedLambda>),
+    GetExprs(Vec<u32>, Box<OwnedLambda>),
     
     Eval,
     EvalAs(String),
     EvalToken,
 }
 
+impl OwnedLambda {
+    /// Add the names of the env lambdas this lambda calls to `names`.
+    pub fn collect_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            OwnedLambda::LambdaOr(lambdas) => lambdas.iter().for_each(|lambda| lambda.collect_names(names)),
+            OwnedLambda::Lambda(name, _) | OwnedLambda::EvalAs(name) => names.push(name),
+            OwnedLambda::GetExpr(_, lambda) | OwnedLambda::GetExprs(_, lambda) => lambda.collect_names(names),
+            OwnedLambda::Eval | OwnedLambda::EvalToken => {}
+        }
+    }
+}
+
 impl std::fmt::Display for OwnedLambda {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{{ ")?;
@@ -82,6 +113,9 @@ impl std::fmt::Display for OwnedLambda {
                 write!(f, "{} ", args.iter().map(|arg| format!("${arg}")).collect::<Vec<String>>().join(" "))
             }, 
             OwnedLambda::GetExpr(arg, lambda) => write!(f, "with &{arg} {lambda} "),
+            OwnedLambda::GetExprs(args, lambda) => {
+                write!(f, "with {} {lambda} ", args.iter().map(|arg| format!("&{arg}")).collect::<Vec<String>>().join(" "))
+            },
             OwnedLambda::Eval => write!(f, "EVAL "),
             OwnedLambda::EvalAs(lambda) => write!(f, "{lambda} "), 
             OwnedLambda::EvalToken => write!(f, "EVAL_TOKEN "),
@@ -100,6 +134,7 @@ impl<'a> From<&'a Lambda<'a>> for OwnedLambda {
             }
             Lambda::Lambda(name, args) => OwnedLambda::Lambda(name.to_string(), args.to_vec()),
             Lambda::GetExpr(id, lambda) => OwnedLambda::GetExpr(*id, Box::new(lambda.into())),
+            Lambda::GetExprs(ids, lambda) => OwnedLambda::GetExprs(ids.to_vec(), Box::new(lambda.into())),
             Lambda::Eval => OwnedLambda::Eval,
             Lambda::EvalAs(name) => OwnedLambda::EvalAs(name.to_string()),
             Lambda::EvalToken => OwnedLambda::EvalToken,
@@ -109,7 +144,7 @@ impl<'a> From<&'a Lambda<'a>> for OwnedLambda {
 
 impl<'a> From<&&'a Lambda<'a>> for OwnedLambda {
     fn from(lambda: &&'a Lambda<'a>) -> Self {
-        let borrowed_lambda: &'a Lambda<'a> = *lambda; // Dereference once to get `&Lambda`
+        let borrowed_lambda: &'a Lambda<'a> = lambda; // Dereference once to get `&Lambda`
         From::from(borrowed_lambda) // Convert borrowed Lambda to OwnedLambda
     }
 }
@@ -228,6 +263,26 @@ mod tests {
         let owned_lambda_eval_token: OwnedLambda = lambda_eval_token.into();
         assert_eq!(owned_lambda_eval_token, OwnedLambda::EvalToken);
     }
+
+    #[test]
+    fn test_into_owned_lambda_get_exprs() {
+        // Create a Lambda::GetExprs variant
+        let lambda = Lambda::Lambda("ADD", &[1, 2]);
+        let lambda_get_exprs = Lambda::GetExprs(&[2, 4], &lambda);
+
+        // Convert GetExprs into OwnedLambda
+        let owned_lambda: OwnedLambda = lambda_get_exprs.into();
+
+        // Assert that the conversion produced the expected OwnedLambda variant
+        assert_eq!(
+            owned_lambda,
+            OwnedLambda::GetExprs(
+                vec![2, 4],
+                Box::new(OwnedLambda::Lambda("ADD".to_string(), vec![1, 2]))
+            )
+        );
+        assert_eq!(owned_lambda.to_string(), "{ with &2 &4 { ADD $1 $2 } }");
+    }
     
 
 }
@@ -0,0 +1,340 @@
+//! # Type Checking
+//! 
+//! Contains [`VirtualEnv::check_types`], which works out the types in a syntax tree from
+//! the [`Signature`] of each lambda to find type errors without running anything.
+//! 
+
+use std::collections::HashMap;
+
+use crate::lexer::ReadPointer;
+use crate::parser::syntax::{AbstractSyntaxTree, TreeNode};
+use super::syntax::OwnedLambda;
+use super::{NodeValue, VirtualEnv};
+
+/// The type of a value worked out without running, see [`VirtualEnv::check_types`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Integer,
+    Float,
+    /// Only with the `decimal` feature
+    #[cfg(feature = "decimal")]
+    Decimal,
+    Rational,
+    String,
+    Char,
+    Boolean,
+    List,
+    Map,
+    Function,
+    None,
+    Null,
+    /// The type isn't known until the code is run
+    Any,
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+impl ValueType {
+    /// Get the type of a value, values that only exist while running are [`Any`](ValueType::Any).
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{ValueType, NodeValue};
+    /// assert_eq!(ValueType::of(&NodeValue::BigInteger(2)), ValueType::Integer);
+    /// assert_eq!(ValueType::of(&NodeValue::Ident("x".into())), ValueType::Any);
+    /// ```
+    pub fn of(value: &NodeValue) -> ValueType {
+        match value {
+            NodeValue::BigFloat(_) | NodeValue::Float(_) => ValueType::Float,
+            NodeValue::BigInteger(_) | NodeValue::Integer(_) => ValueType::Integer,
+            #[cfg(feature = "decimal")]
+            NodeValue::Decimal(_) => ValueType::Decimal,
+            NodeValue::Rational(..) => ValueType::Rational,
+            NodeValue::String(_) => ValueType::String,
+            NodeValue::Char(_) => ValueType::Char,
+            NodeValue::Boolean(_) => ValueType::Boolean,
+            NodeValue::List(_) => ValueType::List,
+            NodeValue::Map(_) => ValueType::Map,
+            NodeValue::Function(_) => ValueType::Function,
+            NodeValue::None => ValueType::None,
+            NodeValue::Null => ValueType::Null,
+            NodeValue::Token(_) | NodeValue::Ident(_) | NodeValue::ValueError(_) => ValueType::Any,
+        }
+    }
+
+    /// Check if the type is one of the number types.
+    pub fn is_number(self) -> bool {
+        match self {
+            ValueType::Integer | ValueType::Float | ValueType::Rational => true,
+            #[cfg(feature = "decimal")]
+            ValueType::Decimal => true,
+            _ => false,
+        }
+    }
+}
+
+/// Works out the type a lambda gives from the types of its args, or why the args can't
+/// be used - see [`define_signature`](VirtualEnv::define_signature).
+pub type Signature = fn(&[ValueType]) -> Result<ValueType, String>;
+
+/// How [`check_types`](VirtualEnv::check_types) works out the type a lambda gives.
+#[derive(Clone, Copy)]
+pub(crate) enum TypeRule {
+    /// From the types of its args
+    Signature(Signature),
+    /// The type of the variable named by the first token of its node, see
+    /// [`define_variable_signature`](VirtualEnv::define_variable_signature)
+    Variable,
+}
+
+/// Get the type of an arithmetic operator on two numbers, integers stay integers and any
+/// float makes a float - a decimal makes a decimal but can't be mixed with a float or a
+/// rational, and a rational with an integer stays a rational. `verb` names the operation
+/// in the error, like `add`.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::exec::{types, ValueType};
+/// assert_eq!(types::arithmetic("add", &[ValueType::Integer, ValueType::Float]), Ok(ValueType::Float));
+/// assert_eq!(types::arithmetic("add", &[ValueType::String, ValueType::Integer]), Err("cannot add String and Integer".into()));
+/// ```
+pub fn arithmetic(verb: &str, args: &[ValueType]) -> Result<ValueType, String> {
+    match args {
+        [ValueType::Any, _] | [_, ValueType::Any] => Ok(ValueType::Any),
+        [ValueType::Integer, ValueType::Integer] => Ok(ValueType::Integer),
+        #[cfg(feature = "decimal")]
+        [ValueType::Decimal, ValueType::Integer | ValueType::Decimal] | [ValueType::Integer, ValueType::Decimal] => Ok(ValueType::Decimal),
+        #[cfg(feature = "decimal")]
+        [lhs @ ValueType::Decimal, rhs @ ValueType::Float] | [lhs @ ValueType::Float, rhs @ ValueType::Decimal] => {
+            Err(format!("cannot {verb} {lhs} and {rhs} without converting one"))
+        }
+        #[cfg(feature = "decimal")]
+        [lhs @ ValueType::Decimal, rhs @ ValueType::Rational] | [lhs @ ValueType::Rational, rhs @ ValueType::Decimal] => {
+            Err(format!("cannot {verb} {lhs} and {rhs} without converting one"))
+        }
+        [ValueType::Rational, ValueType::Integer | ValueType::Rational] | [ValueType::Integer, ValueType::Rational] => Ok(ValueType::Rational),
+        [lhs, rhs] if lhs.is_number() && rhs.is_number() => Ok(ValueType::Float),
+        [lhs, rhs] => Err(format!("cannot {verb} {lhs} and {rhs}")),
+        _ => Ok(ValueType::Any),
+    }
+}
+
+/// A type error found by [`check_types`](VirtualEnv::check_types).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDiagnostic {
+    pub message: String,
+    /// Where the node with the error was read from, if it was read
+    pub position: Option<ReadPointer>,
+}
+
+impl std::fmt::Display for TypeDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.position {
+            Some(position) => {
+                let (line, col) = position.user_position();
+                write!(f, "{} at line {line} col {col}", self.message)
+            }
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl VirtualEnv {
+    /// Find the type errors in the tree without running it, using the
+    /// [signatures](VirtualEnv::define_signature) of the lambdas and the types of the
+    /// variables in `var_types`. A lambda without a signature or a variable without a
+    /// type could be anything, so they are never an error.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use std::collections::HashMap;
+    /// use interpreter::exec::ValueType;
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("x * (\"a\" + 1)"))?;
+    /// let errors = math::env().check_types(&ast, &HashMap::from([("x".into(), ValueType::Integer)]));
+    /// assert_eq!(errors[0].to_string(), "cannot add String and Integer at line 1 col 6");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn check_types(&self, ast: &AbstractSyntaxTree, var_types: &HashMap<String, ValueType>) -> Vec<TypeDiagnostic> {
+        let mut checker = TypeChecker { env: self, var_types, diagnostics: vec![] };
+        checker.infer(&ast.root);
+        checker.diagnostics
+    }
+
+    /// Get the type the root of the tree gives, [`Any`](ValueType::Any) when it has type
+    /// errors or can't be known before running.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use std::collections::HashMap;
+    /// use interpreter::exec::ValueType;
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("2 * 3.5"))?;
+    /// assert_eq!(math::env().infer_type(&ast, &HashMap::new()), ValueType::Float);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn infer_type(&self, ast: &AbstractSyntaxTree, var_types: &HashMap<String, ValueType>) -> ValueType {
+        TypeChecker { env: self, var_types, diagnostics: vec![] }.infer(&ast.root)
+    }
+}
+
+/// Walks a tree working out the type of each node.
+struct TypeChecker<'a> {
+    env: &'a VirtualEnv,
+    var_types: &'a HashMap<String, ValueType>,
+    diagnostics: Vec<TypeDiagnostic>,
+}
+
+impl TypeChecker<'_> {
+    /// Get the type of the node after checking its branches.
+    fn infer(&mut self, node: &TreeNode) -> ValueType {
+        if let Some(value) = &node.value {
+            return ValueType::of(value);
+        }
+        let types: Vec<_> = node.nodes.iter().map(|branch| self.infer(branch)).collect();
+        // an arg that isn't a branch is an error when running, not a type error
+        let args = |args: &[u32]| -> Vec<ValueType> {
+            args.iter()
+                .map(|arg| arg.checked_sub(1).and_then(|arg| types.get(arg as usize)).copied().unwrap_or(ValueType::Any))
+                .collect()
+        };
+        match &node.lambda {
+            OwnedLambda::Lambda(name, ids) => self.apply(name, node, &args(ids)),
+            OwnedLambda::EvalAs(name) => self.apply(name, node, &[]),
+            OwnedLambda::GetExpr(id, lambda) => self.apply_with(lambda, node, &args(&[*id])),
+            OwnedLambda::GetExprs(ids, lambda) => self.apply_with(lambda, node, &args(ids)),
+            OwnedLambda::Eval => match types.as_slice() {
+                [only] => *only,
+                _ => ValueType::Any,
+            },
+            OwnedLambda::EvalToken | OwnedLambda::LambdaOr(_) => ValueType::Any,
+        }
+    }
+
+    /// Get the type of a lambda run on the branches picked out by a `with` lambda.
+    fn apply_with(&mut self, lambda: &OwnedLambda, node: &TreeNode, args: &[ValueType]) -> ValueType {
+        match (lambda, args) {
+            (OwnedLambda::Eval, [only]) => *only,
+            (OwnedLambda::Lambda(name, _) | OwnedLambda::EvalAs(name), args) => self.apply(name, node, args),
+            _ => ValueType::Any,
+        }
+    }
+
+    /// Get the type of the named lambda from its signature, an error is reported and
+    /// gives [`Any`](ValueType::Any) so it isn't reported again by the nodes above.
+    fn apply(&mut self, name: &str, node: &TreeNode, args: &[ValueType]) -> ValueType {
+        let signature = match self.env.type_rule(name) {
+            Some(TypeRule::Signature(signature)) => signature,
+            Some(TypeRule::Variable) => return node.leaves().next()
+                .and_then(|ident| self.var_types.get(&ident.value))
+                .copied()
+                .unwrap_or(ValueType::Any),
+            None => return ValueType::Any,
+        };
+        signature(args).unwrap_or_else(|message| {
+            self.diagnostics.push(TypeDiagnostic { message, position: node.span() });
+            ValueType::Any
+        })
+    }
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::math;
+    use crate::lexer::LineReader;
+
+    /// Check the types of the math source with the variable types.
+    fn check(src: &str, vars: &[(&str, ValueType)]) -> Result<(ValueType, Vec<String>), String> {
+        let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new(src))?;
+        let vars = vars.iter().map(|(name, found)| (name.to_string(), *found)).collect();
+        let env = math::env();
+        let errors = env.check_types(&ast, &vars).iter().map(|err| err.to_string()).collect();
+        Ok((env.infer_type(&ast, &vars), errors))
+    }
+
+    /// arithmetic on a string is flagged where it happens.
+    #[test]
+    fn check_types_mismatch() -> Result<(), String> {
+        assert_eq!(check("\"a\" - 1", &[])?, (ValueType::Any, vec!["cannot subtract String and Integer at line 1 col 1".into()]));
+        assert_eq!(check("1 + 2 * [1]", &[])?.1, ["cannot multiply Integer and List at line 1 col 5"]);
+        // only the innermost error is reported
+        assert_eq!(check("(s + 1) * 2 - 3", &[("s", ValueType::String)])?.1, ["cannot add String and Integer at line 1 col 2"]);
+        Ok(())
+    }
+
+    /// the types of the variables and literals give the type of the result.
+    #[test]
+    fn check_types_infer() -> Result<(), String> {
+        assert_eq!(check("x + 1", &[("x", ValueType::Float)])?, (ValueType::Float, vec![]));
+        assert_eq!(check("x + 1", &[("x", ValueType::Integer)])?, (ValueType::Integer, vec![]));
+        assert_eq!(check("(2 + 3) % 2", &[])?, (ValueType::Integer, vec![]));
+        assert_eq!(check("str(x) ", &[])?, (ValueType::String, vec![]));
+        assert_eq!(check("y := 2.5 * 2", &[])?, (ValueType::Float, vec![]));
+        assert_eq!(check("x * 2", &[("x", ValueType::Rational)])?, (ValueType::Rational, vec![]));
+        assert_eq!(check("x + 0.5", &[("x", ValueType::Rational)])?, (ValueType::Float, vec![]));
+        // a variable without a type could be anything
+        assert_eq!(check("x + 1", &[])?, (ValueType::Any, vec![]));
+        Ok(())
+    }
+
+    /// lambdas and functions without a signature are never an error.
+    #[test]
+    fn check_types_unknown() -> Result<(), String> {
+        assert_eq!(check("sqrt(\"a\") + 1", &[])?, (ValueType::Any, vec![]));
+        assert_eq!(check("[1, \"a\"][0] - 1", &[])?, (ValueType::Any, vec![]));
+        let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("1 - 2"))?;
+        assert!(crate::exec::VirtualEnv::new().check_types(&ast, &HashMap::new()).is_empty());
+        Ok(())
+    }
+
+    /// an arg that isn't a branch of the node has no type instead of panicking.
+    #[test]
+    fn check_types_bad_arg() -> Result<(), String> {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("EXPR", SubExpr(&[Expr("NUM"), Expr("NUM")]), Lambda("ADD", &[0, 1]));
+        let ast = parser.parse_tree(&math::lexer(), &mut LineReader::new("1 2"))?;
+        let env = math::env();
+        assert!(env.check_types(&ast, &HashMap::new()).is_empty());
+        assert_eq!(env.infer_type(&ast, &HashMap::new()), ValueType::Any);
+        Ok(())
+    }
+
+    /// any lambda can read the variable types, not only the math one.
+    #[test]
+    fn check_types_variable_signature() -> Result<(), String> {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = math::parser();
+        parser.define("VAR", Token("ident", ""), EvalAs("LOOKUP"));
+        let ast = parser.parse_tree(&math::lexer(), &mut LineReader::new("x + 1"))?;
+        let vars = HashMap::from([("x".to_string(), ValueType::String)]);
+        let mut env = math::env();
+        assert!(env.check_types(&ast, &vars).is_empty());
+        env.define_variable_signature("LOOKUP");
+        assert_eq!(env.check_types(&ast, &vars)[0].message, "cannot add String and Integer");
+        Ok(())
+    }
+}
@@ -0,0 +1,456 @@
+pub use calc::*;
+
+#[allow(clippy::module_inception)]
+pub mod calc {
+    use std::sync::Arc;
+    use once_cell::sync::Lazy;
+    use crate::parser::{Assoc, Parser, PrecedenceBuilder};
+    use crate::parser::syntax::TreeNode;
+    use crate::lexer::Lexer;
+    use crate::exec::{Executor, Function, NodeValue, StateNode, VirtualEnv};
+    use crate::lang::math;
+
+    pub fn exec() -> Executor<'static> {
+        let (lexer, parser) = self::grammar();
+        Executor::from_shared(lexer, parser, self::env())
+    }
+
+    /// Get the lexer and parser shared by every executor from [`exec`], they are compiled
+    /// the first time they are used.
+    pub fn grammar() -> (Arc<Lexer>, Arc<Parser<'static>>) {
+        static GRAMMAR: Lazy<(Arc<Lexer>, Arc<Parser<'static>>)> = Lazy::new(|| (Arc::new(self::lexer()), Arc::new(self::parser())));
+        (GRAMMAR.0.clone(), GRAMMAR.1.clone())
+    }
+
+    pub fn lexer() -> Lexer {
+        let mut lexer = Lexer::new();
+        let _ = lexer.define("op_cmp", "==|!=|<=|>=|<|>");
+        let _ = lexer.define("op_add", "\\+|\\-");
+        let _ = lexer.define("op_mul", "\\*|\\/\\/|\\/|%");
+        let _ = lexer.define("punct", "\\(|\\)|\\{|\\}|,");
+        let _ = lexer.define("float", "[0-9]+\\.[0-9]+");
+        let _ = lexer.define("int", "[0-9]+");
+        let _ = lexer.define("assign", "=");
+        let _ = lexer.define("ident", "[a-zA-Z_][a-zA-Z0-9_]*");
+        // statements end at a `;` or a new line, blank lines are part of the same separator
+        let _ = lexer.define("sep", "(?:[;\\n][ \\t\\r]*(?:#[^\\n]*)?)+");
+        if let Ok(string) = crate::lexer::TokenDef::with_transform("string", "\"(?:[^\"\\\\\\n]|\\\\.)*\"", unquote) {
+            lexer.define_token(string);
+        }
+        if let Ok(char) = crate::lexer::TokenDef::with_transform("char", "'(?:[^'\\\\\\n]|\\\\.)'", unquote) {
+            lexer.define_token(char);
+        }
+        lexer.define_keyword("kw:fn", "fn");
+        lexer.define_keyword("kw:if", "if");
+        lexer.define_keyword("kw:else", "else");
+        lexer.define_keyword("kw:while", "while");
+        lexer.define_keyword("kw:true", "true");
+        lexer.define_keyword("kw:false", "false");
+        lexer.define_keyword("kw:null", "null");
+        let _ = lexer.define_skip("space", "[ \\t\\r]+");
+        let _ = lexer.define_skip("comment", "#[^\\n]*");
+        lexer
+    }
+
+    pub fn parser() -> Parser<'static> {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = Parser::new();
+        // each parse is one statement and the separator after it
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[ Token("sep", ""), Expr("LINE") ]),
+            Expr("LINE"),
+        ]), LambdaOr(&[
+            GetExpr(2, &Eval),
+            Eval,
+        ]));
+        parser.define("LINE", ExprOr(&[
+            SubExpr(&[ Expr("STMT"), Token("sep", "") ]),
+            SubExpr(&[ Expr("STMT"), End ]),
+        ]), GetExpr(1, &Eval));
+        parser.define("STMT", ExprOr(&[
+            Expr("FN"),
+            Expr("WHILE"),
+            Expr("IF"),
+            Expr("ASSIGN"),
+            Expr("CMP"),
+        ]), Eval);
+        // a block gives the value of its last statement
+        parser.define("BLOCK", ExprOr(&[
+            SubExpr(&[ Token("punct", "{"), Token("sep", ""), Expr("STMTS"), Token("punct", "}") ]),
+            SubExpr(&[ Token("punct", "{"), Expr("STMTS"), Token("punct", "}") ]),
+            SubExpr(&[ Token("punct", "{"), Token("sep", ""), Token("punct", "}") ]),
+            SubExpr(&[ Token("punct", "{"), Token("punct", "}") ]),
+        ]), LambdaOr(&[
+            GetExpr(3, &Eval),
+            GetExpr(2, &Eval),
+            EvalAs("NONE"),
+            EvalAs("NONE"),
+        ]));
+        parser.define("STMTS", ExprOr(&[
+            SubExpr(&[ Expr("STMT"), Token("sep", ""), Expr("STMTS") ]),
+            SubExpr(&[ Expr("STMT"), Token("sep", "") ]),
+            Expr("STMT"),
+        ]), LambdaOr(&[
+            Lambda("SEQ", &[1, 3]),
+            GetExpr(1, &Eval),
+            Eval,
+        ]));
+        parser.define("FN",
+            SubExpr(&[
+                Token("kw:fn", ""), Expr("IDENT"),
+                Token("punct", "("), SepBy(&Expr("IDENT"), &Token("punct", ",")), Token("punct", ")"),
+                Expr("BLOCK"),
+            ]),
+            EvalAs("FN"),
+        );
+        parser.define("IF", ExprOr(&[
+            SubExpr(&[ Token("kw:if", ""), Expr("CMP"), Expr("BLOCK"), Token("kw:else", ""), Expr("IF") ]),
+            SubExpr(&[ Token("kw:if", ""), Expr("CMP"), Expr("BLOCK"), Token("kw:else", ""), Expr("BLOCK") ]),
+            SubExpr(&[ Token("kw:if", ""), Expr("CMP"), Expr("BLOCK") ]),
+        ]), LambdaOr(&[
+            Lambda("IF", &[2, 3, 5]),
+            Lambda("IF", &[2, 3, 5]),
+            Lambda("IF", &[2, 3]),
+        ]));
+        parser.define("WHILE",
+            SubExpr(&[ Token("kw:while", ""), Expr("CMP"), Expr("BLOCK") ]),
+            Lambda("WHILE", &[2, 3]),
+        );
+        parser.define("ASSIGN",
+            SubExpr(&[ Expr("IDENT"), Token("assign", ""), Expr("CMP") ]),
+            Lambda("SET_IDENT", &[1, 3]),
+        );
+        parser.define("IDENT", Token("ident", ""), EvalToken);
+
+        parser.define("CMP", ExprOr(&[
+            SubExpr(&[ Expr("SUM"), Expr("CMP:OP"), Expr("SUM") ]),
+            Expr("SUM"),
+        ]), LambdaOr(&[
+            Lambda("CMP", &[1, 2, 3]),
+            Eval,
+        ]));
+        parser.define("CMP:OP", Token("op_cmp", ""), EvalToken);
+        PrecedenceBuilder::new("SUM", "VALUE")
+            .level(&[("+", "ADD"), ("-", "SUB")], Assoc::Left)
+            .level(&[("*", "MULT"), ("/", "DIV"), ("//", "FLOOR_DIV"), ("%", "MOD")], Assoc::Left)
+            .build(&mut parser);
+        parser.define("VALUE", ExprOr(&[
+            SubExpr(&[ Token("punct", "("), Expr("CMP"), Token("punct", ")") ]),
+            SubExpr(&[ Token("op_add", "-"), Expr("VALUE") ]),
+            Expr("PRINT"),
+            Expr("CALL"),
+            Expr("NUM"),
+            Expr("BOOL"),
+            Token("kw:null", ""),
+            Token("string", ""),
+            Token("char", ""),
+            Expr("VAR"),
+        ]), LambdaOr(&[
+            GetExpr(2, &Eval),
+            Lambda("NEG", &[2]),
+            Eval,
+            Eval,
+            Eval,
+            Eval,
+            EvalAs("NULL"),
+            EvalAs("STRING"),
+            EvalAs("CHAR"),
+            Eval,
+        ]));
+        parser.define("PRINT",
+            SubExpr(&[ Token("ident", "print"), Token("punct", "("), SepBy(&Expr("CMP"), &Token("punct", ",")), Token("punct", ")") ]),
+            GetExpr(3, &EvalAs("PRINT")),
+        );
+        parser.define("CALL",
+            SubExpr(&[ Expr("IDENT"), Token("punct", "("), SepBy(&Expr("CMP"), &Token("punct", ",")), Token("punct", ")") ]),
+            EvalAs("CALL"),
+        );
+        parser.define("NUM", ExprOr(&[
+            Token("float", ""),
+            Token("int", ""),
+        ]), LambdaOr(&[
+            EvalAs("FLOAT"),
+            EvalAs("INTEGER"),
+        ]));
+        parser.define("BOOL", ExprOr(&[
+            Token("kw:true", ""),
+            Token("kw:false", ""),
+        ]), LambdaOr(&[
+            EvalAs("TRUE"),
+            EvalAs("FALSE"),
+        ]));
+        parser.define("VAR", Expr("IDENT"), Lambda("GET_IDENT", &[1]));
+        parser
+    }
+
+    /// Get the calc environment, the arithmetic, reading variables, `print` and calls are
+    /// the lambdas of the math [`module`](crate::lang::math::module).
+    pub fn env() -> VirtualEnv {
+        use crate::exec::StateNode::*;
+        use crate::exec::Exec;
+        let mut env = VirtualEnv::new();
+        env.register_module(math::module()).expect("a new environment has no modules");
+
+        env.define_pure("NONE", |_, | StateNode::None);
+        env.define_pure("SEQ", |mut frame, | {
+            match frame.eval_arg(1) {
+                err @ RuntimeErr(_) => err,
+                _ => frame.eval_arg(2),
+            }
+        });
+        env.define("FN", |mut frame, | {
+            let node = frame.node();
+            let (Some(name), Some(params), Some(body)) = (node.nodes.get(1).and_then(leaf), node.nodes.get(3), node.nodes.get(5)) else {
+                return RuntimeErr(format!("Could not define a function from `{node}`").into());
+            };
+            let params = match params.nodes.iter().map(leaf).collect::<Option<Vec<_>>>() {
+                Some(params) => params,
+                Option::None => return RuntimeErr(format!("Could not read the parameters of `{name}`").into()),
+            };
+            frame.set_ident(&name, NodeValue::Function(Function { params, body: Arc::new(body.clone()) }));
+            StateNode::None
+        });
+        env.define_pure("IF", |mut frame, | {
+            match frame.eval_arg(1) {
+                err @ RuntimeErr(_) => err,
+                Value(NodeValue::Boolean(true)) => frame.eval_arg(2),
+                Value(NodeValue::Boolean(false)) if frame.arg_count() > 2 => frame.eval_arg(3),
+                Value(NodeValue::Boolean(false)) => StateNode::None,
+                cond => RuntimeErr(format!("The condition of an `if` must be a Boolean, got `{cond:?}`").into()),
+            }
+        });
+        env.define("WHILE", |mut frame, | {
+            loop {
+                match frame.eval_arg(1) {
+                    err @ RuntimeErr(_) => return err,
+                    Value(NodeValue::Boolean(true)) => {
+                        if let err @ RuntimeErr(_) = frame.eval_arg(2) {
+                            return err;
+                        }
+                    },
+                    Value(NodeValue::Boolean(false)) => return StateNode::None,
+                    cond => return RuntimeErr(format!("The condition of a `while` must be a Boolean, got `{cond:?}`").into()),
+                }
+            }
+        });
+        // `=` sets the variable in the current scope and gives the value
+        env.define("SET_IDENT", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(err @ RuntimeErr(_), _) | Exec::BinExpr(_, err @ RuntimeErr(_)) => err,
+                Exec::BinExpr(ident, value) => match ident.into_ident() {
+                    Ok(ident) => {
+                        let value = value.as_node_value();
+                        frame.set_ident(&ident, value.clone());
+                        Value(value)
+                    },
+                    Err(err) => RuntimeErr(err),
+                },
+                _ => RuntimeErr(format!("SET_IDENT expects 2 arguments, got {}", frame.arg_count()).into()),
+            }
+        });
+        env.define_pure("CMP", |mut frame, | {
+            match frame.eval() {
+                Exec::TriExpr(err @ RuntimeErr(_), _, _) | Exec::TriExpr(_, _, err @ RuntimeErr(_)) => err,
+                Exec::TriExpr(lhs, op, rhs) => {
+                    let (lhs, rhs) = (lhs.as_node_value(), rhs.as_node_value());
+                    let ordering = lhs.partial_cmp(&rhs);
+                    let result = match op.as_ident() {
+                        NodeValue::Ident(op) if op == "==" => Some(lhs == rhs),
+                        NodeValue::Ident(op) if op == "!=" => Some(lhs != rhs),
+                        NodeValue::Ident(op) if op == "<" => ordering.map(|ord| ord.is_lt()),
+                        NodeValue::Ident(op) if op == "<=" => ordering.map(|ord| ord.is_le()),
+                        NodeValue::Ident(op) if op == ">" => ordering.map(|ord| ord.is_gt()),
+                        NodeValue::Ident(op) if op == ">=" => ordering.map(|ord| ord.is_ge()),
+                        op => return RuntimeErr(format!("Unknown comparison `{op:?}`").into()),
+                    };
+                    match result {
+                        Some(result) => Value(NodeValue::Boolean(result)),
+                        Option::None => RuntimeErr(format!("Cannot compare `{lhs:?}` and `{rhs:?}`").into()),
+                    }
+                },
+                _ => RuntimeErr("Something cmp".into()),
+            }
+        });
+        // calc reads floats at double precision, unlike math
+        env.define_pure("FLOAT", |mut frame, | {
+            frame.eval_as::<f64>()
+        });
+        env.define_pure("TRUE", |_, | Value(NodeValue::Boolean(true)));
+        env.define_pure("FALSE", |_, | Value(NodeValue::Boolean(false)));
+
+        env
+    }
+
+    /// Get the value of a token node.
+    fn leaf(node: &TreeNode) -> Option<String> {
+        node.leaf.as_ref().map(|token| token.value.clone())
+    }
+
+    /// Remove the quotes from a string and replace its escape sequences.
+    fn unquote(raw: &str) -> String {
+        let mut value = String::new();
+        let mut chars = raw[1..raw.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c) => value.push(c),
+                    None => value.push(c),
+                },
+                c => value.push(c),
+            }
+        }
+        value
+    }
+}
+
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use crate::exec::{Executor, NodeValue};
+    use crate::lexer::LineReader;
+
+    /// make an executor that collects printed lines.
+    fn collecting() -> (Executor<'static>, Arc<Mutex<Vec<String>>>) {
+        let lines = Arc::new(Mutex::new(vec![]));
+        let mut executor = exec();
+        let sink = lines.clone();
+        executor.env_mut().set_output(Box::new(move |line| sink.lock().unwrap().push(line.to_string())));
+        (executor, lines)
+    }
+
+    /// chars are only equal to the same char.
+    #[test]
+    fn char_equality() -> Result<(), String> {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("'a' == 'a'")?, "true");
+        assert_eq!(executor.exec_str("'a' != 'b'")?, "true");
+        assert_eq!(executor.exec_str("'a' == \"a\"")?, "false");
+        executor.exec_str("c = '\\t'")?;
+        assert_eq!(executor.exec_str("c == '\\t'")?, "true");
+        assert!(executor.exec_str("'a' + 'b'").is_err());
+        Ok(())
+    }
+
+    /// `null` is only equal to itself.
+    #[test]
+    fn null_equality() -> Result<(), String> {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("null == null")?, "true");
+        assert_eq!(executor.exec_str("null == 0")?, "false");
+        executor.exec_str("n = null")?;
+        assert_eq!(executor.get_var("n"), Some(&NodeValue::Null));
+        assert_eq!(executor.exec_str("n != null")?, "false");
+        Ok(())
+    }
+
+    /// a multi-line script with a recursive function, a loop and prints.
+    #[test]
+    fn fibonacci_script() -> Result<(), String> {
+        let (mut executor, lines) = collecting();
+        let script = r#"
+            # print the first fibonacci numbers
+            fn fib(n) {
+                if n < 2 { n } else { fib(n - 1) + fib(n - 2) }
+            }
+
+            i = 0
+            while i < 8 {
+                print(i, fib(i))
+                i = i + 1
+            }
+            fib(15)
+        "#;
+        let result = executor.exec_all(&mut LineReader::new(script)).map_err(|err| err.to_string())?;
+        assert_eq!(result, "610");
+        assert_eq!(*lines.lock().unwrap(), ["0 0", "1 1", "2 1", "3 2", "4 3", "5 5", "6 8", "7 13"]);
+        // the parameter doesn't leak out of the call
+        assert!(executor.get_var("n").is_none());
+        assert_eq!(executor.get_var("i"), Some(&NodeValue::Integer(8)));
+        Ok(())
+    }
+
+    /// statements can share a line, operators are left associative and comparisons
+    /// give booleans.
+    #[test]
+    fn statements() -> Result<(), String> {
+        let (mut executor, lines) = collecting();
+        let mut run = |src: &str| executor.exec_all(&mut LineReader::new(src)).map_err(|err| err.to_string());
+        assert_eq!(run("x = 10; y = x - 2 - 3; y")?, "5");
+        assert_eq!(run("20 / 2 / 5")?, "2");
+        assert_eq!(run("10 - 2 * 3 - 1")?, "3");
+        assert_eq!(run("2 + 3 * 4 == 14")?, "true");
+        assert_eq!(run("-(1 + 2) >= 0")?, "false");
+        assert_eq!(run("if x > 5 { \"big\" } else if x > 2 { \"mid\" } else { \"small\" }")?, "big");
+        assert_eq!(run("if false { 1 }")?, "None");
+        assert_eq!(run("print(\"a\", 1.5, true); x")?, "10");
+        assert_eq!(run("z = 7")?, "7");
+        assert_eq!(*lines.lock().unwrap(), ["a 1.5 true"]);
+        Ok(())
+    }
+
+    /// a string with the text of a keyword is still a string.
+    #[test]
+    fn keyword_strings() -> Result<(), String> {
+        let (mut executor, _) = collecting();
+        let mut run = |src: &str| executor.exec_all(&mut LineReader::new(src)).map_err(|err| err.to_string());
+        assert_eq!(run("x = \"if\"; x")?, "if");
+        assert_eq!(run("\"while\" == \"while\"")?, "true");
+        Ok(())
+    }
+
+    /// every print is one line in the output and gives no value.
+    #[test]
+    fn print_lines() -> Result<(), String> {
+        let (mut executor, lines) = collecting();
+        let result = executor.exec_all(&mut LineReader::new("print(1+1, \"x\"); print(3)")).map_err(|err| err.to_string())?;
+        assert_eq!(result, "None");
+        assert_eq!(*lines.lock().unwrap(), ["2 x", "3"]);
+        Ok(())
+    }
+
+    /// runtime errors stop the script.
+    #[test]
+    fn runtime_errors() {
+        let mut executor = exec();
+        let mut run = |src: &str| executor.exec_all(&mut LineReader::new(src)).map_err(|err| err.to_string());
+        assert!(run("if 1 { 2 }").is_err());
+        assert!(run("fn f(a) { a }\nf(1, 2)").is_err());
+        assert!(run("x = 1; x()").is_err());
+        assert!(run("while missing < 3 { }").is_err());
+    }
+
+    /// the operators are the lambdas of the math module, an error in the value of an
+    /// assignment is given back and nothing is set.
+    #[test]
+    fn math_lambdas() -> Result<(), String> {
+        assert!(env().is_defined("math:ADD"));
+        let mut executor = exec();
+        let mut run = |src: &str| executor.exec_all(&mut LineReader::new(src)).map_err(|err| err.to_string());
+        assert_eq!(run("-(1 + 2) * 3 % 5")?, "1");
+        let err = run("x = missing + 1").expect_err("`missing` is not set");
+        assert!(err.contains("undefined variable 'missing'"), "got {err}");
+        assert!(executor.get_var("x").is_none());
+        Ok(())
+    }
+
+    /// a function calling itself doesn't go deeper on the stack, a runaway one stops at the
+    /// depth limit.
+    #[test]
+    fn deep_recursion() -> Result<(), String> {
+        let mut executor = exec();
+        executor.env_mut().set_depth_limit(10_000);
+        let mut run = |src: &str| executor.exec_all(&mut LineReader::new(src)).map_err(|err| err.to_string());
+        assert_eq!(run("fn f(n) { if n == 0 { 0 } else { 1 + f(n - 1) } }\nf(50)")?, "50");
+        assert_eq!(run("f(1000)")?, "1000");
+        let err = run("fn g(n) { g(n) }\ng(1)").expect_err("the recursion never ends");
+        assert!(err.contains("more than 10000 nested nodes"), "got {err}");
+        Ok(())
+    }
+}
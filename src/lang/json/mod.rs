@@ -0,0 +1,284 @@
+pub use json::*;
+
+#[allow(clippy::module_inception)]
+pub mod json {
+    use crate::parser::Parser;
+    use crate::lexer::{Lexer, LineReader, TokenDef};
+    use crate::exec::{ExecError, NodeValue, StateNode, VirtualEnv};
+
+    /// Parse JSON text into a [`NodeValue`], objects become a [`Map`](NodeValue::Map) and
+    /// arrays a [`List`](NodeValue::List). Integers that fit are an [`Integer`](NodeValue::Integer)
+    /// or [`BigInteger`](NodeValue::BigInteger), other numbers are a [`BigFloat`](NodeValue::BigFloat)
+    /// and `null` is [`Null`](NodeValue::Null).
+    ///
+    /// ---
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use interpreter::lang::json;
+    /// let value = json::parse(r#"{"a": [1, true, null]}"#)?;
+    /// assert_eq!(value.to_string()?, "{a: [1, true, null]}");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn parse(src: &str) -> Result<NodeValue, ExecError> {
+        let ast = self::parser().parse_tree(&self::lexer(), &mut LineReader::new(src))?;
+        match self::env().exec(ast) {
+            StateNode::Value(value) => Ok(value),
+            StateNode::RuntimeErr(err) => Err(ExecError::runtime(&err.to_string(), None)),
+            state => Err(ExecError::runtime(&format!("JSON did not give a value: {state:?}"), None)),
+        }
+    }
+
+    pub fn lexer() -> Lexer {
+        let mut lexer = Lexer::new();
+        if let Ok(string) = TokenDef::with_transform("string", "\"(?:[^\"\\\\\\x00-\\x1f]|\\\\[\"\\\\/bfnrt]|\\\\u[0-9a-fA-F]{4})*\"", unescape) {
+            lexer.define_token(string);
+        }
+        let _ = lexer.define("number", "-?(?:0|[1-9][0-9]*)(?:\\.[0-9]+)?(?:[eE][+-]?[0-9]+)?");
+        let _ = lexer.define("literal", "true|false|null");
+        let _ = lexer.define("punct", "\\{|\\}|\\[|\\]|:|,");
+        let _ = lexer.define_skip("space", "[ \\t\\n\\r]+");
+        lexer
+    }
+
+    pub fn parser() -> Parser<'static> {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = Parser::new();
+        // a document is a single value
+        parser.define("EXPR", SubExpr(&[ Expr("VALUE"), End ]), GetExpr(1, &Eval));
+        parser.define("VALUE", ExprOr(&[
+            Expr("OBJECT"),
+            Expr("ARRAY"),
+            Expr("STRING"),
+            Expr("NUMBER"),
+            Expr("LITERAL"),
+        ]), Eval);
+        parser.define("OBJECT",
+            SubExpr(&[ Token("punct", "{"), SepBy(&Expr("MEMBER"), &Token("punct", ",")), Token("punct", "}") ]),
+            GetExpr(2, &EvalAs("OBJECT")),
+        );
+        parser.define("MEMBER",
+            SubExpr(&[ Expr("STRING"), Token("punct", ":"), Expr("VALUE") ]),
+            Lambda("MEMBER", &[1, 3]),
+        );
+        parser.define("ARRAY",
+            SubExpr(&[ Token("punct", "["), SepBy(&Expr("VALUE"), &Token("punct", ",")), Token("punct", "]") ]),
+            GetExpr(2, &EvalAs("ARRAY")),
+        );
+        parser.define("STRING", Token("string", ""), EvalAs("STRING"));
+        parser.define("NUMBER", Token("number", ""), EvalAs("NUMBER"));
+        parser.define("LITERAL", Token("literal", ""), EvalAs("LITERAL"));
+        parser
+    }
+
+    pub fn env() -> VirtualEnv {
+        use std::collections::BTreeMap;
+        use crate::exec::StateNode::*;
+        use crate::exec::Exec;
+        let mut env = VirtualEnv::new();
+
+        env.define("STRING", |mut frame, | {
+            frame.eval_as::<String>()
+        });
+        env.define("NUMBER", |mut frame, | {
+            // the smallest integer type the number fits in, otherwise a float
+            match frame.eval_as::<i32>() {
+                int @ Value(_) => int,
+                _ => match frame.eval_as::<i128>() {
+                    int @ Value(_) => int,
+                    _ => frame.eval_as::<f64>(),
+                },
+            }
+        });
+        env.define("LITERAL", |mut frame, | {
+            match frame.eval_as::<String>().as_node_value() {
+                NodeValue::String(literal) if literal == "true" => Value(NodeValue::Boolean(true)),
+                NodeValue::String(literal) if literal == "false" => Value(NodeValue::Boolean(false)),
+                NodeValue::String(literal) if literal == "null" => Value(NodeValue::Null),
+                literal => RuntimeErr(format!("Unknown literal `{literal:?}`").into()),
+            }
+        });
+        env.define("ARRAY", |mut frame, | {
+            let mut list = vec![];
+            for value in frame.eval_nodes() {
+                match value {
+                    err @ RuntimeErr(_) => return err,
+                    value => list.push(value.as_node_value()),
+                }
+            }
+            Value(NodeValue::List(list))
+        });
+        // each member is evaluated to a `[key, value]` list for `OBJECT`
+        env.define("MEMBER", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(err @ RuntimeErr(_), _) | Exec::BinExpr(_, err @ RuntimeErr(_)) => err,
+                Exec::BinExpr(key, value) => Value(NodeValue::List(vec![key.as_node_value(), value.as_node_value()])),
+                _ => RuntimeErr("Something member".into()),
+            }
+        });
+        env.define("OBJECT", |mut frame, | {
+            let mut map = BTreeMap::new();
+            for member in frame.eval_nodes() {
+                match member {
+                    err @ RuntimeErr(_) => return err,
+                    member => match member.as_node_value() {
+                        NodeValue::List(mut member) if member.len() == 2 => {
+                            let value = member.swap_remove(1);
+                            if let NodeValue::String(key) = member.swap_remove(0) {
+                                // the last duplicate key wins
+                                map.insert(key, value);
+                            }
+                        },
+                        member => return RuntimeErr(format!("Could not use `{member:?}` as an Object member").into()),
+                    },
+                }
+            }
+            Value(NodeValue::Map(map))
+        });
+
+        env
+    }
+
+    /// Remove the quotes from a JSON string and replace its escape sequences, half of a
+    /// surrogate pair without the other half becomes `U+FFFD`.
+    fn unescape(raw: &str) -> String {
+        let mut value = String::new();
+        let mut chars = raw[1..raw.len() - 1].chars();
+        let mut high: Option<u32> = None;
+        while let Some(c) = chars.next() {
+            let escape = match c {
+                '\\' => chars.next(),
+                _ => None,
+            };
+            let code = match escape {
+                Some('u') => u32::from_str_radix(&chars.by_ref().take(4).collect::<String>(), 16).ok(),
+                _ => None,
+            };
+            // a low surrogate can only follow a high surrogate
+            if let Some(prev) = high.take() {
+                match code {
+                    Some(low @ 0xDC00..=0xDFFF) => {
+                        let code = 0x10000 + ((prev - 0xD800) << 10) + (low - 0xDC00);
+                        value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        continue;
+                    },
+                    _ => value.push(char::REPLACEMENT_CHARACTER),
+                }
+            }
+            match (escape, code) {
+                (Some('u'), Some(code @ 0xD800..=0xDBFF)) => high = Some(code),
+                (Some('u'), Some(code)) => value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)),
+                (Some('b'), _) => value.push('\u{8}'),
+                (Some('f'), _) => value.push('\u{c}'),
+                (Some('n'), _) => value.push('\n'),
+                (Some('r'), _) => value.push('\r'),
+                (Some('t'), _) => value.push('\t'),
+                (Some(c), _) => value.push(c),
+                (None, _) => value.push(c),
+            }
+        }
+        if high.is_some() {
+            value.push(char::REPLACEMENT_CHARACTER);
+        }
+        value
+    }
+}
+
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::exec::{ExecErrorKind, NodeValue};
+
+    /// make a map from its entries.
+    fn map<const N: usize>(entries: [(&str, NodeValue); N]) -> NodeValue {
+        NodeValue::Map(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect::<BTreeMap<_, _>>())
+    }
+
+    /// objects and arrays nest inside each other.
+    #[test]
+    fn nested_document() -> Result<(), String> {
+        let value = parse(r#"
+            {
+                "name": "json",
+                "tags": ["a", [], {}],
+                "nested": {"ok": true, "no": false, "nothing": null},
+                "count": 3
+            }
+        "#)?;
+        let expected = map([
+            ("name", NodeValue::String("json".into())),
+            ("tags", NodeValue::List(vec![
+                NodeValue::String("a".into()),
+                NodeValue::List(vec![]),
+                map([]),
+            ])),
+            ("nested", map([
+                ("ok", NodeValue::Boolean(true)),
+                ("no", NodeValue::Boolean(false)),
+                ("nothing", NodeValue::Null),
+            ])),
+            ("count", NodeValue::Integer(3)),
+        ]);
+        assert_eq!(value, expected);
+        // any value can be the whole document
+        assert_eq!(parse("null")?, NodeValue::Null);
+        assert_eq!(parse(" \"text\" ")?, NodeValue::String("text".into()));
+        Ok(())
+    }
+
+    /// every escape sequence is replaced, including surrogate pairs.
+    #[test]
+    fn string_escapes() -> Result<(), String> {
+        let value = parse(r#""\"\\\/\b\f\n\r\t\u0041\u00e9\ud83d\ude00""#)?;
+        assert_eq!(value, NodeValue::String("\"\\/\u{8}\u{c}\n\r\tAé😀".into()));
+        // a lone surrogate can't be a char
+        assert_eq!(parse(r#""\ud83dx""#)?, NodeValue::String("\u{FFFD}x".into()));
+        // control characters must be escaped
+        assert!(parse("\"a\nb\"").is_err());
+        assert!(parse(r#""\x""#).is_err());
+        Ok(())
+    }
+
+    /// numbers use the smallest integer type that fits, otherwise a float.
+    #[test]
+    fn numbers() -> Result<(), String> {
+        let value = parse("[0, -12, 12345678901, 1.5, 1e3, -2.5E-2, 6.02e+23]")?;
+        let NodeValue::List(list) = value else { return Err(format!("expected a list, got {value:?}")) };
+        assert!(matches!(list[0], NodeValue::Integer(0)));
+        assert!(matches!(list[1], NodeValue::Integer(-12)));
+        assert!(matches!(list[2], NodeValue::BigInteger(12345678901)));
+        assert!(matches!(list[3], NodeValue::BigFloat(float) if float == 1.5));
+        assert!(matches!(list[4], NodeValue::BigFloat(float) if float == 1000.0));
+        assert!(matches!(list[5], NodeValue::BigFloat(float) if float == -0.025));
+        assert!(matches!(list[6], NodeValue::BigFloat(float) if float == 6.02e23));
+        // leading zeros and bare fractions aren't JSON
+        assert!(parse("01").is_err());
+        assert!(parse(".5").is_err());
+        Ok(())
+    }
+
+    /// malformed documents point at the token that broke them, a trailing comma is an
+    /// error at the missing value.
+    #[test]
+    fn error_positions() {
+        let cases = [
+            ("[1, 2,]", (0, 6)),
+            ("{\"a\": 1,}", (0, 8)),
+            ("{\"a\" 1}", (0, 5)),
+            ("[\n  1\n  2\n]", (2, 2)),
+            ("[1] 2", (0, 4)),
+        ];
+        for (src, (line, col)) in cases {
+            let err = parse(src).expect_err(&format!("`{src}` should not parse"));
+            assert_eq!(err.kind, ExecErrorKind::Parse, "{src}: {err}");
+            let position = err.position.clone().expect("parse errors have a position");
+            assert_eq!(position.start_line_col(), (line, col), "{src}: {err}");
+        }
+    }
+}
@@ -1,144 +1,1426 @@
 
 pub use math::*;
 
+#[allow(clippy::module_inception)]
 pub mod math {
-    use crate::parser::Parser;
-    use crate::lexer::Lexer;
-    use crate::exec::{Executor, NodeValue, StateNode, VirtualEnv};
+    use crate::parser::{Assoc, ParseError, Parser, PrecedenceBuilder};
+    use crate::parser::syntax::TreeNode;
+    use crate::lexer::{Lexer, TokenDef};
+    use std::cmp::Ordering as CmpOrdering;
+    use std::sync::Arc;
+    use once_cell::sync::Lazy;
+    use crate::exec::{builtin, types, Args, Arity, DivisionMode, Executor, Module, NodeValue, StateNode, ValueType, VirtualEnv};
+
+    /// Reject an assignment unless the left side is a lone identifier.
+    fn check_assign(node: &TreeNode) -> Result<(), ParseError> {
+        let Some(target) = node.nodes.first() else { return Ok(()) };
+        if target.leaf.as_ref().is_some_and(|token| token.token_type == "ident") {
+            return Ok(());
+        }
+        match target.span() {
+            Some(position) => Err(ParseError::new("left side of assignment must be an identifier", position)),
+            None => Ok(()),
+        }
+    }
 
     pub fn exec() -> Executor<'static> {
-        Executor::new(self::lexer(), self::parser(), self::env())
+        let (lexer, parser) = self::grammar();
+        Executor::from_shared(lexer, parser, self::env())
+    }
+
+    /// Make an executor where `/` divides integers into exact fractions, so `1/3 + 1/6`
+    /// is `1/2` - see [`DivisionMode::Rational`]. `float(x)` converts a fraction to a float.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// let mut executor = math::exec_rational();
+    /// assert_eq!(executor.exec_str("1/3 + 1/6"), Ok("1/2".into()));
+    /// assert_eq!(executor.exec_str("float(1/4)"), Ok("0.25".into()));
+    /// ```
+    pub fn exec_rational() -> Executor<'static> {
+        let (lexer, parser) = self::grammar();
+        let mut env = self::env();
+        env.set_division_mode(DivisionMode::Rational);
+        Executor::from_shared(lexer, parser, env)
+    }
+
+    /// Make an executor that reads float literals as exact decimals, so `0.1 + 0.2` is
+    /// `0.3` - see [`prefer_decimal`].
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// let mut executor = math::exec_decimal();
+    /// assert_eq!(executor.exec_str("0.1 + 0.2"), Ok("0.3".into()));
+    /// assert_eq!(executor.exec_str("1.10 * 3"), Ok("3.3".into()));
+    /// ```
+    #[cfg(feature = "decimal")]
+    pub fn exec_decimal() -> Executor<'static> {
+        let mut parser = self::parser();
+        prefer_decimal(&mut parser, true);
+        let mut env = self::env();
+        env.register_module(decimals()).expect("the math environment has no decimal module");
+        Executor::new(self::lexer(), parser, env)
+    }
+
+    /// Switch the float literals of a math `parser` to be read as decimals with the
+    /// `DECIMAL` lambda of the [`decimals`] module, or back to floats.
+    #[cfg(feature = "decimal")]
+    pub fn prefer_decimal(parser: &mut Parser<'static>, prefer: bool) {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let lambda = match prefer {
+            true => LambdaOr(&[EvalAs("DECIMAL"), EvalAs("INTEGER")]),
+            false => LambdaOr(&[EvalAs("FLOAT"), EvalAs("INTEGER")]),
+        };
+        parser.define("NUM", ExprOr(&[
+            Token("float", ""),
+            Token("int", ""),
+        ]), lambda);
+    }
+
+    /// Get the lexer and parser shared by every executor from [`exec`], they are compiled
+    /// the first time they are used.
+    pub fn grammar() -> (Arc<Lexer>, Arc<Parser<'static>>) {
+        static GRAMMAR: Lazy<(Arc<Lexer>, Arc<Parser<'static>>)> = Lazy::new(|| (Arc::new(self::lexer()), Arc::new(self::parser())));
+        (GRAMMAR.0.clone(), GRAMMAR.1.clone())
     }
 
     pub fn lexer() -> Lexer {
         let mut lexer = Lexer::new();
-        let _ = lexer.define("op", "\\+|\\-|\\*|\\/|\\(|\\)");
+        let _ = lexer.define_literals("op_assign", &["+=", "-=", "*=", "/="]);
+        let _ = lexer.define_literals("op", &["+", "-", "*", "//", "/", "%", "(", ")", "[", "]", "{", "}", ".", ","]);
         let _ = lexer.define("float", "[0-9]+\\.[0-9]+");
         let _ = lexer.define("int", "[0-9]+");
-        let _ = lexer.define("assign", "\\:\\=|\\=");
+        let _ = lexer.define_literal("declare", ":=");
+        let _ = lexer.define_literal("assign", "=");
         let _ = lexer.define("ident", "[a-zA-Z_]+");
+        lexer.define_keyword("kw:null", "null");
+        let _ = lexer.define_literal("colon", ":");
+        let _ = lexer.define_literal("sep", ";");
+        if let Ok(string) = TokenDef::with_transform("string", "\"[^\"\\n]*\"", |raw| raw[1..raw.len() - 1].to_string()) {
+            lexer.define_token(string);
+        }
+        if let Ok(char) = TokenDef::with_transform("char", "'(?:[^'\\\\\\n]|\\\\.)'", unescape_char) {
+            lexer.define_token(char);
+        }
+        let _ = lexer.define_skip("space", "\\s+");
         lexer
     }
 
+    /// Take the char out of a `'x'` literal, `\n` and `\t` are escapes and a `\` before
+    /// any other char is that char - like `'\''`.
+    fn unescape_char(raw: &str) -> String {
+        let inner = &raw[1..raw.len() - 1];
+        match inner.strip_prefix('\\') {
+            Some("n") => "\n".into(),
+            Some("t") => "\t".into(),
+            Some(escaped) => escaped.into(),
+            None => inner.into(),
+        }
+    }
+
     pub fn parser() -> Parser<'static> {
         use crate::parser::syntax::Expression::*;
         use crate::exec::syntax::Lambda::*;
         let mut parser = Parser::new();
-        let _ = parser.define("EXPR", Expr("MATH:EXPR"), Eval);
-        let _ = parser.define("EXPR", ExprOr(&[
+        parser.define("EXPR", Expr("MATH:EXPR"), Eval);
+        parser.define("EXPR", ExprOr(&[
             Expr("ASSIGN"),
             Expr("MATH:EXPR"),
         ]), Eval);
-        let _ = parser.define("ASSIGN", 
-            SubExpr(&[Expr("IDENT"), Token("assign", ""), Expr("MATH:EXPR")]),
-            Lambda("SET_IDENT", &[1, 3])
-        );
-        let _ = parser.define("IDENT", Token("ident", ""), EvalToken);
-        
-        let _ = parser.define("MATH:EXPR", ExprOr(&[
-            SubExpr(&[ Expr("TERM"), Token("op", "+"), Expr("MATH:EXPR") ]),
-            SubExpr(&[ Expr("TERM"), Token("op", "-"), Expr("MATH:EXPR") ]),
-            Expr("TERM"),
-        ]), LambdaOr(&[
-            Lambda("ADD", &[1, 3]),
-            Lambda("SUB", &[1, 3]),
-            Eval,
-        ]));
-        let _ = parser.define("TERM", ExprOr(&[
-            SubExpr(&[ Expr("FACTOR"), Token("op", "*"), Expr("TERM") ]),
-            Expr("FACTOR"),
-        ]), LambdaOr(&[
-            Lambda("MULT", &[1, 3]),
-            Eval,
-        ]));
-        let _ = parser.define("FACTOR", ExprOr(&[
-            SubExpr(&[ Expr("VALUE"), Token("op", "/"), Expr("FACTOR") ]),
-            Expr("VALUE"),
+        // `:=` declares in the current scope and `=` changes the variable where it was
+        // declared
+        parser.define_with_check("ASSIGN", ExprOr(&[
+            SubExpr(&[Expr("IDENT"), Token("op", "."), Expr("IDENT"), Token("assign", ""), Expr("MATH:EXPR")]),
+            SubExpr(&[Expr("IDENT"), Expr("ASSIGN:OP"), Expr("MATH:EXPR")]),
+            SubExpr(&[Expr("ASSIGN:TARGET"), Token("declare", ""), Expr("EXPR")]),
+            SubExpr(&[Expr("ASSIGN:TARGET"), Token("assign", ""), Expr("EXPR")]),
         ]), LambdaOr(&[
-            Lambda("DIV", &[1, 3]),
-            Eval,
-        ]));
-        let _ = parser.define("VALUE", ExprOr(&[
-            SubExpr(&[ Token("op", "("), Expr("MATH:EXPR"), Token("op", ")")]),
+            Lambda("MAP_SET", &[1, 3, 5]),
+            Lambda("OP_ASSIGN", &[1, 2, 3]),
+            Lambda("DECLARE", &[1, 3]),
+            Lambda("ASSIGN", &[1, 3]),
+        ]), check_assign);
+        parser.define("ASSIGN:OP", Token("op_assign", ""), EvalToken);
+        // an identifier being assigned to, anything else is read as an expression so the
+        // check on `ASSIGN` can reject it with a clear error
+        parser.define("ASSIGN:TARGET", ExprOr(&[
+            SubExpr(&[Not(&SubExpr(&[Expr("IDENT"), ExprOr(&[Token("declare", ""), Token("assign", "")])])), Expr("MATH:EXPR")]),
+            Expr("IDENT"),
+        ]), Eval);
+        parser.define("IDENT", Token("ident", ""), EvalToken);
+        
+        PrecedenceBuilder::new("MATH:EXPR", "VALUE")
+            .level(&[("+", "ADD"), ("-", "SUB")], Assoc::Left)
+            .level(&[("*", "MULT"), ("/", "DIV"), ("//", "FLOOR_DIV"), ("%", "MOD")], Assoc::Left)
+            .build(&mut parser);
+        // any value can be indexed, `a[0][1]` indexes the result of `a[0]`
+        parser.define_postfix("VALUE", "VALUE:ATOM",
+            SubExpr(&[ Token("op", "["), Expr("MATH:EXPR"), Token("op", "]") ]),
+            Lambda("INDEX", &[1, 3]),
+        );
+        parser.define("VALUE:ATOM", ExprOr(&[
+            SubExpr(&[ Expr("VAR"), Token("op", "."), Expr("IDENT") ]),
+            SubExpr(&[ Token("op", "("), Expr("EXPR"), Token("op", ")")]),
+            SubExpr(&[ Token("op", "-"), Expr("VALUE") ]),
+            Expr("LIST"),
+            Expr("MAP"),
+            Expr("BLOCK"),
+            SubExpr(&[ Token("ident", "print"), Token("op", "("), SepBy(&Expr("MATH:EXPR"), &Token("op", ",")), Token("op", ")") ]),
+            SubExpr(&[ Token("ident", "int"), Token("op", "("), Expr("MATH:EXPR"), Token("op", ")") ]),
+            SubExpr(&[ Token("ident", "float"), Token("op", "("), Expr("MATH:EXPR"), Token("op", ")") ]),
+            SubExpr(&[ Token("ident", "str"), Token("op", "("), Expr("MATH:EXPR"), Token("op", ")") ]),
+            SubExpr(&[ Token("ident", "clamp"), Token("op", "("), Expr("MATH:EXPR"), Token("op", ","), Expr("MATH:EXPR"), Token("op", ","), Expr("MATH:EXPR"), Token("op", ")") ]),
+            SubExpr(&[ Expr("IDENT"), Token("op", "("), SepBy(&Expr("MATH:EXPR"), &Token("op", ",")), Token("op", ")") ]),
+            Token("kw:null", ""),
             Expr("NUM"),
+            Token("string", ""),
+            Token("char", ""),
             Expr("VAR"),
         ]), LambdaOr(&[
+            Lambda("MAP_GET", &[1, 3]),
             GetExpr(2, &Eval),
+            Lambda("NEG", &[2]),
+            Eval,
+            Eval,
+            Eval,
+            GetExpr(3, &EvalAs("PRINT")),
+            Lambda("TO_INT", &[3]),
+            Lambda("TO_FLOAT", &[3]),
+            Lambda("TO_STRING", &[3]),
+            Lambda("CLAMP", &[3, 5, 7]),
+            EvalAs("CALL"),
+            EvalAs("NULL"),
             Eval,
+            EvalAs("STRING"),
+            EvalAs("CHAR"),
             Eval,
         ]));
-        let _ = parser.define("NUM", ExprOr(&[
+        parser.define("LIST",
+            SubExpr(&[ Token("op", "["), SepBy(&Expr("MATH:EXPR"), &Token("op", ",")), Token("op", "]") ]),
+            GetExpr(2, &EvalAs("LIST_NEW")),
+        );
+        parser.define("MAP",
+            SubExpr(&[ Token("op", "{"), SepBy(&Expr("MAP:ENTRY"), &Token("op", ",")), Token("op", "}") ]),
+            GetExpr(2, &EvalAs("MAP_NEW")),
+        );
+        // a block runs its expressions in a new scope and gives the value of the last one,
+        // `{}` is an empty map
+        parser.define("BLOCK",
+            SubExpr(&[ Token("op", "{"), SepBy(&Expr("EXPR"), &Token("sep", ";")), Token("op", "}") ]),
+            GetExpr(2, &EvalAs("BLOCK")),
+        );
+        // each entry is evaluated to a `[key, value]` list for `MAP_NEW`
+        parser.define("MAP:ENTRY",
+            SubExpr(&[ Expr("IDENT"), Token("colon", ""), Expr("MATH:EXPR") ]),
+            Lambda("MAP_ENTRY", &[1, 3]),
+        );
+        parser.define("NUM", ExprOr(&[
             Token("float", ""),
             Token("int", ""),
         ]), LambdaOr(&[
             EvalAs("FLOAT"),
             EvalAs("INTEGER"),
         ]));
-        let _ = parser.define("VAR", Expr("IDENT"), Lambda("GET_IDENT", &[1]));
+        parser.define("VAR", Expr("IDENT"), Lambda("GET_IDENT", &[1]));
         parser
     }
 
-    pub fn env() -> VirtualEnv {
-        use crate::exec::syntax::Lambda::*;
+    /// Get the lambdas of the math language as the `math` [`Module`].
+    pub fn module() -> Module {
+        use std::collections::BTreeMap;
         use crate::exec::StateNode::*;
         use crate::exec::Exec;
-        let mut env = VirtualEnv::new();
+        let mut module = Module::new("math");
        
-        env.define("ADD", |mut frame, | {
+        module.define_pure("ADD", |mut frame, | {
             match frame.eval() {
                 Exec::BinExpr(lhs, rhs) => lhs + rhs,
                 _ => RuntimeErr("Something add".into()),
             }
         });
-        env.define("SUB", |mut frame, | {
+        module.define_pure("SUB", |mut frame, | {
             match frame.eval() {
                 Exec::BinExpr(lhs, rhs) => lhs - rhs,
                 _ => RuntimeErr("Something sub".into()),
             }
         });
-        env.define("MULT", |mut frame, | {
+        module.define_pure("MULT", |mut frame, | {
             match frame.eval() {
                 Exec::BinExpr(lhs, rhs) => lhs * rhs,
                 _ => RuntimeErr("Something mult".into()),
             }
         });
-        env.define("DIV", |mut frame, | {
+        module.define_pure("DIV", |mut frame, | {
             match frame.eval() {
-                Exec::BinExpr(lhs, rhs) => lhs / rhs,
+                Exec::BinExpr(lhs, rhs) => lhs.div_with(rhs, frame.division_mode()),
                 _ => RuntimeErr("Something div".into()),
             }
         });
-        env.define("INTEGER", |frame, | {
+        module.define_pure("FLOOR_DIV", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => lhs.floor_div(rhs),
+                _ => RuntimeErr("Something floor div".into()),
+            }
+        });
+        module.define_pure("MOD", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => lhs.modulo(rhs),
+                _ => RuntimeErr("Something mod".into()),
+            }
+        });
+        module.define_pure("NEG", |mut frame, | {
+            match frame.eval() {
+                Exec::UniExpr(value) => -value,
+                _ => RuntimeErr("Something negate".into()),
+            }
+        });
+        module.define_pure("CLAMP", |mut frame, | {
+            match frame.eval() {
+                Exec::TriExpr(value, lo, hi) => clamp(value, lo, hi),
+                _ => RuntimeErr("Something clamp".into()),
+            }
+        });
+        module.define_pure("INTEGER", |mut frame, | {
             frame.eval_as::<i32>()
         });
-        env.define("FLOAT", |frame, | {
+        module.define_pure("FLOAT", |mut frame, | {
             frame.eval_as::<f32>()
         });
-        env.define("GET_IDENT", |mut frame, | {
+        module.define_pure("STRING", |mut frame, | {
+            frame.eval_as::<String>()
+        });
+        module.define_pure("CHAR", |mut frame, | {
+            frame.eval_as::<char>()
+        });
+        module.define_pure("NULL", |_, | Value(NodeValue::Null));
+        // casts of an evaluated value, unlike `EvalAs` on a token
+        module.define_pure("TO_INT", |mut frame, | {
             match frame.eval() {
-                Exec::UniExpr(ident) => {
-                    if let NodeValue::Ident(ident) = ident.as_ident() {
-                        return frame.get_ident(&ident);
-                    }
-                    return RuntimeErr(format!("Could not get Identifier `{ident:?}`"));
+                Exec::UniExpr(value) => value.to_int(),
+                _ => RuntimeErr("Something int".into()),
+            }
+        });
+        module.define_pure("TO_FLOAT", |mut frame, | {
+            match frame.eval() {
+                Exec::UniExpr(value) => value.to_float(),
+                _ => RuntimeErr("Something float".into()),
+            }
+        });
+        module.define_pure("TO_STRING", |mut frame, | {
+            match frame.eval() {
+                Exec::UniExpr(err @ RuntimeErr(_)) => err,
+                Exec::UniExpr(value) => match frame.display(&value.as_node_value()) {
+                    Ok(string) => Value(NodeValue::String(string)),
+                    Err(err) => RuntimeErr(err.into()),
+                },
+                _ => RuntimeErr("Something str".into()),
+            }
+        });
+        // each value is written to the env's output on one line
+        module.define("PRINT", builtin::print);
+        module.define("GET_IDENT", |mut frame, | {
+            match frame.eval() {
+                Exec::UniExpr(ident) => match ident.into_ident() {
+                    Ok(ident) => frame.get_ident(&ident),
+                    Err(err) => RuntimeErr(err),
                 },
-                _ => RuntimeErr("Something get ident".into()),
+                _ => RuntimeErr(format!("GET_IDENT expects 1 argument, got {}", frame.arg_count()).into()),
             }
         });
-        env.define("SET_IDENT", |mut frame, | {
+        // `:=` declares the variable in the current scope - either assignment gives the
+        // value so they can be chained
+        module.define("DECLARE", |mut frame, | {
             match frame.eval() {
+                Exec::BinExpr(_, err @ RuntimeErr(_)) => err,
                 Exec::BinExpr(ident, value) => {
-                    if let NodeValue::Ident(ident) = ident.as_ident() {
-                        frame.set_ident(&ident, value.as_node_value());
-                    } else {
-                        return RuntimeErr(format!("Could not set Identifier `{ident:?}`"));
+                    let ident = match ident.into_ident() {
+                        Ok(ident) => ident,
+                        Err(err) => return RuntimeErr(err),
+                    };
+                    let value = value.as_node_value();
+                    frame.set_ident(&ident, value.clone());
+                    Value(value)
+                },
+                _ => RuntimeErr("Something declare".into()),
+            }
+        });
+        // `=` changes the variable in the nearest scope that declared it
+        module.define("ASSIGN", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(_, err @ RuntimeErr(_)) => err,
+                Exec::BinExpr(ident, value) => {
+                    let ident = match ident.into_ident() {
+                        Ok(ident) => ident,
+                        Err(err) => return RuntimeErr(err),
+                    };
+                    let value = value.as_node_value();
+                    match frame.assign_ident(&ident, value.clone()) {
+                        Ok(()) => Value(value),
+                        Err(err) => RuntimeErr(err),
+                    }
+                },
+                _ => RuntimeErr("Something assign".into()),
+            }
+        });
+        module.define("BLOCK", |mut frame, | {
+            frame.push_scope();
+            let mut result = StateNode::None;
+            for node in &frame.node().nodes {
+                result = frame.eval_tree(node);
+                if let RuntimeErr(_) = result {
+                    break;
+                }
+            }
+            frame.pop_scope();
+            result
+        });
+        module.define_pure("LIST_NEW", |mut frame, | {
+            let mut list = vec![];
+            for value in frame.eval_nodes() {
+                match value {
+                    err @ RuntimeErr(_) => return err,
+                    value => list.push(value.as_node_value()),
+                }
+            }
+            Value(NodeValue::List(list))
+        });
+        module.define_pure("INDEX", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(err @ RuntimeErr(_), _) | Exec::BinExpr(_, err @ RuntimeErr(_)) => err,
+                Exec::BinExpr(list, index) => match (list.as_node_value(), index.as_node_value()) {
+                    (NodeValue::List(list), NodeValue::Integer(index)) => index_list(list, index as i128),
+                    (NodeValue::List(list), NodeValue::BigInteger(index)) => index_list(list, index),
+                    (NodeValue::List(_), index) => RuntimeErr(format!("Cannot index a List with `{index:?}`").into()),
+                    (value, _) => RuntimeErr(format!("Cannot index `{value:?}`, it is not a List").into()),
+                },
+                _ => RuntimeErr("Something index".into()),
+            }
+        });
+        module.define("OP_ASSIGN", |mut frame, | {
+            match frame.eval() {
+                Exec::TriExpr(_, _, err @ RuntimeErr(_)) => err,
+                Exec::TriExpr(ident, op, value) => {
+                    let NodeValue::Ident(ident) = ident.as_ident() else {
+                        return RuntimeErr(format!("Could not set Identifier `{ident:?}`").into());
+                    };
+                    let current = match frame.get_ident(&ident) {
+                        err @ RuntimeErr(_) => return err,
+                        current => current,
+                    };
+                    let result = match op.as_ident() {
+                        NodeValue::Ident(op) if op == "+=" => current + value,
+                        NodeValue::Ident(op) if op == "-=" => current - value,
+                        NodeValue::Ident(op) if op == "*=" => current * value,
+                        NodeValue::Ident(op) if op == "/=" => current.div_with(value, frame.division_mode()),
+                        op => return RuntimeErr(format!("Unknown assignment operator `{op:?}`").into()),
+                    };
+                    if let Value(value) = &result {
+                        if let Err(err) = frame.assign_ident(&ident, value.clone()) {
+                            return RuntimeErr(err);
+                        }
                     }
-                    StateNode::None
+                    result
+                },
+                _ => RuntimeErr("Something op assign".into()),
+            }
+        });
+        module.define_pure("MAP_ENTRY", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(_, err @ RuntimeErr(_)) => err,
+                Exec::BinExpr(key, value) => match key.as_ident() {
+                    NodeValue::Ident(key) => Value(NodeValue::List(vec![NodeValue::String(key), value.as_node_value()])),
+                    _ => RuntimeErr(format!("Could not use `{key:?}` as a Map key").into()),
+                },
+                _ => RuntimeErr("Something map entry".into()),
+            }
+        });
+        module.define_pure("MAP_NEW", |mut frame, | {
+            let mut map = BTreeMap::new();
+            for entry in frame.eval_nodes() {
+                match entry {
+                    err @ RuntimeErr(_) => return err,
+                    entry => match entry.as_node_value() {
+                        NodeValue::List(mut entry) if entry.len() == 2 => {
+                            let value = entry.pop().unwrap_or(NodeValue::ValueError("Missing value".into()));
+                            match entry[0].to_string() {
+                                Ok(key) => map.insert(key, value),
+                                Err(err) => return RuntimeErr(format!("Could not use `{:?}` as a Map key: {err}", entry[0]).into()),
+                            };
+                        },
+                        entry => return RuntimeErr(format!("Could not use `{entry:?}` as a Map entry").into()),
+                    },
+                }
+            }
+            Value(NodeValue::Map(map))
+        });
+        module.define_pure("MAP_GET", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(err @ RuntimeErr(_), _) => err,
+                Exec::BinExpr(map, key) => match (map.as_node_value(), key.as_ident()) {
+                    (NodeValue::Map(mut map), NodeValue::Ident(key)) => match map.remove(&key) {
+                        Some(value) => Value(value),
+                        _ => RuntimeErr(format!("Map has no key `{key}`").into()),
+                    },
+                    (NodeValue::Map(_), key) => RuntimeErr(format!("Could not use `{key:?}` as a Map key").into()),
+                    (value, _) => RuntimeErr(format!("Cannot get a key of `{value:?}`, it is not a Map").into()),
+                },
+                _ => RuntimeErr("Something map get".into()),
+            }
+        });
+        // `p.x = value` gives the value like the other assignments
+        module.define("MAP_SET", |mut frame, | {
+            match frame.eval() {
+                Exec::TriExpr(_, _, err @ RuntimeErr(_)) => err,
+                Exec::TriExpr(ident, key, value) => match (ident.as_ident(), key.as_ident()) {
+                    (NodeValue::Ident(ident), NodeValue::Ident(key)) => match frame.get_ident(&ident) {
+                        Value(NodeValue::Map(mut map)) => {
+                            let value = value.as_node_value();
+                            map.insert(key, value.clone());
+                            match frame.assign_ident(&ident, NodeValue::Map(map)) {
+                                Ok(()) => Value(value),
+                                Err(err) => RuntimeErr(err),
+                            }
+                        },
+                        err @ RuntimeErr(_) => err,
+                        value => RuntimeErr(format!("Cannot set a key of `{value:?}`, it is not a Map").into()),
+                    },
+                    (ident, key) => RuntimeErr(format!("Could not set `{ident:?}.{key:?}`").into()),
                 },
-                _ => RuntimeErr("Something set ident".into()),
+                _ => RuntimeErr("Something map set".into()),
             }
         });
+        module.define("CALL", builtin::call);
+        module
+    }
+
+    /// Get the `bitwise` [`Module`] with lambdas for the bitwise operators on integers -
+    /// `BAND`, `BOR`, `BXOR`, `SHL`, `SHR` and the unary `BNOT`. The math grammar has no
+    /// tokens for them, so a language using them registers the module and wires up its
+    /// own operators.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// use interpreter::lang::math;
+    /// use interpreter::parser::Assoc;
+    /// let mut lexer = math::lexer();
+    /// lexer.define_literals("bitop", &["&", "|", "<<", ">>"])?;
+    /// let mut parser = math::parser();
+    /// parser.define_binop("MATH:EXPR", "MATH:EXPR:1", &[("+", "ADD"), ("-", "SUB"), ("&", "BAND"), ("<<", "SHL")], Assoc::Left);
+    /// let mut env = math::env();
+    /// env.register_module(math::bitwise())?;
+    /// let mut executor = Executor::new(lexer, parser, env);
+    /// assert_eq!(executor.exec_str("6 & 3"), Ok("2".into()));
+    /// assert_eq!(executor.exec_str("1 << 4"), Ok("16".into()));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn bitwise() -> Module {
+        use crate::exec::StateNode::*;
+        use crate::exec::Exec;
+        let mut module = Module::new("bitwise");
+        module.define_pure("BAND", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => lhs & rhs,
+                _ => RuntimeErr("Something bitwise and".into()),
+            }
+        });
+        module.define_pure("BOR", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => lhs | rhs,
+                _ => RuntimeErr("Something bitwise or".into()),
+            }
+        });
+        module.define_pure("BXOR", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => lhs ^ rhs,
+                _ => RuntimeErr("Something bitwise xor".into()),
+            }
+        });
+        module.define_pure("SHL", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => lhs << rhs,
+                _ => RuntimeErr("Something shift left".into()),
+            }
+        });
+        module.define_pure("SHR", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => lhs >> rhs,
+                _ => RuntimeErr("Something shift right".into()),
+            }
+        });
+        module.define_pure("BNOT", |mut frame, | {
+            match frame.eval() {
+                Exec::UniExpr(value) => !value,
+                _ => RuntimeErr("Something bitwise not".into()),
+            }
+        });
+        module
+    }
+
+    /// Get the `logic` [`Module`] with the lazy `AND` and `OR` lambdas, the unary `NOT` and
+    /// the `GT` and `LT` comparisons. `AND` only evaluates its right side when the left is `true` and
+    /// `OR` only when it is `false`, both sides have to be a Boolean. Like
+    /// [`bitwise`], the math grammar has no tokens for them.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, NodeValue};
+    /// use interpreter::lang::math;
+    /// use interpreter::parser::{Assoc, PrecedenceBuilder};
+    /// let mut lexer = math::lexer();
+    /// lexer.define_literals("logic", &["&&", "||", ">"])?;
+    /// let mut parser = math::parser();
+    /// PrecedenceBuilder::new("MATH:EXPR", "VALUE")
+    ///     .level(&[("||", "OR")], Assoc::Left)
+    ///     .level(&[("&&", "AND")], Assoc::Left)
+    ///     .level(&[(">", "GT")], Assoc::Left)
+    ///     .level(&[("+", "ADD"), ("-", "SUB")], Assoc::Left)
+    ///     .build(&mut parser);
+    /// let mut env = math::env();
+    /// env.register_module(math::logic())?;
+    /// env.set_ident("no", NodeValue::Boolean(false));
+    /// let mut executor = Executor::new(lexer, parser, env);
+    /// assert_eq!(executor.exec_str("2 > 1 && 1 > 0"), Ok("true".into()));
+    /// assert_eq!(executor.exec_str("no && x > 0"), Ok("false".into()));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn logic() -> Module {
+        use crate::exec::StateNode::*;
+        use crate::exec::Exec;
+        let mut module = Module::new("logic");
+        module.define_pure("AND", |mut frame, | {
+            match boolean(frame.eval_arg(1), "&&") {
+                Value(NodeValue::Boolean(true)) => boolean(frame.eval_arg(2), "&&"),
+                lhs => lhs,
+            }
+        });
+        module.define_pure("OR", |mut frame, | {
+            match boolean(frame.eval_arg(1), "||") {
+                Value(NodeValue::Boolean(false)) => boolean(frame.eval_arg(2), "||"),
+                lhs => lhs,
+            }
+        });
+        module.define_pure("NOT", |mut frame, | {
+            match frame.eval() {
+                Exec::UniExpr(value) => match boolean(value, "!") {
+                    Value(NodeValue::Boolean(value)) => Value(NodeValue::Boolean(!value)),
+                    err => err,
+                },
+                _ => RuntimeErr("Something not".into()),
+            }
+        });
+        module.define_pure("GT", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => compare(lhs, rhs, CmpOrdering::Greater, ">"),
+                _ => RuntimeErr("Something greater than".into()),
+            }
+        });
+        module.define_pure("LT", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => compare(lhs, rhs, CmpOrdering::Less, "<"),
+                _ => RuntimeErr("Something less than".into()),
+            }
+        });
+        module
+    }
+
+    /// Get the `decimal` [`Module`] with the `DECIMAL` lambda, which reads a literal
+    /// token as an exact decimal like `INTEGER` and `FLOAT` do. Languages opt in with
+    /// `EvalAs("DECIMAL")`, see [`prefer_decimal`].
+    #[cfg(feature = "decimal")]
+    pub fn decimals() -> Module {
+        use crate::exec::decimal::Decimal;
+        let mut module = Module::new("decimal");
+        module.define_pure("DECIMAL", |mut frame, | {
+            frame.eval_as::<Decimal>()
+        });
+        module
+    }
+
+    pub fn env() -> VirtualEnv {
+        let mut env = VirtualEnv::new();
+        env.register_module(module()).expect("a new environment has no modules");
+        env.define_signature("ADD", |args| types::arithmetic("add", args));
+        env.define_signature("SUB", |args| types::arithmetic("subtract", args));
+        env.define_signature("MULT", |args| types::arithmetic("multiply", args));
+        env.define_signature("FLOOR_DIV", |args| types::arithmetic("floor divide", args));
+        env.define_signature("MOD", |args| types::arithmetic("take the remainder of", args));
+        env.define_signature("DIV", |args| match types::arithmetic("divide", args)? {
+            // dividing integers depends on the division mode
+            ValueType::Integer => Ok(ValueType::Any),
+            found => Ok(found),
+        });
+        env.define_signature("INTEGER", |_| Ok(ValueType::Integer));
+        env.define_signature("FLOAT", |_| Ok(ValueType::Float));
+        env.define_signature("STRING", |_| Ok(ValueType::String));
+        env.define_signature("CHAR", |_| Ok(ValueType::Char));
+        env.define_signature("NULL", |_| Ok(ValueType::Null));
+        #[cfg(feature = "decimal")]
+        env.define_signature("DECIMAL", |_| Ok(ValueType::Decimal));
+        env.define_signature("TO_INT", |_| Ok(ValueType::Integer));
+        env.define_signature("TO_FLOAT", |_| Ok(ValueType::Float));
+        env.define_signature("TO_STRING", |_| Ok(ValueType::String));
+        env.define_signature("LIST_NEW", |_| Ok(ValueType::List));
+        env.define_signature("MAP_NEW", |_| Ok(ValueType::Map));
+        env.define_signature("DECLARE", |args| Ok(args.get(1).copied().unwrap_or(ValueType::Any)));
+        env.define_signature("ASSIGN", |args| Ok(args.get(1).copied().unwrap_or(ValueType::Any)));
+        env.define_signature("PRINT", |_| Ok(ValueType::None));
+        env.define_variable_signature("GET_IDENT");
+        env.register_fn("len", Arity::Exact(1), |args| {
+            let len = args.list(0)?.len();
+            i32::try_from(len).map(NodeValue::Integer).map_err(|_| format!("a length of {len} is too big to be an Integer"))
+        });
+        env.register_fn("abs", Arity::Exact(1), |args| {
+            let overflow = || format!("{} is too big to be positive", args[0].to_string().unwrap_or_default());
+            match args.value(0)? {
+                NodeValue::Integer(int) => int.checked_abs().map(NodeValue::Integer).ok_or_else(overflow),
+                NodeValue::BigInteger(int) => int.checked_abs().map(NodeValue::BigInteger).ok_or_else(overflow),
+                NodeValue::Float(float) => Ok(NodeValue::Float(float.abs())),
+                NodeValue::Rational(ratio) => Ok(NodeValue::rational(ratio.numer().checked_abs().ok_or_else(overflow)?, ratio.denom())),
+                #[cfg(feature = "decimal")]
+                NodeValue::Decimal(decimal) if decimal.mantissa() < 0 => Ok(decimal.checked_neg().map(NodeValue::Decimal)?),
+                #[cfg(feature = "decimal")]
+                NodeValue::Decimal(decimal) => Ok(NodeValue::Decimal(*decimal)),
+                _ => Ok(NodeValue::BigFloat(args.f64(0)?.abs())),
+            }
+        });
+        env.register_fn("sqrt", Arity::Exact(1), |args| {
+            if args.f64(0)? < 0.0 {
+                return Err("cannot take the square root of a negative number".into());
+            }
+            match args[0].to_float() {
+                NodeValue::Float(float) => Ok(NodeValue::Float(float.sqrt())),
+                _ => Ok(NodeValue::BigFloat(args.f64(0)?.sqrt())),
+            }
+        });
+        // rounding a float gives an integer of the same size
+        env.register_fn("floor", Arity::Exact(1), |args| round(args, f64::floor));
+        env.register_fn("ceil", Arity::Exact(1), |args| round(args, f64::ceil));
+        #[cfg(feature = "decimal")]
+        env.register_fn("decimal", Arity::Exact(1), |args| match args[0].to_decimal() {
+            NodeValue::ValueError(err) => Err(err),
+            decimal => Ok(decimal),
+        });
+        env.register_fn("min", Arity::AtLeast(1), |args| extreme(args, CmpOrdering::Less));
+        env.register_fn("max", Arity::AtLeast(1), |args| extreme(args, CmpOrdering::Greater));
         env
     }
+
+    /// Round a number to an integer, integers are already rounded.
+    fn round(args: &[NodeValue], round: fn(f64) -> f64) -> Result<NodeValue, String> {
+        let rounded = match &args[0] {
+            NodeValue::Integer(_) | NodeValue::BigInteger(_) => return Ok(args[0].clone()),
+            NodeValue::Float(float) => NodeValue::Float(round(*float as f64) as f32),
+            _ => NodeValue::BigFloat(round(args.f64(0)?)),
+        };
+        match rounded.to_int() {
+            NodeValue::ValueError(err) => Err(err),
+            int => Ok(int),
+        }
+    }
+
+    /// Get the first number that is ordered `wanted` compared to every other number.
+    fn extreme(args: &[NodeValue], wanted: CmpOrdering) -> Result<NodeValue, String> {
+        let mut best = 0;
+        for index in 0..args.len() {
+            args.f64(index)?;
+            if args[index].partial_cmp(&args[best]) == Some(wanted) {
+                best = index;
+            }
+        }
+        Ok(args[best].clone())
+    }
+
+    /// Check that an operand of the logical operator `op` is a Boolean, errors are kept.
+    fn boolean(state: StateNode, op: &str) -> StateNode {
+        match state.as_value() {
+            StateNode::Value(value) if !matches!(value, NodeValue::Boolean(_)) => {
+                StateNode::RuntimeErr(format!("Cannot use `{op}` on {}, a Boolean is expected", value.describe()).into())
+            }
+            state => state,
+        }
+    }
+
+    /// Limit the number to be from `lo` to `hi`, the limits have to be in order.
+    fn clamp(value: StateNode, lo: StateNode, hi: StateNode) -> StateNode {
+        let (value, lo, hi) = match (value.as_value(), lo.as_value(), hi.as_value()) {
+            (StateNode::Value(value), StateNode::Value(lo), StateNode::Value(hi)) => (value, lo, hi),
+            (err @ StateNode::RuntimeErr(_), _, _) | (_, err @ StateNode::RuntimeErr(_), _) | (_, _, err @ StateNode::RuntimeErr(_)) => return err,
+            _ => return StateNode::RuntimeErr("Cannot clamp a Node".into()),
+        };
+        if let Some(value) = [&value, &lo, &hi].into_iter().find(|value| !value.is_number()) {
+            return StateNode::RuntimeErr(format!("Cannot clamp {}, a number is expected", value.describe()).into());
+        }
+        if lo > hi {
+            return StateNode::RuntimeErr(format!("Cannot clamp from {} to {}, the limits are out of order", lo.describe(), hi.describe()).into());
+        }
+        match (value < lo, value > hi) {
+            (true, _) => StateNode::Value(lo),
+            (_, true) => StateNode::Value(hi),
+            _ => StateNode::Value(value),
+        }
+    }
+
+    /// Check if the numbers are ordered `wanted`, only numbers can be compared.
+    fn compare(lhs: StateNode, rhs: StateNode, wanted: CmpOrdering, op: &str) -> StateNode {
+        let (lhs, rhs) = match (lhs.as_value(), rhs.as_value()) {
+            (StateNode::Value(lhs), StateNode::Value(rhs)) => (lhs, rhs),
+            (err @ StateNode::RuntimeErr(_), _) | (_, err @ StateNode::RuntimeErr(_)) => return err,
+            _ => return StateNode::RuntimeErr(format!("Cannot use `{op}` on a Node").into()),
+        };
+        match lhs.is_number() && rhs.is_number() {
+            true => StateNode::Value(NodeValue::Boolean(lhs.partial_cmp(&rhs) == Some(wanted))),
+            false => StateNode::RuntimeErr(format!("Cannot use `{op}` on {} and {}", lhs.describe(), rhs.describe()).into()),
+        }
+    }
+
+    /// Get the value at a 0-based index of a list.
+    fn index_list(mut list: Vec<NodeValue>, index: i128) -> StateNode {
+        match usize::try_from(index).ok().filter(|index| *index < list.len()) {
+            Some(index) => StateNode::Value(list.swap_remove(index)),
+            None => StateNode::RuntimeErr(format!("Index {index} is out of range for a List of length {}", list.len()).into()),
+        }
+    }
 }
 
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::{DivisionMode, Executor, NodeValue};
+    use crate::lexer::LineReader;
+
+    /// run every line and get the result of the last one.
+    fn run(src: &str) -> Result<String, String> {
+        exec().exec_all(&mut LineReader::new(src)).map_err(String::from)
+    }
+
+    /// list literals are displayed with their items.
+    #[test]
+    fn list_literal() -> Result<(), String> {
+        assert_eq!(run("[1, 2, 3]")?, "[1, 2, 3]");
+        assert_eq!(run("[]")?, "[]");
+        assert_eq!(run("[1.5, 2 * 3, (4)]")?, "[1.5, 6, 4]");
+        assert_eq!(run("[[1], []]")?, "[[1], []]");
+        assert_eq!(run("len([1, 2])")?, "2");
+        Ok(())
+    }
+
+    /// lists are indexed from 0.
+    #[test]
+    fn list_index() -> Result<(), String> {
+        assert_eq!(run("a := [1, 2, 3]\na[1] + a[2]")?, "5");
+        assert_eq!(run("a := [[1, 2], [3]]\nb := a[0]\nb[1]")?, "2");
+        assert_eq!(run("[4, 5][1 - 1]")?, "4");
+        // indexes chain on any value
+        assert_eq!(run("a := [[1, 2], [3]]\na[0][1]")?, "2");
+        assert_eq!(run("[[1, 2], [3]][1][0] * 2")?, "6");
+        assert_eq!(run("a := [1, 2]\n0 - (a)[1]")?, "-2");
+        Ok(())
+    }
+
+    /// bad indexes and arithmetic on lists are errors.
+    #[test]
+    fn list_errors() {
+        let err = run("a := [1, 2, 3]\na[3]").expect_err("index 3 is out of range");
+        assert!(err.ends_with("Index 3 is out of range for a List of length 3"), "got {err}");
+        let err = run("a := [1, 2, 3]\na[0 - 1]").expect_err("index -1 is out of range");
+        assert!(err.ends_with("Index -1 is out of range for a List of length 3"), "got {err}");
+        let err = run("a := 2\na[0]").expect_err("an integer is not a list");
+        assert!(err.contains("it is not a List"), "got {err}");
+        let err = run("[1] + 1").expect_err("lists can't be added");
+        assert!(err.ends_with("Cannot add Integer `1` to List `[1]`"), "got {err}");
+    }
+
+    /// printed lines go to the env's output instead of the result.
+    #[test]
+    fn print_output() -> Result<(), String> {
+        use std::sync::{Arc, Mutex};
+        let lines = Arc::new(Mutex::new(vec![]));
+        let sink = lines.clone();
+        let mut executor = exec();
+        executor.env_mut().set_output(Box::new(move |line| sink.lock().unwrap().push(line.to_owned())));
+        let result = executor.exec_all(&mut LineReader::new("print(1+1, \"x\")\nprint(3)")).map_err(String::from)?;
+        assert_eq!(result, "None");
+        assert_eq!(*lines.lock().unwrap(), ["2 x", "3"]);
+        Ok(())
+    }
+
+    /// builtin functions check their arguments before they are called.
+    #[test]
+    fn builtin_functions() -> Result<(), String> {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("sqrt(16) + abs(0 - 2)")?, "6");
+        assert_eq!(executor.exec_str("max(1, 2.5, 2)")?, "2.5");
+        assert_eq!(executor.exec_str("min(3)")?, "3");
+        // wrong arity
+        assert_eq!(executor.exec_str("sqrt(1, 2, 3)"), Err("sqrt expects 1 argument, got 3".into()));
+        assert_eq!(executor.exec_str("max()"), Err("max expects at least 1 argument, got 0".into()));
+        // wrong type
+        assert_eq!(executor.exec_str("sqrt([1])"), Err("sqrt: argument 1 must be a Number, got `[1]`".into()));
+        assert_eq!(executor.exec_str("len(2)"), Err("len: argument 1 must be a List, got `2`".into()));
+        // unknown function
+        assert_eq!(executor.exec_str("nope(1)"), Err("Unknown function `nope`".into()));
+        // a variable isn't a function
+        executor.exec_str("x := 1")?;
+        assert_eq!(executor.exec_str("x(1)"), Err("`x` is not a function".into()));
+        Ok(())
+    }
+
+    /// the number builtins keep integers as integers where they can.
+    #[test]
+    fn number_builtins() -> Result<(), String> {
+        let mut executor = exec();
+        let mut eval = |src: &str| -> Result<NodeValue, String> {
+            let compiled = executor.compile(src)?;
+            executor.exec_compiled(&compiled).map_err(|err| err.to_string())
+        };
+        assert!(matches!(eval("abs(0 - 3)")?, NodeValue::Integer(3)));
+        assert!(matches!(eval("abs(0 - 2.5)")?, NodeValue::Float(2.5)));
+        assert!(matches!(eval("sqrt(4)")?, NodeValue::Float(2.0)));
+        assert!(matches!(eval("sqrt(2.25)")?, NodeValue::Float(1.5)));
+        assert!(matches!(eval("floor(2.7)")?, NodeValue::Integer(2)));
+        assert!(matches!(eval("floor(0 - 2.5)")?, NodeValue::Integer(-3)));
+        assert!(matches!(eval("ceil(2.1)")?, NodeValue::Integer(3)));
+        assert!(matches!(eval("ceil(7)")?, NodeValue::Integer(7)));
+        // numbers are compared across types
+        assert!(matches!(eval("min(2, 1.5)")?, NodeValue::Float(1.5)));
+        assert!(matches!(eval("max(2, 1.5)")?, NodeValue::Integer(2)));
+        assert!(matches!(eval("max(3, 3.0)")?, NodeValue::Integer(3)));
+        // errors
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("sqrt(0 - 1)"), Err("sqrt: cannot take the square root of a negative number".into()));
+        assert_eq!(executor.exec_str("floor(\"x\")"), Err("floor: argument 1 must be a Number, got `x`".into()));
+        assert_eq!(executor.exec_str("ceil()"), Err("ceil expects 1 argument, got 0".into()));
+        executor.env_mut().set_ident("low", NodeValue::Integer(i32::MIN));
+        assert_eq!(executor.exec_str("abs(low)"), Err("abs: -2147483648 is too big to be positive".into()));
+        Ok(())
+    }
+
+    /// using an operator on the wrong types says which operator it was.
+    #[test]
+    fn operator_type_errors() {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("\"x\" - 1"), Err("Cannot subtract Integer `1` from String `x`".into()));
+        assert_eq!(executor.exec_str("[1] * 2"), Err("Cannot multiply List `[1]` by Integer `2`".into()));
+    }
+
+    /// a char literal is read back as the char it shows, and arithmetic on it fails.
+    #[test]
+    fn char_literal() -> Result<(), String> {
+        let mut executor = exec();
+        let value = executor.exec_value("'x'").map_err(|err| err.to_string())?;
+        assert!(matches!(value, NodeValue::Char('x')), "{value:?}");
+        assert_eq!(value.to_string()?, "x");
+        assert_eq!(executor.exec_str("str('x')")?, "x");
+        assert_eq!(executor.exec_value("'\\n'").map_err(|err| err.to_string())?, NodeValue::Char('\n'));
+        assert_eq!(executor.exec_value("'\\''").map_err(|err| err.to_string())?, NodeValue::Char('\''));
+        assert_eq!(executor.exec_value("'é'").map_err(|err| err.to_string())?, NodeValue::Char('é'));
+        // a char that shows a number is still a char
+        assert_eq!(executor.exec_str("'3' + 1"), Err("Cannot add Integer `1` to Char `3`".into()));
+        assert_eq!(executor.exec_str("'a' * 'b'"), Err("Cannot multiply Char `a` by Char `b`".into()));
+        assert!(executor.exec_str("'ab'").is_err());
+        let ast = parser().parse_tree(&lexer(), &mut LineReader::new("c := 'x'"))?;
+        assert_eq!(env().infer_type(&ast, &Default::default()), crate::exec::ValueType::Char);
+        Ok(())
+    }
+
+    /// `null` is a value that can be stored, unlike the `None` a statement can give.
+    #[test]
+    fn null_literal() -> Result<(), String> {
+        let mut executor = exec();
+        assert_eq!(executor.exec_value("x := null").map_err(|err| err.to_string())?, NodeValue::Null);
+        assert_eq!(executor.get_var("x"), Some(&NodeValue::Null));
+        assert_eq!(executor.exec_str("x")?, "null");
+        assert_eq!(executor.exec_str("[null, 1]")?, "[null, 1]");
+        assert_ne!(NodeValue::Null, NodeValue::None);
+        assert_eq!(executor.exec_str("null + 1"), Err("Cannot add Integer `1` to Null".into()));
+        // `null` is a keyword, not a name that can be set
+        assert!(executor.exec_str("null := 5").is_err());
+        assert_eq!(executor.exec_str("null")?, "null");
+        Ok(())
+    }
+
+    /// casts convert values that were already evaluated.
+    #[test]
+    fn casts() -> Result<(), String> {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("int(3.9)")?, "3");
+        assert_eq!(executor.exec_str("int(0 - 3.9) + 1")?, "-2");
+        let float = executor.exec_compiled(&executor.compile("float(2)")?).map_err(|err| err.to_string())?;
+        assert!(matches!(float, NodeValue::Float(2.0)), "{float:?}");
+        assert_eq!(executor.exec_str("int(\"12\") + 1")?, "13");
+        assert_eq!(executor.exec_str("str(1 / 4)")?, "0.25");
+        assert_eq!(executor.exec_str("len([str(1), int(2.5)])")?, "2");
+        assert_eq!(executor.exec_str("int(\"x\")"), Err("Failed to parse 'x' as Integer".into()));
+        assert_eq!(executor.exec_str("float([1])"), Err("Cannot convert `[1]` to a Float".into()));
+        Ok(())
+    }
+
+    /// a long chain of operators is as deep as it is long, it must not overflow the stack.
+    #[test]
+    fn deep_expression() -> Result<(), String> {
+        let src = vec!["1"; 50_000].join("+");
+        assert_eq!(run(&src)?, "50000");
+        Ok(())
+    }
+
+    /// operators of the same precedence are evaluated from the left.
+    #[test]
+    fn left_associative() -> Result<(), String> {
+        assert_eq!(run("1 - 2 - 3")?, "-4");
+        assert_eq!(run("2 - 3 + 4")?, "3");
+        assert_eq!(run("7 % 4 * 2")?, "6");
+        assert_eq!(run("20 // 3 // 2")?, "3");
+        assert_eq!(run("10 - 2 * 3 - 1")?, "3");
+        Ok(())
+    }
+
+    /// compound assignments update the variable and give the new value.
+    #[test]
+    fn op_assign() -> Result<(), String> {
+        let mut executor = exec();
+        executor.exec_str("x := 10")?;
+        assert_eq!(executor.exec_str("x += 5")?, "15");
+        assert_eq!(executor.exec_str("x -= 3")?, "12");
+        assert_eq!(executor.exec_str("x *= 2")?, "24");
+        assert_eq!(executor.exec_str("x /= 4")?, "6");
+        assert_eq!(executor.exec_str("x")?, "6");
+        // the new value can be used in an expression
+        assert_eq!(executor.exec_str("y := (x += 1)")?, "7");
+        assert_eq!(executor.exec_str("y * 10 + x")?, "77");
+        Ok(())
+    }
+
+    /// `:=` declares a variable and `=` only changes one that exists.
+    #[test]
+    fn declare_and_assign() -> Result<(), String> {
+        let mut executor = exec();
+        let err = executor.exec_str("x = 1").expect_err("`x` is not declared");
+        assert_eq!(err, "undefined variable 'x'");
+        assert!(executor.get_var("x").is_none());
+        executor.exec_str("x := 1")?;
+        executor.exec_str("x = x + 1")?;
+        assert_eq!(executor.exec_str("x")?, "2");
+        // declaring again replaces the value
+        executor.exec_str("x := 5")?;
+        assert_eq!(executor.exec_str("x")?, "5");
+        Ok(())
+    }
+
+    /// a block declares its variables in its own scope, `=` changes the outer ones.
+    #[test]
+    fn block_scopes() -> Result<(), String> {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("x = 1"), Err("undefined variable 'x'".into()));
+        assert_eq!(executor.exec_str("x := 1")?, "1");
+        // `=` changes the variable outside of the block
+        assert_eq!(executor.exec_str("{ x = 5 }")?, "5");
+        assert_eq!(executor.exec_str("{ x += 1; x * 2 }")?, "12");
+        assert_eq!(executor.get_var("x"), Some(&NodeValue::Integer(6)));
+        // `:=` hides it until the block ends
+        assert_eq!(executor.exec_str("{ x := 10; x = x + 1; x }")?, "11");
+        assert_eq!(executor.exec_str("x")?, "6");
+        // the nearest declaration is changed
+        assert_eq!(executor.exec_str("{ count := 1; { count = count + 1; x = count }; count }")?, "2");
+        assert_eq!(executor.exec_str("x")?, "2");
+        // the block's variables are dropped with it
+        assert_eq!(executor.exec_str("count"), Err("undefined variable 'count'".into()));
+        assert_eq!(executor.exec_str("{ total = 1 }"), Err("undefined variable 'total'".into()));
+        assert_eq!(executor.exec_str("{ temp := 1; temp / 0 }"), Err("Cannot divide by zero".into()));
+        assert_eq!(executor.exec_str("temp"), Err("undefined variable 'temp'".into()));
+        assert_eq!(executor.variables().len(), 1);
+        // an empty pair of braces is still a map
+        assert_eq!(executor.exec_str("{}")?, "{}");
+        Ok(())
+    }
+
+    /// assigning gives the assigned value, so assignments can be chained.
+    #[test]
+    fn assignment_value() -> Result<(), String> {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("x := 5")?, "5");
+        assert_eq!(executor.exec_str("x = x * 2")?, "10");
+        assert_eq!(executor.exec_str("x := y := 3")?, "3");
+        assert_eq!(executor.get_var("x"), Some(&NodeValue::Integer(3)));
+        assert_eq!(executor.get_var("y"), Some(&NodeValue::Integer(3)));
+        assert_eq!(executor.exec_str("z := (x = 4) + y")?, "7");
+        assert_eq!(executor.exec_str("x")?, "4");
+        // a failed assignment in the chain leaves the outer variable unset
+        assert!(executor.exec_str("w := undefined = 1").is_err());
+        assert_eq!(executor.get_var("w"), None);
+        Ok(())
+    }
+
+    /// identifiers are read as `Ident` values, anything else can't be assigned to.
+    #[test]
+    fn ident_values() -> Result<(), String> {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut executor = exec();
+        executor.exec_str("total := 4")?;
+        assert_eq!(executor.exec_str("total * 2")?, "8");
+        // `(1+2) = 5` parses when the grammar allows any expression before `=`
+        let mut parser = parser();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[Token("op", "("), Expr("MATH:EXPR"), Token("op", ")"), Token("assign", ""), Expr("MATH:EXPR")]),
+            SubExpr(&[Token("ident", "ident"), Expr("IDENT")]),
+        ]), LambdaOr(&[
+            Lambda("ASSIGN", &[2, 5]),
+            GetExpr(2, &Eval),
+        ]));
+        let mut executor = Executor::new(lexer(), parser, env());
+        assert_eq!(executor.exec_str("(1+2) = 5"), Err("Cannot use Integer `3` as an Identifier".into()));
+        let compiled = executor.compile("ident x")?;
+        assert_eq!(executor.exec_compiled(&compiled).map_err(|err| err.message)?, NodeValue::Ident("x".into()));
+        Ok(())
+    }
+
+    /// undefined variables are errors at the identifier, naming a close variable.
+    #[test]
+    fn undefined_variable() -> Result<(), String> {
+        let mut executor = exec();
+        executor.exec_str("foo := 1")?;
+        assert_eq!(executor.exec_str("foa + 1"), Err("undefined variable 'foa', did you mean 'foo'?".into()));
+        assert_eq!(executor.exec_str("total + 1"), Err("undefined variable 'total'".into()));
+        let err = executor.exec_all(&mut LineReader::new("foo + 1
+1 + (2 * fooo)")).expect_err("`fooo` is undefined");
+        assert_eq!(err.to_string(), "2:10: Runtime Error: undefined variable 'fooo', did you mean 'foo'?");
+        let compiled = executor.compile("2 * bar")?;
+        let err = executor.exec_compiled(&compiled).expect_err("`bar` is undefined");
+        assert_eq!(err.position.map(|position| position.user_position()), Some((1, 5)));
+        Ok(())
+    }
+
+    /// only identifiers can be assigned to, anything else is a parse error at the left side.
+    #[test]
+    fn assign_target() -> Result<(), String> {
+        let (lexer, parser) = grammar();
+        let err = parser.parse_tree(&lexer, &mut LineReader::new("5 = x")).expect_err("`5` is not an identifier");
+        assert_eq!(err.message, "left side of assignment must be an identifier");
+        assert_eq!(err.position.user_position(), (1, 1));
+        let err = parser.parse_tree(&lexer, &mut LineReader::new("x+1 = 2")).expect_err("`x+1` is not an identifier");
+        assert_eq!(err.to_string(), "left side of assignment must be an identifier at line 1 col 1");
+        assert_eq!(err.position.read_pos, (0, 3));
+        assert_eq!(run("x := 1\nx = 2\nx")?, "2");
+        Ok(())
+    }
+
+    /// backtracking re-reads tokens from the cache instead of running the regexes again.
+    #[test]
+    fn cached_parse() -> Result<(), String> {
+        use crate::lexer::CachingReader;
+        use std::time::{Duration, Instant};
+        let src = "x = (1+2)*(3-4)/[5, 6][0]";
+        let executor = exec();
+        let started = Instant::now();
+        let mut reader = CachingReader::new(LineReader::new(src));
+        let cached = executor.check(&mut reader)?;
+        assert!(started.elapsed() < Duration::from_secs(30), "parsing took {:?}", started.elapsed());
+        // the same tree as the uncached path
+        assert_eq!(cached, executor.check(&mut LineReader::new(src))?);
+        // most reads were answered by the cache
+        assert!(reader.regex_calls() < reader.cache_hits(), "{} regex calls, {} cache hits", reader.regex_calls(), reader.cache_hits());
+        Ok(())
+    }
+
+    /// each division mode pins down `/`, while `//` and `%` round down in all of them.
+    #[test]
+    fn division_modes() -> Result<(), String> {
+        let run_in = |mode, src: &str| {
+            let mut executor = exec();
+            executor.env_mut().set_division_mode(mode);
+            executor.exec_str(src)
+        };
+        for mode in [DivisionMode::Legacy, DivisionMode::True, DivisionMode::TrueInf] {
+            assert_eq!(run_in(mode, "7/2")?, "3.5");
+            assert_eq!(run_in(mode, "7//2")?, "3");
+            assert_eq!(run_in(mode, "(0-7)//2")?, "-4");
+            assert_eq!(run_in(mode, "7%(0-2)")?, "-1");
+            assert_eq!(run_in(mode, "(0-7)%2")?, "1");
+            assert_eq!(run_in(mode, "7.5//2")?, "3");
+            assert_eq!(run_in(mode, "7//0"), Err("Cannot divide by zero".into()));
+            assert_eq!(run_in(mode, "7%0"), Err("Cannot divide by zero".into()));
+        }
+        // the result type of `/`
+        let mut executor = exec();
+        executor.exec_str("x := 8/2")?;
+        assert_eq!(executor.get_var("x"), Some(&NodeValue::Float(4.0)));
+        executor.env_mut().set_division_mode(DivisionMode::Legacy);
+        executor.exec_str("x = 8/2")?;
+        assert!(matches!(executor.get_var("x"), Some(NodeValue::Integer(4))));
+        // dividing by zero
+        assert_eq!(run_in(DivisionMode::Legacy, "1/0"), Err("Cannot divide by zero".into()));
+        assert_eq!(run_in(DivisionMode::True, "1/0"), Err("Cannot divide by zero".into()));
+        assert_eq!(run_in(DivisionMode::TrueInf, "1/0")?, "inf");
+        assert_eq!(run_in(DivisionMode::TrueInf, "0/0")?, "NaN");
+        Ok(())
+    }
+
+    /// the rational mode divides integers into exact fractions.
+    #[test]
+    fn rational_mode() -> Result<(), String> {
+        let mut executor = exec_rational();
+        assert_eq!(executor.exec_str("1/3 + 1/6")?, "1/2");
+        assert_eq!(executor.exec_str("2/(0-4)")?, "-1/2");
+        assert_eq!(executor.exec_str("x := 6/4")?, "3/2");
+        assert_eq!(executor.exec_str("x * 2")?, "3");
+        assert_eq!(executor.exec_str("x - 1/2")?, "1");
+        assert_eq!(executor.exec_str("x // 1")?, "1");
+        assert_eq!(executor.exec_str("x % 1")?, "1/2");
+        assert_eq!(executor.exec_str("abs(-x)")?, "3/2");
+        assert_eq!(executor.exec_str("x /= 3")?, "1/2");
+        // floats only on demand
+        assert_eq!(executor.exec_str("float(1/8)")?, "0.125");
+        assert_eq!(executor.exec_str("1/4 + 0.5")?, "0.75");
+        assert_eq!(executor.exec_str("int(7/2)")?, "3");
+        // dividing by a zero fraction
+        assert_eq!(executor.exec_str("1/2 / (1/2 - x)"), Err("Cannot divide by zero".into()));
+        assert_eq!(executor.exec_str("1/0"), Err("Cannot divide by zero".into()));
+        Ok(())
+    }
+
+    /// compound assignments need the variable to be set and a valid value.
+    #[test]
+    fn op_assign_errors() -> Result<(), String> {
+        let mut executor = exec();
+        let err = executor.exec_str("z += 1").expect_err("`z` is not set");
+        assert_eq!(err, "undefined variable 'z'");
+        assert!(!executor.env().variables.contains_key("z"));
+        executor.exec_str("x := 1")?;
+        assert_eq!(executor.exec_str("x /= 0"), Err("Cannot divide by zero".into()));
+        assert_eq!(executor.exec_str("x")?, "1");
+        Ok(())
+    }
+
+    /// map literals are displayed sorted by key.
+    #[test]
+    fn map_literal() -> Result<(), String> {
+        assert_eq!(run("{y: 2, x: 1}")?, "{x: 1, y: 2}");
+        assert_eq!(run("{}")?, "{}");
+        assert_eq!(run("p := {x: 1, y: 2}\np.x + p.y")?, "3");
+        assert_eq!(run("p := {x: 1, x: 2}\np")?, "{x: 2}");
+        Ok(())
+    }
+
+    /// keys can be added and changed on a map variable, and setting one gives the value.
+    #[test]
+    fn map_set() -> Result<(), String> {
+        let mut executor = exec();
+        assert_eq!(executor.exec_all(&mut LineReader::new("p := {x: 1}\np.x = 5\np.z = p.x * 2"))?, "10");
+        let p = executor.get_var("p").ok_or("no variable `p`")?;
+        assert_eq!(p.to_string()?, "{x: 5, z: 10}");
+        Ok(())
+    }
+
+    /// missing keys and non-map values are errors.
+    #[test]
+    fn map_errors() {
+        let err = run("p := {x: 1}\np.y").expect_err("`y` is not a key");
+        assert!(err.ends_with("Map has no key `y`"), "got {err}");
+        let err = run("p := 1\np.x").expect_err("an integer is not a map");
+        assert!(err.contains("it is not a Map"), "got {err}");
+        let err = run("p := 1\np.x = 2").expect_err("an integer is not a map");
+        assert!(err.contains("it is not a Map"), "got {err}");
+        let err = run("{x: 1} * 2").expect_err("maps can't be multiplied");
+        assert!(err.ends_with("Cannot multiply Map `{x: 1}` by Integer `2`"), "got {err}");
+    }
+
+    /// a map entry with a key that can't be displayed is an error instead of being dropped.
+    #[test]
+    fn map_bad_key() {
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut parser = parser();
+        parser.define("EXPR", Expr("ENTRY"), EvalAs("MAP_NEW"));
+        parser.define("ENTRY", Token("int", ""), EvalAs("BAD_ENTRY"));
+        let mut env = env();
+        env.define("BAD_ENTRY", |_| crate::exec::StateNode::Value(NodeValue::List(vec![NodeValue::ValueError("no key".into()), NodeValue::Integer(1)])));
+        let mut executor = Executor::new(lexer(), parser, env);
+        let err = executor.exec_str("1").expect_err("the key is an error");
+        assert!(err.ends_with("as a Map key: no key"), "got {err}");
+    }
+
+    /// the bitwise lambdas run once the operators are wired up.
+    #[test]
+    fn bitwise_module() -> Result<(), String> {
+        use crate::parser::Assoc;
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut lexer = lexer();
+        lexer.define_literals("bitop", &["&", "|", "^", "<<", ">>", "~"])?;
+        let mut parser = parser();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[Literal("~"), Expr("VALUE")]),
+            Expr("ASSIGN"),
+            Expr("MATH:EXPR"),
+        ]), LambdaOr(&[Lambda("BNOT", &[2]), Eval, Eval]));
+        parser.define_binop("MATH:EXPR", "MATH:EXPR:1", &[
+            ("+", "ADD"), ("-", "SUB"), ("&", "BAND"), ("|", "BOR"), ("^", "BXOR"), ("<<", "SHL"), (">>", "SHR"),
+        ], Assoc::Left);
+        let mut env = env();
+        env.register_module(bitwise())?;
+        let mut executor = Executor::new(lexer, parser, env);
+        assert_eq!(executor.exec_str("6 & 3"), Ok("2".into()));
+        assert_eq!(executor.exec_str("1 << 4"), Ok("16".into()));
+        assert_eq!(executor.exec_str("~0"), Ok("-1".into()));
+        assert_eq!(executor.exec_str("5 ^ 1 | 8"), Ok("12".into()));
+        assert_eq!(executor.exec_str("(1 << 3) - 1 & 5"), Ok("5".into()));
+        let err = executor.exec_str("1 << (0 - 1)").expect_err("a negative shift is an error");
+        assert!(err.ends_with("Cannot shift by -1, it must be from 0 to 31"), "got {err}");
+        let err = executor.exec_str("1.5 & 1").expect_err("floats have no bits");
+        assert!(err.contains("Cannot use `&` on Float"), "got {err}");
+        Ok(())
+    }
+
+    /// the logical operators only evaluate the right side when they need it.
+    #[test]
+    fn logic_short_circuit() -> Result<(), String> {
+        use crate::parser::{Assoc, PrecedenceBuilder};
+        use crate::parser::syntax::Expression::*;
+        use crate::exec::syntax::Lambda::*;
+        let mut lexer = lexer();
+        lexer.define_literals("logic", &["&&", "||", ">", "<", "!"])?;
+        let mut parser = parser();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[Literal("!"), Expr("VALUE")]),
+            Expr("ASSIGN"),
+            Expr("MATH:EXPR"),
+        ]), LambdaOr(&[Lambda("NOT", &[2]), Eval, Eval]));
+        PrecedenceBuilder::new("MATH:EXPR", "VALUE")
+            .level(&[("||", "OR")], Assoc::Left)
+            .level(&[("&&", "AND")], Assoc::Left)
+            .level(&[(">", "GT"), ("<", "LT")], Assoc::Left)
+            .level(&[("+", "ADD"), ("-", "SUB")], Assoc::Left)
+            .level(&[("*", "MULT"), ("/", "DIV")], Assoc::Left)
+            .build(&mut parser);
+        let mut env = env();
+        env.register_module(logic())?;
+        env.set_ident("true", NodeValue::Boolean(true));
+        env.set_ident("false", NodeValue::Boolean(false));
+        let mut executor = Executor::new(lexer, parser, env);
+        // the division by zero on the right is never run
+        assert_eq!(executor.exec_str("false && (1/0 > 0)"), Ok("false".into()));
+        assert_eq!(executor.exec_str("true || (1/0 > 0)"), Ok("true".into()));
+        let err = executor.exec_str("true && (1/0 > 0)").expect_err("the right side is run");
+        assert!(err.contains("Cannot divide by zero"), "got {err}");
+        assert_eq!(executor.exec_str("1 < 2 && 3 > 2"), Ok("true".into()));
+        assert_eq!(executor.exec_str("1 > 2 || 2 > 3"), Ok("false".into()));
+        // both sides have to be booleans
+        let err = executor.exec_str("1 && true").expect_err("1 is not a Boolean");
+        assert!(err.ends_with("Cannot use `&&` on Integer `1`, a Boolean is expected"), "got {err}");
+        let err = executor.exec_str("false || 2").expect_err("2 is not a Boolean");
+        assert!(err.ends_with("Cannot use `||` on Integer `2`, a Boolean is expected"), "got {err}");
+        assert_eq!(executor.exec_str("!false"), Ok("true".into()));
+        assert_eq!(executor.exec_str("!(1 > 2 || 2 > 3)"), Ok("true".into()));
+        let err = executor.exec_str("!1").expect_err("1 is not a Boolean");
+        assert!(err.ends_with("Cannot use `!` on Integer `1`, a Boolean is expected"), "got {err}");
+        let err = executor.exec_str("\"a\" > 1").expect_err("strings aren't ordered");
+        assert!(err.ends_with("Cannot use `>` on String `a` and Integer `1`"), "got {err}");
+        Ok(())
+    }
+
+    /// a `-` in front of a value negates it.
+    #[test]
+    fn negate() {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("-2"), Ok("-2".into()));
+        assert_eq!(executor.exec_str("-2 * 3"), Ok("-6".into()));
+        assert_eq!(executor.exec_str("1 - -2.5"), Ok("3.5".into()));
+        assert_eq!(executor.exec_str("-(1 + 2) + --4"), Ok("1".into()));
+        let err = executor.exec_str("-\"a\"").expect_err("strings can't be negated");
+        assert!(err.ends_with("Cannot use `-` on String `a`"), "got {err}");
+    }
+
+    /// clamp takes three args and keeps the value within the limits.
+    #[test]
+    fn clamp_values() {
+        let mut executor = exec();
+        assert_eq!(executor.exec_str("clamp(5, 0, 3)"), Ok("3".into()));
+        assert_eq!(executor.exec_str("clamp(-5, 0, 3)"), Ok("0".into()));
+        assert_eq!(executor.exec_str("clamp(1.5, 0, 3)"), Ok("1.5".into()));
+        let err = executor.exec_str("clamp(1, 3, 0)").expect_err("the limits are out of order");
+        assert!(err.ends_with("Cannot clamp from Integer `3` to Integer `0`, the limits are out of order"), "got {err}");
+        let err = executor.exec_str("clamp(\"a\", 0, 1)").expect_err("strings aren't numbers");
+        assert!(err.ends_with("Cannot clamp String `a`, a number is expected"), "got {err}");
+        assert!(executor.exec_str("clamp(1, 2)").is_err());
+    }
+
+    /// float literals read as decimals are exact.
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn decimal_literals() -> Result<(), String> {
+        use crate::exec::decimal::Decimal;
+        let mut executor = exec_decimal();
+        let sum = executor.exec_value("0.1 + 0.2")?;
+        assert_eq!(sum, NodeValue::Decimal("0.3".parse()?));
+        assert_eq!(sum.type_name(), "Decimal");
+        assert_eq!(executor.exec_str("1.50 - 0.25"), Ok("1.25".into()));
+        // integers are promoted, floats have to be converted
+        assert_eq!(executor.exec_str("0.5 * 3"), Ok("1.5".into()));
+        let err = executor.exec_str("0.5 + float(1)").expect_err("a float can't be mixed with a decimal");
+        assert!(err.ends_with("Cannot add Float `1` to Decimal `0.5`"), "got {err}");
+        assert_eq!(executor.exec_str("decimal(float(0.1)) + 0.2"), Ok("0.3".into()));
+        assert_eq!(executor.exec_str("float(0.25)"), Ok("0.25".into()));
+        assert_eq!(executor.exec_str("int(-2.75)"), Ok("-2".into()));
+        // the floats are back when switched off
+        let mut parser = parser();
+        prefer_decimal(&mut parser, false);
+        let mut floats = Executor::new(lexer(), parser, env());
+        assert_eq!(floats.exec_value("0.5")?.type_name(), "Float");
+        assert_eq!(Decimal::from(3), "3.0".parse()?);
+        Ok(())
+    }
+
+    /// decimal division is exact when it can be and rounded half to even when it can't.
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn decimal_division() {
+        let mut executor = exec_decimal();
+        assert_eq!(executor.exec_str("1.0 / 8"), Ok("0.125".into()));
+        assert_eq!(executor.exec_str("1.0 / 3"), Ok("0.3333333333333333333333333333".into()));
+        assert_eq!(executor.exec_str("2.0 / 3"), Ok("0.6666666666666666666666666667".into()));
+        assert_eq!(executor.exec_str("7.5 // 2"), Ok("3".into()));
+        assert_eq!(executor.exec_str("7.5 % 2"), Ok("1.5".into()));
+        let err = executor.exec_str("1.5 / 0").expect_err("dividing by zero is an error");
+        assert!(err.ends_with("Cannot divide by zero"), "got {err}");
+        // the errors name the operator
+        let err = executor.exec_str("7.5 // float(2)").expect_err("a float can't be mixed with a decimal");
+        assert!(err.ends_with("Cannot floor divide Decimal `7.5` by Float `2`"), "got {err}");
+        let err = executor.exec_str("7.5 % float(2)").expect_err("a float can't be mixed with a decimal");
+        assert!(err.ends_with("Cannot take the remainder of Decimal `7.5` by Float `2`"), "got {err}");
+        // integers alone still divide in the division mode
+        assert_eq!(executor.exec_value("1 / 2").map(|value| value.type_name()), Ok("Float"));
+    }
+
+    /// results too big for a decimal are errors.
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn decimal_overflow() {
+        let mut executor = exec_decimal();
+        let big = format!("{}.5", i128::MAX / 10);
+        let err = executor.exec_str(&format!("{big} * 100")).expect_err("the product is too big");
+        assert!(err.ends_with("Decimal overflow in `*`"), "got {err}");
+        let err = executor.exec_str(&format!("{big} + {big}")).expect_err("the sum is too big");
+        assert!(err.ends_with("Decimal overflow in `+`"), "got {err}");
+        let err = executor.exec_str(&format!("{}0.1", i128::MAX)).expect_err("the literal is too big");
+        assert!(err.contains("is too big for a Decimal"), "got {err}");
+    }
+}
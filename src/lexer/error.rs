@@ -0,0 +1,37 @@
+//! # Lexer Errors
+//! 
+//! Contains the [`LexError`] returned when the [`Lexer`](super::Lexer) cannot
+//! read a token.
+//! 
+
+use super::ReadPointer;
+
+/// An error from reading tokens with the position in the source where it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    /// Message describing what went wrong
+    pub message: String,
+    /// Where in the source the error happened
+    pub position: ReadPointer,
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (line, col) = self.position.user_position();
+        write!(f, "{} at line {line} col {col}", self.message)
+    }
+}
+
+/// Keeps lex errors usable where a `String` error is expected.
+impl From<LexError> for String {
+    fn from(err: LexError) -> Self {
+        err.to_string()
+    }
+}
+
+impl LexError {
+    /// Make a new lex error at the `position`.
+    pub fn new(message: &str, position: ReadPointer) -> LexError {
+        LexError { message: message.to_owned(), position }
+    }
+}
@@ -0,0 +1,102 @@
+//! # Token Assertion Macros
+//!
+//! This is where the [`assert_tokens`] macro is defined and tested.
+//!
+
+
+// -=-=-=-=- Testing Macros -=-=-=-=- //
+
+/// Asserts that a source lexes to exactly the expected tokens, written in their
+/// [compact](crate::lexer::Token::compact) form `type:value@line:col` and separated by
+/// spaces - see [`Lexer::lex_to_string`](crate::lexer::Lexer::lex_to_string).
+///
+/// On panic both token streams are printed, a source that can't be lexed panics with the
+/// error.
+///
+/// Like [`assert`], this macro has a second form, where a custom panic message can be provided
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::assert_tokens;
+/// use interpreter::lang::math;
+/// assert_tokens!(math::lexer(), "2 * (x)", "int:2@1:1 op:*@1:3 op:(@1:5 ident:x@1:6 op:)@1:7");
+/// ```
+#[macro_export]
+macro_rules! assert_tokens {
+    ( $lexer:expr, $source:expr, $expected:expr $(, $($arg:tt)+)? ) => {
+        {
+            // matched so temporaries live until the end of the assertion
+            match (&$lexer, &$source, &$expected) { (lexer, source, expected) => {
+            let lexer: &$crate::lexer::Lexer = lexer;
+            let source: &str = ::std::convert::AsRef::as_ref(source);
+            let expected: &str = ::std::convert::AsRef::as_ref(expected);
+            let msg = ::std::string::String::new() $(+ &format!($($arg)+))?;
+            match lexer.lex_to_string(&mut $crate::lexer::LineReader::new(source)) {
+                Ok(tokens) if tokens == expected => {},
+                Ok(tokens) => panic!("When trying to match tokens: {msg}\n  tokens: {tokens}\nexpected: {expected}"),
+                Err(err) => panic!("When trying to match tokens: {msg}\nCould not lex the source: {err}"),
+            }
+            }}
+        }
+    };
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+/// Test that [`assert_tokens`] and the compact token stream are working properly.
+#[cfg(test)]
+mod tests {
+    use crate::lang::math;
+    use crate::lexer::{Lexer, LineReader};
+
+    /// a representative math expression, every kind of token is there.
+    #[test]
+    fn math_tokens() {
+        assert_tokens!(math::lexer(), "total := abs(-2.5) * [1, x][0] // 3",
+            "ident:total@1:1 declare::=@1:7 ident:abs@1:10 op:(@1:13 op:-@1:14 float:2.5@1:15 op:)@1:18 \
+            op:*@1:20 op:[@1:22 int:1@1:23 op:,@1:24 ident:x@1:26 op:]@1:27 op:[@1:28 int:0@1:29 op:]@1:30 \
+            op://@1:32 int:3@1:35");
+        assert_tokens!(math::lexer(), "p.x = {a: \"hi\"}; y += 1",
+            "ident:p@1:1 op:.@1:2 ident:x@1:3 assign:=@1:5 op:{@1:7 ident:a@1:8 colon::@1:9 string:hi@1:11 \
+            op:}@1:15 sep:;@1:16 ident:y@1:18 op_assign:+=@1:20 int:1@1:23");
+        assert_tokens!(math::lexer(), "", "");
+    }
+
+    /// the positions of tokens on later lines count from the start of their line.
+    #[test]
+    fn multi_line_tokens() -> Result<(), String> {
+        assert_tokens!(math::lexer(), "x := 1\n  y := x\n\nx + y",
+            "ident:x@1:1 declare::=@1:3 int:1@1:6 ident:y@2:3 declare::=@2:5 ident:x@2:8 \
+            ident:x@4:1 op:+@4:3 ident:y@4:5");
+        // a token over several lines stays on one line of the stream
+        let mut lexer = Lexer::new();
+        lexer.define("text", "<[^>]*>")?;
+        lexer.define_skip("space", "\\s+")?;
+        assert_tokens!(lexer, "<a\nb> <c\\d>", "text:<a\\nb>@1:1 text:<c\\\\d>@2:4");
+        Ok(())
+    }
+
+    /// the error of a source that can't be lexed is given back.
+    #[test]
+    fn lex_to_string_error() {
+        let err = math::lexer().lex_to_string(&mut LineReader::new("1 + #")).expect_err("`#` is not a token");
+        assert_eq!(err.to_string(), "Unknown token at line 1 col 5");
+    }
+
+    /// the failure shows both token streams.
+    #[test]
+    #[should_panic(expected = "  tokens: int:1@1:1\nexpected: int:2@1:1")]
+    fn assert_tokens_failure() {
+        assert_tokens!(math::lexer(), "1", "int:2@1:1", "the value differs");
+    }
+
+    /// a source that can't be lexed fails the assertion.
+    #[test]
+    #[should_panic(expected = "Could not lex the source")]
+    fn assert_tokens_lex_error() {
+        assert_tokens!(math::lexer(), "#", "");
+    }
+}
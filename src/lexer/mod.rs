@@ -2,10 +2,15 @@
 //! 
 //! manages parsing tokens from a reader.
 
+#[macro_use]
+mod macros;
+
 mod token;
 mod reader;
+mod error;
 
 pub use reader::*;
+pub use error::*;
 pub use token::*;
 
 
@@ -6,6 +6,9 @@
 //! - Replace `Result<(), String>` with custom error 
 //! 
 
+use std::{cell::{Cell, RefCell}, collections::HashMap, path::{Path, PathBuf}};
+use std::io::{BufRead, BufReader, Read};
+
 use regex::Regex;
 
 // -=-=- SizeType for Pointer -=-=- //
@@ -35,7 +38,7 @@ use regex::Regex;
 /// assert_eq!("def", val);
 /// ```
 pub trait SizeType {
-    /// get the reader size of a struct that implements the SizeType trait
+    /// get the reader size in chars of a struct that implements the SizeType trait
     fn get_size(&self) -> usize;
 }
 
@@ -48,9 +51,9 @@ impl SizeType for u32 {
 /// A marker trait for SizeTypes with a len component.
 trait SizeTypeLen {}
 impl SizeTypeLen for str {}
-impl<'a> SizeTypeLen for &'a str {}
+impl SizeTypeLen for &str {}
 impl SizeTypeLen for String {}
-impl<'a> SizeTypeLen for &'a String {}
+impl SizeTypeLen for &String {}
 
 // all the string types
 impl<T: ?Sized> SizeType for T
@@ -59,7 +62,7 @@ where
     T: SizeTypeLen
 {
     fn get_size(&self) -> usize {
-        self.as_ref().len()
+        self.as_ref().chars().count()
     }
 }
 
@@ -68,13 +71,16 @@ where
 
 /// A read-only pointer to a start and end position in a Reader's content
 #[derive(Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReadPointer {
     /// pointer stack
     stack: Vec<ReadPointer>,
     /// Format (start: line, col, end: line, col)
     pub line_pos: (u32,u32, u32,u32), 
-    /// Format (start, end)
+    /// Format (start, end) counted in chars
     pub read_pos: (u32, u32),
+    /// Format (start, end) counted in bytes, used to slice the reader's content
+    pub byte_pos: (u32, u32),
 }
 
 impl PartialEq for ReadPointer {
@@ -94,7 +100,8 @@ impl ReadPointer {
         ReadPointer::from_pos((0,0, 0,0), (0,0))
     }
 
-    /// make a NEW pointer that spans the line positions and read positions.
+    /// make a NEW pointer that spans the line positions and read positions, the byte
+    /// positions are the same as the read positions so the content should be ASCII.
     /// 
     /// ---
     /// 
@@ -105,13 +112,34 @@ impl ReadPointer {
     /// let ptr1 = ReadPointer::from_pos((0,3, 0,6), (3, 6));
     /// ```
     pub fn from_pos (line_pos: (u32,u32, u32,u32), read_pos: (u32,u32)) -> ReadPointer {
-        ReadPointer {line_pos, read_pos, stack: vec![] }
+        ReadPointer::from_pos_bytes(line_pos, read_pos, read_pos)
+    }
+
+    /// make a NEW pointer that spans the line positions, read positions and byte positions,
+    /// the byte positions are where the read positions are in the content.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::ReadPointer;
+    /// // `é` takes two bytes
+    /// let ptr = ReadPointer::from_pos_bytes((0,1, 0,2), (1, 2), (2, 3));
+    /// assert_eq!(ptr.byte_len(), 1);
+    /// ```
+    pub fn from_pos_bytes (line_pos: (u32,u32, u32,u32), read_pos: (u32,u32), byte_pos: (u32,u32)) -> ReadPointer {
+        ReadPointer {line_pos, read_pos, byte_pos, stack: vec![] }
     }
 
     /// make a NEW pointer that spans the position from one pointer to another.
     /// 
     /// ---
     /// 
+    /// The new pointer continues from `to` so it keeps the stack of `to` - any positions
+    /// pushed while reading from `from` to `to` can still be restored with `back`. When
+    /// `to` was read forward from `from` the stack of `from` is always the bottom of it.
+    /// 
     /// ## Example
     /// 
     /// ```
@@ -126,10 +154,140 @@ impl ReadPointer {
         ReadPointer {
             line_pos: (from.line_pos.0, from.line_pos.1, to.line_pos.2, to.line_pos.3),
             read_pos: (from.read_pos.0, to.read_pos.1),
-            stack: from.stack.clone(), // Required for parser backtracking
+            byte_pos: (from.byte_pos.0, to.byte_pos.1),
+            stack: to.stack.clone(), // Required for parser backtracking
         }
     }
 
+    /// Get the offset from the start of the content to the start of the pointer.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::ReadPointer;
+    /// let ptr = ReadPointer::from_pos((1,2, 1,5), (10, 13));
+    /// assert_eq!(ptr.start_offset(), 10);
+    /// assert_eq!(ptr.end_offset(), 13);
+    /// assert_eq!(ptr.start_line_col(), (1, 2));
+    /// assert_eq!(ptr.end_line_col(), (1, 5));
+    /// ```
+    pub fn start_offset(&self) -> usize {
+        self.read_pos.0 as usize
+    }
+
+    /// Get the offset from the start of the content to the end of the pointer.
+    pub fn end_offset(&self) -> usize {
+        self.read_pos.1 as usize
+    }
+
+    /// Get the 0-based line and column of the start of the pointer.
+    pub fn start_line_col(&self) -> (u32, u32) {
+        (self.line_pos.0, self.line_pos.1)
+    }
+
+    /// Get the 0-based line and column of the end of the pointer.
+    pub fn end_line_col(&self) -> (u32, u32) {
+        (self.line_pos.2, self.line_pos.3)
+    }
+
+    /// Get the line and column of the start of the pointer counted from 1, the way they
+    /// are shown to users.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::ReadPointer;
+    /// let ptr = ReadPointer::from_pos((0,0, 0,1), (0, 1));
+    /// assert_eq!(ptr.user_position(), (1, 1));
+    /// ```
+    pub fn user_position(&self) -> (u32, u32) {
+        (self.line_pos.0 + 1, self.line_pos.1 + 1)
+    }
+
+    /// Check if the 0-based `line` and `col` is between the start and the end of the
+    /// pointer, the end is not included.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::ReadPointer;
+    /// let ptr = ReadPointer::from_pos((0,3, 1,2), (3, 10));
+    /// assert!(ptr.contains(0, 3));
+    /// assert!(ptr.contains(1, 1));
+    /// assert!(!ptr.contains(1, 2));
+    /// assert!(!ptr.contains(0, 2));
+    /// ```
+    pub fn contains(&self, line: u32, col: u32) -> bool {
+        (self.start_line_col()..self.end_line_col()).contains(&(line, col))
+    }
+
+    /// Check if the pointers share any of the content between them.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::ReadPointer;
+    /// let ptr = ReadPointer::from_pos((0,3, 0,6), (3, 6));
+    /// assert!(ptr.overlaps(&ReadPointer::from_pos((0,5, 0,8), (5, 8))));
+    /// assert!(!ptr.overlaps(&ReadPointer::from_pos((0,6, 0,8), (6, 8))));
+    /// ```
+    pub fn overlaps(&self, other: &ReadPointer) -> bool {
+        self.start_offset() < other.end_offset() && other.start_offset() < self.end_offset()
+    }
+
+    /// Get an empty pointer at the end of the pointer, reading continues from there when
+    /// it is [restored](Reader::restore).
+    pub fn to_end(&self) -> ReadPointer {
+        let mut ptr = ReadPointer { stack: vec![], ..self.clone() };
+        ptr.commit();
+        ptr
+    }
+
+    /// Move a pointer that starts at or after `from` as if `from` had been moved to `to`,
+    /// for the content after `from` staying the same - such as the tokens after an edit.
+    /// The column only changes on the line `from` starts on.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::ReadPointer;
+    /// // `b` in "a b\nc" after an edit moved `a` from col 0 to col 2 of the next line
+    /// let from = ReadPointer::from_pos((0,0, 0,1), (0, 1));
+    /// let to = ReadPointer::from_pos((1,2, 1,3), (5, 6));
+    /// let mut ptr = ReadPointer::from_pos((0,2, 0,3), (2, 3));
+    /// ptr.shift(&from, &to);
+    /// assert_eq!(ptr, ReadPointer::from_pos((1,4, 1,5), (7, 8)));
+    /// // content on the lines after keeps its column
+    /// let mut ptr = ReadPointer::from_pos((1,0, 1,1), (4, 5));
+    /// ptr.shift(&from, &to);
+    /// assert_eq!(ptr, ReadPointer::from_pos((2,0, 2,1), (9, 10)));
+    /// ```
+    pub fn shift(&mut self, from: &ReadPointer, to: &ReadPointer) {
+        let moved = |pos: u32, from: u32, to: u32| pos - from + to;
+        let (line, col) = (from.line_pos.0, from.line_pos.1);
+        let (to_line, to_col) = (to.line_pos.0, to.line_pos.1);
+        if self.line_pos.0 == line {
+            self.line_pos.1 = moved(self.line_pos.1, col, to_col);
+        }
+        if self.line_pos.2 == line {
+            self.line_pos.3 = moved(self.line_pos.3, col, to_col);
+        }
+        self.line_pos.0 = moved(self.line_pos.0, line, to_line);
+        self.line_pos.2 = moved(self.line_pos.2, line, to_line);
+        self.read_pos = (moved(self.read_pos.0, from.read_pos.0, to.read_pos.0), moved(self.read_pos.1, from.read_pos.0, to.read_pos.0));
+        self.byte_pos = (moved(self.byte_pos.0, from.byte_pos.0, to.byte_pos.0), moved(self.byte_pos.1, from.byte_pos.0, to.byte_pos.0));
+    }
+
     /// Move a referenced pointer using the string provided
     /// 
     /// ---
@@ -146,12 +304,10 @@ impl ReadPointer {
     fn move_pointer(ptr: &mut ReadPointer, raw: &str) {
         let mut chars = raw.chars().peekable();
         while let Some(c) = chars.next() {
-            ptr.increment();
+            ptr.increment(c);
             match c {
                 '\n' => ptr.increment_line(),
-                '\r' => {
-                    if chars.peek() != Some(&'\n') { ptr.increment_line(); }
-                }
+                '\r' if chars.peek() != Some(&'\n') => ptr.increment_line(),
                 _ => {}
             }
         }
@@ -159,12 +315,13 @@ impl ReadPointer {
 
     // -=-=- Seeking -=-=- //
 
-    /// Increment the line column and read position of a pointer.
-    fn increment(&mut self) {
+    /// Increment the line column and read position of a pointer past the char.
+    fn increment(&mut self, c: char) {
         // add one to col
         self.line_pos.3 += 1;
         // add one to read pos
         self.read_pos.1 += 1;
+        self.byte_pos.1 += c.len_utf8() as u32;
     }
 
     /// Increment the line number and return the line column to 0.
@@ -179,6 +336,7 @@ impl ReadPointer {
     fn commit(&mut self) {
         // (start, end)
         self.read_pos.0 = self.read_pos.1;
+        self.byte_pos.0 = self.byte_pos.1;
         // (start: line, col, end: line, col)
         self.line_pos.0 = self.line_pos.2;
         self.line_pos.1 = self.line_pos.3;
@@ -203,7 +361,7 @@ impl ReadPointer {
     /// // Push the current state then modiyfy it
     /// let start = ptr.clone();
     /// ptr.push();
-    /// ptr.increment();
+    /// ptr.increment('a');
     /// ptr.increment_line();
     /// // Ensure the pointer's state has changed
     /// assert_ne!(ptr, start);
@@ -228,7 +386,7 @@ impl ReadPointer {
     /// // Push the current state then modiyfy it
     /// let start = ptr.clone();
     /// ptr.push();
-    /// ptr.increment();
+    /// ptr.increment('a');
     /// ptr.increment_line();
     /// let new = ptr.clone();
     /// // Ensure the pointer's state has changed
@@ -240,7 +398,9 @@ impl ReadPointer {
     /// assert_ne!(ptr, start);
     /// ```
     fn push(&mut self) {
-        self.stack.push(self.clone());
+        // only the positions are saved, saving the stack too would double its size on every push
+        let saved = ReadPointer { stack: vec![], line_pos: self.line_pos, read_pos: self.read_pos, byte_pos: self.byte_pos };
+        self.stack.push(saved);
         // println!("PUSH [{}] {self}", self.stack.len())
     }
     
@@ -250,7 +410,11 @@ impl ReadPointer {
     /// 
     /// See: [`ReadPointer::push`]
     fn back(&mut self) {
-        *self = self.stack.pop().unwrap_or_else(|| self.clone());
+        if let Some(saved) = self.stack.pop() {
+            self.line_pos = saved.line_pos;
+            self.read_pos = saved.read_pos;
+            self.byte_pos = saved.byte_pos;
+        }
         // println!("POP  [{}] {self}", self.stack.len())
     }
     
@@ -264,10 +428,39 @@ impl ReadPointer {
         // println!("PULL [{}] {self}", self.stack.len())
     }
 
-    /// Get the length of a pointer
+    /// Get the length of a pointer in chars, see [`byte_len`](ReadPointer::byte_len) for
+    /// the length of the content it spans.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Reader, LineReader};
+    /// let mut reader = LineReader::new("αβγ");
+    /// let _ = reader.next(3);
+    /// assert_eq!(reader.get_pointer().len(), 3);
+    /// assert_eq!(reader.get_pointer().byte_len(), 6);
+    /// ```
     pub fn len(&self) -> usize {
         ( self.read_pos.1 - self.read_pos.0 ) as usize
     }
+
+    /// Get the length of the content a pointer spans in bytes.
+    pub fn byte_len(&self) -> usize {
+        ( self.byte_pos.1 - self.byte_pos.0 ) as usize
+    }
+
+    /// Check if the pointer spans no content
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Get how many bytes the first `count` chars of the content take, `None` when the
+/// content is shorter.
+fn char_bytes(content: &str, count: usize) -> Option<usize> {
+    content.char_indices().map(|(i, _)| i).chain([content.len()]).nth(count)
 }
 
 // -=-=-=-=- Readers -=-=-=-=- //
@@ -290,6 +483,9 @@ pub trait Reader {
     
     /// Read the next value in the line if it matches a regular expression
     fn read_regex(&self, regex: &Regex) -> Option<(&str, ReadPointer)>;
+
+    /// Read the value after the end of the ReadPointer if it matches a regular expression
+    fn read_regex_at(&self, ptr: &ReadPointer, regex: &Regex) -> Option<(&str, ReadPointer)>;
     
     // -=- Seeking -=- //
     
@@ -326,10 +522,66 @@ pub trait Reader {
     /// See: [`ReadPointer`]
     fn commit(&mut self);
     
+    /// Restore the pointer to a checkpoint made with [`checkpoint`](Reader::checkpoint).
+    /// 
+    /// ---
+    /// 
+    /// The checkpoint holds the pointer's stack at the time it was made, so any
+    /// [`push`](Reader::push) made after the checkpoint is dropped when restoring.
+    /// 
+    /// The default goes [`back`](Reader::back) to the last position pushed before the
+    /// checkpoint and reads forward to it, so it can't go behind that position - readers
+    /// that can set their pointer should restore it directly.
+    fn restore(&mut self, checkpoint: ReadPointer) {
+        // going back to the last position pushed before the checkpoint and pushing it
+        // again leaves the stack the checkpoint had
+        let depth = checkpoint.stack.len();
+        while self.get_pointer().stack.len() >= depth.max(1) {
+            self.back();
+        }
+        if depth > 0 {
+            self.push();
+        }
+        let (start, end) = checkpoint.read_pos;
+        match self.get_pointer().read_pos {
+            (from, to) if from == start && to <= end => {
+                let _ = self.next(end - to);
+            }
+            (_, to) if to <= start => {
+                let _ = self.next(start - to);
+                self.commit();
+                let _ = self.next(end - start);
+            }
+            _ => (),
+        }
+    }
+    
     // -=- Pointer Getters -=-=- //
     
     /// Get the current pointer value
     fn get_pointer(&self) -> &ReadPointer;
+
+    /// Get a checkpoint of the current pointer that can be passed to
+    /// [`restore`](Reader::restore) to roll the reader back.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Reader, LineReader};
+    /// let mut reader = LineReader::new("abcdefg");
+    /// let checkpoint = reader.checkpoint();
+    /// 
+    /// let _ = reader.next(3);
+    /// assert_eq!("abc", reader.read_current().unwrap());
+    /// 
+    /// reader.restore(checkpoint);
+    /// assert_eq!("", reader.read_current().unwrap());
+    /// ```
+    fn checkpoint(&self) -> ReadPointer {
+        self.get_pointer().clone()
+    }
     
     /// get a token's pointer using a starting pointer and raw value
     fn get_token_pointer(raw: &str, ptr: &ReadPointer) -> ReadPointer {
@@ -396,8 +648,8 @@ impl Reader for LineReader {
     /// assert_eq!('d', ch);
     /// ```
     fn read_char(&self) -> Option<char> {
-        let i = self.pointer.read_pos.1 as usize;
-        self.content.chars().nth(i)
+        let i = self.pointer.byte_pos.1 as usize;
+        self.content.get(i..)?.chars().next()
     }
     
     /// Read the current value pointed at internally
@@ -432,10 +684,8 @@ impl Reader for LineReader {
     /// assert_eq!("abcd", val);
     /// ```
     fn read_next(&self, size: usize) -> Option<(&str, ReadPointer)> {
-        // todo set up read bounds
-        let i = self.pointer.read_pos.1 as usize;
-        let j = i + size;
-        let raw = &self.content[i..j];
+        let rest = self.content.get(self.pointer.byte_pos.1 as usize..)?;
+        let raw = &rest[..char_bytes(rest, size)?];
         Some((raw, <Self as Reader>::get_token_pointer(raw, &self.pointer)))
     }
 
@@ -454,10 +704,9 @@ impl Reader for LineReader {
     /// assert_eq!("def", val);
     /// ```
     fn read_pointer(&self, ptr: &ReadPointer) -> Option<&str> {
-        // todo set up read bounds
-        let i = ptr.read_pos.0 as usize;
-        let j = ptr.read_pos.1 as usize;
-        Some(&self.content[i..j])
+        let i = ptr.byte_pos.0 as usize;
+        let j = ptr.byte_pos.1 as usize;
+        self.content.get(i..j)
     }
 
     /// Read the next value in the line if it matches a regular expression
@@ -476,10 +725,32 @@ impl Reader for LineReader {
     /// assert_eq!("abcd", val);
     /// ```
     fn read_regex(&self, regex: &Regex) -> Option<(&str, ReadPointer)> {
-        let i = self.pointer.read_pos.1 as usize;
-        let m = regex.find(&self.content[i..])?;
+        self.read_regex_at(&self.pointer, regex)
+    }
+
+    /// Read the value after the end of the ReadPointer if it matches a regular expression,
+    /// a match that doesn't start right at the pointer is not read.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{ReadPointer, Reader, LineReader};
+    /// use regex::Regex;
+    /// let reader = LineReader::new("abcdefg");
+    /// let ptr = ReadPointer::from_pos((0,0, 0,3), (0, 3));
+    /// let re = Regex::new("^[a-e]+").unwrap();
+    /// 
+    /// let (val, ptr) = reader.read_regex_at(&ptr, &re).unwrap();
+    /// assert_eq!("de", val);
+    /// assert_eq!(ptr, ReadPointer::from_pos((0,3, 0,5), (3, 5)));
+    /// ```
+    fn read_regex_at(&self, ptr: &ReadPointer, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        let i = ptr.byte_pos.1 as usize;
+        let m = regex.find(self.content.get(i..)?).filter(|m| m.start() == 0)?;
         let raw = m.as_str();
-        Some((raw, <Self as Reader>::get_token_pointer(raw, &self.pointer)))
+        Some((raw, <Self as Reader>::get_token_pointer(raw, ptr)))
     }
     
     // -=-=- Pointer -=-=- //
@@ -649,11 +920,159 @@ impl Reader for LineReader {
         self.pointer.back();
         // println!("Pop [{}] {:?}", self.stack.len(), self.pointer);
     }
+
+    /// Restore the pointer to a checkpoint made with [`checkpoint`](Reader::checkpoint).
+    /// 
+    /// ---
+    /// 
+    /// See: [`Reader::checkpoint`]
+    fn restore(&mut self, checkpoint: ReadPointer) {
+        self.pointer = checkpoint;
+    }
     
     
 
 }
 
+// -=-=- Session Reader -=-=- //
+
+/// Reads a document that grows one input at a time, such as the lines entered into the
+/// REPL, so the positions it gives count from the start of the session.
+/// 
+/// ---
+/// 
+/// Each [`push_line`](SessionReader::push_line) appends a line to the document and starts
+/// the pointer where the previous line ended, so only the new line is left to read.
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::lexer::{Reader, SessionReader};
+/// let mut reader = SessionReader::new();
+/// reader.push_line("x := 1");
+/// reader.push_line("y := 2");
+/// 
+/// let (val, ptr) = reader.read_next(1).unwrap();
+/// assert_eq!("y", val);
+/// assert_eq!(ptr.user_position(), (2, 1));
+/// assert_eq!(reader.document(), "x := 1\ny := 2");
+/// ```
+pub struct SessionReader {
+    reader: LineReader,
+    /// where the document ends, the next line starts after it
+    end: ReadPointer,
+    lines: usize,
+}
+
+impl SessionReader {
+    /// Make a new session reader with an empty document.
+    pub fn new() -> SessionReader {
+        SessionReader { reader: LineReader::new(""), end: ReadPointer::new(), lines: 0 }
+    }
+
+    /// Append a line to the document and point at the start of it.
+    pub fn push_line(&mut self, line: &str) {
+        if self.lines > 0 {
+            self.reader.content.push('\n');
+            ReadPointer::move_pointer(&mut self.end, "\n");
+        }
+        self.end.commit();
+        self.reader.content.push_str(line);
+        self.reader.pointer = self.end.clone();
+        ReadPointer::move_pointer(&mut self.end, line);
+        self.lines += 1;
+    }
+
+    /// Get the number of lines pushed so far.
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Get every line pushed so far, separated by newlines.
+    pub fn document(&self) -> &str {
+        &self.reader.content
+    }
+}
+
+impl Default for SessionReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reader for SessionReader {
+    // -=-=- Reading -=-=- //
+    
+    /// Read the next character in the document
+    fn read_char(&self) -> Option<char> {
+        self.reader.read_char()
+    }
+    
+    /// Read the current value pointed at internally
+    fn read_current(&self) -> Option<&str> {
+        self.reader.read_current()
+    }
+    
+    /// Read the next value in the document with a length of `size`
+    fn read_next(&self, size: usize) -> Option<(&str, ReadPointer)> {
+        self.reader.read_next(size)
+    }
+    
+    /// Read the value pointed at by the ReadPointer
+    fn read_pointer(&self, ptr: &ReadPointer) -> Option<&str> {
+        self.reader.read_pointer(ptr)
+    }
+    
+    /// Read the next value in the document if it matches a regular expression
+    fn read_regex(&self, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        self.reader.read_regex(regex)
+    }
+
+    /// Read the value after the ReadPointer if it matches a regular expression
+    fn read_regex_at(&self, ptr: &ReadPointer, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        self.reader.read_regex_at(ptr, regex)
+    }
+    
+    // -=-=- Seeking -=-=- //
+    
+    /// Move the pointer ahead by the size of the supplied value.
+    fn next<T>(&mut self, size: T) -> Result<(), String> where T: SizeType {
+        self.reader.next(size)
+    }
+    
+    /// Pulls the pointers start position to the end position.
+    fn commit(&mut self) {
+        self.reader.commit()
+    }
+    
+    /// Push the pointer on the stack to save it's state for one `back` or `pop` call.
+    fn push(&mut self) {
+        self.reader.push()
+    }
+    
+    /// pop the pointer off the stack without restoring the pointer
+    fn pop(&mut self) {
+        self.reader.pop()
+    }
+    
+    /// Restore the pointer and Pop the pointer off the stack
+    fn back(&mut self) {
+        self.reader.back()
+    }
+
+    /// Restore the pointer to a checkpoint
+    fn restore(&mut self, checkpoint: ReadPointer) {
+        self.reader.restore(checkpoint)
+    }
+    
+    // -=-=- Pointer -=-=- //
+    
+    /// Get the current pointer value
+    fn get_pointer(&self) -> &ReadPointer {
+        self.reader.get_pointer()
+    }
+}
+
 /// Takes a file path and reads the file contents for implementing the Reader functionality.
 /// 
 /// ---
@@ -662,72 +1081,449 @@ impl Reader for LineReader {
 /// 
 /// ``` ignore
 /// use interpreter::lexer::FileReader;
-/// let reader = FileReader::new("./path/to/file.ext");
+/// let reader = FileReader::new("./path/to/file.ext")?;
 /// ```
-struct _FileReader {}
+pub struct FileReader {
+    path: PathBuf,
+    reader: LineReader,
+}
+
+impl FileReader {
+    /// Make a new file reader by reading the whole file at `path`.
+    pub fn new<P>(path: P) -> Result<FileReader, String> where P: AsRef<Path> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read file `{}`: {err}", path.display()))?;
+        Ok(FileReader { path: path.to_owned(), reader: LineReader::new(&content) })
+    }
 
-impl Reader for _FileReader {
+    /// Get the path of the file being read.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Reader for FileReader {
     // -=-=- Reading -=-=- //
     
-    /// Read the next character in the line
+    /// Read the next character in the file
     fn read_char(&self) -> Option<char> {
-        todo!()
+        self.reader.read_char()
     }
     
     /// Read the current value pointed at internally
     fn read_current(&self) -> Option<&str> {
-        todo!()
+        self.reader.read_current()
     }
     
-    /// Read the next value in the line with a length of `size`
-    fn read_next(&self, _size: usize) -> Option<(&str, ReadPointer)> {
-        todo!()
+    /// Read the next value in the file with a length of `size`
+    fn read_next(&self, size: usize) -> Option<(&str, ReadPointer)> {
+        self.reader.read_next(size)
     }
     
     /// Read the value pointed at by the ReadPointer
-    fn read_pointer(&self, _ptr: &ReadPointer) -> Option<&str> {
-        todo!()
+    fn read_pointer(&self, ptr: &ReadPointer) -> Option<&str> {
+        self.reader.read_pointer(ptr)
     }
     
-    /// Read the next value in the line if it matches a regular expression
-    fn read_regex(&self, _regex: &Regex) -> Option<(&str, ReadPointer)> {
-        todo!()
+    /// Read the next value in the file if it matches a regular expression
+    fn read_regex(&self, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        self.reader.read_regex(regex)
+    }
+
+    /// Read the value after the ReadPointer if it matches a regular expression
+    fn read_regex_at(&self, ptr: &ReadPointer, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        self.reader.read_regex_at(ptr, regex)
     }
     
     // -=-=- Seeking -=-=- //
     
     /// Move the pointer ahead by the size of the supplied value.
-    fn next<T>(&mut self, _size: T) -> Result<(), String> where T: SizeType {
-        todo!()
+    fn next<T>(&mut self, size: T) -> Result<(), String> where T: SizeType {
+        self.reader.next(size)
     }
     
     /// Pulls the pointers start position to the end position.
     fn commit(&mut self) {
-        todo!()
+        self.reader.commit()
+    }
+    
+    /// Push the pointer on the stack to save it's state for one `back` or `pop` call.
+    fn push(&mut self) {
+        self.reader.push()
+    }
+    
+    /// pop the pointer off the stack without restoring the pointer
+    fn pop(&mut self) {
+        self.reader.pop()
+    }
+    
+    /// Restore the pointer and Pop the pointer off the stack
+    fn back(&mut self) {
+        self.reader.back()
+    }
+
+    /// Restore the pointer to a checkpoint
+    fn restore(&mut self, checkpoint: ReadPointer) {
+        self.reader.restore(checkpoint)
+    }
+    
+    // -=-=- Pointer -=-=- //
+    
+    /// Get the current pointer value
+    fn get_pointer(&self) -> &ReadPointer {
+        self.reader.get_pointer()
+    }
+}
+
+
+// -=-=- Stream Reader -=-=- //
+
+/// How many bytes past the pointer a [`StreamReader`] keeps read from its source.
+const STREAM_LOOKAHEAD: usize = 4096;
+
+/// Reads the content from an [`io::Read`](std::io::Read) source as it is needed instead of
+/// all up front, so it can read from a pipe or stdin while it is still being written.
+/// 
+/// ---
+/// 
+/// Whole lines are read from the source until there are at least `lookahead` bytes past
+/// the pointer and more than whitespace, a token or skipped run longer than that can be
+/// cut short. The content
+/// before the pointer is dropped when it is [committed](Reader::commit) with nothing
+/// [pushed](Reader::push), so it can't be [restored](Reader::restore) to after that.
+/// 
+/// ## Example
+/// 
+/// ```
+/// use std::io::Cursor;
+/// use interpreter::lexer::{Lexer, StreamReader};
+/// let mut lexer = Lexer::new();
+/// lexer.define("int", "[0-9]+")?;
+/// lexer.define_skip("space", "\\s+")?;
+/// 
+/// let mut reader = StreamReader::new(Cursor::new("1 2\n3"));
+/// assert_eq!(lexer.tokenize(&mut reader)?.len(), 3);
+/// Ok::<(), String>(())
+/// ```
+pub struct StreamReader<R: Read> {
+    source: BufReader<R>,
+    /// the content that is still kept, starting at the read position `base`
+    content: String,
+    base: u32,
+    pointer: ReadPointer,
+    lookahead: usize,
+    eof: bool,
+    error: Option<String>,
+}
+
+impl<R: Read> StreamReader<R> {
+    /// Make a new stream reader, the first lines are read from the source straight away.
+    pub fn new(source: R) -> StreamReader<R> {
+        StreamReader::with_lookahead(source, STREAM_LOOKAHEAD)
+    }
+
+    /// Make a new stream reader that keeps at least `lookahead` bytes past the pointer read.
+    pub fn with_lookahead(source: R, lookahead: usize) -> StreamReader<R> {
+        let mut reader = StreamReader {
+            source: BufReader::new(source),
+            content: String::new(),
+            base: 0,
+            pointer: ReadPointer::new(),
+            lookahead,
+            eof: false,
+            error: None,
+        };
+        reader.fill(0);
+        reader
+    }
+
+    /// Get the number of bytes of content that are kept.
+    pub fn buffered(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Get the error that stopped the source from being read, the content ends where it
+    /// happened.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Read lines from the source until there are `extra` chars past the pointer, as well
+    /// as the lookahead and something other than whitespace.
+    fn fill(&mut self, extra: usize) {
+        // a char is at most 4 bytes
+        let end = self.pointer.byte_pos.1 as usize + (extra * 4).max(self.lookahead);
+        while !self.eof && (self.base as usize + self.content.len() < end
+            || !self.content.ends_with('\n')
+            || self.content_at(self.pointer.byte_pos.1).is_none_or(|rest| rest.trim().is_empty()))
+        {
+            match self.source.read_line(&mut self.content) {
+                Ok(0) => self.eof = true,
+                Ok(_) => {},
+                Err(err) => {
+                    self.error = Some(err.to_string());
+                    self.eof = true;
+                },
+            }
+        }
+    }
+
+    /// Get the content from a byte position on, if it is still kept.
+    fn content_at(&self, pos: u32) -> Option<&str> {
+        self.content.get(pos.checked_sub(self.base)? as usize..)
+    }
+}
+
+impl<R: Read> Reader for StreamReader<R> {
+    // -=-=- Reading -=-=- //
+    
+    /// Read the next character in the stream
+    fn read_char(&self) -> Option<char> {
+        self.content_at(self.pointer.byte_pos.1)?.chars().next()
+    }
+    
+    /// Read the current value pointed at internally
+    fn read_current(&self) -> Option<&str> {
+        self.read_pointer(&self.pointer)
+    }
+    
+    /// Read the next value in the stream with a length of `size`
+    fn read_next(&self, size: usize) -> Option<(&str, ReadPointer)> {
+        let rest = self.content_at(self.pointer.byte_pos.1)?;
+        let raw = &rest[..char_bytes(rest, size)?];
+        Some((raw, <Self as Reader>::get_token_pointer(raw, &self.pointer)))
+    }
+    
+    /// Read the value pointed at by the ReadPointer
+    fn read_pointer(&self, ptr: &ReadPointer) -> Option<&str> {
+        self.content_at(ptr.byte_pos.0)?.get(..ptr.byte_len())
+    }
+    
+    /// Read the next value in the stream if it matches a regular expression
+    fn read_regex(&self, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        self.read_regex_at(&self.pointer, regex)
+    }
+
+    /// Read the value after the ReadPointer if it matches a regular expression, a match
+    /// that doesn't start right at the pointer is not read.
+    fn read_regex_at(&self, ptr: &ReadPointer, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        let raw = regex.find(self.content_at(ptr.byte_pos.1)?).filter(|m| m.start() == 0)?.as_str();
+        Some((raw, <Self as Reader>::get_token_pointer(raw, ptr)))
+    }
+    
+    // -=-=- Seeking -=-=- //
+    
+    /// Move the pointer ahead by the size of the supplied value, reading more of the
+    /// stream when it is needed.
+    fn next<T>(&mut self, size: T) -> Result<(), String> where T: SizeType {
+        let count = size.get_size();
+        self.fill(count);
+        let raw = match self.read_next(count) {
+            Some((raw, _)) => raw.to_owned(),
+            None => return Err(String::from("Couldn't read next, the stream ended")),
+        };
+        ReadPointer::move_pointer(&mut self.pointer, &raw);
+        self.fill(0);
+        Ok(())
+    }
+    
+    /// Pulls the pointers start position to the end position, the content before it is
+    /// dropped when nothing is pushed.
+    fn commit(&mut self) {
+        self.pointer.commit();
+        if self.pointer.stack.is_empty() {
+            let consumed = (self.pointer.byte_pos.0 - self.base) as usize;
+            self.content.drain(..consumed);
+            self.base = self.pointer.byte_pos.0;
+        }
+        self.fill(0);
+    }
+    
+    /// Push the pointer on the stack to save it's state for one `back` or `pop` call.
+    fn push(&mut self) {
+        self.pointer.push()
+    }
+    
+    /// pop the pointer off the stack without restoring the pointer
+    fn pop(&mut self) {
+        self.pointer.pop()
+    }
+    
+    /// Restore the pointer and Pop the pointer off the stack
+    fn back(&mut self) {
+        self.pointer.back();
+        self.fill(0);
+    }
+
+    /// Restore the pointer to a checkpoint
+    fn restore(&mut self, checkpoint: ReadPointer) {
+        self.pointer = checkpoint;
+        self.fill(0);
+    }
+    
+    // -=-=- Pointer -=-=- //
+    
+    /// Get the current pointer value
+    fn get_pointer(&self) -> &ReadPointer {
+        &self.pointer
     }
+}
+
+
+// -=-=- Caching Reader -=-=- //
 
+/// The end of a regex match: (end: line, col), the end read position and the end byte position.
+type MatchEnd = ((u32, u32), u32, u32);
+
+/// Wraps another reader and remembers the result of every regex read by the position it
+/// was read at. When the parser backtracks it probes the same positions again and again, so
+/// those reads are looked up instead of running the regex again.
+/// 
+/// ---
+/// 
+/// The content of the wrapped reader never changes, so a cached read is always the same as
+/// an uncached one - only the regex is skipped.
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::lexer::{CachingReader, Lexer, LineReader};
+/// let mut lexer = Lexer::new();
+/// lexer.define("num", "[0-9]+")?;
+/// let reader = CachingReader::new(LineReader::new("123"));
+/// 
+/// let first = lexer.get_next_token("num", &reader).ok_or("Couldn't find token")?;
+/// let second = lexer.get_next_token("num", &reader).ok_or("Couldn't find token")?;
+/// assert_eq!(first, second);
+/// assert_eq!(reader.regex_calls(), 1);
+/// assert_eq!(reader.cache_hits(), 1);
+/// Ok::<(), String>(())
+/// ```
+pub struct CachingReader<R: Reader> {
+    reader: R,
+    /// regex source => start read position => end of the match
+    cache: RefCell<HashMap<String, HashMap<u32, Option<MatchEnd>>>>,
+    regex_calls: Cell<usize>,
+    cache_hits: Cell<usize>,
+}
+
+impl<R: Reader> CachingReader<R> {
+    /// Wrap a reader so its regex reads are cached.
+    pub fn new(reader: R) -> CachingReader<R> {
+        CachingReader { reader, cache: RefCell::new(HashMap::new()), regex_calls: Cell::new(0), cache_hits: Cell::new(0) }
+    }
+
+    /// Unwrap the reader, dropping the cache.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Get the number of times a regex was run on the wrapped reader.
+    pub fn regex_calls(&self) -> usize {
+        self.regex_calls.get()
+    }
+
+    /// Get the number of regex reads answered from the cache.
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits.get()
+    }
+}
+
+impl<R: Reader> Reader for CachingReader<R> {
+    // -=-=- Reading -=-=- //
+    
+    /// Read the next character in the wrapped reader
+    fn read_char(&self) -> Option<char> {
+        self.reader.read_char()
+    }
     
+    /// Read the current value pointed at internally
+    fn read_current(&self) -> Option<&str> {
+        self.reader.read_current()
+    }
+    
+    /// Read the next value in the wrapped reader with a length of `size`
+    fn read_next(&self, size: usize) -> Option<(&str, ReadPointer)> {
+        self.reader.read_next(size)
+    }
+    
+    /// Read the value pointed at by the ReadPointer
+    fn read_pointer(&self, ptr: &ReadPointer) -> Option<&str> {
+        self.reader.read_pointer(ptr)
+    }
+    
+    /// Read the next value in the wrapped reader if it matches a regular expression
+    fn read_regex(&self, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        self.read_regex_at(self.get_pointer(), regex)
+    }
+
+    /// Read the value after the ReadPointer if it matches a regular expression, using
+    /// the cached match when the regex was already read at the same position.
+    fn read_regex_at(&self, ptr: &ReadPointer, regex: &Regex) -> Option<(&str, ReadPointer)> {
+        let start = ptr.read_pos.1;
+        let cached = self.cache.borrow().get(regex.as_str()).and_then(|reads| reads.get(&start).copied());
+        let end = match cached {
+            Some(end) => {
+                self.cache_hits.set(self.cache_hits.get() + 1);
+                end
+            }
+            None => {
+                self.regex_calls.set(self.regex_calls.get() + 1);
+                let end = self.reader.read_regex_at(ptr, regex)
+                    .map(|(_, next)| ((next.line_pos.2, next.line_pos.3), next.read_pos.1, next.byte_pos.1));
+                self.cache.borrow_mut().entry(regex.as_str().to_owned()).or_default().insert(start, end);
+                end
+            }
+        };
+        // the pointer continues from `ptr` so it keeps its stack
+        let ((line, col), read, byte) = end?;
+        let mut next = ptr.clone();
+        next.commit();
+        next.line_pos.2 = line;
+        next.line_pos.3 = col;
+        next.read_pos.1 = read;
+        next.byte_pos.1 = byte;
+        Some((self.reader.read_pointer(&next)?, next))
+    }
+    
+    // -=-=- Seeking -=-=- //
+    
+    /// Move the pointer ahead by the size of the supplied value.
+    fn next<T>(&mut self, size: T) -> Result<(), String> where T: SizeType {
+        self.reader.next(size)
+    }
+    
+    /// Pulls the pointers start position to the end position.
+    fn commit(&mut self) {
+        self.reader.commit()
+    }
     
     /// Push the pointer on the stack to save it's state for one `back` or `pop` call.
     fn push(&mut self) {
-        todo!()
+        self.reader.push()
     }
     
     /// pop the pointer off the stack without restoring the pointer
     fn pop(&mut self) {
-        todo!()
+        self.reader.pop()
     }
     
     /// Restore the pointer and Pop the pointer off the stack
     fn back(&mut self) {
-        todo!()
+        self.reader.back()
+    }
+
+    /// Restore the pointer to a checkpoint
+    fn restore(&mut self, checkpoint: ReadPointer) {
+        self.reader.restore(checkpoint)
     }
     
     // -=-=- Pointer -=-=- //
     
     /// Get the current pointer value
     fn get_pointer(&self) -> &ReadPointer {
-        todo!()
+        self.reader.get_pointer()
     }
 }
 
@@ -755,6 +1551,153 @@ mod doctest {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
+
+    /// the stream is read a few lines at a time while it is tokenized.
+    #[test]
+    fn stream_reader_tokenize() -> Result<(), String> {
+        let lexer = crate::lang::math::lexer();
+        let src: String = (0..200).map(|n| format!("x := {n} + 1.5\n")).collect();
+        let mut reader = StreamReader::with_lookahead(Cursor::new(src.clone().into_bytes()), 16);
+        assert!(reader.buffered() < 64, "only the first lines are read, got {}", reader.buffered());
+        let tokens = lexer.tokenize(&mut reader)?;
+        assert_eq!(tokens.len(), 200 * 5);
+        assert_eq!(tokens[7].value, "1");
+        assert_eq!(tokens[7].position.user_position(), (2, 6));
+        // the same tokens as reading it all up front
+        assert_eq!(tokens, lexer.tokenize(&mut LineReader::new(&src))?);
+        // the committed content is dropped
+        assert!(reader.buffered() < 64, "kept {} bytes", reader.buffered());
+        assert!(lexer.at_end(&reader));
+        assert!(reader.error().is_none());
+        Ok(())
+    }
+
+    /// every line pushed is read from where the last one ended, so an error on the third
+    /// line is reported there.
+    #[test]
+    fn session_reader_lines() -> Result<(), String> {
+        let mut executor = crate::exec::Executor::math();
+        let mut reader = SessionReader::new();
+        reader.push_line("x := 1");
+        executor.exec_spanned(&mut reader).map_err(|err| err.to_string())?;
+        reader.push_line("y := x + 2");
+        let (value, span) = executor.exec_spanned(&mut reader).map_err(|err| err.to_string())?;
+        assert_eq!(value, "3");
+        assert_eq!(span.user_position(), (2, 1));
+        reader.push_line("x / (y - 3)");
+        let err = executor.exec_spanned(&mut reader).expect_err("`y - 3` is zero");
+        assert_eq!(err.position.map(|pos| pos.user_position()), Some((3, 1)));
+        assert_eq!(reader.lines(), 3);
+        assert_eq!(reader.document(), "x := 1\ny := x + 2\nx / (y - 3)");
+        Ok(())
+    }
+
+    /// each push saves only the positions, the saved states don't hold copies of the stack.
+    #[test]
+    fn pointer_push_saves_positions() {
+        let mut ptr = ReadPointer::from_pos((0,0, 0,0), (0, 0));
+        for c in "abc".chars() {
+            ptr.push();
+            ptr.increment(c);
+        }
+        assert_eq!(ptr.stack.len(), 3);
+        assert!(ptr.stack.iter().all(|saved| saved.stack.is_empty()));
+        // popping keeps the position and drops the saved state
+        let end = ptr.clone();
+        ptr.pop();
+        assert_eq!(ptr.stack.len(), 2);
+        assert_eq!(ptr, end);
+        ptr.back();
+        ptr.back();
+        assert_eq!(ptr, ReadPointer::from_pos((0,0, 0,0), (0, 0)));
+        // going back with nothing saved leaves the pointer where it is
+        ptr.increment('d');
+        let moved = ptr.clone();
+        ptr.back();
+        assert_eq!(ptr, moved);
+    }
+
+    /// positions count chars while the content is sliced by bytes.
+    #[test]
+    fn multibyte_positions() -> Result<(), String> {
+        let mut reader = LineReader::new("αβγ δε");
+        let (val, ptr) = reader.read_next(3).ok_or("`αβγ` can be read")?;
+        assert_eq!(val, "αβγ");
+        assert_eq!(ptr.len(), 3);
+        assert_eq!(ptr.byte_len(), 6);
+        reader.next(val.to_owned())?;
+        assert_eq!(reader.read_char(), Some(' '));
+        assert_eq!(reader.read_current(), Some("αβγ"));
+        assert_eq!(reader.get_pointer().read_pos, (0, 3));
+        assert!(reader.read_next(4).is_none());
+
+        let mut lexer = crate::lexer::Lexer::new();
+        lexer.define("word", "\\w+")?;
+        lexer.define_skip("space", "\\s+")?;
+        let src = "αβγ δε";
+        let mut stream = StreamReader::new(Cursor::new(src.as_bytes().to_vec()));
+        let tokens = lexer.tokenize(&mut stream)?;
+        assert_eq!(tokens, lexer.tokenize(&mut LineReader::new(src))?);
+        assert_eq!(tokens[1].value, "δε");
+        assert_eq!(tokens[1].position.user_position(), (1, 5));
+        assert_eq!(tokens[1].position.len(), 2);
+        Ok(())
+    }
+
+    /// the parser can backtrack within the content that is still kept.
+    #[test]
+    fn stream_reader_backtracking() -> Result<(), String> {
+        let mut executor = crate::exec::Executor::math();
+        let src = "x := 2\ny := (x * 3) + [1, 2][1]\n\n\n\ny + 1\n";
+        let mut reader = StreamReader::with_lookahead(Cursor::new(src.as_bytes().to_vec()), 1);
+        assert_eq!(executor.exec_all(&mut reader), Ok("9".into()));
+        // a cut off expression is an error at true EOF
+        let mut reader = StreamReader::new(Cursor::new(b"1 +".to_vec()));
+        assert!(executor.exec_all(&mut reader).is_err());
+        // content that isn't UTF-8 ends the stream with an error
+        let reader = StreamReader::new(Cursor::new(b"1\n\xff\n".to_vec()));
+        assert_eq!(reader.read_char(), Some('1'));
+        assert!(reader.error().is_some());
+        Ok(())
+    }
+
+    /// a spanning pointer can still go back to positions pushed by nested alternatives.
+    #[test]
+    fn from_to_keeps_destination_stack() {
+        let mut from = ReadPointer::from_pos((0, 0, 0, 0), (0, 0));
+        // outer alternative
+        from.push();
+        let mut to = from.clone();
+        ReadPointer::move_pointer(&mut to, "ab");
+        // nested alternative pushed after reading `ab`
+        to.push();
+        ReadPointer::move_pointer(&mut to, "cd");
+
+        let mut span = ReadPointer::from_to(&from, &to);
+        assert_eq!(span, ReadPointer::from_pos((0, 0, 0, 4), (0, 4)));
+        // the nested alternative goes back to the end of `ab`
+        span.back();
+        assert_eq!(span.end_offset(), 2);
+        // and the outer one to the start
+        span.back();
+        assert_eq!(span.end_offset(), 0);
+    }
+
+    /// the accessors follow the pointer across a new line.
+    #[test]
+    fn pointer_offsets_across_lines() {
+        let mut ptr = ReadPointer::from_pos((0, 3, 0, 3), (3, 3));
+        ReadPointer::move_pointer(&mut ptr, "ab\ncd");
+        assert_eq!(ptr.start_offset(), 3);
+        assert_eq!(ptr.end_offset(), 8);
+        assert_eq!(ptr.start_line_col(), (0, 3));
+        assert_eq!(ptr.end_line_col(), (1, 2));
+        // committing moves the start to the end
+        ptr.commit();
+        assert_eq!(ptr.start_offset(), 8);
+        assert_eq!(ptr.start_line_col(), (1, 2));
+    }
 
     #[test]
     fn move_pointer_with_all_line_endings() {
@@ -786,7 +1729,7 @@ mod tests {
     #[test]
     fn pointer_increment() {
         let mut ptr = ReadPointer::from_pos((0, 3, 0, 6), (3, 6) );
-        ptr.increment();
+        ptr.increment('a');
         assert_eq!(ptr, ReadPointer::from_pos((0, 3, 0, 7), (3, 7) ));
     }
 
@@ -812,7 +1755,7 @@ mod tests {
         let state_0 = ptr.clone();
         ptr.push();
         // Modify the pointer's state
-        ptr.increment();
+        ptr.increment('a');
         ptr.increment_line();
         // Ensure the pointer's state has changed
         assert_ne!(ptr, state_0);
@@ -822,7 +1765,7 @@ mod tests {
         // Modify the pointer's state
         ptr.increment_line();
         ptr.commit();
-        ptr.increment();
+        ptr.increment('a');
         // Ensure the pointer's state has changed
         assert_ne!(ptr, state_0);
         assert_ne!(ptr, state_1);
@@ -844,7 +1787,7 @@ mod tests {
         let state_0 = ptr.clone();
         ptr.push();
         // Modify the pointer's state
-        ptr.increment();
+        ptr.increment('a');
         ptr.increment_line();
         // Ensure the pointer's state has changed
         assert_ne!(ptr, state_0);
@@ -854,7 +1797,7 @@ mod tests {
         // Modify the pointer's state
         ptr.increment_line();
         ptr.commit();
-        ptr.increment();
+        ptr.increment('a');
         let state_2 = ptr.clone();
         // Ensure the pointer's state has changed
         assert_ne!(ptr, state_0);
@@ -869,4 +1812,134 @@ mod tests {
         assert_eq!(ptr, state_0);
     }
 
+    #[test]
+    fn reader_checkpoint_restore() -> Result<(), String> {
+        use crate::lexer::Lexer;
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "\\+")?;
+        let mut reader = LineReader::new("12+34+56");
+        // read a token before the checkpoint so the reader has moved
+        let tok = lexer.get_next_any(&reader).ok_or("no token")?;
+        reader.next(&tok)?;
+        reader.commit();
+        let checkpoint = reader.checkpoint();
+        // read the rest of the tokens
+        let mut first = vec![];
+        while let Some(tok) = lexer.get_next_any(&reader) {
+            reader.next(&tok)?;
+            reader.commit();
+            first.push(tok.value);
+        }
+        assert_eq!(first, vec!["+", "34", "+", "56"]);
+        // restore and read the same tokens again
+        reader.restore(checkpoint.clone());
+        assert_eq!(reader.get_pointer(), &checkpoint);
+        let mut second = vec![];
+        while let Some(tok) = lexer.get_next_any(&reader) {
+            reader.next(&tok)?;
+            reader.commit();
+            second.push(tok.value);
+        }
+        assert_eq!(first, second);
+        Ok(())
+    }
+
+    #[test]
+    fn reader_restore_with_stack() {
+        let mut reader = LineReader::new("abcdefg");
+        let _ = reader.next(1);
+        reader.push();
+        let checkpoint = reader.checkpoint();
+        // push more state after the checkpoint then restore it
+        let _ = reader.next(2);
+        reader.push();
+        let _ = reader.next(2);
+        reader.restore(checkpoint);
+        assert_eq!("a", reader.read_current().unwrap());
+        // the stack from before the checkpoint still works
+        let _ = reader.next(3);
+        assert_eq!("abcd", reader.read_current().unwrap());
+        reader.back();
+        assert_eq!("a", reader.read_current().unwrap());
+    }
+
+    /// a reader that only has the required methods.
+    struct PlainReader(LineReader);
+
+    impl Reader for PlainReader {
+        fn read_char(&self) -> Option<char> { self.0.read_char() }
+        fn read_current(&self) -> Option<&str> { self.0.read_current() }
+        fn read_pointer(&self, ptr: &ReadPointer) -> Option<&str> { self.0.read_pointer(ptr) }
+        fn read_next(&self, size: usize) -> Option<(&str, ReadPointer)> { self.0.read_next(size) }
+        fn read_regex(&self, regex: &Regex) -> Option<(&str, ReadPointer)> { self.0.read_regex(regex) }
+        fn read_regex_at(&self, ptr: &ReadPointer, regex: &Regex) -> Option<(&str, ReadPointer)> { self.0.read_regex_at(ptr, regex) }
+        fn next<T>(&mut self, size: T) -> Result<(), String> where T: SizeType { self.0.next(size) }
+        fn pop(&mut self) { self.0.pop() }
+        fn push(&mut self) { self.0.push() }
+        fn back(&mut self) { self.0.back() }
+        fn commit(&mut self) { self.0.commit() }
+        fn get_pointer(&self) -> &ReadPointer { self.0.get_pointer() }
+    }
+
+    /// the default restore goes back through the pushed positions to the checkpoint.
+    #[test]
+    fn default_restore() {
+        let mut reader = PlainReader(LineReader::new("abcdefg"));
+        let _ = reader.next(1);
+        reader.push();
+        let _ = reader.next(1);
+        let checkpoint = reader.checkpoint();
+        let _ = reader.next(2);
+        reader.push();
+        let _ = reader.next(2);
+        reader.restore(checkpoint.clone());
+        assert_eq!(reader.get_pointer(), &checkpoint);
+        assert_eq!("ab", reader.read_current().unwrap());
+        // the stack from before the checkpoint still works
+        reader.back();
+        assert_eq!("a", reader.read_current().unwrap());
+        // a checkpoint ahead of the pointer is read forward to
+        let mut other = LineReader::new("abcdefg");
+        let _ = other.next(4);
+        other.commit();
+        let _ = other.next(2);
+        let ahead = other.checkpoint();
+        reader.restore(ahead.clone());
+        assert_eq!(reader.get_pointer(), &ahead);
+        assert_eq!("ef", reader.read_current().unwrap());
+    }
+
+    /// cached reads give the same tokens and pointers as the wrapped reader.
+    #[test]
+    fn caching_reader_same_reads() -> Result<(), String> {
+        use crate::lexer::Lexer;
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "\\+")?;
+        lexer.define_skip("space", "\\s+")?;
+        let src = "12 +\n 34+  56";
+        let mut plain = LineReader::new(src);
+        let mut cached = CachingReader::new(LineReader::new(src));
+        for _ in 0..5 {
+            // probe every token type more than once before moving on
+            for token_type in ["num", "op", "num"] {
+                assert_eq!(
+                    lexer.get_next_token(token_type, &plain).map(|tok| tok.position),
+                    lexer.get_next_token(token_type, &cached).map(|tok| tok.position),
+                );
+            }
+            let tok = lexer.get_next_any(&plain).ok_or("no token")?;
+            assert_eq!(Some(&tok), lexer.get_next_any(&cached).as_ref());
+            plain.next(&tok)?;
+            plain.commit();
+            cached.next(&tok)?;
+            cached.commit();
+            assert_eq!(plain.get_pointer(), cached.get_pointer());
+        }
+        assert!(lexer.at_end(&cached));
+        assert!(cached.cache_hits() > cached.regex_calls());
+        Ok(())
+    }
+
 }
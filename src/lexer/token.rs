@@ -3,14 +3,18 @@
 //! Contains structures for managing and building tokens.
 //! 
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use once_cell::sync::Lazy;
 use regex::Regex;
 
-use super::{ReadPointer, Reader, SizeType};
+use super::{LexError, LineReader, ReadPointer, Reader, SizeType};
 
 /// A raw token object.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     // should be an enum but I prefer a hierarchical naming.
     /// String name of a token type
@@ -18,47 +22,139 @@ pub struct Token {
     /// Raw value of the token
     pub value: String,
     /// The read position of where the token was found.
-    pub position: ReadPointer
+    pub position: ReadPointer,
+    /// Length of the skipped content (like whitespace) before the token.
+    pub(crate) skipped: usize,
+    /// The skipped content before the token when the lexer
+    /// [preserves trivia](Lexer::set_preserve_trivia).
+    pub leading_trivia: Option<String>,
+    /// The matched text of a token with a [transform](TokenDef::with_transform) when the
+    /// lexer [preserves trivia](Lexer::set_preserve_trivia).
+    pub raw: Option<String>,
 }
 
+/// The size of a token includes the skipped content before it so
+/// [`Reader::next`] moves past both.
 impl SizeType for &Token {
     fn get_size(&self) -> usize {
-        self.position.len()
+        self.skipped + self.position.len()
     }
 }
 
+/// Shows `type:value`, the alternate form `{:#}` adds where the token is as
+/// `type:value (line:col len)`.
 impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // write!(
-        //     f,
-        //     "{}:{} ({}, {} - {})",
-        //     self.token_type,
-        //     self.value,
-        //     self.position.line_pos.0,
-        //     self.position.line_pos.1,
-        //     self.position.len(),
-        // )
-        write!(
-            f,
-            "{}:{}",
-            self.token_type,
-            self.value,
-        )
+        write!(f, "{}:{}", self.token_type, self.value)?;
+        if f.alternate() {
+            let (line, col) = self.position.user_position();
+            write!(f, " ({line}:{col} {})", self.position.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Tokens are equal when they have the same type and value, where they were read
+/// from is not compared.
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.token_type == other.token_type && self.value == other.value
     }
 }
 
 impl Token {
     /// Make a new token
     pub fn new(token_type: &str, value: &str, position: ReadPointer) -> Token {
-        Token { token_type: token_type.to_string(), value: value.to_string(), position }
+        Token { token_type: token_type.to_string(), value: value.to_string(), position, skipped: 0, leading_trivia: None, raw: None }
+    }
+
+    /// Get the text the token was read from, the [`raw`](Token::raw) text of a transformed
+    /// token when it was kept and otherwise its value.
+    pub fn source_text(&self) -> &str {
+        self.raw.as_deref().unwrap_or(&self.value)
+    }
+
+    /// Get the token as `type:value (line:col len)` for diagnostics, the plain
+    /// [`Display`](std::fmt::Display) leaves out the position.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{ReadPointer, Token};
+    /// let token = Token::new("ident", "foo", ReadPointer::from_pos((1,4, 1,7), (10, 13)));
+    /// assert_eq!(token.to_string(), "ident:foo");
+    /// assert_eq!(token.display_with_pos(), "ident:foo (2:5 3)");
+    /// ```
+    pub fn display_with_pos(&self) -> String {
+        format!("{self:#}")
+    }
+
+    /// Get the token as `type:value@line:col` for comparing token streams in tests, see
+    /// [`Lexer::lex_to_string`]. Backslashes and line breaks or tabs in the value are
+    /// escaped so the token stays on one line.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{ReadPointer, Token};
+    /// let token = Token::new("int", "12", ReadPointer::from_pos((0,0, 0,2), (0, 2)));
+    /// assert_eq!(token.compact(), "int:12@1:1");
+    /// let token = Token::new("space", "\n\t", ReadPointer::from_pos((1,4, 2,1), (10, 12)));
+    /// assert_eq!(token.compact(), "space:\\n\\t@2:5");
+    /// ```
+    pub fn compact(&self) -> String {
+        let mut value = String::with_capacity(self.value.len());
+        for char in self.value.chars() {
+            match char {
+                '\\' => value.push_str("\\\\"),
+                '\n' => value.push_str("\\n"),
+                '\r' => value.push_str("\\r"),
+                '\t' => value.push_str("\\t"),
+                char => value.push(char),
+            }
+        }
+        let (line, col) = self.position.user_position();
+        format!("{}:{value}@{line}:{col}", self.token_type)
     }
 }
 
+/// An edit to the source for [`Lexer::relex`], in bytes from the start of the source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextEdit {
+    /// Where the edit starts
+    pub start: usize,
+    /// How much of the old source was removed
+    pub removed: usize,
+    /// How much of the new source was inserted
+    pub inserted: usize,
+}
+
+/// Regexes already compiled for token definitions keyed by their anchored pattern, a
+/// [`Regex`] is cheap to clone so every lexer with the same pattern shares one.
+static REGEX_CACHE: Lazy<Mutex<RegexCache>> = Lazy::new(|| Mutex::new(RegexCache::new(REGEX_CACHE_LIMIT)));
+
+/// How many compiled regexes the [`REGEX_CACHE`] keeps.
+const REGEX_CACHE_LIMIT: usize = 512;
+
+/// Matches any single char, used to step over content no definition matches.
+static ANY_CHAR: Lazy<Regex> = Lazy::new(|| Regex::new("\\A(?s:.)").unwrap());
+
+/// How many token definitions got their regex from the cache.
+static REGEX_CACHE_HITS: AtomicUsize = AtomicUsize::new(0);
+
 /// A regex definition for a token
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TokenDef {
     token_type: String,
     regex: Regex,
+    /// transforms the matched text into the token's value
+    transform: Option<fn(&str) -> String>,
+    /// the highest priority wins when more than one definition matches
+    priority: i32,
 }
 
 impl TokenDef {
@@ -67,34 +163,287 @@ impl TokenDef {
     /// cannot be created.
     pub fn new(token_type: &str, regex: &str) -> Result<TokenDef, String> {
         let regex = TokenDef::build_regex(regex)?;
-        Ok(TokenDef { token_type: token_type.into(), regex })
+        Ok(TokenDef { token_type: token_type.into(), regex, transform: None, priority: 0 })
+    }
+
+    /// Make a new token definition that applies `transform` to the matched text before
+    /// it is stored as the token's value - such as removing the quotes from a string.
+    /// The token's position still covers all of the matched text.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader, TokenDef};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define_token(TokenDef::with_transform("str", "'[^']*'", |raw| {
+    ///     raw[1..raw.len() - 1].to_string()
+    /// })?);
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("'abc'")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.value, "abc");
+    /// assert_eq!(token.position.len(), 5);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn with_transform(token_type: &str, regex: &str, transform: fn(&str) -> String) -> Result<TokenDef, String> {
+        let mut def = TokenDef::new(token_type, regex)?;
+        def.transform = Some(transform);
+        Ok(def)
+    }
+
+    /// Make a new token definition with a `priority` - when more than one definition
+    /// matches, the one with the highest priority is read no matter the order they were
+    /// defined in. Definitions are made with a priority of `0`.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader, TokenDef};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define_token(TokenDef::new("ident", "[a-z]+")?);
+    /// lexer.define_token(TokenDef::with_priority("kw:let", "let", 1)?);
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("let")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.token_type, "kw:let");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn with_priority(token_type: &str, regex: &str, priority: i32) -> Result<TokenDef, String> {
+        let mut def = TokenDef::new(token_type, regex)?;
+        def.priority = priority;
+        Ok(def)
+    }
+
+    /// Make a new token definition from an already built `regex`, so the same regex can be
+    /// shared or built with [`RegexBuilder`](regex::RegexBuilder) options. A regex that
+    /// can match empty content can't be used.
+    /// 
+    /// ---
+    /// 
+    /// The regex is used as it is so it should be anchored with `\A`, like the definitions
+    /// built from a pattern. Only a match that starts at the reader's position is read, so an
+    /// unanchored regex still works but a failed match searches the rest of the content.
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader, TokenDef};
+    /// use regex::Regex;
+    /// let def = TokenDef::from_regex("num", Regex::new("\\A[0-9]+").unwrap())?;
+    /// let lexer = Lexer::from_defs(vec![def]);
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("123")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.value, "123");
+    /// assert!(TokenDef::from_regex("num", Regex::new("[0-9]*").unwrap()).is_err());
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn from_regex(token_type: &str, regex: Regex) -> Result<TokenDef, String> {
+        if regex.is_match("") {
+            return Err(format!("Cannot Build Token Definition - Pattern can match empty content: {}", regex.as_str()));
+        }
+        Ok(TokenDef { token_type: token_type.into(), regex, transform: None, priority: 0 })
+    }
+
+    /// Get the token type the definition matches.
+    pub fn token_type(&self) -> &str {
+        &self.token_type
+    }
+
+    /// Get the priority of the definition, see [`TokenDef::with_priority`].
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Get how many definitions reused a regex compiled for an earlier definition with the
+    /// same pattern, across every lexer.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::TokenDef;
+    /// let hits = TokenDef::regex_cache_hits();
+    /// TokenDef::new("num", "[0-9]+")?;
+    /// TokenDef::new("int", "[0-9]+")?;
+    /// assert!(TokenDef::regex_cache_hits() > hits);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn regex_cache_hits() -> usize {
+        REGEX_CACHE_HITS.load(Ordering::Relaxed)
     }
 
     /// builds a regex string from the supplied value with the format `\A( {regex} )`. This
     /// ensures that the token definition requires that a token be next in the content when
     /// matching.
+    /// 
+    /// ---
+    /// 
+    /// The pattern is always grouped so top level alternation like `a|b` is anchored as a
+    /// whole, and a leading `^` is dropped since the pattern is already anchored. Patterns
+    /// that can match empty content are rejected, an empty token would never move the reader.
     fn build_regex(regex: &str) -> Result<Regex, String> {
-        let regex = format!("\\A({regex})");
-        match Regex::new(&regex){
-            Ok(regex) => Ok(regex),
-            Err(_) => Err(format!("Cannot Build Token Definition - Regex Error for: {regex:}"))
+        let pattern = regex.strip_prefix('^').unwrap_or(regex);
+        let built = format!("\\A({pattern})");
+        let lock = || REGEX_CACHE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(cached) = lock().get(&built) {
+            REGEX_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+            return Ok(cached);
+        }
+        // compiled without holding the lock, so other lexers aren't kept waiting
+        match Regex::new(&built) {
+            Ok(compiled) if compiled.is_match("") => Err(format!("Cannot Build Token Definition - Pattern can match empty content: {regex}")),
+            Ok(compiled) => Ok(lock().insert(built, compiled)),
+            Err(_) => Err(format!("Cannot Build Token Definition - Regex Error for: {built}"))
+        }
+    }
+}
+
+/// Compiled regexes keyed by their pattern, the oldest is dropped once there are `limit`.
+struct RegexCache {
+    limit: usize,
+    regexes: HashMap<String, Regex>,
+    /// the patterns from the oldest
+    order: VecDeque<String>,
+}
+
+impl RegexCache {
+    fn new(limit: usize) -> RegexCache {
+        RegexCache { limit, regexes: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn get(&self, pattern: &str) -> Option<Regex> {
+        self.regexes.get(pattern).cloned()
+    }
+
+    /// Add the regex for the `pattern`, giving the cached one if another lexer compiled it first.
+    fn insert(&mut self, pattern: String, regex: Regex) -> Regex {
+        if let Some(cached) = self.regexes.get(&pattern) {
+            return cached.clone();
         }
+        if self.order.len() >= self.limit {
+            if let Some(oldest) = self.order.pop_front() {
+                self.regexes.remove(&oldest);
+            }
+        }
+        self.order.push_back(pattern.clone());
+        self.regexes.insert(pattern, regex.clone());
+        regex
     }
 }
 
 /// Works with the Parser to create a stream of Tokens from a Reader.
+#[derive(Debug, Clone)]
 pub struct Lexer {
-    definitions: HashMap<String, TokenDef>,
+    /// token definitions in the order they were defined
+    definitions: Vec<TokenDef>,
+    /// indices of the definitions from the highest priority, in the order they were
+    /// defined when the priorities are the same
+    by_priority: Vec<usize>,
+    /// keyword literals mapped to their token type
+    keywords: HashMap<String, String>,
+    /// token types that are reclassified when their text is a keyword literal
+    keyword_types: Vec<String>,
+    /// definitions for content skipped between tokens
+    skips: Vec<TokenDef>,
+    /// keep the skipped content on the token after it
+    preserve_trivia: bool,
+}
+
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Lexer {
     /// Create a new tokenizer to parse the code source reader.
     pub fn new() -> Lexer {
-        Lexer { definitions: HashMap::new() }
+        Lexer { definitions: vec![], by_priority: vec![], keywords: HashMap::new(), keyword_types: vec!["ident".into()], skips: vec![], preserve_trivia: false }
+    }
+
+    /// Create a lexer from token definitions, they are [defined](Lexer::define_token) in order.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, TokenDef};
+    /// let defs = vec![TokenDef::new("ident", "[a-z]+")?, TokenDef::new("num", "[0-9]+")?];
+    /// let lexer = Lexer::from_defs(defs.clone());
+    /// assert_eq!(lexer.definitions(), ["ident", "num"]);
+    /// // the definitions can be shared with more lexers
+    /// let other = Lexer::from_defs(defs);
+    /// assert_eq!(other.definitions(), lexer.definitions());
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn from_defs(defs: Vec<TokenDef>) -> Lexer {
+        let mut lexer = Lexer::new();
+        defs.into_iter().for_each(|def| lexer.define_token(def));
+        lexer
+    }
+
+    /// Get the defined token types in the order they were defined, without the keywords
+    /// and skipped content.
+    pub fn definitions(&self) -> Vec<&str> {
+        self.definitions.iter().map(|def| def.token_type()).collect()
     }
 
     // -=-=- Define Token -=-=- //
 
+    /// Add or replace a token definition that matches any of the `literals` exactly, they
+    /// are escaped and the longest are tried first so `<=` isn't read as `<`.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define_literals("op", &["<", "<=", "|"])?;
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("<=")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.value, "<=");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_literals(&mut self, token_type: &str, literals: &[&str]) -> Result<(), String> {
+        let mut literals = literals.to_vec();
+        literals.sort_by_key(|literal| std::cmp::Reverse(literal.len()));
+        let escaped: Vec<_> = literals.iter().map(|literal| regex::escape(literal)).collect();
+        self.define(token_type, &escaped.join("|"))
+    }
+
+    /// Add or replace a token definition that matches the `literal` exactly.
+    pub fn define_literal(&mut self, token_type: &str, literal: &str) -> Result<(), String> {
+        self.define_literals(token_type, &[literal])
+    }
+
+    /// Add or replace a token definition from an already built `regex`, see
+    /// [`TokenDef::from_regex`] for how it should be anchored.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// use regex::RegexBuilder;
+    /// let mut lexer = Lexer::new();
+    /// lexer.define_regex("kw:select", RegexBuilder::new("\\Aselect").case_insensitive(true).build().unwrap())?;
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("SELECT")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.token_type, "kw:select");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_regex(&mut self, token_type: &str, regex: Regex) -> Result<(), String> {
+        self.define_token(TokenDef::from_regex(token_type, regex)?);
+        Ok(())
+    }
+
     /// Add or replace a token definition in the current possible tokens that the 
     /// Lexer can parse.
     /// 
@@ -132,7 +481,149 @@ impl Lexer {
     /// ```
     pub fn define_token(&mut self, def: TokenDef) {
         // println!("{:#?}", def);
-        self.definitions.insert(def.token_type.to_owned(), def);
+        match self.definitions.iter_mut().find(|d| d.token_type == def.token_type) {
+            Some(existing) => *existing = def,
+            None => self.definitions.push(def),
+        }
+        // a stable sort keeps the definition order for the same priority
+        self.by_priority = (0..self.definitions.len()).collect();
+        self.by_priority.sort_by_key(|&i| std::cmp::Reverse(self.definitions[i].priority));
+    }
+
+    /// Add or replace a token definition with a `priority`, see [`TokenDef::with_priority`].
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("int", "[0-9]+")?;
+    /// lexer.define_with_priority("float", "[0-9]+\\.[0-9]+", 1)?;
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("12.0")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.token_type, "float");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_with_priority(&mut self, token_type: &str, regex: &str, priority: i32) -> Result<(), String> {
+        self.define_token(TokenDef::with_priority(token_type, regex, priority)?);
+        Ok(())
+    }
+
+    /// Define a keyword with the token type `token_type` for the `literal` value. When an
+    /// `ident` token is matched and its full text equals the literal it is reclassified as
+    /// the keyword, so `if` is a keyword while `iffy` is still an identifier.
+    /// 
+    /// ---
+    /// 
+    /// Keywords don't have their own regex - the `ident` definition needs to match the
+    /// keyword's text. Use [`set_keyword_types`](Lexer::set_keyword_types) when the
+    /// identifiers have another token type.
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("ident", "[a-z]+")?;
+    /// lexer.define_keyword("kw:if", "if");
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("if")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.token_type, "kw:if");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_keyword(&mut self, token_type: &str, literal: &str) {
+        self.keywords.insert(literal.to_owned(), token_type.to_owned());
+    }
+
+    /// Set the token types that become keywords when their text is a keyword literal,
+    /// by default only `ident` tokens are reclassified.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("name", "[a-z]+")?;
+    /// lexer.define_keyword("kw:if", "if");
+    /// lexer.set_keyword_types(&["name"]);
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("if")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.token_type, "kw:if");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn set_keyword_types(&mut self, token_types: &[&str]) {
+        self.keyword_types = token_types.iter().map(|token_type| token_type.to_string()).collect();
+    }
+
+    /// Define content that is skipped before each token, such as whitespace or comments.
+    /// Skipped content is never matched as a token but it still moves the reader.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("num", "[0-9]+")?;
+    /// lexer.define_skip("space", "\\s+")?;
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new("  123")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.value, "123");
+    /// assert_eq!(token.position.read_pos, (2, 5));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_skip(&mut self, token_type: &str, regex: &str) -> Result<(), String> {
+        self.skips.push(TokenDef::new(token_type, regex)?);
+        Ok(())
+    }
+
+    /// Keep the skipped content before each token as its
+    /// [`leading_trivia`](Token::leading_trivia), so the source can be rebuilt from the
+    /// tokens - the trivia and [source text](Token::source_text) of each token followed by
+    /// the [`trivia`](Lexer::trivia) after the last one. Tokens with a
+    /// [transform](TokenDef::with_transform) keep the transformed value and the text they
+    /// were read from as their [`raw`](Token::raw) text.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("num", "[0-9]+")?;
+    /// lexer.define_skip("space", "\\s+")?;
+    /// lexer.set_preserve_trivia(true);
+    /// 
+    /// let token = lexer.get_next_any(&LineReader::new(" \n 123")).ok_or("Couldn't find token")?;
+    /// assert_eq!(token.leading_trivia.as_deref(), Some(" \n "));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn set_preserve_trivia(&mut self, preserve: bool) {
+        self.preserve_trivia = preserve;
+    }
+
+    /// Check if the token type is defined as a keyword.
+    fn is_keyword(&self, token_type: &str) -> bool {
+        self.keywords.values().any(|keyword| keyword == token_type)
+    }
+
+    /// Reclassify a token of the [keyword types](Lexer::set_keyword_types) as a keyword if
+    /// the text it was read from is a keyword literal, other token types (like a string
+    /// holding `if`) are left alone.
+    fn classify(&self, mut token: Token, raw: &str) -> Token {
+        if !self.keyword_types.contains(&token.token_type) {
+            return token;
+        }
+        if let Some(keyword) = self.keywords.get(raw) {
+            token.token_type = keyword.to_owned();
+        }
+        token
     }
 
     // -=-=- Get Token -=-=- //
@@ -158,11 +649,19 @@ impl Lexer {
     /// ```
     pub fn get_next_token<T>(&self, token_type: &str, reader: &T) -> Option<Token>
     where T: Reader {
-        let def = self.definitions.get(token_type)?;
-        self.get_next(def, reader)
+        let token = match self.definitions.iter().find(|def| def.token_type == token_type) {
+            Some(def) => self.get_next(def, reader)?,
+            // keywords are found by reclassifying other tokens
+            None if self.is_keyword(token_type) => self.get_next_any(reader)?,
+            None => return None,
+        };
+        // the token might have been reclassified as a keyword
+        (token.token_type == token_type).then_some(token)
     }
 
     /// Get the next token in the reader that matches any of the defined token types.
+    /// The definitions are tried from the highest [priority](TokenDef::with_priority),
+    /// then in the order they were defined.
     /// 
     /// ---
     /// 
@@ -182,19 +681,39 @@ impl Lexer {
     /// ```
     pub fn get_next_any<T>(&self, reader: &T) -> Option<Token>
     where T: Reader {
-        for key in self.definitions.keys() {
-            let def = match self.definitions.get(key) {
-                Some(tok) => tok,
-                None => continue,
-            };
-            if let Some(t) = self.get_next(def, reader) {
-                return Some(t);
-            }
-        }
-        None
+        self.by_priority.iter().find_map(|&i| self.get_next(&self.definitions[i], reader))
+    }
+
+    /// Get the longest next token in the reader that matches any of the defined token
+    /// types. Every definition is tried, a match with a higher
+    /// [priority](TokenDef::with_priority) beats a longer one and the same length is read
+    /// as the first definition.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("int", "[0-9]+")?;
+    /// lexer.define("float", "[0-9]+\\.[0-9]+")?;
+    /// 
+    /// let reader = LineReader::new("12.0");
+    /// assert_eq!(lexer.get_next_any(&reader).ok_or("Couldn't find token")?.token_type, "int");
+    /// assert_eq!(lexer.get_next_longest(&reader).ok_or("Couldn't find token")?.token_type, "float");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn get_next_longest<T>(&self, reader: &T) -> Option<Token>
+    where T: Reader {
+        self.definitions.iter().enumerate()
+            .filter_map(|(i, def)| Some((def.priority, self.get_next(def, reader)?, i)))
+            .max_by_key(|(priority, token, i)| (*priority, token.position.len(), std::cmp::Reverse(*i)))
+            .map(|(_, token, _)| token)
     }
 
-    /// Get the next token in the reader that matches the provided token definition.
+    /// Get the next token in the reader that matches the provided token definition. An
+    /// empty match is never a token, it would not move the reader.
     /// 
     /// ---
     /// 
@@ -214,9 +733,596 @@ impl Lexer {
     /// ```
     pub fn get_next<T>(&self, def: &TokenDef, reader: &T) -> Option<Token>
     where T: Reader {
-        if let Some((value, position)) = reader.read_regex(&def.regex) {
-            return Some(Token::new( &def.token_type, value, position));
+        let mut trivia = self.preserve_trivia.then(String::new);
+        let ptr = self.skip_collecting(reader, trivia.as_mut());
+        if let Some((value, position)) = reader.read_regex_at(&ptr, &def.regex).filter(|(value, _)| !value.is_empty()) {
+            let mut token = match def.transform {
+                Some(transform) => Token::new( &def.token_type, &transform(value), position),
+                None => Token::new( &def.token_type, value, position),
+            };
+            token.skipped = ptr.read_pos.1 as usize - reader.get_pointer().read_pos.1 as usize;
+            token.leading_trivia = trivia;
+            if self.preserve_trivia && def.transform.is_some() {
+                token.raw = Some(value.to_owned());
+            }
+            return Some(self.classify(token, value));
         }
         None
     }
+
+    // -=-=- Skipping -=-=- //
+
+    /// Get a pointer to the end of the skipped content after the reader's pointer.
+    fn skip<T>(&self, reader: &T) -> ReadPointer
+    where T: Reader {
+        self.skip_collecting(reader, None)
+    }
+
+    /// Skip the content like [`skip`](Lexer::skip), adding the skipped text to `trivia`.
+    fn skip_collecting<T>(&self, reader: &T, mut trivia: Option<&mut String>) -> ReadPointer
+    where T: Reader {
+        let mut ptr = reader.get_pointer().clone();
+        while let Some((raw, next)) = self.skips.iter().find_map(|def| {
+            reader.read_regex_at(&ptr, &def.regex).filter(|(raw, _)| !raw.is_empty())
+        }) {
+            if let Some(trivia) = trivia.as_mut() {
+                trivia.push_str(raw);
+            }
+            ptr = next;
+        }
+        ptr
+    }
+
+    /// Get the skipped content after the reader's pointer, such as the whitespace and
+    /// comments after the last token.
+    pub fn trivia<T>(&self, reader: &T) -> String
+    where T: Reader {
+        let mut trivia = String::new();
+        self.skip_collecting(reader, Some(&mut trivia));
+        trivia
+    }
+
+    /// Get an empty pointer to where the next token would start in the reader.
+    pub fn next_position<T>(&self, reader: &T) -> ReadPointer
+    where T: Reader {
+        T::get_token_pointer("", &self.skip(reader))
+    }
+
+    /// Check if there is nothing but skipped content left in the reader.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define_skip("space", "\\s+")?;
+    /// 
+    /// assert!(lexer.at_end(&LineReader::new("  \n ")));
+    /// assert!(!lexer.at_end(&LineReader::new("  1")));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn at_end<T>(&self, reader: &T) -> bool
+    where T: Reader {
+        reader.read_regex_at(&self.skip(reader), &ANY_CHAR).is_none()
+    }
+
+    /// Read all the tokens left in the reader, committing the reader past each one.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("int", "[0-9]+")?;
+    /// lexer.define("add", "\\+")?;
+    /// lexer.define_skip("space", "\\s+")?;
+    /// 
+    /// let tokens = lexer.tokenize(&mut LineReader::new("1 + 2"))?;
+    /// let values: Vec<_> = tokens.iter().map(|token| token.value.as_str()).collect();
+    /// assert_eq!(values, ["1", "+", "2"]);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn tokenize<T>(&self, reader: &mut T) -> Result<Vec<Token>, LexError>
+    where T: Reader {
+        let mut tokens = vec![];
+        while !self.at_end(reader) {
+            let position = self.next_position(reader);
+            let token = self.get_next_any(reader)
+                .ok_or_else(|| LexError::new("Unknown token", position.clone()))?;
+            reader.next(&token).map_err(|err| LexError::new(&err, position))?;
+            reader.commit();
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    /// Read all the tokens left in the reader like [`tokenize`](Lexer::tokenize) and join
+    /// their [compact](Token::compact) forms with spaces, for checking what an input lexes
+    /// to in a single line - see [`assert_tokens`](crate::assert_tokens).
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::LineReader;
+    /// let lexer = interpreter::lang::math::lexer();
+    /// let tokens = lexer.lex_to_string(&mut LineReader::new("x := 12"))?;
+    /// assert_eq!(tokens, "ident:x@1:1 declare::=@1:3 int:12@1:6");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn lex_to_string<T>(&self, reader: &mut T) -> Result<String, LexError>
+    where T: Reader {
+        let tokens = self.tokenize(reader)?;
+        Ok(tokens.iter().map(Token::compact).collect::<Vec<_>>().join(" "))
+    }
+
+    /// Read all the tokens left in the reader like [`tokenize`](Lexer::tokenize), but content
+    /// that no definition matches is kept as an `<error>` token instead of stopping. The
+    /// error token spans everything up to where a token or skipped content can start.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// let lexer = interpreter::lang::math::lexer();
+    /// let tokens = lexer.tokens_lossy(&mut LineReader::new("1 + ?? 2"));
+    /// assert_eq!(tokens[2].to_string(), "<error>:??");
+    /// assert_eq!(tokens.len(), 4);
+    /// ```
+    pub fn tokens_lossy<T>(&self, reader: &mut T) -> Vec<Token>
+    where T: Reader {
+        let mut tokens = vec![];
+        while !self.at_end(reader) {
+            let token = self.get_next_any(reader).unwrap_or_else(|| self.error_token(reader));
+            if reader.next(&token).is_err() {
+                break;
+            }
+            reader.commit();
+            tokens.push(token);
+        }
+        tokens
+    }
+
+    /// Make an `<error>` token for the content after the reader's pointer that no
+    /// definition matches.
+    fn error_token<T>(&self, reader: &T) -> Token
+    where T: Reader {
+        let mut trivia = self.preserve_trivia.then(String::new);
+        let start = self.skip_collecting(reader, trivia.as_mut()).to_end();
+        let mut end = start.clone();
+        while let Some((_, next)) = reader.read_regex_at(&end, &ANY_CHAR) {
+            end = ReadPointer::from_to(&start, &next);
+            if self.starts_token(reader, &end) {
+                break;
+            }
+        }
+        let mut token = Token::new("<error>", reader.read_pointer(&end).unwrap_or_default(), end);
+        token.skipped = start.read_pos.0 as usize - reader.get_pointer().read_pos.1 as usize;
+        token.leading_trivia = trivia;
+        token
+    }
+
+    /// Check if a token or skipped content can start after the pointer.
+    fn starts_token<T>(&self, reader: &T, ptr: &ReadPointer) -> bool
+    where T: Reader {
+        self.definitions.iter().chain(&self.skips)
+            .any(|def| reader.read_regex_at(ptr, &def.regex).is_some_and(|(raw, _)| !raw.is_empty()))
+    }
+
+    /// Get the tokens of the `source` after an `edit`, from the `old_tokens` of the source
+    /// before it. The tokens before the edit are kept, the content is lexed again from the
+    /// token before the edit until a token past the edit lines up with an old one - the old
+    /// tokens after it are [shifted](ReadPointer::shift) to where they are now.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader, TextEdit};
+    /// let lexer = interpreter::lang::math::lexer();
+    /// let old = lexer.tokenize(&mut LineReader::new("a + b * c"))?;
+    /// // "b" is replaced by "(b - 1)"
+    /// let source = "a + (b - 1) * c";
+    /// let tokens = lexer.relex(&old, source, TextEdit { start: 4, removed: 1, inserted: 7 })?;
+    /// assert_eq!(tokens, lexer.tokenize(&mut LineReader::new(source))?);
+    /// assert_eq!(tokens[8].position.read_pos, (14, 15));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn relex(&self, old_tokens: &[Token], source: &str, edit: TextEdit) -> Result<Vec<Token>, LexError> {
+        // the token before the edit is lexed again too, its match could go on into the edit
+        let kept = old_tokens.iter()
+            .take_while(|token| (token.position.byte_pos.1 as usize) < edit.start)
+            .count()
+            .saturating_sub(1);
+        let mut tokens = old_tokens[..kept].to_vec();
+        let mut reader = LineReader::new(source);
+        if let Some(last) = tokens.last() {
+            reader.restore(last.position.to_end());
+        }
+        let edit_end = edit.start + edit.inserted;
+        while !self.at_end(&reader) {
+            let position = self.next_position(&reader);
+            let token = self.get_next_any(&reader)
+                .ok_or_else(|| LexError::new("Unknown token", position.clone()))?;
+            reader.next(&token).map_err(|err| LexError::new(&err, position))?;
+            reader.commit();
+            let start = token.position.byte_pos.0 as usize;
+            if start >= edit_end {
+                let old_start = (start - edit.inserted + edit.removed) as u32;
+                let synced = old_tokens.binary_search_by_key(&old_start, |old| old.position.byte_pos.0).ok()
+                    .filter(|&i| old_tokens[i] == token && old_tokens[i].position.byte_len() == token.position.byte_len());
+                if let Some(i) = synced {
+                    let (from, to) = (&old_tokens[i].position, token.position.clone());
+                    tokens.push(token);
+                    tokens.extend(old_tokens[i + 1..].iter().map(|old| {
+                        let mut old = old.clone();
+                        old.position.shift(from, &to);
+                        old
+                    }));
+                    return Ok(tokens);
+                }
+            }
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::LineReader;
+
+    /// content nothing matches is an error token and lexing goes on after it.
+    #[test]
+    fn tokens_lossy() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("int", "[0-9]+")?;
+        lexer.define("op", "\\+")?;
+        lexer.define_skip("space", "\\s+")?;
+        let tokens = lexer.tokens_lossy(&mut LineReader::new("1 + @@ + 2"));
+        let values: Vec<_> = tokens.iter().map(|token| (token.to_string(), token.position.read_pos)).collect();
+        assert_eq!(values, [
+            ("int:1".to_owned(), (0, 1)),
+            ("op:+".to_owned(), (2, 3)),
+            ("<error>:@@".to_owned(), (4, 6)),
+            ("op:+".to_owned(), (7, 8)),
+            ("int:2".to_owned(), (9, 10)),
+        ]);
+        assert_eq!(tokens[2].skipped, 1);
+        // the error stops where a token starts, even without a space
+        let tokens = lexer.tokens_lossy(&mut LineReader::new("@1?é "));
+        let values: Vec<_> = tokens.iter().map(|token| token.to_string()).collect();
+        assert_eq!(values, ["<error>:@", "int:1", "<error>:?é"]);
+        // the strict path still stops
+        assert!(lexer.tokenize(&mut LineReader::new("1 + @@ + 2")).is_err());
+        Ok(())
+    }
+
+    /// an edit in the middle of a document only lexes the tokens around it again.
+    #[test]
+    fn relex() -> Result<(), String> {
+        static WORDS: AtomicUsize = AtomicUsize::new(0);
+        let mut lexer = Lexer::new();
+        lexer.define_token(TokenDef::with_transform("word", "[a-zéö]+", |raw| {
+            WORDS.fetch_add(1, Ordering::Relaxed);
+            raw.to_owned()
+        })?);
+        lexer.define("num", "[0-9]+")?;
+        lexer.define_literals("op", &["+", "==", "="])?;
+        lexer.define_skip("space", "\\s+")?;
+        let source: String = (0..250).map(|i| format!("wörd{i} = é + {i}{}", ["\n", " "][i % 2])).collect();
+        let old = lexer.tokenize(&mut LineReader::new(&source))?;
+        assert!(old.len() >= 1000, "{}", old.len());
+        // compare everything about the tokens, positions included
+        let details = |tokens: &[Token]| -> Vec<_> {
+            tokens.iter().map(|token| (token.to_string(), token.position.line_pos, token.position.read_pos, token.position.byte_pos, token.skipped)).collect()
+        };
+        let middle = source.find("wörd125").ok_or("the middle word is in the source")?;
+        let edits = [
+            (middle + 1, 0, "x"),  // a char inside a word
+            (middle + 5, 0, " "),  // splits the word from its number
+            (middle, 0, "ab\né"), // a new line before the word
+            (middle - 2, 1, "=="), // replaces a digit with an op
+            (middle, 9, ""),       // removes text
+        ];
+        for (start, removed, inserted) in edits {
+            let edited = format!("{}{inserted}{}", &source[..start], &source[start + removed..]);
+            let words = WORDS.load(Ordering::Relaxed);
+            let tokens = lexer.relex(&old, &edited, TextEdit { start, removed, inserted: inserted.len() })?;
+            assert!(WORDS.load(Ordering::Relaxed) - words < 10, "relexed {} words", WORDS.load(Ordering::Relaxed) - words);
+            assert_eq!(details(&tokens), details(&lexer.tokenize(&mut LineReader::new(&edited))?), "after inserting {inserted:?}");
+        }
+        Ok(())
+    }
+
+    /// literals are escaped and the longest one is read.
+    #[test]
+    fn define_literals() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define_literals("op", &["<", "<=", "+", "++"])?;
+        lexer.define("ident", "[a-z]+")?;
+        let tokens = lexer.tokenize(&mut LineReader::new("a<=b++c<d+e"))?;
+        let values: Vec<_> = tokens.iter().map(|token| token.value.as_str()).collect();
+        assert_eq!(values, ["a", "<=", "b", "++", "c", "<", "d", "+", "e"]);
+        // `|` and `.` are literals, not regex syntax
+        lexer.define_literals("op", &["|", "."])?;
+        assert!(lexer.get_next_any(&LineReader::new("x")).is_some_and(|token| token.token_type == "ident"));
+        lexer.define_literal("op", "|")?;
+        assert_eq!(lexer.tokenize(&mut LineReader::new("a|b"))?.len(), 3);
+        assert!(lexer.define_literals("none", &[]).is_err());
+        Ok(())
+    }
+
+    /// regexes built outside the lexer keep their options and only match at the position.
+    #[test]
+    fn define_regex() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        let word = regex::RegexBuilder::new("\\A[a-z]+").case_insensitive(true).size_limit(1 << 16).build();
+        lexer.define_regex("word", word.map_err(|err| err.to_string())?)?;
+        // not anchored, it must not match the digits after the word
+        lexer.define_regex("num", Regex::new("[0-9]+").map_err(|err| err.to_string())?)?;
+        lexer.define_skip("space", "\\s+")?;
+        let tokens = lexer.tokenize(&mut LineReader::new("Hello 42 WORLD"))?;
+        let values: Vec<_> = tokens.iter().map(|token| token.to_string()).collect();
+        assert_eq!(values, ["word:Hello", "num:42", "word:WORLD"]);
+        assert!(lexer.get_next_any(&LineReader::new("?1")).is_none());
+        assert!(lexer.define_regex("empty", Regex::new("\\A[a-z]*").map_err(|err| err.to_string())?).is_err());
+        Ok(())
+    }
+
+    /// the position is only shown in the alternate format.
+    #[test]
+    fn display_with_pos() -> Result<(), String> {
+        let tokens = crate::lang::math::lexer().tokenize(&mut LineReader::new("x :=\n  42"))?;
+        assert_eq!(tokens[2].to_string(), "int:42");
+        assert_eq!(format!("{:#}", tokens[2]), "int:42 (2:3 2)");
+        assert_eq!(tokens[2].display_with_pos(), format!("{:#}", tokens[2]));
+        assert_ne!(tokens[0].display_with_pos(), tokens[0].to_string());
+        Ok(())
+    }
+
+    /// the regex cache drops the oldest regex once it is full.
+    #[test]
+    fn regex_cache_limit() -> Result<(), String> {
+        let compile = |pattern: &str| Regex::new(pattern).map_err(|err| err.to_string());
+        let mut cache = RegexCache::new(2);
+        cache.insert("a".into(), compile("a")?);
+        cache.insert("b".into(), compile("b")?);
+        // a pattern compiled twice gives the cached one
+        cache.insert("a".into(), compile("a")?);
+        assert_eq!(cache.regexes.len(), 2);
+        cache.insert("c".into(), compile("c")?);
+        assert_eq!(cache.regexes.len(), 2);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some() && cache.get("c").is_some());
+        Ok(())
+    }
+
+    /// lexers built from the same patterns share the compiled regexes.
+    #[test]
+    fn regex_cache() -> Result<(), String> {
+        let patterns = [("ident", "[a-z_][a-z0-9_]*"), ("num", "[0-9]+(\\.[0-9]+)?"), ("op", "[-+*/]")];
+        let hits = TokenDef::regex_cache_hits();
+        let lexers = (0..1000).map(|_| {
+            let mut lexer = Lexer::new();
+            patterns.iter().try_for_each(|(token_type, regex)| lexer.define(token_type, regex))?;
+            Ok(lexer)
+        }).collect::<Result<Vec<_>, String>>()?;
+        // at most the first lexer compiled them
+        assert!(TokenDef::regex_cache_hits() - hits >= 999 * patterns.len());
+        let token = lexers[999].get_next_any(&LineReader::new("x1 + 2")).ok_or("no token")?;
+        assert_eq!(token.value, "x1");
+        // a cloned lexer has the same definitions
+        let lexer = lexers[0].clone();
+        assert_eq!(lexer.definitions(), ["ident", "num", "op"]);
+        // errors are not cached
+        assert!(TokenDef::new("bad", "(").is_err());
+        assert!(TokenDef::new("bad", "(").is_err());
+        Ok(())
+    }
+
+    /// keywords are reclassified only when the full value matches.
+    #[test]
+    fn keyword_or_ident() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("ident", "[a-z]+")?;
+        lexer.define_keyword("kw:if", "if");
+        // an identifier that starts with a keyword
+        let token = lexer.get_next_any(&LineReader::new("iffy")).ok_or("no token")?;
+        assert_eq!(token.token_type, "ident");
+        assert_eq!(token.value, "iffy");
+        // the keyword itself
+        let token = lexer.get_next_any(&LineReader::new("if")).ok_or("no token")?;
+        assert_eq!(token.token_type, "kw:if");
+        assert_eq!(token.value, "if");
+        Ok(())
+    }
+
+    /// only identifiers become keywords, a string with the same text stays a string.
+    #[test]
+    fn keyword_only_ident() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("ident", "[a-z]+")?;
+        lexer.define_token(TokenDef::with_transform("string", "\"[a-z]*\"", |raw| raw.trim_matches('"').to_owned())?);
+        lexer.define_keyword("kw:if", "if");
+        let token = lexer.get_next_any(&LineReader::new("\"if\"")).ok_or("no token")?;
+        assert_eq!(token.token_type, "string");
+        assert_eq!(token.value, "if");
+        Ok(())
+    }
+
+    /// matching by type respects keyword reclassification.
+    #[test]
+    fn keyword_get_next_token() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("ident", "[a-z]+")?;
+        lexer.define_keyword("kw:if", "if");
+        let reader = LineReader::new("if");
+        assert!(lexer.get_next_token("ident", &reader).is_none());
+        assert!(lexer.get_next_token("kw:if", &reader).is_some());
+        let reader = LineReader::new("iffy");
+        assert!(lexer.get_next_token("ident", &reader).is_some());
+        assert!(lexer.get_next_token("kw:if", &reader).is_none());
+        Ok(())
+    }
+
+    /// the token types reclassified as keywords can be set.
+    #[test]
+    fn keyword_types() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("name", "[a-z]+")?;
+        lexer.define("ident", "_[a-z]+")?;
+        lexer.define_keyword("kw:if", "if");
+        lexer.define_keyword("kw:_if", "_if");
+        // only `ident` by default
+        let token = lexer.get_next_any(&LineReader::new("if")).ok_or("no token")?;
+        assert_eq!(token.token_type, "name");
+        lexer.set_keyword_types(&["name"]);
+        let token = lexer.get_next_any(&LineReader::new("if")).ok_or("no token")?;
+        assert_eq!(token.token_type, "kw:if");
+        let token = lexer.get_next_any(&LineReader::new("_if")).ok_or("no token")?;
+        assert_eq!(token.token_type, "ident");
+        assert!(lexer.get_next_token("kw:if", &LineReader::new("if")).is_some());
+        Ok(())
+    }
+
+    /// skipped content is not part of the token but the reader moves past it.
+    #[test]
+    fn skip_whitespace() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define_skip("space", "[ \\t]+")?;
+        lexer.define_skip("newline", "\\n")?;
+        let mut reader = LineReader::new(" 12 \n  34 ");
+        let mut tokens = vec![];
+        while let Some(token) = lexer.get_next_token("num", &reader) {
+            reader.next(&token)?;
+            reader.commit();
+            tokens.push(token);
+        }
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].value, "12");
+        assert_eq!(tokens[0].position, ReadPointer::from_pos((0,1, 0,3), (1, 3)));
+        assert_eq!(tokens[1].value, "34");
+        assert_eq!(tokens[1].position, ReadPointer::from_pos((1,2, 1,4), (7, 9)));
+        assert!(lexer.at_end(&reader));
+        Ok(())
+    }
+
+    /// patterns that can match empty content are rejected.
+    #[test]
+    fn reject_empty_pattern() {
+        for pattern in ["[a-z]*", "a?", "", "a|", "^(?:b*)"] {
+            assert!(TokenDef::new("tok", pattern).is_err(), "`{pattern}` should be rejected");
+        }
+    }
+
+    /// a pattern that can match nothing is rejected with a message saying why, and a
+    /// pattern that only matches nothing in some places never gives an empty token.
+    #[test]
+    fn reject_zero_width_token() -> Result<(), String> {
+        let err = TokenDef::new("num", "[0-9]*").expect_err("`[0-9]*` matches empty content");
+        assert_eq!(err, "Cannot Build Token Definition - Pattern can match empty content: [0-9]*");
+        // `\b` can't match "" so it is defined, but it only ever matches nothing
+        let mut lexer = Lexer::new();
+        lexer.define("edge", "\\b")?;
+        lexer.define("word", "[a-z]+")?;
+        let mut reader = LineReader::new("ab");
+        assert!(lexer.get_next_token("edge", &reader).is_none());
+        let tokens = lexer.tokenize(&mut reader).map_err(String::from)?;
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_type, "word");
+        Ok(())
+    }
+
+    /// a leading `^` is the same as the anchor the lexer adds.
+    #[test]
+    fn caret_anchored_pattern() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("word", "^[a-d]+")?;
+        lexer.define("pair", "^x|y")?;
+        lexer.define_skip("space", "\\s+")?;
+        let mut reader = LineReader::new("ab cd y");
+        let values = lexer.tokenize(&mut reader).map_err(String::from)?;
+        let values: Vec<_> = values.iter().map(|tok| (tok.token_type.as_str(), tok.value.as_str())).collect();
+        assert_eq!(values, [("word", "ab"), ("word", "cd"), ("pair", "y")]);
+        // the alternation is anchored as a whole
+        assert!(lexer.get_next_token("pair", &LineReader::new("ay")).is_none());
+        Ok(())
+    }
+
+    /// Remove the quotes from a string and replace the escape sequences.
+    fn unquote(raw: &str) -> String {
+        let mut value = String::new();
+        let mut chars = raw[1..raw.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match (c, c == '\\') {
+                (_, true) => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c) => value.push(c),
+                    None => {}
+                },
+                (c, false) => value.push(c),
+            }
+        }
+        value
+    }
+
+    /// transformed tokens store the new value but move the reader over the source.
+    #[test]
+    fn transform_token_value() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define_token(TokenDef::with_transform("str", "\"(?:[^\"\\\\]|\\\\.)*\"", unquote)?);
+        lexer.define("num", "[0-9]+")?;
+        let mut reader = LineReader::new("\"a \\\"b\\\" \\\\n\\tc\"12");
+        // the string token
+        let token = lexer.get_next_token("str", &reader).ok_or("no string token")?;
+        assert_eq!(token.value, "a \"b\" \\n\tc");
+        assert_eq!(token.position, ReadPointer::from_pos((0,0, 0,16), (0, 16)));
+        reader.next(&token)?;
+        reader.commit();
+        // the reader is past the full quoted span
+        let token = lexer.get_next_token("num", &reader).ok_or("no num token")?;
+        assert_eq!(token.value, "12");
+        assert_eq!(token.position, ReadPointer::from_pos((0,16, 0,18), (16, 18)));
+        Ok(())
+    }
+
+    /// a higher priority beats the definition order, then the length and then the order.
+    #[test]
+    fn token_priority() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("int", "[0-9]+")?;
+        lexer.define("float", "[0-9]+\\.[0-9]+")?;
+        lexer.define("num", "[0-9]+(\\.[0-9]+)?")?;
+        let reader = LineReader::new("12.0");
+        // in the order they were defined
+        assert_eq!(lexer.get_next_any(&reader).ok_or("no token")?.token_type, "int");
+        // the longest is the first of the same length
+        assert_eq!(lexer.get_next_longest(&reader).ok_or("no token")?.token_type, "float");
+        // the priority overrides both
+        lexer.define_with_priority("num", "[0-9]+(\\.[0-9]+)?", 2)?;
+        lexer.define_with_priority("int", "[0-9]+", 1)?;
+        assert_eq!(lexer.get_next_any(&reader).ok_or("no token")?.token_type, "num");
+        assert_eq!(lexer.get_next_longest(&reader).ok_or("no token")?.token_type, "num");
+        // the shorter match with the higher priority is still read first
+        lexer.define_with_priority("num", "[0-9]+(\\.[0-9]+)?", 0)?;
+        let token = lexer.get_next_longest(&reader).ok_or("no token")?;
+        assert_eq!((token.token_type.as_str(), token.value.as_str()), ("int", "12"));
+        // redefining keeps the definition order
+        assert_eq!(lexer.definitions(), ["int", "float", "num"]);
+        Ok(())
+    }
 }
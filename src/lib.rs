@@ -2,15 +2,16 @@
 //! 
 //! Something something, basic interpreter in rust. 
 
-use std::ops::Deref;
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::Arc;
 
-use crate::{exec::StateNode, parser::syntax::TreeNode};
-
-mod macros;
 pub mod lexer;
 pub mod parser;
 pub mod exec;
 pub mod lang;
+pub mod repl;
+pub mod report;
 
 /// run a basic input loop where the user will be prompted with `@>` or `#>` to enter
 /// code to be executed.
@@ -18,32 +19,48 @@ pub mod lang;
 /// ---
 /// 
 /// it can be started with `interpreter::run()` or by running the interpreter executable.
+/// A line ending with `\` is continued on the next line after the `#>` prompt. Errors
+/// are colored when stdout is a terminal.
 pub fn run() {
+    run_with_config(&repl::ReplConfig { color: report::use_color(), ..Default::default() });
+}
+
+/// run the input loop with a math [`Executor`](exec::Executor) reading from `input` and
+/// writing to `out` instead of stdin and stdout, so it can be driven by a script.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// let mut out = Vec::new();
+/// interpreter::run_io("x := 2\nx * 4\nexit\n".as_bytes(), &mut out).map_err(|err| err.to_string())?;
+/// assert!(String::from_utf8_lossy(&out).contains("@> 8\n"));
+/// Ok::<(), String>(())
+/// ```
+pub fn run_io<R, W>(input: R, out: &mut W) -> std::io::Result<()>
+where R: std::io::BufRead, W: std::io::Write {
     let mut executor: exec::Executor = exec::Executor::math();
+    repl::run_with(&mut executor, &repl::ReplConfig::default(), input, out)
+}
 
-    use macros::io::*;
-    loop {
-        // spacer
-        println!("---");
-        // prompt the user for input
-        let raw = prompt!("@> ");
-        let input = raw.trim();
-        if input == "exit" {
-            break;
-        }
-        // exec the input
-        let mut reader = lexer::LineReader::new(input);
-        let result = match executor.exec(&mut reader) {
-            Ok(val) => val,
-            Err(err) => {
-                // this is where you can check for ErrorEOF
-                println!("Encountered Error: {err}");
-                continue;
-            }
-        };
-        // display the result
-        println!("{result}");
-    }
+/// run the input loop on stdin and stdout with the prompts, spacer and banner of `config`.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ``` ignore
+/// use interpreter::repl::ReplConfig;
+/// interpreter::run_with_config(&ReplConfig {
+///     prompt: "calc> ".into(),
+///     banner: Some("My Calculator v1.0".into()),
+///     ..Default::default()
+/// });
+/// ```
+pub fn run_with_config(config: &repl::ReplConfig) {
+    let mut executor: exec::Executor = exec::Executor::math();
+    let _ = repl::run_with(&mut executor, config, std::io::stdin().lock(), &mut std::io::stdout());
 }
 
 /// Executes a line of our custom programming language using a typical, yet complex process.
@@ -53,52 +70,209 @@ pub fn run() {
 /// Uses a LineReader and Lexer to make a list of Tokens - those are fed to a Parser to 
 /// produce an Abstract Syntax Tree - finally the Interpreter will traverse the AST and
 /// run your code - producing a Result.
-pub fn exec(expr_str: &str) -> Result<String, String>{
-    unimplemented!()
-    // use lang::math;
+/// 
+/// The math [`Executor`](exec::Executor) is made the first time `exec` is called and kept
+/// for the rest of the thread, so variables persist between calls.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// assert_eq!(interpreter::exec("2*(3+4)"), Ok("14".into()));
+/// 
+/// interpreter::exec("x:=5")?;
+/// assert_eq!(interpreter::exec("x*2"), Ok("10".into()));
+/// Ok::<(), String>(())
+/// ```
+pub fn exec(expr_str: &str) -> Result<String, String> {
+    thread_local! {
+        static EXECUTOR: RefCell<exec::Executor<'static>> = RefCell::new(exec::Executor::math());
+    }
+    EXECUTOR.with(|executor| executor.borrow_mut().exec_str(expr_str))
+}
+
+/// Executes every expression in a script file with a math [`Executor`](exec::Executor),
+/// what the script prints is written to stdout.
+/// 
+/// ---
+/// 
+/// Errors include the file name along with the line and column they happened on.
+/// 
+/// ``` ignore
+/// interpreter::run_file(Path::new("./path/to/script.m"))?;
+/// ```
+pub fn run_file(path: &Path) -> Result<(), exec::ExecError> {
+    run_file_with(&mut exec::Executor::math(), path)
+}
 
-    // -=- line reader -=- //
-    
+/// Executes every expression in a script file like [`run_file`] with the `executor`, so
+/// its output and variables can be set up or looked at afterwards.
+pub fn run_file_with(executor: &mut exec::Executor, path: &Path) -> Result<(), exec::ExecError> {
+    let mut reader = lexer::FileReader::new(path)
+        .map_err(|err| exec::ExecError::io(&err).in_file(path))?;
+    executor.exec_all(&mut reader).map(|_| ()).map_err(|err| err.in_file(path))
+}
+
+/// Evaluates every expression in `expr` with a new math [`Executor`](exec::Executor)
+/// and returns the value of the last one, nothing is kept between calls.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::exec::NodeValue;
+/// assert_eq!(interpreter::eval("x := 4\nx * 2"), Ok(NodeValue::Integer(8)));
+/// ```
+pub fn eval(expr: &str) -> Result<exec::NodeValue, String> {
+    exec::Executor::math().exec_value(expr).map_err(|err| err.to_string())
+}
+
+/// Parse `input` with the built-in language named by `grammar` (`math`, `calc` or `json`)
+/// for fuzzing, any input gives `Ok` or an error and a panic while parsing is returned
+/// as an error starting with `Parser panicked`.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// assert_eq!(interpreter::fuzz_target("math", "x := (1 + 2) * 3"), Ok(()));
+/// assert!(interpreter::fuzz_target("math", "1 + * )").is_err());
+/// assert!(interpreter::fuzz_target("lisp", "(+ 1 2)").is_err());
+/// ```
+pub fn fuzz_target(grammar: &str, input: &str) -> Result<(), String> {
+    let (lexer, parser) = match grammar {
+        "math" => lang::math::grammar(),
+        "calc" => lang::calc::grammar(),
+        "json" => (Arc::new(lang::json::lexer()), Arc::new(lang::json::parser())),
+        _ => return Err(format!("Unknown grammar `{grammar}`, expected math, calc or json")),
+    };
+    parser.parse_all_catching(&lexer, &mut lexer::LineReader::new(input))?;
+    Ok(())
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
 
-    
+    /// Write a script to a temporary file for running.
+    fn script(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("interpreter-{}-{name}", std::process::id()));
+        std::fs::write(&path, content).expect("could not write the script file");
+        path
+    }
 
-    // -=- lexer (tokenizer) -=- //
+    /// a script with assignments over several lines.
+    #[test]
+    fn exec_script_file() -> Result<(), String> {
+        let path = script("assign.m", "a := 2\nb := a * 3\n\nc := (a + b) * 2\nprint(c - 1)\n");
+        let mut reader = lexer::FileReader::new(&path)?;
+        let result = exec::Executor::math().exec_all(&mut reader);
+        assert_eq!(result, Ok("None".into()));
+        // what the script prints is its output
+        let lines = Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = lines.clone();
+        let mut executor = exec::Executor::math();
+        executor.env_mut().set_output(Box::new(move |line| sink.lock().unwrap().push(line.to_owned())));
+        assert_eq!(run_file_with(&mut executor, &path), Ok(()));
+        assert_eq!(*lines.lock().unwrap(), ["15"]);
+        assert_eq!(executor.variables().get("c"), Some(&exec::NodeValue::Integer(16)));
+        assert_eq!(run_file(&path), Ok(()));
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
 
-    // let mut _lexer = lexer::Lexer::new(); // typically you would make this only once.
-    // _lexer.define("num", "[0-9]+")?;
-    // _lexer.define("str", "[a-zA-Z_]+")?;
+    /// errors in a script name the file, line and column.
+    #[test]
+    fn run_file_error_position() {
+        let path = script("error.m", "a := 2\nb := a\n  )\n");
+        let err = run_file(&path).expect_err("the script should fail");
+        assert_eq!(err.kind, exec::ExecErrorKind::Parse);
+        assert_eq!(err.file.as_deref(), Some(path.as_path()));
+        assert!(err.to_string().starts_with(&format!("{}:3:3: Parse Error", path.display())), "{err}");
+        let _ = std::fs::remove_file(path);
+        // runtime errors point to the start of the failing expression
+        let path = script("runtime.m", "a := 2\n  b := 1 / 0\n");
+        let err = run_file(&path).expect_err("the script should fail");
+        assert_eq!(err.kind, exec::ExecErrorKind::Runtime);
+        assert!(err.to_string().starts_with(&format!("{}:2:3: Runtime Error", path.display())), "{err}");
+        let _ = std::fs::remove_file(path);
+    }
 
-    // match by token type
-    // if let Some(tok) = _lexer.get_next_token("num", &reader) {
-    //     println!("Hey you found the number: {}", tok.value);
-    // }
+    /// the whole line has to be one expression.
+    #[test]
+    fn exec_trailing_input() {
+        assert!(exec("1 2").is_err());
+        assert!(exec("1 +").is_err());
+        assert_eq!(exec("1 + 2"), Ok("3".into()));
+    }
 
-    // match any
-    // if let Some(tok) = _lexer.get_next_any(&reader) {
-    //     println!("Found: {tok:?}");
-    // };
+    /// values are returned without being displayed.
+    #[test]
+    fn eval_value() {
+        assert_eq!(eval("3/2"), Ok(exec::NodeValue::Float(1.5)));
+        let err = eval("1/0").expect_err("dividing by zero is an error");
+        assert!(err.contains("Runtime Error"), "{err}");
+        // every call is a new environment
+        assert_eq!(eval("y := 1"), Ok(exec::NodeValue::Integer(1)));
+        assert!(eval("y").is_err());
+    }
 
-    // -=- parser (ast builder) -=- //
+    /// missing files are an IO error.
+    #[test]
+    fn run_file_missing() {
+        let err = run_file(Path::new("./does/not/exist.m")).expect_err("the file should not exist");
+        assert_eq!(err.kind, exec::ExecErrorKind::Io);
+    }
 
-    // let mut parser = parser::Parser::new(); // typically you would make this only once.
-    // parser.define("expr", parser::RuleType::Token("num")); // TODO
+    /// scripted input is run and the output captured.
+    #[test]
+    fn run_io_scripted() -> Result<(), String> {
+        let mut out = Vec::new();
+        run_io("1+1\nexit\n".as_bytes(), &mut out).map_err(|err| err.to_string())?;
+        let out = String::from_utf8(out).map_err(|err| err.to_string())?;
+        assert!(out.contains('2'), "{out}");
+        assert_eq!(out, "---\n@> 2\n---\n@> ");
+        Ok(())
+    }
 
-    // let ast = PARSER.parse_tree(&LEXER, &mut reader)?;
-    // println!("AST:\n{ast:}");
+    /// the pieces the fuzz inputs are made of.
+    const FUZZ_PIECES: &[&str] = &["1", "42", "3.5", "x", "foo", "+", "-", "*", "/", "//", "%", "(", ")", "[", "]",
+        "{", "}", ",", ".", ":", ":=", "=", "+=", " ", "\n", "\"", "é", "#", "print"];
 
-    // -=- interpreter -=- //
-    // ENV.set_ident("thing", exec::NodeValue::Integer(-1));
-    // let result = ENV.exec(ast);
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(3000))]
 
-    // match result {
-    //     StateNode::None => Ok("None".into()),
-    //     StateNode::Value(val) => Ok(val.to_string().unwrap_or_default()),
-        
-    //     StateNode::RuntimeErr(err) => Err(err),
-    //     StateNode::Node(node) => Err(format!("Node Result: {node}")),
-    // }
-    // println!("Result: {result:?}");
+        /// random operators, numbers and brackets parse or give an error, they never panic.
+        #[test]
+        fn fuzz_math(pieces in proptest::collection::vec(proptest::sample::select(FUZZ_PIECES), 0..16)) {
+            let input = pieces.concat();
+            for grammar in ["math", "calc"] {
+                if let Err(err) = fuzz_target(grammar, &input) {
+                    proptest::prop_assert!(!err.starts_with("Parser panicked"), "{} panicked on {:?}: {}", grammar, input, err);
+                }
+            }
+        }
+    }
 
-    // just ping back the input for now
-    // Ok(format!("Ping: {expr_str:?}"))
-}
\ No newline at end of file
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(64))]
+
+        /// brackets and signs nested deeper than the parser's depth limit give an error, they
+        /// never overflow the stack.
+        #[test]
+        fn fuzz_nesting(opens in proptest::collection::vec(proptest::sample::select(&["(", "[", "{", "-", "if true {"][..]), 0..400)) {
+            let input = opens.concat() + "1";
+            for grammar in ["math", "calc", "json"] {
+                if let Err(err) = fuzz_target(grammar, &input) {
+                    proptest::prop_assert!(!err.starts_with("Parser panicked"), "{} panicked on {:?}: {}", grammar, input, err);
+                }
+            }
+        }
+    }
+}
@@ -2,11 +2,35 @@
 //! 
 //! Where the interpreter is launched from.
 //! 
+//! - `interpreter` starts the input loop
+//! - `interpreter script.m` runs a script file
+//! - `interpreter -e "1+2"` evaluates a single line
 //! 
 //! Note: to remove backtracing run `$env:RUST_BACKTRACE=0`
 //! 
 
+use std::path::Path;
+use std::process::exit;
+use interpreter::exec::Executor;
+use interpreter::lexer::LineReader;
+
 /// The main entry point for our program
 fn main() {
-    interpreter::run();
-}
\ No newline at end of file
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [] => {
+            interpreter::run();
+            return;
+        }
+        [flag, expr] if flag == "-e" => Executor::math().exec_all(&mut LineReader::new(expr)).map(|result| println!("{result}")),
+        [path] if !path.starts_with('-') => interpreter::run_file(Path::new(path)),
+        _ => {
+            eprintln!("Usage: interpreter [script | -e expression]");
+            exit(2);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("{err}");
+        exit(1);
+    }
+}
@@ -0,0 +1,46 @@
+//! # Parser Errors
+//! 
+//! Contains the [`ParseError`] returned when the [`Parser`](super::Parser) cannot
+//! build an Abstract Syntax Tree.
+//! 
+
+use crate::lexer::ReadPointer;
+
+/// An error from parsing with the position in the source where it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// Message describing what went wrong
+    pub message: String,
+    /// Where in the source the error happened
+    pub position: ReadPointer,
+    /// Stops the parser from trying other alternatives, set for errors from a rule's
+    /// [check](super::Parser::define_with_check)
+    pub fatal: bool,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (line, col) = self.position.user_position();
+        write!(f, "{} at line {line} col {col}", self.message)
+    }
+}
+
+/// Keeps parse errors usable where a `String` error is expected.
+impl From<ParseError> for String {
+    fn from(err: ParseError) -> Self {
+        err.to_string()
+    }
+}
+
+impl ParseError {
+    /// Make a new parse error at the `position`.
+    pub fn new(message: &str, position: ReadPointer) -> ParseError {
+        ParseError { message: message.to_owned(), position, fatal: false }
+    }
+
+    /// Make the error [fatal](ParseError::fatal) so no other alternative is tried.
+    pub fn into_fatal(mut self) -> ParseError {
+        self.fatal = true;
+        self
+    }
+}
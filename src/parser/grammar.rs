@@ -0,0 +1,357 @@
+//! # Grammar Text
+//!
+//! Reads the lines of grammar text written by [`to_grammar_text`](super::Parser::to_grammar_text)
+//! back into rules for [`from_grammar`](super::Parser::from_grammar), and writes the
+//! lambdas of the rules.
+//!
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use crate::exec::syntax::Lambda;
+use super::Assoc;
+use super::syntax::Expression;
+
+/// A line of grammar text.
+#[derive(Clone)]
+pub(super) enum Line {
+    /// `%separator expr`
+    Separator(Expression<'static>),
+    /// `%sync "a" "b"`
+    Sync(Vec<&'static str>),
+    /// `NAME := ...`
+    Rule(String, Rule),
+}
+
+/// The right side of a rule line.
+#[derive(Clone)]
+pub(super) enum Rule {
+    /// `expr => lambda`
+    Define(Expression<'static>, Lambda<'static>),
+    /// `binop left OPERAND [ "+" ADD, ... ]`
+    BinOp(String, &'static [(&'static str, &'static str)], Assoc),
+    /// `postfix OPERAND [ suffix ] => lambda`
+    Postfix(String, Expression<'static>, Lambda<'static>),
+}
+
+/// Write a lambda the way [`parse_line`] reads it - `EVAL`, `EVAL_TOKEN`, `NAME` for an
+/// [`EvalAs`](Lambda::EvalAs), `NAME($1, $3)`, `&2 lambda`, `&[1, 3] lambda` and
+/// `a || b` with a nested `||` in brackets.
+pub(super) fn lambda_text(lambda: &Lambda, nested: bool) -> String {
+    let join = |args: &[u32], prefix: &str| args.iter().map(|arg| format!("{prefix}{arg}")).collect::<Vec<_>>().join(", ");
+    match lambda {
+        Lambda::LambdaOr(lambdas) => {
+            let text = lambdas.iter().map(|lambda| lambda_text(lambda, true)).collect::<Vec<_>>().join(" || ");
+            match nested {
+                true => format!("( {text} )"),
+                false => text,
+            }
+        }
+        Lambda::Lambda(name, args) => format!("{name}({})", join(args, "$")),
+        Lambda::GetExpr(arg, lambda) => format!("&{arg} {}", lambda_text(lambda, true)),
+        Lambda::GetExprs(args, lambda) => format!("&[{}] {}", join(args, ""), lambda_text(lambda, true)),
+        Lambda::Eval => "EVAL".into(),
+        Lambda::EvalAs(name) => name.to_string(),
+        Lambda::EvalToken => "EVAL_TOKEN".into(),
+    }
+}
+
+/// Read a line of grammar text, a blank line or a `#` comment gives `None`. The strings
+/// and slices of the rules are leaked so the rules live as long as the program, the lines
+/// are kept by their text so reading the same line again reuses them instead.
+pub(super) fn parse_line(line: &str) -> Result<Option<Line>, String> {
+    static LINES: OnceLock<Mutex<HashMap<String, Line>>> = OnceLock::new();
+    let lines = LINES.get_or_init(Default::default);
+    if let Some(parsed) = lines.lock().unwrap_or_else(|err| err.into_inner()).get(line) {
+        return Ok(Some(parsed.clone()));
+    }
+    let parsed = read_line(line)?;
+    if let Some(parsed) = &parsed {
+        lines.lock().unwrap_or_else(|err| err.into_inner()).insert(line.to_owned(), parsed.clone());
+    }
+    Ok(parsed)
+}
+
+fn read_line(line: &str) -> Result<Option<Line>, String> {
+    let mut cursor = Cursor { text: line, pos: 0 };
+    cursor.skip_space();
+    if cursor.at_end() || cursor.rest().starts_with('#') {
+        return Ok(None);
+    }
+    let line = match () {
+        _ if cursor.eat_word("%separator") => Line::Separator(cursor.expr()?),
+        _ if cursor.eat_word("%sync") => {
+            let mut values = vec![];
+            cursor.skip_space();
+            while !cursor.at_end() {
+                values.push(leak_str(cursor.string()?));
+                cursor.skip_space();
+            }
+            Line::Sync(values)
+        }
+        _ => {
+            let name = cursor.name()?;
+            cursor.expect(":=")?;
+            Line::Rule(name, cursor.rule()?)
+        }
+    };
+    cursor.skip_space();
+    match cursor.at_end() {
+        true => Ok(Some(line)),
+        false => Err(format!("Unexpected `{}`", cursor.rest())),
+    }
+}
+
+fn leak_str(text: String) -> &'static str {
+    Box::leak(text.into_boxed_str())
+}
+
+fn leak<T>(items: Vec<T>) -> &'static [T] {
+    Box::leak(items.into_boxed_slice())
+}
+
+fn leak_one<T>(item: T) -> &'static T {
+    Box::leak(Box::new(item))
+}
+
+/// Reads the parts of a line one after the other.
+struct Cursor<'t> {
+    text: &'t str,
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.rest().is_empty()
+    }
+
+    fn skip_space(&mut self) {
+        self.pos = self.text.len() - self.rest().trim_start().len();
+    }
+
+    /// Read `text` if it comes next.
+    fn eat(&mut self, text: &str) -> bool {
+        self.skip_space();
+        match self.rest().starts_with(text) {
+            true => {
+                self.pos += text.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    /// Read the `word` if it comes next followed by a space.
+    fn eat_word(&mut self, word: &str) -> bool {
+        self.skip_space();
+        match self.rest().strip_prefix(word) {
+            Some(rest) if rest.starts_with(char::is_whitespace) => {
+                self.pos += word.len();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        match self.eat(text) {
+            true => Ok(()),
+            false => Err(format!("Expected `{text}` at `{}`", self.rest())),
+        }
+    }
+
+    /// Read the name of a rule or a lambda, like `MATH:EXPR:1`.
+    fn name(&mut self) -> Result<String, String> {
+        self.skip_space();
+        let len = self.rest().find(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':')).unwrap_or(self.rest().len());
+        match len {
+            0 => Err(format!("Expected a name at `{}`", self.rest())),
+            len => {
+                let name = self.rest()[..len].to_owned();
+                self.pos += len;
+                Ok(name)
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        self.skip_space();
+        let len = self.rest().find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest().len());
+        let number = self.rest()[..len].parse().map_err(|_| format!("Expected a number at `{}`", self.rest()))?;
+        self.pos += len;
+        Ok(number)
+    }
+
+    /// Read a quoted string with the escapes of `{:?}`.
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(value);
+                }
+                '\\' => value.push(match chars.next().map(|(_, c)| c) {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('0') => '\0',
+                    Some('u') => {
+                        let code: String = chars.by_ref().map(|(_, c)| c).skip(1).take_while(|c| *c != '}').collect();
+                        u32::from_str_radix(&code, 16).ok().and_then(char::from_u32)
+                            .ok_or_else(|| format!("Bad escape `\\u{{{code}}}`"))?
+                    }
+                    Some(c) => c,
+                    None => break,
+                }),
+                c => value.push(c),
+            }
+        }
+        Err(format!("Unclosed string at `{}`", self.rest()))
+    }
+
+    fn rule(&mut self) -> Result<Rule, String> {
+        if self.eat_word("binop") {
+            let assoc = match self.name()?.as_str() {
+                "left" => Assoc::Left,
+                "right" => Assoc::Right,
+                assoc => return Err(format!("Unknown associativity `{assoc}`")),
+            };
+            let operand = self.name()?;
+            self.expect("[")?;
+            let mut ops = vec![];
+            while !self.eat("]") {
+                if !ops.is_empty() {
+                    self.expect(",")?;
+                }
+                let op = leak_str(self.string()?);
+                ops.push((op, leak_str(self.name()?)));
+            }
+            return Ok(Rule::BinOp(operand, leak(ops), assoc));
+        }
+        if self.eat_word("postfix") {
+            let operand = self.name()?;
+            self.expect("[")?;
+            let suffix = self.expr()?;
+            self.expect("]")?;
+            self.expect("=>")?;
+            return Ok(Rule::Postfix(operand, suffix, self.lambda()?));
+        }
+        let expr = self.expr()?;
+        self.expect("=>")?;
+        Ok(Rule::Define(expr, self.lambda()?))
+    }
+
+    /// Read an expression in the form of its [`Display`](Expression#impl-Display-for-Expression%3C'_%3E).
+    fn expr(&mut self) -> Result<Expression<'static>, String> {
+        self.skip_space();
+        if self.eat("(") {
+            if self.eat(")") {
+                return Ok(Expression::SubExpr(&[]));
+            }
+            let mut exprs = vec![self.expr()?];
+            if self.eat("|") {
+                exprs.push(self.expr()?);
+                while !self.eat(")") {
+                    self.expect("|")?;
+                    exprs.push(self.expr()?);
+                }
+                return Ok(Expression::ExprOr(leak(exprs)));
+            }
+            while !self.eat(")") {
+                exprs.push(self.expr()?);
+            }
+            return Ok(Expression::SubExpr(leak(exprs)));
+        }
+        if self.eat("<") {
+            self.skip_space();
+            let len = self.rest().find(|c: char| c.is_whitespace() || c == '>').unwrap_or(self.rest().len());
+            let token = leak_str(self.rest()[..len].to_owned());
+            self.pos += len;
+            self.skip_space();
+            let value = match self.rest().starts_with('"') {
+                true => leak_str(self.string()?),
+                false => "",
+            };
+            self.expect(">")?;
+            return Ok(Expression::Token(token, value));
+        }
+        if self.rest().starts_with('"') {
+            return Ok(Expression::Literal(leak_str(self.string()?)));
+        }
+        if self.eat("sep_by(") {
+            let item = self.expr()?;
+            self.expect(",")?;
+            let sep = self.expr()?;
+            self.expect(")")?;
+            return Ok(Expression::SepBy(leak_one(item), leak_one(sep)));
+        }
+        if self.eat("$end") {
+            return Ok(Expression::End);
+        }
+        if self.eat("!") {
+            return Ok(Expression::Not(leak_one(self.expr()?)));
+        }
+        Ok(Expression::Expr(leak_str(self.name()?)))
+    }
+
+    /// Read a lambda in the form of [`lambda_text`].
+    fn lambda(&mut self) -> Result<Lambda<'static>, String> {
+        let mut lambdas = vec![self.lambda_alt()?];
+        while self.eat("||") {
+            lambdas.push(self.lambda_alt()?);
+        }
+        match lambdas.len() {
+            1 => Ok(lambdas.remove(0)),
+            _ => Ok(Lambda::LambdaOr(leak(lambdas))),
+        }
+    }
+
+    /// Read a lambda that isn't an alternative of a `||`.
+    fn lambda_alt(&mut self) -> Result<Lambda<'static>, String> {
+        if self.eat("(") {
+            let lambda = self.lambda()?;
+            self.expect(")")?;
+            return Ok(lambda);
+        }
+        if self.eat("&[") {
+            let mut args = vec![];
+            while !self.eat("]") {
+                if !args.is_empty() {
+                    self.expect(",")?;
+                }
+                args.push(self.number()?);
+            }
+            return Ok(Lambda::GetExprs(leak(args), leak_one(self.lambda_alt()?)));
+        }
+        if self.eat("&") {
+            let arg = self.number()?;
+            return Ok(Lambda::GetExpr(arg, leak_one(self.lambda_alt()?)));
+        }
+        let name = self.name()?;
+        // the args come straight after the name
+        if self.rest().starts_with('(') {
+            self.pos += 1;
+            let mut args = vec![];
+            while !self.eat(")") {
+                if !args.is_empty() {
+                    self.expect(",")?;
+                }
+                self.expect("$")?;
+                args.push(self.number()?);
+            }
+            return Ok(Lambda::Lambda(leak_str(name), leak(args)));
+        }
+        Ok(match name.as_str() {
+            "EVAL" => Lambda::Eval,
+            "EVAL_TOKEN" => Lambda::EvalToken,
+            _ => Lambda::EvalAs(leak_str(name)),
+        })
+    }
+}
@@ -1,62 +1,104 @@
 //! # AST Assertion Macros
 //! 
-//! This is where the [`assert_ast`] and [`assert_ast_ne`] macros are defined and tested.
+//! This is where the [`assert_ast`], [`assert_ast_ne`] and [`assert_ast_display`] macros
+//! are defined and tested.
 //! 
 
 
 // -=-=-=-=- Testing Macros -=-=-=-=- //
 
 /// Asserts that two [Abstract Syntax Trees](crate::parser::syntax::AbstractSyntaxTree) 
-/// are equal to each other (using [`PartialEq`]).
+/// or [nodes](crate::parser::syntax::TreeNode) have the same structure - the shape, node
+/// types, lambdas, tokens and where the tokens were read from.
 ///
-/// On panic, this macro will print the values of the expressions with their debug representations.
+/// Parts of the trees can be left out with `; ignore(..)` and the fields of
+/// [`Ignore`](crate::parser::syntax::Ignore), on panic both trees are printed side by side
+/// with the first differing node marked.
 ///
 /// Like [`assert`], this macro has a second form, where a custom panic message can be provided
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::assert_ast;
+/// use interpreter::lang::math;
+/// use interpreter::lexer::LineReader;
+/// use interpreter::parser::syntax::{Expression::*, TreeNode};
+/// let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("x"))?;
+/// assert_ast!(ast, TreeNode::from_expr(&SubExpr(&[Token("ident", "x")])); ignore(positions, lambdas));
+/// Ok::<(), String>(())
+/// ```
 #[macro_export]
 macro_rules! assert_ast {
-    ( $ast:expr, $expected:expr ) => {
+    ( $ast:expr, $expected:expr $(; ignore($($ignore:ident),+))? $(, $($arg:tt)+)? ) => {
         {
-            let ast_str = format!("{}", $ast);
-            let expected_str = format!("{}", $expected);
-            assert_eq!(ast_str, expected_str, "When trying to match ASTs:");
+            #[allow(clippy::needless_update)]
+            let ignore = $crate::parser::syntax::Ignore { $($($ignore: true,)+)? ..::std::default::Default::default() };
+            // matched so temporaries live until the end of the assertion
+            match (&$ast, &$expected) { (ast, expected) => {
+            let ast: &$crate::parser::syntax::TreeNode = ::std::convert::AsRef::as_ref(ast);
+            let expected: &$crate::parser::syntax::TreeNode = ::std::convert::AsRef::as_ref(expected);
+            if let Some(diff) = ast.diff(expected, ignore) {
+                let msg = ::std::string::String::new() $(+ &format!($($arg)+))?;
+                panic!("When trying to match ASTs: {msg}\n{diff}");
+            }
+            }}
         }
     };
-    ( $ast:expr, $expected:expr, $($arg:tt)+ ) => {
+}
+
+/// Asserts that two [Abstract Syntax Trees](crate::parser::syntax::AbstractSyntaxTree)
+/// or [nodes](crate::parser::syntax::TreeNode) have a different structure, the opposite
+/// of [`assert_ast`] with the same forms.
+#[macro_export]
+macro_rules! assert_ast_ne {
+    ( $ast:expr, $expected:expr $(; ignore($($ignore:ident),+))? $(, $($arg:tt)+)? ) => {
         {
-            let ast_str = format!("{}", $ast);
-            let expected_str = format!("{}", $expected);
-            assert_eq!(ast_str, expected_str, "When trying to match ASTs: {}", format!($($arg)+));
+            #[allow(clippy::needless_update)]
+            let ignore = $crate::parser::syntax::Ignore { $($($ignore: true,)+)? ..::std::default::Default::default() };
+            // matched so temporaries live until the end of the assertion
+            match (&$ast, &$expected) { (ast, expected) => {
+            let ast: &$crate::parser::syntax::TreeNode = ::std::convert::AsRef::as_ref(ast);
+            let expected: &$crate::parser::syntax::TreeNode = ::std::convert::AsRef::as_ref(expected);
+            if ast.first_difference(expected, ignore).is_none() {
+                let msg = ::std::string::String::new() $(+ &format!($($arg)+))?;
+                panic!("Both are equal when trying to match ASTs: {msg}\n{ast:#}");
+            }
+            }}
         }
     };
 }
 
-/// Asserts that two expressions are equal to each other (using [`PartialEq`]).
+/// Asserts that two [Abstract Syntax Trees](crate::parser::syntax::AbstractSyntaxTree) 
+/// are displayed the same - trees that differ only in what isn't displayed (like the
+/// lambdas) are equal.
 ///
 /// On panic, this macro will print the values of the expressions with their debug representations.
 ///
 /// Like [`assert`], this macro has a second form, where a custom panic message can be provided
 #[macro_export]
-macro_rules! assert_ast_ne {
+macro_rules! assert_ast_display {
     ( $ast:expr, $expected:expr ) => {
         {
             let ast_str = format!("{}", $ast);
             let expected_str = format!("{}", $expected);
-            assert_ne!(ast_str, expected_str, "Both are equal when trying to match ASTs:");
+            assert_eq!(ast_str, expected_str, "When trying to match ASTs:");
         }
     };
     ( $ast:expr, $expected:expr, $($arg:tt)+ ) => {
         {
             let ast_str = format!("{}", $ast);
             let expected_str = format!("{}", $expected);
-            let msg = format!($($arg)+);
-            assert_ne!(ast_str, expected_str, "Both are equal when trying to match ASTs: {}", msg);
+            assert_eq!(ast_str, expected_str, "When trying to match ASTs: {}", format!($($arg)+));
         }
     };
 }
 
 // -=-=-=-=- Unit Tests -=-=-=-=- //
 
-/// Test that [`assert_ast`] and [`assert_ast_ne`] are working properly.
+/// Test that [`assert_ast`], [`assert_ast_ne`] and [`assert_ast_display`] are working properly.
 #[cfg(test)]
 mod tests {
     // lexer
@@ -66,7 +108,7 @@ mod tests {
     use crate::parser::Parser;
     use crate::parser::syntax::TreeNode;
     use crate::parser::syntax::Expression::*;
-    use crate::exec::syntax::Lambda::Eval;
+    use crate::exec::syntax::Lambda::{Eval, EvalAs};
     
     /// assert two [tokens](Token) can be matched with [`assert_ast`].
     #[test]
@@ -82,7 +124,7 @@ mod tests {
         // Define the expected AST structure
         let exp = TreeNode::from_token(Token("tok:a", "abc").token());
         // Assert that the AST matches the expected structure
-        assert_ast!(exp, ast);
+        assert_ast!(exp, ast; ignore(positions, lambdas));
         Ok(())
     }
 
@@ -100,7 +142,7 @@ mod tests {
         // Define the expected AST structure
         let exp = TreeNode::from_token(Token("tok:a", "def").token());
         // Assert that the AST doesn't match the expected structure
-        assert_ast_ne!(exp, ast);
+        assert_ast_ne!(exp, ast; ignore(positions, lambdas));
         Ok(())
     }
 
@@ -130,9 +172,9 @@ mod tests {
         let exp_2 = TreeNode::from_token(Token("tok:b", "def").token());
         let exp_3 = TreeNode::from_token(Token("tok:c", "ghi").token());
         // Assert that the AST matches the expected structure
-        assert_ast!(exp_1, ast_1);
-        assert_ast!(exp_2, ast_2);
-        assert_ast!(exp_3, ast_3);
+        assert_ast!(exp_1, ast_1; ignore(positions, lambdas));
+        assert_ast!(exp_2, ast_2; ignore(positions, lambdas));
+        assert_ast!(exp_3, ast_3; ignore(positions, lambdas));
         Ok(())
     }
     
@@ -167,10 +209,10 @@ mod tests {
         let exp_3 = TreeNode::from_token(Token("tok:a", "abc").token());
         let exp_4 = TreeNode::from_token(Token("tok:c", "ghi").token());
         // Assert that the AST matches the expected structure
-        assert_ast!(exp_1, ast_1);
-        assert_ast!(exp_2, ast_2);
-        assert_ast!(exp_3, ast_3);
-        assert_ast!(exp_4, ast_4);
+        assert_ast!(exp_1, ast_1; ignore(positions, lambdas));
+        assert_ast!(exp_2, ast_2; ignore(positions, lambdas));
+        assert_ast!(exp_3, ast_3; ignore(positions, lambdas));
+        assert_ast!(exp_4, ast_4; ignore(positions, lambdas));
         Ok(())
     }
 
@@ -192,7 +234,7 @@ mod tests {
         // Define the expected AST structure
         let exp = TreeNode::from_token(Token("tok", "token").token());
         // Assert that the AST matches the expected structure
-        assert_ast!(exp, ast);
+        assert_ast!(exp, ast; ignore(positions, lambdas));
         Ok(())
     }
 
@@ -229,7 +271,7 @@ mod tests {
             ]),
         ]));
         // Assert that the AST matches the expected structure
-        assert_ast!(exp, ast);
+        assert_ast!(exp, ast; ignore(positions, lambdas));
         Ok(())
     }
 
@@ -270,7 +312,46 @@ mod tests {
             ]),
         ]));
         // Assert that the AST matches the expected structure
-        assert_ast!(exp, ast);
+        assert_ast!(exp, ast; ignore(positions, lambdas));
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// trees displayed the same but with different lambdas are told apart.
+    #[test]
+    fn assert_ast_structure() {
+        let tree = TreeNode::from_expr(&SubExpr(&[Token("num", "1"), Token("op", "+")]));
+        let mut other = tree.clone();
+        other.nodes[0].set_lambda(&EvalAs("INTEGER"));
+        assert_ast_display!(tree, other);
+        assert_ast_ne!(tree, other);
+        assert_ast!(tree, other; ignore(lambdas));
+        let diff = tree.diff(&other, Default::default()).expect("the lambdas differ");
+        assert_eq!(diff.lines().next(), Some("first difference at [0]: `num:1 (1:1 0) { EVAL_TOKEN }` != `num:1 (1:1 0) { INTEGER }`"));
+        assert!(diff.lines().nth(2).is_some_and(|line| line.starts_with(">   num:1") && line.contains("| >   num:1")));
+    }
+
+    /// the token positions are compared unless they are ignored.
+    #[test]
+    fn assert_ast_positions() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("tok", "[a-z]+")?;
+        lexer.define_skip("space", "\\s+")?;
+        let mut parser = Parser::new();
+        parser.define("EXPR", Token("tok", ""), Eval);
+        let ast = parser.parse_tree(&lexer, &mut LineReader::new("abc"))?;
+        let moved = parser.parse_tree(&lexer, &mut LineReader::new("  abc"))?;
+        assert_ast_display!(ast, moved);
+        assert_ast_ne!(ast, moved);
+        assert_ast!(ast, moved; ignore(positions), "only the position differs");
+        Ok(())
+    }
+
+    /// the failure shows both trees.
+    #[test]
+    #[should_panic(expected = "first difference at [0]")]
+    fn assert_ast_failure() {
+        let tree = TreeNode::from_expr(&SubExpr(&[Token("num", "1")]));
+        let other = TreeNode::from_expr(&SubExpr(&[Token("int", "1")]));
+        assert_ast!(tree, other; ignore(positions, lambdas));
+    }
+}
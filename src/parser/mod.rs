@@ -7,6 +7,12 @@
 mod macros;
 
 pub mod syntax;
+#[allow(clippy::module_inception)]
 mod parser;
+mod error;
+mod visit;
+mod grammar;
 
-pub use parser::*;
\ No newline at end of file
+pub use parser::*;
+pub use error::*;
+pub use visit::*;
\ No newline at end of file
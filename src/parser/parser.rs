@@ -2,52 +2,675 @@
 //! 
 //! Contains the definitions for the [`Parser`] and its unit testing.
 //! 
-//! ---
-//! 
-//! Note: unit testing is [unimplemented].
-//! 
 
 use std::collections::HashMap;
-use crate::lexer::{Lexer, Reader};
+use std::panic::{self, AssertUnwindSafe};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use crate::lexer::{Lexer, LineReader, ReadPointer, Reader};
 use crate::exec::syntax::Lambda;
 use super::syntax::{AbstractSyntaxTree, Expression, TreeNode};
+use super::ParseError;
+use super::grammar::{self, lambda_text, Line, Rule};
 
 /// Parser has all the language syntax for a language. It can extract the next Abstract
 /// Syntax Tree ([AST](AbstractSyntaxTree)) from a [`Reader`] using a [`Lexer`]. 
 pub struct Parser<'a> {
-    definitions: HashMap<String, ParserDef<'a>>
+    definitions: HashMap<String, ParserDef<'a>>,
+    separator: Option<Expression<'a>>,
+    sync_tokens: Vec<&'a str>,
+    /// remember what each rule parsed to at each position within a parse
+    memoize: bool,
+    /// how many rules can be expanded inside each other
+    depth_limit: usize,
+}
+
+/// How many rules can be expanded inside each other by default, see
+/// [`set_depth_limit`](Parser::set_depth_limit).
+const DEFAULT_DEPTH_LIMIT: usize = 128;
+
+impl Default for Parser<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> Parser<'a> {
     pub fn new() -> Parser<'a> {
-        Parser { definitions: HashMap::new() }
+        Parser { definitions: HashMap::new(), separator: None, sync_tokens: vec![], memoize: true, depth_limit: DEFAULT_DEPTH_LIMIT }
     }
 
     /// Use a [`Lexer`] and a [`Reader`] to parse the next [`Expression`] from the Reader's content.
-    pub fn parse_tree<T>(&self, lexer: &Lexer, reader: &mut T) -> Result<AbstractSyntaxTree, String>
+    pub fn parse_tree<T>(&self, lexer: &Lexer, reader: &mut T) -> Result<AbstractSyntaxTree, ParseError>
     where T: Reader {
         // println!("Parsing an Expression");
         let expr = match self.definitions.get("EXPR") {
             Some(expr) => expr,
             None => { 
-                return Err("You need to define an Expression for EXPR".into());
+                let msg = "You need to define an Expression for EXPR";
+                return Err(ParseError::new(msg, lexer.next_position(reader)));
             }
         };
-        let root = expr.get(lexer, &self, reader)?;
+        let root = expr.get(lexer, self, reader)?;
         reader.commit();
         Ok(AbstractSyntaxTree::new(root))
     }
 
+    /// Parse the next expression like [`parse_tree`](Parser::parse_tree), but a panic while
+    /// parsing is returned as an error at the reader's position instead of unwinding - so
+    /// a bad grammar, token transform or reader can't take down the process. A stack
+    /// overflow still aborts, deep nesting is stopped by the [depth limit](Parser::set_depth_limit).
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let ast = math::parser().parse_tree_catching(&math::lexer(), &mut LineReader::new("1 + 2"))?;
+    /// assert_eq!(ast.root.nodes.len(), 3);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn parse_tree_catching<T>(&self, lexer: &Lexer, reader: &mut T) -> Result<AbstractSyntaxTree, ParseError>
+    where T: Reader {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.parse_tree(lexer, reader)));
+        caught(result, reader.get_pointer())
+    }
+
+    /// Parse the next expression like [`parse_tree`](Parser::parse_tree) where it has to
+    /// be the rest of the reader's content, only an optional [separator](Parser::set_separator)
+    /// can come after it.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let (lexer, parser) = (math::lexer(), math::parser());
+    /// assert!(parser.parse_complete(&lexer, &mut LineReader::new("1 + 2")).is_ok());
+    /// assert!(parser.parse_complete(&lexer, &mut LineReader::new("1 2")).is_err());
+    /// ```
+    pub fn parse_complete<T>(&self, lexer: &Lexer, reader: &mut T) -> Result<AbstractSyntaxTree, ParseError>
+    where T: Reader {
+        let ast = self.parse_tree(lexer, reader)?;
+        self.skip_separator(lexer, reader);
+        match lexer.at_end(reader) {
+            true => Ok(ast),
+            false => Err(ParseError::new("Unexpected token after expression", lexer.next_position(reader))),
+        }
+    }
+
+    /// Parse every expression until the end of the reader's content, an optional
+    /// [separator](Parser::set_separator) is read after each one. An expression that is
+    /// cut off by the end of the content is an error.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let trees = math::parser().parse_all(&math::lexer(), &mut LineReader::new("x = 1\nx + 2"))?;
+    /// assert_eq!(trees.len(), 2);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn parse_all<T>(&self, lexer: &Lexer, reader: &mut T) -> Result<Vec<AbstractSyntaxTree>, ParseError>
+    where T: Reader {
+        let mut trees = vec![];
+        while !lexer.at_end(reader) {
+            let start = reader.get_pointer().read_pos.1;
+            trees.push(self.parse_tree(lexer, reader)?);
+            self.skip_separator(lexer, reader);
+            // an expression that reads nothing would be parsed forever
+            if reader.get_pointer().read_pos.1 == start {
+                return Err(ParseError::new("Expression did not read anything", lexer.next_position(reader)));
+            }
+        }
+        Ok(trees)
+    }
+
+    /// Parse every expression like [`parse_all`](Parser::parse_all), a panic is returned as
+    /// an error like [`parse_tree_catching`](Parser::parse_tree_catching).
+    pub fn parse_all_catching<T>(&self, lexer: &Lexer, reader: &mut T) -> Result<Vec<AbstractSyntaxTree>, ParseError>
+    where T: Reader {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.parse_all(lexer, reader)));
+        caught(result, reader.get_pointer())
+    }
+
+    /// Parse every expression like [`parse_all`](Parser::parse_all), but keep going after
+    /// an expression fails to parse. The error is recorded and the tokens are skipped up to
+    /// and including the next [sync token](Parser::set_sync_tokens), or to the end of the
+    /// content when there are none.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// use interpreter::parser::syntax::Expression::Token;
+    /// let mut lexer = math::lexer();
+    /// lexer.define("sep", ";")?;
+    /// let mut parser = math::parser();
+    /// parser.set_separator(Token("sep", ";"));
+    /// parser.set_sync_tokens(&[";"]);
+    /// let (trees, errors) = parser.parse_all_recovering(&lexer, &mut LineReader::new("1 + 2; ) 3; 4"));
+    /// assert_eq!(trees.len(), 2);
+    /// assert_eq!(errors.len(), 1);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn parse_all_recovering<T>(&self, lexer: &Lexer, reader: &mut T) -> (Vec<AbstractSyntaxTree>, Vec<ParseError>)
+    where T: Reader {
+        let mut trees = vec![];
+        let mut errors = vec![];
+        while !lexer.at_end(reader) {
+            let checkpoint = reader.checkpoint();
+            match self.parse_tree(lexer, reader) {
+                Ok(tree) => trees.push(tree),
+                Err(err) => {
+                    errors.push(err);
+                    reader.restore(checkpoint.clone());
+                    self.synchronize(lexer, reader);
+                },
+            }
+            self.skip_separator(lexer, reader);
+            // an expression that reads nothing is skipped like a failed one
+            if reader.get_pointer().read_pos.1 == checkpoint.read_pos.1 {
+                errors.push(ParseError::new("Expression did not read anything", lexer.next_position(reader)));
+                self.synchronize(lexer, reader);
+            }
+        }
+        (trees, errors)
+    }
+
+    /// Read the [separator](Parser::set_separator) if it is next in the reader.
+    fn skip_separator<T>(&self, lexer: &Lexer, reader: &mut T)
+    where T: Reader {
+        if let Some(separator) = &self.separator {
+            reader.push();
+            match separator.get(lexer, self, reader, &Lambda::Eval) {
+                Ok(_) => reader.pop(),
+                Err(_) => reader.back(),
+            }
+            reader.commit();
+        }
+    }
+
+    /// Skip tokens up to and including the next sync token, a character that isn't a token
+    /// is skipped on its own.
+    fn synchronize<T>(&self, lexer: &Lexer, reader: &mut T)
+    where T: Reader {
+        static ANY: Lazy<Regex> = Lazy::new(|| Regex::new("\\A(?s:.)").unwrap());
+        while !lexer.at_end(reader) {
+            match lexer.get_next_any(reader) {
+                Some(token) => {
+                    let _ = reader.next(&token);
+                    reader.commit();
+                    if self.sync_tokens.contains(&token.value.as_str()) {
+                        return;
+                    }
+                },
+                None => {
+                    let unknown = reader.read_regex_at(&lexer.next_position(reader), &ANY).map(|(c, _)| c.to_owned());
+                    let skipped = lexer.trivia(reader) + &unknown.unwrap_or_default();
+                    let _ = reader.next(skipped);
+                    reader.commit();
+                },
+            }
+        }
+    }
+
+    /// Set the token values that [`parse_all_recovering`](Parser::parse_all_recovering)
+    /// skips to after an expression fails to parse, like `;`.
+    pub fn set_sync_tokens(&mut self, values: &[&'a str]) {
+        self.sync_tokens = values.to_vec();
+    }
+
+    /// Set the [`Expression`] that can be between the expressions in
+    /// [`parse_all`](Parser::parse_all), like a `;` token.
+    pub fn set_separator(&mut self, separator: Expression<'a>) {
+        self.separator = Some(separator);
+    }
+
+    /// Set if the result of each rule at each position is remembered for the rest of a
+    /// parse, so backtracking into the same rule at the same position doesn't parse it
+    /// again. It is on by default, without it each level of nesting multiplies the work
+    /// by the number of alternatives that share a prefix.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let mut parser = math::parser();
+    /// parser.set_memoize(false);
+    /// let ast = parser.parse_tree(&math::lexer(), &mut LineReader::new("(1 + 2) * 3"))?;
+    /// assert_eq!(ast.root.nodes.len(), 3);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn set_memoize(&mut self, memoize: bool) {
+        self.memoize = memoize;
+    }
+
+    /// Check if the parser [remembers rule results](Parser::set_memoize).
+    pub(crate) fn memoizes(&self) -> bool {
+        self.memoize
+    }
+
+    /// Limit how many rules can be expanded inside each other before the parse fails with
+    /// a [fatal](ParseError::fatal) error, so deeply nested brackets can't overflow the
+    /// stack. The default of 128 fits in the 2 MiB stack of a spawned thread, a host parsing
+    /// on a bigger stack can raise it.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let mut parser = math::parser();
+    /// parser.set_depth_limit(50);
+    /// assert!(parser.parse_tree(&math::lexer(), &mut LineReader::new("((1))")).is_ok());
+    /// let nested = format!("{}1{}", "(".repeat(20), ")".repeat(20));
+    /// let err = parser.parse_tree(&math::lexer(), &mut LineReader::new(&nested)).expect_err("too deep");
+    /// assert!(err.message.contains("more than 50 rules"));
+    /// ```
+    pub fn set_depth_limit(&mut self, limit: usize) {
+        self.depth_limit = limit;
+    }
+
+    /// Get how many rules can be [expanded inside each other](Parser::set_depth_limit).
+    pub(crate) fn depth_limit(&self) -> usize {
+        self.depth_limit
+    }
+
     /// Get a defined [`Expression`] from the parser.
-    pub fn get_expr(&self, expr: &str) -> Result<&ParserDef, String> {
+    pub fn get_expr(&self, expr: &str) -> Result<&ParserDef<'_>, String> {
         self.definitions.get(expr).ok_or_else(|| format!("Parser has no definition for `{expr}`"))
     }
 
+    /// Get the sorted names of every env lambda the definitions call.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::parser::Parser;
+    /// use interpreter::parser::syntax::Expression::*;
+    /// use interpreter::exec::syntax::Lambda::*;
+    /// let mut parser = Parser::new();
+    /// parser.define("EXPR", Token("num", ""), LambdaOr(&[EvalAs("INTEGER"), Lambda("ADD", &[1, 3])]));
+    /// parser.define("NUM", Token("num", ""), EvalAs("INTEGER"));
+    /// assert_eq!(parser.lambda_names(), ["ADD", "INTEGER"]);
+    /// ```
+    pub fn lambda_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.lambda_uses().into_iter().map(|(_, name)| name).collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Get every env lambda the definitions call along with the rule calling it, sorted by
+    /// the rule and then the lambda.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::parser::Parser;
+    /// use interpreter::parser::syntax::Expression::*;
+    /// use interpreter::exec::syntax::Lambda::*;
+    /// let mut parser = Parser::new();
+    /// parser.define("EXPR", Token("num", ""), LambdaOr(&[EvalAs("INTEGER"), Lambda("ADD", &[1, 3])]));
+    /// parser.define("NUM", Token("num", ""), EvalAs("INTEGER"));
+    /// assert_eq!(parser.lambda_uses(), [("EXPR", "ADD"), ("EXPR", "INTEGER"), ("NUM", "INTEGER")]);
+    /// ```
+    pub fn lambda_uses(&self) -> Vec<(&str, &str)> {
+        let mut uses = vec![];
+        self.definitions.iter().for_each(|(rule, def)| {
+            let mut names = vec![];
+            def.lambda.collect_names(&mut names);
+            if let Some(binop) = &def.binop {
+                names.extend(binop.ops.iter().map(|(_, lambda)| *lambda));
+            }
+            if let Some(postfix) = &def.postfix {
+                postfix.lambda.collect_names(&mut names);
+            }
+            uses.extend(names.into_iter().map(|name| (rule.as_str(), name)));
+        });
+        uses.sort_unstable();
+        uses.dedup();
+        uses
+    }
+
+    /// Find the rules with alternatives that more than one alternative can match, as a
+    /// best-effort check of the grammar - the parser always takes the first alternative
+    /// that matches, so the others can never be reached for that input.
+    /// 
+    /// ---
+    /// 
+    /// Each alternative of every rule defined with an [`ExprOr`](Expression::ExprOr) is
+    /// tried on its own against the whole of each sample, an alternative matches when it
+    /// reads all of it and passes the rule's [check](Parser::define_with_check). The
+    /// alternatives are numbered from 1 in the report.
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::Lexer;
+    /// use interpreter::parser::Parser;
+    /// use interpreter::parser::syntax::Expression::*;
+    /// use interpreter::exec::syntax::Lambda::*;
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("num", "[0-9]+")?;
+    /// lexer.define("ident", "[a-z]+")?;
+    /// let mut parser = Parser::new();
+    /// parser.define("EXPR", ExprOr(&[Token("num", ""), Token("ident", ""), Token("num", "0")]), Eval);
+    /// assert!(parser.check_ambiguity(&lexer, &["12", "x"]).is_empty());
+    /// assert_eq!(parser.check_ambiguity(&lexer, &["0"]), ["`0` matches alternatives 1, 3 of rule `EXPR`"]);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn check_ambiguity(&self, lexer: &Lexer, samples: &[&str]) -> Vec<String> {
+        let mut rules: Vec<_> = self.definitions.iter()
+            .filter_map(|(name, def)| match (&def.binop, &def.expr) {
+                (None, Expression::ExprOr(alternatives)) => Some((name, *alternatives, def.check)),
+                _ => None,
+            })
+            .collect();
+        rules.sort_unstable_by_key(|(name, _, _)| *name);
+        let mut reports = vec![];
+        for sample in samples {
+            for (name, alternatives, check) in &rules {
+                let matched: Vec<_> = alternatives.iter().enumerate()
+                    .filter(|(_, alternative)| {
+                        let mut reader = LineReader::new(sample);
+                        match alternative.get(lexer, self, &mut reader, &Lambda::Eval) {
+                            Ok(node) => lexer.at_end(&reader) && check.is_none_or(|check| check(&node).is_ok()),
+                            Err(_) => false,
+                        }
+                    })
+                    .map(|(i, _)| (i + 1).to_string())
+                    .collect();
+                if matched.len() > 1 {
+                    reports.push(format!("`{sample}` matches alternatives {} of rule `{name}`", matched.join(", ")));
+                }
+            }
+        }
+        reports
+    }
+
+    /// Get every rule of the parser as grammar text, one line per rule sorted by name so
+    /// the text is the same however the rules were defined.
+    /// 
+    /// ---
+    /// 
+    /// A rule is written as `NAME := expr => lambda` with the expression
+    /// [displayed](Expression#impl-Display-for-Expression%3C'_%3E), and a
+    /// [binop](Parser::define_binop) rule as `NAME := binop left OPERAND [ "+" ADD, ... ]`
+    /// and a [postfix](Parser::define_postfix) rule as `NAME := postfix OPERAND [ suffix ] => lambda`.
+    /// The lambdas are written as `EVAL`, `EVAL_TOKEN`, `NAME` for an [`EvalAs`](Lambda::EvalAs),
+    /// `NAME($1, $3)`, `&2 lambda`, `&[1, 3] lambda` and `a || b`. The separator and sync
+    /// tokens come first as `%separator` and `%sync` lines. The text can be read back with
+    /// [`from_grammar`](Parser::from_grammar), except for a [check](Parser::define_with_check)
+    /// which is rust code so it isn't written.
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::parser::{Assoc, Parser};
+    /// use interpreter::parser::syntax::Expression::*;
+    /// use interpreter::exec::syntax::Lambda::*;
+    /// let mut parser = Parser::new();
+    /// parser.define("NUM", Token("num", ""), EvalAs("INTEGER"));
+    /// parser.define_binop("EXPR", "NUM", &[("+", "ADD")], Assoc::Left);
+    /// assert_eq!(parser.to_grammar_text(), [
+    ///     "EXPR := binop left NUM [ \"+\" ADD ]",
+    ///     "NUM := <num> => INTEGER",
+    ///     "",
+    /// ].join("\n"));
+    /// ```
+    pub fn to_grammar_text(&self) -> String {
+        let mut lines = vec![];
+        if let Some(separator) = &self.separator {
+            lines.push(format!("%separator {separator}"));
+        }
+        if !self.sync_tokens.is_empty() {
+            let values: Vec<_> = self.sync_tokens.iter().map(|value| format!("{value:?}")).collect();
+            lines.push(format!("%sync {}", values.join(" ")));
+        }
+        let mut rules: Vec<_> = self.definitions.iter().collect();
+        rules.sort_unstable_by_key(|(name, _)| *name);
+        for (name, def) in rules {
+            let line = match &def.binop {
+                Some(binop) => {
+                    let ops: Vec<_> = binop.ops.iter().map(|(op, lambda)| format!("{op:?} {lambda}")).collect();
+                    let assoc = match binop.assoc {
+                        Assoc::Left => "left",
+                        Assoc::Right => "right",
+                    };
+                    format!("{name} := binop {assoc} {} [ {} ]", binop.operand, ops.join(", "))
+                }
+                None => match &def.postfix {
+                    Some(postfix) => format!("{name} := postfix {} [ {} ] => {}", postfix.operand, postfix.suffix, lambda_text(&postfix.lambda, false)),
+                    None => format!("{name} := {} => {}", def.expr, lambda_text(&def.lambda, false)),
+                },
+            };
+            lines.push(line);
+        }
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+
+    /// Make a parser from the grammar text written by [`to_grammar_text`](Parser::to_grammar_text),
+    /// one rule per line. Blank lines and lines starting with `#` are skipped.
+    /// 
+    /// ---
+    /// 
+    /// The rules are kept for the rest of the program like the rules of the built-in
+    /// languages, loading the same rule lines again reuses the rules that were kept.
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::Executor;
+    /// use interpreter::lang::math;
+    /// use interpreter::parser::Parser;
+    /// let parser = Parser::from_grammar(&[
+    ///     "# numbers added together",
+    ///     "EXPR := binop left NUM [ \"+\" ADD ]",
+    ///     "NUM := <int> => INTEGER",
+    /// ].join("\n"))?;
+    /// let mut executor = Executor::new(math::lexer(), parser, math::env());
+    /// assert_eq!(executor.exec_str("1 + 2 + 3"), Ok("6".into()));
+    /// 
+    /// let err = Parser::from_grammar("NUM = <int>").err();
+    /// assert_eq!(err.as_deref(), Some("Grammar error on line 1: Expected `:=` at `= <int>`"));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn from_grammar(text: &str) -> Result<Parser<'static>, String> {
+        let mut parser = Parser::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = grammar::parse_line(line).map_err(|err| format!("Grammar error on line {}: {err}", i + 1))?;
+            match line {
+                None => (),
+                Some(Line::Separator(separator)) => parser.set_separator(separator),
+                Some(Line::Sync(values)) => parser.set_sync_tokens(&values),
+                Some(Line::Rule(name, Rule::Define(expr, lambda))) => parser.define(&name, expr, lambda),
+                Some(Line::Rule(name, Rule::BinOp(operand, ops, assoc))) => parser.define_binop(&name, &operand, ops, assoc),
+                Some(Line::Rule(name, Rule::Postfix(operand, suffix, lambda))) => parser.define_postfix(&name, &operand, suffix, lambda),
+            }
+        }
+        Ok(parser)
+    }
+
     /// Define an [`Expression`] that can be matched in [`parse_tree`](Parser::parse_tree).
     pub fn define(&mut self, expr_type: &str, expr: Expression<'a>, lambda: Lambda<'a>) {
         // transform to a sub object with both an expr and a lambda
         self.definitions.insert(expr_type.to_owned(), ParserDef::from(expr, lambda));
     }
+
+    /// Define an [`Expression`] like [`define`](Parser::define) along with a `check` that is
+    /// run on every node the rule builds. An error from the check is [fatal](ParseError::fatal),
+    /// the rule matched so no other alternative is tried and the error is kept as it is.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// use interpreter::parser::{ParseError, Parser};
+    /// use interpreter::parser::syntax::Expression::*;
+    /// use interpreter::exec::syntax::Lambda::*;
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("num", "[0-9]+").unwrap();
+    /// let mut parser = Parser::new();
+    /// parser.define_with_check("EXPR", Token("num", ""), EvalAs("INTEGER"), |node| {
+    ///     match node.leaves().all(|token| token.value.len() < 4) {
+    ///         true => Ok(()),
+    ///         false => Err(ParseError::new("number is too long", node.span().unwrap())),
+    ///     }
+    /// });
+    /// assert!(parser.parse_tree(&lexer, &mut LineReader::new("123")).is_ok());
+    /// let err = parser.parse_tree(&lexer, &mut LineReader::new("12345")).unwrap_err();
+    /// assert_eq!(err.to_string(), "number is too long at line 1 col 1");
+    /// ```
+    pub fn define_with_check(&mut self, expr_type: &str, expr: Expression<'a>, lambda: Lambda<'a>, check: Check) {
+        let mut def = ParserDef::from(expr, lambda);
+        def.check = Some(check);
+        self.definitions.insert(expr_type.to_owned(), def);
+    }
+
+    /// Define a rule for operators of the same precedence between `operand` rules, each
+    /// operator is a token value and the name of the lambda called with the operands as
+    /// its 1st and 3rd args. The operators are grouped by the [associativity](Assoc), a rule
+    /// with only one operand gives the operand.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// use interpreter::parser::Assoc;
+    /// let mut parser = math::parser();
+    /// parser.define_binop("MATH:EXPR", "MATH:EXPR:1", &[("+", "ADD"), ("-", "SUB")], Assoc::Left);
+    /// // `1 - 2 - 3` is `(1 - 2) - 3`
+    /// let ast = parser.parse_tree(&math::lexer(), &mut LineReader::new("1 - 2 - 3"))?;
+    /// assert_eq!(ast.to_string(), "( ( int:1 op:- int:2 ) op:- int:3 )");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_binop(&mut self, expr_type: &str, operand: &str, ops: &'a [(&'a str, &'a str)], assoc: Assoc) {
+        let binop = BinOp { rule: expr_type.to_owned(), operand: operand.to_owned(), ops, assoc };
+        let def = ParserDef { expr: Expression::End, lambda: Lambda::Eval, binop: Some(binop), postfix: None, check: None };
+        self.definitions.insert(expr_type.to_owned(), def);
+    }
+
+    /// Define a rule for an `operand` rule followed by any number of `suffix` expressions,
+    /// like indexing `a[0][1]`. Each suffix makes a node of the value so far and the
+    /// suffix's branches, so the value so far is the 1st arg of the `lambda`. A rule with
+    /// no suffix gives the operand.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// use interpreter::parser::syntax::Expression::*;
+    /// use interpreter::exec::syntax::Lambda::*;
+    /// let mut parser = math::parser();
+    /// parser.define_postfix("VALUE", "NUM", SubExpr(&[Token("op", "["), Expr("NUM"), Token("op", "]")]), Lambda("INDEX", &[1, 3]));
+    /// // `1[2][3]` is `(1[2])[3]`
+    /// let ast = parser.parse_tree(&math::lexer(), &mut LineReader::new("1[2][3]"))?;
+    /// assert_eq!(ast.to_string(), "( ( int:1 op:[ int:2 op:] ) op:[ int:3 op:] )");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn define_postfix(&mut self, expr_type: &str, operand: &str, suffix: Expression<'a>, lambda: Lambda<'a>) {
+        let postfix = Postfix { operand: operand.to_owned(), suffix, lambda };
+        let def = ParserDef { expr: Expression::End, lambda: Lambda::Eval, binop: None, postfix: Some(postfix), check: None };
+        self.definitions.insert(expr_type.to_owned(), def);
+    }
+}
+
+/// How a chain of operators with the same precedence is grouped, see
+/// [`define_binop`](Parser::define_binop).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`
+    Right,
+}
+
+/// Defines the rules for levels of operators from the lowest to the highest precedence,
+/// each level is a [`define_binop`](Parser::define_binop) rule between the rules of the
+/// next level. The lowest level is the named rule, the levels above it are named
+/// `{rule}:{level}`.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::exec::Executor;
+/// use interpreter::lang::math;
+/// use interpreter::parser::{Assoc, PrecedenceBuilder};
+/// let mut parser = math::parser();
+/// PrecedenceBuilder::new("MATH:EXPR", "VALUE")
+///     .level(&[("+", "ADD"), ("-", "SUB")], Assoc::Left)
+///     .level(&[("*", "MULT"), ("/", "DIV")], Assoc::Left)
+///     .build(&mut parser);
+/// let mut executor = Executor::new(math::lexer(), parser, math::env());
+/// assert_eq!(executor.exec_str("1 + 2 * 3 - 4"), Ok("3".into()));
+/// ```
+pub struct PrecedenceBuilder<'a> {
+    rule: String,
+    operand: String,
+    levels: Vec<(&'a [(&'a str, &'a str)], Assoc)>,
+}
+
+impl<'a> PrecedenceBuilder<'a> {
+    /// Start the levels for the `rule`, the `operand` rule is between the operators of
+    /// the highest level.
+    pub fn new(rule: &str, operand: &str) -> PrecedenceBuilder<'a> {
+        PrecedenceBuilder { rule: rule.to_owned(), operand: operand.to_owned(), levels: vec![] }
+    }
+
+    /// Add a level of operators with a higher precedence than the levels before it.
+    pub fn level(mut self, ops: &'a [(&'a str, &'a str)], assoc: Assoc) -> Self {
+        self.levels.push((ops, assoc));
+        self
+    }
+
+    /// Define the rule of every level in the `parser`.
+    pub fn build(self, parser: &mut Parser<'a>) {
+        let name = |level: usize| match level {
+            0 => self.rule.clone(),
+            level => format!("{}:{level}", self.rule),
+        };
+        if self.levels.is_empty() {
+            return parser.define_binop(&self.rule, &self.operand, &[], Assoc::Left);
+        }
+        for (level, (ops, assoc)) in self.levels.iter().enumerate() {
+            let operand = match level + 1 < self.levels.len() {
+                true => name(level + 1),
+                false => self.operand.clone(),
+            };
+            parser.define_binop(&name(level), &operand, ops, *assoc);
+        }
+    }
 }
 
 // -=-=- Parser Definition -=-=- //
@@ -55,23 +678,425 @@ impl<'a> Parser<'a> {
 pub struct ParserDef<'a> {
     expr: Expression<'a>,
     lambda: Lambda<'a>,
+    binop: Option<BinOp<'a>>,
+    postfix: Option<Postfix<'a>>,
+    check: Option<Check>,
+}
+
+/// Checks a node built by a rule, see [`define_with_check`](Parser::define_with_check).
+pub type Check = fn(&TreeNode) -> Result<(), ParseError>;
+
+/// Turn a panic caught while parsing into an error at the `position`.
+fn caught<R>(result: std::thread::Result<Result<R, ParseError>>, position: &ReadPointer) -> Result<R, ParseError> {
+    result.unwrap_or_else(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|msg| msg.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".into());
+        Err(ParseError::new(&format!("Parser panicked: {message}"), position.clone()))
+    })
+}
+
+/// The operators of a rule from [`define_binop`](Parser::define_binop).
+struct BinOp<'a> {
+    rule: String,
+    operand: String,
+    ops: &'a [(&'a str, &'a str)],
+    assoc: Assoc,
 }
 
 impl ParserDef<'_> {
     pub fn from<'a>(expr: Expression<'a>, lambda: Lambda<'a>) -> ParserDef<'a> {
-        ParserDef { expr, lambda }
+        ParserDef { expr, lambda, binop: None, postfix: None, check: None }
     }
 
     // -=-=- //
 
-    pub fn get<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T) -> Result<TreeNode, String>
+    pub fn get<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T) -> Result<TreeNode, ParseError>
     where T: Reader
     {
-        self.expr.get(lexer, parser, reader, &self.lambda)
+        let result = match (&self.binop, &self.postfix) {
+            (Some(binop), _) => binop.get(lexer, parser, reader),
+            (None, Some(postfix)) => postfix.get(lexer, parser, reader),
+            (None, None) => self.expr.get(lexer, parser, reader, &self.lambda),
+        };
+        match self.check {
+            Some(check) => Self::checked(check, result),
+            None => result,
+        }
+    }
+
+    /// Run the [check](Parser::define_with_check) on a node the rule built.
+    fn checked(check: Check, result: Result<TreeNode, ParseError>) -> Result<TreeNode, ParseError> {
+        let node = result?;
+        check(&node).map_err(ParseError::into_fatal)?;
+        Ok(node)
     }
 
 }
 
+impl BinOp<'_> {
+    /// Parse the operands and operators, an operator without an operand after it is left
+    /// in the reader for whatever comes next.
+    fn get<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T) -> Result<TreeNode, ParseError>
+    where T: Reader
+    {
+        let mut lhs = Expression::Expr(&self.operand).get(lexer, parser, reader, &Lambda::Eval)?;
+        loop {
+            reader.push();
+            let Some((op, lambda)) = self.ops.iter().find_map(|(value, lambda)| {
+                Expression::Literal(value).get(lexer, parser, reader, &Lambda::Eval).ok().map(|op| (op, lambda))
+            }) else {
+                reader.back();
+                return Ok(lhs);
+            };
+            let rhs = match self.assoc {
+                Assoc::Left => Expression::Expr(&self.operand),
+                Assoc::Right => Expression::Expr(&self.rule),
+            }.get(lexer, parser, reader, &Lambda::Eval);
+            let rhs = match rhs {
+                Ok(rhs) => rhs,
+                Err(err) if err.fatal => {
+                    reader.back();
+                    return Err(err);
+                }
+                Err(_) => {
+                    reader.back();
+                    return Ok(lhs);
+                }
+            };
+            reader.pop();
+            let mut node = TreeNode::from_nodes(vec![lhs, op, rhs]);
+            node.set_lambda(&Lambda::Lambda(lambda, &[1, 3]));
+            // the right operand already holds the rest of the chain
+            if self.assoc == Assoc::Right {
+                return Ok(node);
+            }
+            lhs = node;
+        }
+    }
+}
+
+/// The suffix of a rule from [`define_postfix`](Parser::define_postfix).
+struct Postfix<'a> {
+    operand: String,
+    suffix: Expression<'a>,
+    lambda: Lambda<'a>,
+}
+
+impl Postfix<'_> {
+    /// Parse the operand and every suffix after it, a suffix that only partly matches is
+    /// left in the reader for whatever comes next.
+    fn get<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T) -> Result<TreeNode, ParseError>
+    where T: Reader
+    {
+        let mut lhs = Expression::Expr(&self.operand).get(lexer, parser, reader, &Lambda::Eval)?;
+        loop {
+            reader.push();
+            let mut suffix = match self.suffix.get(lexer, parser, reader, &Lambda::Eval) {
+                Ok(suffix) => suffix,
+                Err(err) if err.fatal => {
+                    reader.back();
+                    return Err(err);
+                }
+                Err(_) => {
+                    reader.back();
+                    return Ok(lhs);
+                }
+            };
+            reader.pop();
+            let mut nodes = vec![lhs];
+            match suffix.leaf {
+                Some(_) => nodes.push(suffix),
+                None => nodes.append(&mut suffix.nodes),
+            }
+            lhs = TreeNode::from_nodes(nodes);
+            lhs.set_lambda(&self.lambda);
+        }
+    }
+}
+
 // -=-=-=-=- Unit Tests -=-=-=-=- //
 
-// TODO: Make unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::NodeValue;
+    use crate::lexer::LineReader;
+    use crate::parser::syntax::Expression::*;
+    use crate::exec::syntax::Lambda::*;
+
+    fn lexer() -> Result<Lexer, String> {
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "\\+|;")?;
+        lexer.define_skip("space", "\\s+")?;
+        Ok(lexer)
+    }
+
+    fn parser<'a>() -> Parser<'a> {
+        let mut parser = Parser::new();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[ Token("num", ""), Token("op", "+"), Token("num", "") ]),
+            Token("num", ""),
+        ]), Eval);
+        parser
+    }
+
+    /// every expression is parsed until the end of the content.
+    #[test]
+    fn parse_all() -> Result<(), String> {
+        let lexer = lexer()?;
+        let trees = parser().parse_all(&lexer, &mut LineReader::new("1+1 2+2 3+3"))?;
+        assert_eq!(trees.len(), 3);
+        assert_eq!(trees[2].root.nodes.len(), 3);
+        assert!(parser().parse_all(&lexer, &mut LineReader::new("  "))?.is_empty());
+        // the last expression is cut off, `2` is parsed on its own so the `+` is left over
+        let err = parser().parse_all(&lexer, &mut LineReader::new("1+1 2+")).expect_err("`2+` is incomplete");
+        assert_eq!(err.position.read_pos.0, 5);
+        Ok(())
+    }
+
+    /// the separator is optional between expressions.
+    #[test]
+    fn parse_all_separator() -> Result<(), String> {
+        let lexer = lexer()?;
+        let mut parser = parser();
+        parser.set_separator(Token("op", ";"));
+        let trees = parser.parse_all(&lexer, &mut LineReader::new("1+1; 2 3;"))?;
+        assert_eq!(trees.len(), 3);
+        assert!(parser.parse_all(&lexer, &mut LineReader::new("1;;")).is_err());
+        Ok(())
+    }
+
+    /// a garbage expression is recorded and skipped up to the sync token.
+    #[test]
+    fn parse_all_recovering() -> Result<(), String> {
+        let lexer = lexer()?;
+        let mut synced = parser();
+        synced.set_separator(Token("op", ";"));
+        synced.set_sync_tokens(&[";"]);
+        let (trees, errors) = synced.parse_all_recovering(&lexer, &mut LineReader::new("1+1;\n+ ? 2;\n3+3"));
+        assert_eq!(trees.len(), 2);
+        assert_eq!(trees[1].root.nodes.len(), 3);
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(errors[0].position.user_position(), (2, 1));
+        // without a sync token the rest of the content is skipped
+        let (trees, errors) = parser().parse_all_recovering(&lexer, &mut LineReader::new("1+1 + 2"));
+        assert_eq!((trees.len(), errors.len()), (1, 1));
+        Ok(())
+    }
+
+    /// operators are grouped by their associativity.
+    #[test]
+    fn define_binop() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "\\-|\\^")?;
+        lexer.define_skip("space", "\\s+")?;
+        let mut parser = Parser::new();
+        parser.define("EXPR", Expr("SUB"), Eval);
+        parser.define_binop("SUB", "POW", &[("-", "SUB")], Assoc::Left);
+        parser.define_binop("POW", "NUM", &[("^", "POW")], Assoc::Right);
+        parser.define("NUM", Token("num", ""), Eval);
+        let parse = |src: &str| parser.parse_tree(&lexer, &mut LineReader::new(src)).map(|ast| ast.to_string());
+        assert_eq!(parse("1 - 2 - 3")?, "( ( num:1 op:- num:2 ) op:- num:3 )");
+        assert_eq!(parse("2 ^ 3 ^ 4")?, "( num:2 op:^ ( num:3 op:^ num:4 ) )");
+        assert_eq!(parse("1 - 2 ^ 3 ^ 4 - 5")?, "( ( num:1 op:- ( num:2 op:^ ( num:3 op:^ num:4 ) ) ) op:- num:5 )");
+        assert_eq!(parse("7")?, "num:7");
+        // an operator without an operand is left in the reader
+        let mut reader = LineReader::new("1 - 2 -");
+        assert_eq!(parser.parse_tree(&lexer, &mut reader)?.to_string(), "( num:1 op:- num:2 )");
+        assert!(!lexer.at_end(&reader));
+        assert_eq!(parser.lambda_names(), ["POW", "SUB"]);
+        Ok(())
+    }
+
+    /// every level of the table binds tighter than the one before it.
+    #[test]
+    fn precedence_builder() -> Result<(), String> {
+        use crate::exec::{Exec, Executor, StateNode};
+        use crate::lang::math;
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "\\+|\\-|\\*|\\^")?;
+        lexer.define_skip("space", "\\s+")?;
+        let mut parser = Parser::new();
+        parser.define("EXPR", Expr("SUM"), Eval);
+        PrecedenceBuilder::new("SUM", "NUM")
+            .level(&[("+", "ADD"), ("-", "SUB")], Assoc::Left)
+            .level(&[("*", "MULT")], Assoc::Left)
+            .level(&[("^", "POW")], Assoc::Right)
+            .build(&mut parser);
+        parser.define("NUM", Token("num", ""), EvalAs("INTEGER"));
+        let mut env = math::env();
+        env.define("POW", |mut frame, | {
+            match frame.eval() {
+                Exec::BinExpr(lhs, rhs) => match (lhs.as_node_value(), rhs.as_node_value()) {
+                    (NodeValue::Integer(lhs), NodeValue::Integer(rhs)) => StateNode::Value(NodeValue::Integer(lhs.pow(rhs as u32))),
+                    _ => StateNode::RuntimeErr("Something pow".into()),
+                },
+                _ => StateNode::RuntimeErr("Something pow".into()),
+            }
+        });
+        let mut executor = Executor::new(lexer, parser, env);
+        executor.validate().map_err(|missing| missing.join("\n"))?;
+        assert_eq!(executor.exec_str("2+3*4")?, "14");
+        assert_eq!(executor.exec_str("2*3^2")?, "18");
+        assert_eq!(executor.exec_str("2^3^2")?, "512");
+        assert_eq!(executor.exec_str("10-2-3*2")?, "2");
+        Ok(())
+    }
+
+    /// alternatives that match the same input are reported for that input.
+    #[test]
+    fn check_ambiguity() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "[-+]")?;
+        lexer.define_skip("space", "\\s+")?;
+        let mut parser = Parser::new();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[Expr("NUM"), Token("op", "+"), Expr("NUM")]),
+            SubExpr(&[Expr("NUM"), Token("op", ""), Expr("NUM")]),
+            Expr("NUM"),
+        ]), LambdaOr(&[Lambda("ADD", &[1, 3]), Lambda("OP", &[1, 2, 3]), Eval]));
+        parser.define("NUM", Token("num", ""), EvalAs("INTEGER"));
+        assert_eq!(parser.check_ambiguity(&lexer, &["1 + 2", "1 - 2", "3", "1 +"]), ["`1 + 2` matches alternatives 1, 2 of rule `EXPR`"]);
+        // each assignment matches one alternative of the math grammar
+        let reports = crate::lang::math::parser().check_ambiguity(&crate::lang::math::lexer(), &["x = 1", "x := 1", "x += 1", "x.y = 1", "1 + 2"]);
+        assert!(reports.is_empty(), "{reports:?}");
+        Ok(())
+    }
+
+    /// the grammar text is sorted and the same for every build of the parser.
+    #[test]
+    fn to_grammar_text() {
+        let text = crate::lang::math::parser().to_grammar_text();
+        assert_eq!(text, crate::lang::math::parser().to_grammar_text());
+        let names: Vec<_> = text.lines().filter_map(|line| line.split_once(" := ")).map(|(name, _)| name).collect();
+        let mut sorted = names.clone();
+        sorted.sort_unstable();
+        assert_eq!(names, sorted);
+        assert!(text.lines().any(|line| line == r#"MATH:EXPR:1 := binop left VALUE [ "*" MULT, "/" DIV, "//" FLOOR_DIV, "%" MOD ]"#), "{text}");
+        assert!(text.lines().any(|line| line == "VAR := IDENT => GET_IDENT($1)"), "{text}");
+        // the separator and sync tokens come first
+        let mut parser = parser();
+        parser.set_separator(Token("sep", ";"));
+        parser.set_sync_tokens(&[";"]);
+        let text = parser.to_grammar_text();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some(r#"%separator <sep ";">"#));
+        assert_eq!(lines.next(), Some(r#"%sync ";""#));
+    }
+
+    /// the math grammar read back from its text parses and evaluates like the math parser.
+    #[test]
+    fn from_grammar_round_trip() -> Result<(), String> {
+        use crate::exec::Executor;
+        use crate::lang::math;
+        let text = math::parser().to_grammar_text();
+        let loaded = Parser::from_grammar(&text)?;
+        assert_eq!(loaded.to_grammar_text(), text);
+        let lexer = math::lexer();
+        let mut expected = Executor::math();
+        let mut executor = Executor::new(math::lexer(), Parser::from_grammar(&text)?, math::env());
+        for src in [
+            "1 + 2 * 3", "(1 + 2) * 3", "-4 // 3 % 2", "x := 5", "x += 2", "x * 2.5",
+            "[1, 2, 3][1]", "{ a: 1, b: [2] }.b", "{ y := 2; x * y }", "print(1, 2)",
+            "clamp(7, 0, 5)", "str(1) + \"a\"", "'c'", "null", "abs(-3)",
+        ] {
+            let parsed = loaded.parse_tree(&lexer, &mut LineReader::new(src))?;
+            assert_eq!(parsed.to_string(), math::parser().parse_tree(&lexer, &mut LineReader::new(src))?.to_string(), "{src}");
+            assert_eq!(executor.exec_str(src), expected.exec_str(src), "{src}");
+        }
+        Ok(())
+    }
+
+    /// every kind of expression and lambda is read back from its text.
+    #[test]
+    fn from_grammar() -> Result<(), String> {
+        let mut parser = parser();
+        parser.set_separator(Literal(";"));
+        parser.set_sync_tokens(&[";", "\n"]);
+        parser.define("LIST", SepBy(&Token("num", ""), &Literal(",")), GetExprs(&[1, 3], &LambdaOr(&[Lambda("ADD", &[]), EvalAs("SUM")])));
+        parser.define("LAST", SubExpr(&[Not(&Expr("EXPR")), End, ExprOr(&[SubExpr(&[]), Token("op", "\"")])]), GetExpr(2, &EvalToken));
+        let text = parser.to_grammar_text();
+        assert_eq!(Parser::from_grammar(&text)?.to_grammar_text(), text);
+        assert!(text.contains(r#"LAST := ( !EXPR $end ( (  ) | <op "\""> ) ) => &2 EVAL_TOKEN"#), "{text}");
+        assert!(text.contains("LIST := sep_by(<num>, \",\") => &[1, 3] ( ADD() || SUM )"), "{text}");
+
+        let err = |text: &str| Parser::from_grammar(text).err().unwrap_or_default();
+        assert_eq!(err("A := B\n"), "Grammar error on line 1: Expected `=>` at ``");
+        assert_eq!(err("\n# comment\nA := binop up B [ ]"), "Grammar error on line 3: Unknown associativity `up`");
+        assert_eq!(err("A := \"a => EVAL"), "Grammar error on line 1: Unclosed string at `a => EVAL`");
+        assert_eq!(err("A := B => EVAL EVAL"), "Grammar error on line 1: Unexpected `EVAL`");
+        Ok(())
+    }
+
+    /// loading a grammar again reuses the rules kept by the first load.
+    #[test]
+    fn from_grammar_reuses_rules() -> Result<(), String> {
+        let text = "%sync \"reused\"\nNUM := <int> => INTEGER\n";
+        let (first, second) = (Parser::from_grammar(text)?, Parser::from_grammar(text)?);
+        assert!(std::ptr::eq(first.sync_tokens[0], second.sync_tokens[0]));
+        match (&first.definitions["NUM"].expr, &second.definitions["NUM"].expr) {
+            (Token(first, _), Token(second, _)) => assert!(std::ptr::eq(*first, *second)),
+            exprs => return Err(format!("expected tokens, got {exprs:?}")),
+        }
+        Ok(())
+    }
+
+    /// a failed check stops the other alternatives from being tried.
+    #[test]
+    fn define_with_check() -> Result<(), String> {
+        let lexer = lexer()?;
+        let mut parser = Parser::new();
+        parser.define("EXPR", ExprOr(&[Expr("SUM"), Token("num", "")]), Eval);
+        parser.define_with_check("SUM", SubExpr(&[Token("num", ""), Token("op", "+"), Token("num", "")]), Eval, |node| {
+            match node.leaves().any(|token| token.value == "0") {
+                true => Err(ParseError::new("cannot add zero", node.span().expect("the sum has leaves"))),
+                false => Ok(()),
+            }
+        });
+        assert!(parser.parse_tree(&lexer, &mut LineReader::new("1+2")).is_ok());
+        let err = parser.parse_tree(&lexer, &mut LineReader::new("1+0")).expect_err("the check fails");
+        assert!(err.fatal);
+        assert_eq!(err.message, "cannot add zero");
+        assert_eq!(err.position.read_pos, (0, 3));
+        Ok(())
+    }
+
+    /// a panic while parsing is returned as an error.
+    #[test]
+    fn parse_tree_catching() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define_token(crate::lexer::TokenDef::with_transform("num", "[0-9]+", |raw| match raw {
+            "13" => panic!("unlucky number"),
+            _ => raw.to_owned(),
+        })?);
+        lexer.define("op", "\\+")?;
+        lexer.define_skip("space", "\\s+")?;
+        let parser = parser();
+        assert!(parser.parse_tree_catching(&lexer, &mut LineReader::new("1+2")).is_ok());
+        let err = parser.parse_tree_catching(&lexer, &mut LineReader::new("1+13")).expect_err("the transform panics");
+        assert_eq!(err.message, "Parser panicked: unlucky number");
+        let err = parser.parse_all_catching(&lexer, &mut LineReader::new("1+2 13")).expect_err("the transform panics");
+        assert_eq!(err.message, "Parser panicked: unlucky number");
+        Ok(())
+    }
+
+    /// deeply nested brackets are an error instead of overflowing the stack.
+    #[test]
+    fn deep_nesting() -> Result<(), String> {
+        use crate::exec::Executor;
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        let executor = Executor::math();
+        assert!(executor.check(&mut LineReader::new(&nested(10))).is_ok());
+        for depth in [150, 200, 10_000] {
+            let err = executor.check(&mut LineReader::new(&nested(depth))).expect_err("the nesting is too deep");
+            assert!(err.fatal);
+            assert_eq!(err.message, "Too deeply nested: more than 128 rules inside each other");
+        }
+        Ok(())
+    }
+}
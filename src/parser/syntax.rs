@@ -3,9 +3,13 @@
 //! Using a tree of [expressions](Expression) you can build a defition to add to a [`Parser`].
 //! 
 
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+
 use crate::lexer::{Lexer, ReadPointer, Reader, Token};
+use crate::exec::NodeValue;
 use crate::exec::syntax::{Lambda, OwnedLambda};
-use super::Parser;
+use super::{ParseError, Parser};
 
 /// Used to define an expression for the [`Parser`] to parse.
 #[derive(Clone, Debug)]
@@ -14,47 +18,98 @@ pub enum Expression<'a> {
     SubExpr(&'a[Self]),
     Expr(&'a str),
     Token(&'a str, &'a str),
+    /// Matches the next token when its value is exactly the literal, whatever its type -
+    /// useful for punctuation that shares a token type with other values.
+    Literal(&'a str),
+    /// Zero or more of the first expression separated by the second, only the items
+    /// are kept as branches. The list ends where an item can't start, an item that fails
+    /// after reading something (or after a separator) is an error.
+    SepBy(&'a Self, &'a Self),
+    /// Matches only at the end of the input without reading anything, giving an empty
+    /// branch. Put it last in a [`SubExpr`](Expression::SubExpr) to reject trailing tokens.
+    End,
+    /// Matches without reading anything only when the expression can't be matched here,
+    /// giving an empty branch. Put it before another expression to exclude what it matches.
+    Not(&'a Self),
+}
+
+/// Display the expression in the text form used by
+/// [`to_grammar_text`](Parser::to_grammar_text).
+/// 
+/// ---
+/// 
+/// Rules are written by name, tokens as `<type>` or `<type "value">`, literals as
+/// `"value"`, sub expressions as `( a b )`, alternatives as `( a | b )`, separated lists as
+/// `sep_by(item, separator)`, [`End`](Expression::End) as `$end` and [`Not`](Expression::Not)
+/// as `!expr`.
+impl std::fmt::Display for Expression<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |exprs: &[Self], sep: &str| exprs.iter().map(|expr| expr.to_string()).collect::<Vec<_>>().join(sep);
+        match self {
+            Expression::ExprOr(exprs) => write!(f, "( {} )", join(exprs, " | ")),
+            Expression::SubExpr(exprs) => write!(f, "( {} )", join(exprs, " ")),
+            Expression::Expr(rule) => write!(f, "{rule}"),
+            Expression::Token(token, "") => write!(f, "<{token}>"),
+            Expression::Token(token, value) => write!(f, "<{token} {value:?}>"),
+            Expression::Literal(value) => write!(f, "{value:?}"),
+            Expression::SepBy(item, sep) => write!(f, "sep_by({item}, {sep})"),
+            Expression::End => write!(f, "$end"),
+            Expression::Not(expr) => write!(f, "!{expr}"),
+        }
+    }
 }
 
 impl Expression<'_> {
     /// Get the resulting [`TreeNode`] from this expression.
-    pub fn get<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, lambda: &Lambda) -> Result<TreeNode, String>
+    pub fn get<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, lambda: &Lambda) -> Result<TreeNode, ParseError>
     where
         T: Reader,
     {
-        let result = match self {
+        match self {
             Expression::ExprOr(expr) => self.get_expr_or(lexer, parser, reader, expr, lambda),
             Expression::SubExpr(expr) => self.get_sub_expr(lexer, parser, reader, expr, lambda),
             Expression::Expr(expr) => self.get_expr(lexer, parser, reader, expr, lambda),
             Expression::Token(token, value) => self.get_token(lexer, reader, token, value, lambda),
-        };
-        result
+            Expression::Literal(value) => self.get_literal(lexer, reader, value, lambda),
+            Expression::SepBy(item, sep) => self.get_sep_by(lexer, parser, reader, item, sep, lambda),
+            Expression::End => self.get_end(lexer, reader, lambda),
+            Expression::Not(expr) => self.get_not(lexer, parser, reader, expr, lambda),
+        }
     }
 
     /// Get the expression as a token - if the expression is not a [`Token`], it
     /// will [panic] with an error message.
+    /// 
+    /// The token is placed at the start of the content, use [`token_at`](Expression::token_at)
+    /// to set the position.
     pub fn token(&self) -> Token {
+        self.token_at(ReadPointer::from_pos((0,0,0,0), (0,0)))
+    }
+
+    /// Get the expression as a token at the `position` - if the expression is not a
+    /// [`Token`], it will [panic] with an error message.
+    pub fn token_at(&self, position: ReadPointer) -> Token {
         match self {
-            Expression::Token(token_type, value) => Token::new(
-                token_type,
-                value,
-                ReadPointer::from_pos((0,0,0,0), (0,0))
-            ),
-            _ => panic!("`token` can only be used on an `Expression::Token`!")
+            Expression::Token(token_type, value) => Token::new(token_type, value, position),
+            _ => panic!("`token_at` can only be used on an `Expression::Token`!")
         }
     }
 
     /// Get the resulting [TreeNode] for an [`ExprOr`](Expression::ExprOr) 
     /// using the passed [`Lexer`], [`Parser`], and [`Reader`].
-    fn get_expr_or<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, expr: &&[Expression], lambda: &Lambda) -> Result<TreeNode, String>
+    fn get_expr_or<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, expr: &&[Expression], lambda: &Lambda) -> Result<TreeNode, ParseError>
     where
         T: Reader,
     {
+        let mut error: Option<ParseError> = None;
         for (i, subexpr) in expr.iter().enumerate() {
             let sub_lambda = match lambda {
                 Lambda::LambdaOr(lambdas) => match lambdas.get(i) {
                     Some(lambda) => lambda,
-                    None => return Err(format!("Could not get Lambda for Expression {i} [{}>{}]", expr.len(), lambdas.len()))
+                    None => return Err(ParseError::new(
+                        &format!("Could not get Lambda for Expression {i} [{}>{}]", expr.len(), lambdas.len()),
+                        lexer.next_position(reader),
+                    )),
                 },
                 _ => lambda,
             };
@@ -62,67 +117,197 @@ impl Expression<'_> {
             // println!("{sub_lambda}");
             reader.push();
             match subexpr.get(lexer, parser, reader, sub_lambda) {
-                Ok(mut node) => {
+                Ok(node) => {
                     reader.pop();
                     // node.set_lambda(sub_lambda);
                     return Ok(node);
                 }
-                Err(_) => {
+                // the expression matched but was rejected, nothing else should be tried
+                Err(err) if err.fatal => {
                     reader.back();
+                    return Err(err);
+                }
+                Err(err) => {
+                    reader.back();
+                    // keep the error that made it the furthest into the content
+                    if error.as_ref().is_none_or(|e| err.position.read_pos.1 > e.position.read_pos.1) {
+                        error = Some(err);
+                    }
                     continue
                 },
             };
         }
-        Err(format!("Could find matching expression for: {self:?}"))
+        Err(error.unwrap_or_else(|| ParseError::new(
+            "Could not find a matching expression",
+            lexer.next_position(reader),
+        )))
     }
 
     /// Get the resulting [TreeNode] for a [`SubExpr`](Expression::SubExpr) 
     /// using the passed [`Lexer`], [`Parser`], and [`Reader`].
-    fn get_sub_expr<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, expr: &&[Expression], lambda: &Lambda) -> Result<TreeNode, String>
+    fn get_sub_expr<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, expr: &&[Expression], lambda: &Lambda) -> Result<TreeNode, ParseError>
     where
         T: Reader,
     {
-        let mut node = TreeNode::from_nodes(
-            expr.iter()
-            .map(|subexpr| subexpr.get(lexer, parser, reader, &Lambda::Eval))
-            .collect::<Result<Vec<TreeNode>, String>>()?
-        );
-        node.set_lambda(lambda);
-        Ok(node)
+        let mut nodes = Vec::with_capacity(expr.len());
+        for subexpr in expr.iter() {
+            match subexpr.get(lexer, parser, reader, &Lambda::Eval) {
+                Ok(node) => nodes.push(node),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Self::branch(nodes, lambda))
+    }
+
+    /// Get the resulting [TreeNode] for a [`SepBy`](Expression::SepBy) 
+    /// using the passed [`Lexer`], [`Parser`], and [`Reader`].
+    fn get_sep_by<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, item: &Expression, sep: &Expression, lambda: &Lambda) -> Result<TreeNode, ParseError>
+    where
+        T: Reader,
+    {
+        let mut nodes = vec![];
+        loop {
+            let start = lexer.next_position(reader).read_pos.1;
+            reader.push();
+            let next = match nodes.is_empty() {
+                true => item.get(lexer, parser, reader, &Lambda::Eval),
+                false => sep.get(lexer, parser, reader, &Lambda::Eval)
+                    .and_then(|_| item.get(lexer, parser, reader, &Lambda::Eval)),
+            };
+            match next {
+                Ok(node) => {
+                    reader.pop();
+                    nodes.push(node);
+                }
+                // the item started but couldn't finish
+                Err(err) if err.fatal || err.position.read_pos.0 > start => {
+                    reader.back();
+                    return Err(err);
+                }
+                Err(_) => {
+                    reader.back();
+                    break;
+                }
+            }
+        }
+        Ok(Self::branch(nodes, lambda))
+    }
+
+    /// Get the resulting [TreeNode] for an [`End`](Expression::End) 
+    /// using the passed [`Lexer`] and [`Reader`].
+    fn get_end<T>(&self, lexer: &Lexer, reader: &mut T, lambda: &Lambda) -> Result<TreeNode, ParseError>
+    where
+        T: Reader,
+    {
+        if !lexer.at_end(reader) {
+            return Err(ParseError::new("Expected the end of the input", lexer.next_position(reader)));
+        }
+        Ok(Self::branch(vec![], lambda))
+    }
+
+    /// Get the resulting [TreeNode] for a [`Not`](Expression::Not) 
+    /// using the passed [`Lexer`], [`Parser`], and [`Reader`].
+    fn get_not<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, expr: &Expression, lambda: &Lambda) -> Result<TreeNode, ParseError>
+    where
+        T: Reader,
+    {
+        let position = lexer.next_position(reader);
+        // the reader always goes back, the expression is only checked
+        reader.push();
+        let result = expr.get(lexer, parser, reader, &Lambda::Eval);
+        reader.back();
+        if result.is_ok() {
+            return Err(ParseError::new(&format!("Unexpected match for {expr:?}"), position));
+        }
+        Ok(Self::branch(vec![], lambda))
     }
 
     /// Get the resulting [TreeNode] for an [`Expr`](Expression::Expr) 
     /// using the passed [`Lexer`], [`Parser`], and [`Reader`].
-    fn get_expr<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, expr: &str, lambda: &Lambda) -> Result<TreeNode, String>
+    fn get_expr<T>(&self, lexer: &Lexer, parser: &Parser, reader: &mut T, expr: &str, lambda: &Lambda) -> Result<TreeNode, ParseError>
     where
         T: Reader,
     {
-        let node = parser
-            .get_expr(expr)?
-            .get(lexer, parser, reader)?;
+        let result = Self::get_rule(lexer, parser, reader, expr);
         match lambda {
-            Lambda::Eval => Ok(node),
-            _ => {
-                let mut node = TreeNode::from_nodes(vec![node]);
-                node.set_lambda(lambda);
-                Ok(node)
+            Lambda::Eval => result,
+            _ => result.map(|node| Self::branch(vec![node], lambda)),
+        }
+    }
+
+    /// Parse the rule named `expr`, or skip to where it ended if it was already parsed here.
+    /// The nested rules are parsed on the stack, so only what is needed across the nested
+    /// parse is kept in the frames along the way.
+    fn get_rule<T>(lexer: &Lexer, parser: &Parser, reader: &mut T, expr: &str) -> Result<TreeNode, ParseError>
+    where
+        T: Reader,
+    {
+        let def = parser
+            .get_expr(expr)
+            .map_err(|err| ParseError::new(&err, lexer.next_position(reader)))?;
+        let start = reader.get_pointer().read_pos.1;
+        if parser.memoizes() {
+            // the rule was already parsed here, skip to where it ended
+            if let Some(result) = Memo::get(parser, expr, start) {
+                return Memo::skip(lexer, reader, start, result);
             }
         }
-        
+        let _guard = Expanding::enter(parser, expr, start)
+            .map_err(|err| ParseError::new(&err, lexer.next_position(reader)).into_fatal())?
+            .ok_or_else(|| Self::left_recursion(lexer, reader, expr))?;
+        let refused = Memo::refused();
+        let result = def.get(lexer, parser, reader);
+        // a result that hit the left recursion guard depends on what else is being expanded
+        if parser.memoizes() && Memo::refused() == refused {
+            Memo::insert(parser, expr, start, reader.get_pointer().read_pos.1, &result);
+        }
+        result
+    }
+
+    /// The error for a rule reaching itself again without reading anything.
+    fn left_recursion<T>(lexer: &Lexer, reader: &mut T, expr: &str) -> ParseError
+    where
+        T: Reader,
+    {
+        ParseError::new(&format!("Left recursion: `{expr}` is already being expanded here"), lexer.next_position(reader))
+    }
+
+    /// Build a branch node with the lambda.
+    fn branch(nodes: Vec<TreeNode>, lambda: &Lambda) -> TreeNode {
+        let mut node = TreeNode::from_nodes(nodes);
+        node.set_lambda(lambda);
+        node
     }
 
     /// Get the resulting [TreeNode] for a [`Token`](Expression::Token) 
     /// using the passed [`Lexer`], [`Parser`], and [`Reader`].
-    fn get_token<T>(&self, lexer: &Lexer, reader: &mut T, token: &str, value: &str, lambda: &Lambda) -> Result<TreeNode, String>
+    fn get_token<T>(&self, lexer: &Lexer, reader: &mut T, token: &str, value: &str, lambda: &Lambda) -> Result<TreeNode, ParseError>
     where
         T: Reader,
     {
         let tok = lexer.get_next_token(token, reader)
-            .ok_or(format!("Could not find token: {token:?}"))?;
-        if value != "" && tok.value != value {
-            return Err(format!("Could not find token: {token:?} with value {value:?}"));
+            .ok_or_else(|| ParseError::new(&format!("Could not find token: {token:?}"), lexer.next_position(reader)))?;
+        if !value.is_empty() && tok.value != value {
+            let msg = format!("Could not find token: {token:?} with value {value:?}");
+            return Err(ParseError::new(&msg, tok.position));
         };
-        reader.next(&tok)?;
+        reader.next(&tok).map_err(|err| ParseError::new(&err, tok.position.clone()))?;
+
+        let mut node = TreeNode::from_token(tok);
+        node.set_lambda(lambda);
+        Ok(node)
+    }
+
+    /// Get the resulting [TreeNode] for a [`Literal`](Expression::Literal) 
+    /// using the passed [`Lexer`] and [`Reader`].
+    fn get_literal<T>(&self, lexer: &Lexer, reader: &mut T, value: &str, lambda: &Lambda) -> Result<TreeNode, ParseError>
+    where
+        T: Reader,
+    {
+        let tok = lexer.get_next_any(reader)
+            .filter(|tok| tok.value == value)
+            .ok_or_else(|| ParseError::new(&format!("Could not find literal: {value:?}"), lexer.next_position(reader)))?;
+        reader.next(&tok).map_err(|err| ParseError::new(&err, tok.position.clone()))?;
 
         let mut node = TreeNode::from_token(tok);
         node.set_lambda(lambda);
@@ -130,26 +315,182 @@ impl Expression<'_> {
     }
 }
 
+// -=-=- Left Recursion Guard -=-=- //
+
+thread_local! {
+    /// The (parser, rule, position) of every [`Expr`](Expression::Expr) being expanded.
+    static EXPANDING: RefCell<HashSet<(usize, String, u32)>> = RefCell::new(HashSet::new());
+}
+
+/// Marks a rule as being expanded at a position until it is dropped, so a rule that
+/// reaches itself again without reading anything can fail instead of looping forever.
+struct Expanding {
+    key: (usize, String, u32),
+}
+
+impl Expanding {
+    /// Start expanding the rule, or [`None`] if it is already being expanded at the position.
+    /// Expanding more rules inside each other than the parser's [depth limit](Parser::set_depth_limit)
+    /// is an error, so deep nesting can't overflow the stack.
+    fn enter(parser: &Parser, expr: &str, position: u32) -> Result<Option<Expanding>, String> {
+        let limit = parser.depth_limit();
+        if EXPANDING.with(|expanding| expanding.borrow().len()) >= limit {
+            return Err(format!("Too deeply nested: more than {limit} rules inside each other"));
+        }
+        let key = (parser as *const Parser as usize, expr.to_owned(), position);
+        let entered = EXPANDING.with(|expanding| expanding.borrow_mut().insert(key.clone()));
+        if !entered {
+            REFUSED.with(|refused| refused.set(refused.get() + 1));
+        }
+        Ok(entered.then_some(Expanding { key }))
+    }
+}
+
+impl Drop for Expanding {
+    fn drop(&mut self) {
+        EXPANDING.with(|expanding| expanding.borrow_mut().remove(&self.key));
+    }
+}
+
+// -=-=- Rule Memo -=-=- //
+
+/// A parsed rule and the read position it ended at.
+type MemoResult = Result<(TreeNode, u32), ParseError>;
+
+thread_local! {
+    /// The result of every (parser, rule, position) parsed so far in the current parse.
+    static MEMO: RefCell<HashMap<(usize, String, u32), MemoResult>> = RefCell::new(HashMap::new());
+    /// How many times the left recursion guard refused to expand a rule.
+    static REFUSED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Remembers what each rule parsed to at each position, so backtracking into the same
+/// rule at the same position doesn't parse it again. Without it each level of nesting
+/// multiplies the work by the number of alternatives that share a prefix.
+struct Memo;
+
+impl Memo {
+    /// Get the result of a rule already parsed at the position.
+    fn get(parser: &Parser, expr: &str, position: u32) -> Option<MemoResult> {
+        // nothing is being expanded so this is a new parse
+        if EXPANDING.with(|expanding| expanding.borrow().is_empty()) {
+            Memo::clear();
+            return None;
+        }
+        let key = (parser as *const Parser as usize, expr.to_owned(), position);
+        MEMO.with(|memo| memo.borrow().get(&key).cloned())
+    }
+
+    /// Remember the result of a rule parsed from the position to the `end`.
+    fn insert(parser: &Parser, expr: &str, position: u32, end: u32, result: &Result<TreeNode, ParseError>) {
+        let key = (parser as *const Parser as usize, expr.to_owned(), position);
+        let result = result.clone().map(|node| (node, end));
+        MEMO.with(|memo| memo.borrow_mut().insert(key, result));
+    }
+
+    /// Skip the reader from the position to where a remembered rule ended.
+    fn skip<T>(lexer: &Lexer, reader: &mut T, position: u32, result: MemoResult) -> Result<TreeNode, ParseError>
+    where
+        T: Reader,
+    {
+        let (node, end) = result?;
+        reader.next(end - position).map_err(|err| ParseError::new(&err, lexer.next_position(reader)))?;
+        Ok(node)
+    }
+
+    /// Get how many times the left recursion guard refused a rule.
+    fn refused() -> usize {
+        REFUSED.with(|refused| refused.get())
+    }
+
+    /// Forget every result, the positions only mean something within one parse.
+    fn clear() {
+        MEMO.with(|memo| memo.borrow_mut().clear());
+    }
+}
+
 /// A branch node on an [Abstract Syntax Tree](AbstractSyntaxTree), it can contain other
 /// nodes for other brances or an optional [Token] as a leaf.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct TreeNode {
     pub nodes: Vec<Self>,
     pub leaf: Option<Token>,
+    /// A value worked out before running, see [`VirtualEnv::fold_constants`](crate::exec::VirtualEnv::fold_constants)
+    pub value: Option<NodeValue>,
     pub node_type: String,
     pub lambda: OwnedLambda,
+    /// Where the node was read from, from its first leaf to its last - see [`span`](TreeNode::span)
+    pub position: Option<ReadPointer>,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// How many nodes were cloned on the thread, so tests can check a path doesn't clone.
+    pub(crate) static CLONED_NODES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Clone without recursing, so trees as deep as a long chain of operators can be cloned
+/// on any stack.
+impl Clone for TreeNode {
+    fn clone(&self) -> Self {
+        let shallow = |node: &TreeNode| {
+            #[cfg(test)]
+            CLONED_NODES.with(|cloned| cloned.set(cloned.get() + 1));
+            TreeNode {
+                nodes: Vec::with_capacity(node.nodes.len()),
+                leaf: node.leaf.clone(),
+                value: node.value.clone(),
+                node_type: node.node_type.clone(),
+                lambda: node.lambda.clone(),
+                position: node.position.clone(),
+            }
+        };
+        // each node being cloned with its clone and the index of its next branch
+        let mut stack = vec![(self, shallow(self), 0)];
+        loop {
+            let (node, _, next) = stack.last_mut().expect("the root is popped last");
+            let node: &TreeNode = node;
+            if let Some(branch) = node.nodes.get(*next) {
+                *next += 1;
+                stack.push((branch, shallow(branch), 0));
+                continue;
+            }
+            let (_, clone, _) = stack.pop().expect("the stack is not empty");
+            match stack.last_mut() {
+                Some((_, parent, _)) => parent.nodes.push(clone),
+                None => return clone,
+            }
+        }
+    }
+}
+
+/// Drop without recursing, see [`Clone`](#impl-Clone-for-TreeNode).
+impl Drop for TreeNode {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.nodes);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.nodes);
+        }
+    }
 }
 
 /// Implement display so the [`TreeNode`] can be displayed nicely.
 /// 
 /// ---
 /// 
-/// Note: this will effect the outcome of [`assert_ast`] and [`assert_ast_ne`]
-/// if changed.
+/// Note: this will effect the outcome of [`assert_ast_display`] if changed.
+/// 
+/// The alternate form `{:#}` displays the tree over multiple indented lines with
+/// the lambda of each branch.
 impl std::fmt::Display for TreeNode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            return self.fmt_pretty(f, 0);
+        }
         if let Some(leaf) = &self.leaf {
             write!(f, "{}", leaf)?;
+        } else if let Some(value) = &self.value {
+            write!(f, "{}", value.to_string().unwrap_or_else(|_| format!("{value:?}")))?;
         } else {
             write!(f, "( ")?;
             self.nodes.iter().enumerate().try_for_each(|(i, node)| {
@@ -162,7 +503,32 @@ impl std::fmt::Display for TreeNode {
     }
 }
 
+/// Trees are equal when they have the same shape, node types, lambdas and tokens, where
+/// the tokens were read from is not compared.
+impl PartialEq for TreeNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.node_type == other.node_type
+            && self.lambda == other.lambda
+            && self.leaf == other.leaf
+            && self.value == other.value
+            && self.nodes == other.nodes
+    }
+}
+
 impl TreeNode {
+    /// Display the node indented by its `depth` with each branch on a new line.
+    fn fmt_pretty(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+        if let Some(leaf) = &self.leaf {
+            return writeln!(f, "{indent}{leaf}");
+        }
+        if let Some(value) = &self.value {
+            return writeln!(f, "{indent}{}", value.to_string().unwrap_or_else(|_| format!("{value:?}")));
+        }
+        writeln!(f, "{indent}{}", self.lambda)?;
+        self.nodes.iter().try_for_each(|node| node.fmt_pretty(f, depth + 1))
+    }
+
     // We might not need a public `new` constructor
     // fn new() -> TreeNode {
     //     TreeNode { nodes: vec![], leaf: None }
@@ -170,12 +536,29 @@ impl TreeNode {
 
     /// Make a leaf node from a [`Token`]
     pub fn from_token(token: Token) -> TreeNode {
-        TreeNode { nodes: vec![], leaf: Some(token), node_type: String::new(), lambda: Lambda::EvalToken.into() }
+        let position = Some(token.position.clone());
+        TreeNode { nodes: vec![], leaf: Some(token), value: None, node_type: String::new(), lambda: Lambda::EvalToken.into(), position }
     }
     
     /// Make a branch node from a vector of [TreeNodes](TreeNode).
     pub fn from_nodes(nodes: Vec<TreeNode>) -> TreeNode {
-        TreeNode { nodes, leaf: None, node_type: String::new(), lambda: Lambda::Eval.into() }
+        let first = nodes.iter().find_map(|node| node.position.as_ref());
+        let last = nodes.iter().rev().find_map(|node| node.position.as_ref());
+        let position = TreeNode::join(first, last);
+        TreeNode { nodes, leaf: None, value: None, node_type: String::new(), lambda: Lambda::Eval.into(), position }
+    }
+
+    /// Make a leaf node that evaluates straight to a [`NodeValue`].
+    pub fn from_value(value: NodeValue) -> TreeNode {
+        TreeNode { nodes: vec![], leaf: None, value: Some(value), node_type: String::new(), lambda: Lambda::Eval.into(), position: None }
+    }
+
+    /// Get the position from the start of `first` to the end of `last`.
+    fn join(first: Option<&ReadPointer>, last: Option<&ReadPointer>) -> Option<ReadPointer> {
+        match (first, last) {
+            (Some(first), Some(last)) => Some(ReadPointer::from_to(first, last)),
+            (first, last) => first.or(last).cloned(),
+        }
     }
 
     /// Make a symbolic [TreeNode] representation of a static [Expression].
@@ -184,19 +567,168 @@ impl TreeNode {
     /// 
     /// You cannot use [`Expr`](Expression::Expr) in a static representation as
     /// there is no [`Parser`] to reference.
+    /// 
+    /// The tokens are placed at the start of the content, the positions of a symbolic
+    /// tree don't matter when it is compared with [`eq_structure`](TreeNode::eq_structure).
     pub fn from_expr(expr: &Expression) -> TreeNode {
+        TreeNode::from_expr_at(expr, &ReadPointer::from_pos((0,0,0,0), (0,0)))
+    }
+
+    /// Make a symbolic [TreeNode] representation of a static [Expression] with every
+    /// token at the `position`.
+    pub fn from_expr_at(expr: &Expression, position: &ReadPointer) -> TreeNode {
         match expr {
             Expression::ExprOr(nodes) | Expression::SubExpr(nodes) => {
-                let nodes = nodes.into_iter().map(TreeNode::from_expr).collect();
+                let nodes = nodes.iter().map(|node| TreeNode::from_expr_at(node, position)).collect();
                 TreeNode::from_nodes(nodes)
             }
             Expression::Expr(_) => {
                 panic!("You can't use a reference when building a symbolic tree.")
             }
-            Expression::Token(token, value) => {
-                let tok = Expression::Token(token, value).token();
-                TreeNode::from_token(tok)
+            Expression::SepBy(..) => {
+                panic!("You can't use a separated list when building a symbolic tree.")
+            }
+            Expression::Literal(_) => {
+                panic!("You can't use a literal when building a symbolic tree, its token type isn't known.")
             }
+            Expression::Token(..) => TreeNode::from_token(expr.token_at(position.clone())),
+            Expression::End | Expression::Not(_) => TreeNode::from_nodes(vec![]),
+        }
+    }
+
+    /// Check if two trees have the same shape and leaf tokens, the token positions
+    /// and the node types and lambdas are not compared - so a symbolic tree can be
+    /// compared with a parsed one.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::lexer::{Lexer, LineReader};
+    /// use interpreter::parser::Parser;
+    /// use interpreter::parser::syntax::{Expression::*, TreeNode};
+    /// use interpreter::exec::syntax::Lambda::Eval;
+    /// let mut lexer = Lexer::new();
+    /// lexer.define("num", "[0-9]+")?;
+    /// let mut parser = Parser::new();
+    /// parser.define("EXPR", Token("num", ""), Eval);
+    /// 
+    /// let ast = parser.parse_tree(&lexer, &mut LineReader::new("42"))?;
+    /// assert!(ast.root.eq_structure(&TreeNode::from_expr(&Token("num", "42"))));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn eq_structure(&self, other: &TreeNode) -> bool {
+        self.leaf == other.leaf
+            && self.value == other.value
+            && self.nodes.len() == other.nodes.len()
+            && self.nodes.iter().zip(&other.nodes).all(|(a, b)| a.eq_structure(b))
+    }
+
+    /// Get the path of branch indices to the first node in pre-order that differs from
+    /// the `other` tree, `None` when the trees are the same apart from what is ignored.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::parser::syntax::{Expression::*, Ignore, TreeNode};
+    /// let tree = TreeNode::from_expr(&SubExpr(&[Token("num", "1"), Token("op", "+"), Token("num", "2")]));
+    /// let other = TreeNode::from_expr(&SubExpr(&[Token("num", "1"), Token("op", "-"), Token("num", "2")]));
+    /// assert_eq!(tree.first_difference(&other, Ignore::default()), Some(vec![1]));
+    /// assert_eq!(tree.first_difference(&tree.clone(), Ignore::default()), None);
+    /// ```
+    pub fn first_difference(&self, other: &TreeNode, ignore: Ignore) -> Option<Vec<usize>> {
+        let position = |node: &TreeNode| node.leaf.as_ref().map(|token| token.position.clone());
+        let differs = self.leaf != other.leaf
+            || self.value != other.value
+            || self.nodes.len() != other.nodes.len()
+            || (!ignore.positions && position(self) != position(other))
+            || (!ignore.lambdas && (self.node_type != other.node_type || self.lambda != other.lambda));
+        if differs {
+            return Some(vec![]);
+        }
+        self.nodes.iter().zip(&other.nodes).enumerate().find_map(|(i, (node, other))| {
+            let mut path = node.first_difference(other, ignore)?;
+            path.insert(0, i);
+            Some(path)
+        })
+    }
+
+    /// Compare the trees and describe the first difference, with both trees shown side by
+    /// side in the [pretty](TreeNode#impl-Display-for-TreeNode) format and the differing
+    /// node marked with `>`. `None` when the trees are the same apart from what is ignored.
+    pub fn diff(&self, other: &TreeNode, ignore: Ignore) -> Option<String> {
+        let path = self.first_difference(other, ignore)?;
+        let (node, other_node) = (self.at_path(&path), other.at_path(&path));
+        let left = format!("{self:#}");
+        let right = format!("{other:#}");
+        let (left, right) = (left.lines().collect::<Vec<_>>(), right.lines().collect::<Vec<_>>());
+        let marked = (self.line_of(&path), other.line_of(&path));
+        let width = left.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let mut report = format!(
+            "first difference at {path:?}: `{}` != `{}`\n",
+            node.describe(ignore),
+            other_node.describe(ignore),
+        );
+        for row in 0..left.len().max(right.len()) {
+            let mark = |line: usize| if line == row { '>' } else { ' ' };
+            let line = format!(
+                "{} {:width$} | {} {}",
+                mark(marked.0),
+                left.get(row).unwrap_or(&""),
+                mark(marked.1),
+                right.get(row).unwrap_or(&""),
+            );
+            report.push_str(line.trim_end());
+            report.push('\n');
+        }
+        Some(report)
+    }
+
+    /// Get the node at the path of branch indices.
+    fn at_path(&self, path: &[usize]) -> &TreeNode {
+        path.iter().fold(self, |node, &i| &node.nodes[i])
+    }
+
+    /// Get the line of the node at the path in the pretty format.
+    fn line_of(&self, path: &[usize]) -> usize {
+        let mut line = 0;
+        let mut node = self;
+        for &i in path {
+            line += 1 + node.nodes[..i].iter().map(TreeNode::pretty_lines).sum::<usize>();
+            node = &node.nodes[i];
+        }
+        line
+    }
+
+    /// Get how many lines the node takes in the pretty format.
+    fn pretty_lines(&self) -> usize {
+        match self.leaf.is_some() || self.value.is_some() {
+            true => 1,
+            false => 1 + self.nodes.iter().map(TreeNode::pretty_lines).sum::<usize>(),
+        }
+    }
+
+    /// Describe the node on its own with what is compared by [`diff`](TreeNode::diff).
+    fn describe(&self, ignore: Ignore) -> String {
+        if let Some(leaf) = &self.leaf {
+            let leaf = match ignore.positions {
+                true => leaf.to_string(),
+                false => leaf.display_with_pos(),
+            };
+            return match ignore.lambdas {
+                true => leaf,
+                false => format!("{leaf} {}", self.lambda),
+            };
+        }
+        if let Some(value) = &self.value {
+            return value.to_string().unwrap_or_else(|_| format!("{value:?}"));
+        }
+        match ignore.lambdas {
+            true => format!("branch with {} nodes", self.nodes.len()),
+            false => format!("{} `{}` branch with {} nodes", self.lambda, self.node_type, self.nodes.len()),
         }
     }
 
@@ -207,22 +739,138 @@ impl TreeNode {
 
     /// Add a [`TreeNode`] branch. 
     pub fn add_branch(&mut self, node: TreeNode<>) {
+        self.position = TreeNode::join(self.position.as_ref(), node.position.as_ref());
         self.nodes.push(node);
     }
 
     /// set the leaf [Token].
     pub fn set_leaf(&mut self, token: Token) {
+        self.position = Some(token.position.clone());
         self.leaf = Some(token);
     }
 
     pub fn set_lambda(&mut self, lambda: &Lambda) {
         self.lambda = lambda.into();
     }
+
+    /// Replace the branch at the 0-based `index` with the `node`, giving back the branch
+    /// that was replaced. A leaf has no branches to replace.
+    /// 
+    /// ---
+    /// 
+    /// The lambda, node type and position of the node are kept, so the replacement
+    /// should be evaluated the same way as the branch it replaces.
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// use interpreter::parser::syntax::TreeNode;
+    /// let mut ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("1 + 2"))?;
+    /// let old = ast.root.replace_child(2, TreeNode::from_value(NodeValue::Integer(5)))?;
+    /// assert_eq!(old.to_string(), "int:2");
+    /// assert_eq!(ast.to_string(), "( int:1 op:+ 5 )");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn replace_child(&mut self, index: usize, node: TreeNode) -> Result<TreeNode, String> {
+        if self.leaf.is_some() {
+            return Err(format!("Cannot replace branch {index} of the leaf node `{self}`"));
+        }
+        let Some(child) = self.nodes.get_mut(index) else {
+            return Err(format!("No branch {index} to replace on node `{self}`, it has {} branches", self.nodes.len()));
+        };
+        let old = std::mem::replace(child, node);
+        debug_assert_eq!(self.check_invariants(), Ok(()));
+        Ok(old)
+    }
+
+    /// Replace every branch of the node with what `f` makes of it.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::NodeValue;
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// use interpreter::parser::syntax::TreeNode;
+    /// let mut ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("1 * 2"))?;
+    /// ast.root.map_children(|node| match node.leaf.as_ref().is_some_and(|token| token.token_type == "int") {
+    ///     true => TreeNode::from_value(NodeValue::Integer(7)),
+    ///     false => node,
+    /// });
+    /// assert_eq!(ast.to_string(), "( 7 op:* 7 )");
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn map_children(&mut self, f: impl FnMut(TreeNode) -> TreeNode) {
+        self.nodes = std::mem::take(&mut self.nodes).into_iter().map(f).collect();
+        debug_assert_eq!(self.check_invariants(), Ok(()));
+    }
+
+    /// Check that the node and its branches are coherent - a leaf has no branches and
+    /// the 1-based args of each lambda are within its node's branches.
+    /// 
+    /// ---
+    /// 
+    /// The mutation methods, like [`replace_child`](TreeNode::replace_child), check this
+    /// in debug builds.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        self.nodes().try_for_each(|node| {
+            if node.leaf.is_some() && !node.nodes.is_empty() {
+                return Err(format!("The leaf node `{node}` has {} branches", node.nodes.len()));
+            }
+            node.check_args(&node.lambda)
+        })
+    }
+
+    /// Check that the args of the `lambda` are branches of the node.
+    fn check_args(&self, lambda: &OwnedLambda) -> Result<(), String> {
+        let args = match lambda {
+            // the alternative used isn't known once the node is built
+            OwnedLambda::LambdaOr(_) | OwnedLambda::Eval | OwnedLambda::EvalAs(_) | OwnedLambda::EvalToken => return Ok(()),
+            OwnedLambda::Lambda(_, args) | OwnedLambda::GetExprs(args, _) => args.as_slice(),
+            OwnedLambda::GetExpr(arg, _) => std::slice::from_ref(arg),
+        };
+        match args.iter().find(|arg| **arg == 0 || **arg as usize > self.nodes.len()) {
+            Some(arg) => Err(format!("The lambda `{lambda}` of node `{self}` uses arg {arg}, it has {} branches", self.nodes.len())),
+            None => Ok(()),
+        }
+    }
+
+    /// Rewrite the node from the bottom up, each node is given to `f` after its branches
+    /// were. Done without recursing, like [`Clone`](#impl-Clone-for-TreeNode).
+    fn rewrite(self, f: &mut impl FnMut(TreeNode) -> TreeNode) -> TreeNode {
+        // each node being rewritten with the branches left to rewrite, last first, and the rewritten ones
+        let open = |mut node: TreeNode| {
+            let mut left = std::mem::take(&mut node.nodes);
+            left.reverse();
+            let done = Vec::with_capacity(left.len());
+            (node, left, done)
+        };
+        let mut stack = vec![open(self)];
+        while let Some((_, left, _)) = stack.last_mut() {
+            if let Some(branch) = left.pop() {
+                stack.push(open(branch));
+                continue;
+            }
+            let Some((mut node, _, done)) = stack.pop() else { break };
+            node.nodes = done;
+            let node = f(node);
+            match stack.last_mut() {
+                Some((_, _, done)) => done.push(node),
+                None => return node,
+            }
+        }
+        unreachable!("the root is returned once it is rewritten")
+    }
 }
 
 
 /// Hold a root [TreeNode] and methods for traversing it.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AbstractSyntaxTree {
     pub root: TreeNode,
 }
@@ -231,10 +879,12 @@ pub struct AbstractSyntaxTree {
 /// 
 /// ---
 /// 
-/// Note: this will effect the outcome of [`assert_ast`] and [`assert_ast_ne`]
-/// if changed.
+/// Note: this will effect the outcome of [`assert_ast_display`] if changed.
 impl std::fmt::Display for AbstractSyntaxTree {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            return write!(f, "{:#}", self.root);
+        }
         write!(f, "{}", self.root)
     }
 }
@@ -243,6 +893,57 @@ impl AbstractSyntaxTree {
     pub fn new(root: TreeNode) -> Self {
         AbstractSyntaxTree { root }
     }
+
+    /// Rewrite every node of the tree from the bottom up, each node is replaced by what
+    /// `f` makes of it after its branches were rewritten.
+    /// 
+    /// ---
+    /// 
+    /// ## Example
+    /// 
+    /// ```
+    /// use interpreter::exec::{Executor, NodeValue, StateNode};
+    /// use interpreter::lexer::LineReader;
+    /// let mut executor = Executor::math();
+    /// let mut ast = executor.parser().parse_tree(executor.lexer(), &mut LineReader::new("2 * (2 + 1)"))?;
+    /// // every number becomes a 10
+    /// ast.transform(|mut node| {
+    ///     if let Some(token) = node.leaf.as_mut().filter(|token| token.token_type == "int") {
+    ///         token.value = "10".into();
+    ///     }
+    ///     node
+    /// });
+    /// assert_eq!(executor.env_mut().exec_tree(&ast), StateNode::Value(NodeValue::Integer(200)));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn transform(&mut self, mut f: impl FnMut(TreeNode) -> TreeNode) {
+        let root = std::mem::replace(&mut self.root, TreeNode::from_nodes(vec![]));
+        self.root = root.rewrite(&mut f);
+        debug_assert_eq!(self.root.check_invariants(), Ok(()));
+    }
+}
+
+/// Lets the [`assert_ast`] macros take a tree or a node.
+impl AsRef<TreeNode> for AbstractSyntaxTree {
+    fn as_ref(&self) -> &TreeNode {
+        &self.root
+    }
+}
+
+impl AsRef<TreeNode> for TreeNode {
+    fn as_ref(&self) -> &TreeNode {
+        self
+    }
+}
+
+/// What [`TreeNode::first_difference`] leaves out when comparing two trees, the shape
+/// and the leaf tokens and values are always compared.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Ignore {
+    /// Where the tokens were read from
+    pub positions: bool,
+    /// The lambdas and node types of the nodes
+    pub lambdas: bool,
 }
 
 // -=-=-=-=- Unit Tests -=-=-=-=- //
@@ -282,9 +983,43 @@ mod tests {
         let exp_2 = TreeNode::from_token(Token("tok:b", "def").token());
         let exp_3 = TreeNode::from_token(Token("tok:c", "ghi").token());
         // Assert that the AST matches the expected structure
-        assert_ast!(exp_1, ast_1);
-        assert_ast!(exp_2, ast_2);
-        assert_ast!(exp_3, ast_3);
+        assert_ast!(exp_1, ast_1; ignore(positions, lambdas));
+        assert_ast!(exp_2, ast_2; ignore(positions, lambdas));
+        assert_ast!(exp_3, ast_3; ignore(positions, lambdas));
+        Ok(())
+    }
+
+    /// the span of a node read through nested alternatives can go back to where each
+    /// alternative started.
+    #[test]
+    fn nested_expr_or_span() -> Result<(), String> {
+        use crate::lexer::Reader;
+        let mut lexer = Lexer::new();
+        lexer.define("a", "a")?;
+        lexer.define("b", "b")?;
+        lexer.define("c", "c")?;
+        let mut parser = Parser::new();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[Token("a", ""), Expr("INNER")]),
+            Token("a", ""),
+        ]), Eval);
+        // the first inner alternative fails after reading `b` and backtracks
+        parser.define("INNER", ExprOr(&[
+            SubExpr(&[Token("b", ""), Token("a", "")]),
+            SubExpr(&[Token("b", ""), Token("c", "")]),
+        ]), Eval);
+        let ast = parser.parse_tree(&lexer, &mut LineReader::new("abc"))?;
+        assert_eq!(ast.to_string(), "( a:a ( b:b c:c ) )");
+        let span = ast.root.position.clone().ok_or("the root has no position")?;
+        assert_eq!((span.start_offset(), span.end_offset()), (0, 3));
+        let mut reader = LineReader::new("abc");
+        reader.restore(span);
+        // the inner alternative started after `a`
+        reader.back();
+        assert_eq!(reader.get_pointer().end_offset(), 1);
+        // and the outer one at the start
+        reader.back();
+        assert_eq!(reader.get_pointer().end_offset(), 0);
         Ok(())
     }
 
@@ -317,10 +1052,101 @@ mod tests {
             ]),
         ]));
         // Assert that the AST matches the expected structure
-        assert_ast!(exp, ast);
+        assert_ast!(exp, ast; ignore(positions, lambdas));
         Ok(())
     }
     
+    /// assert a [`SepBy`] expression stops where an item can't start.
+    #[test]
+    fn test_get_sep_by() -> Result<(), String> {
+        // Setup Lexer
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "\\+|,|;")?;
+        // Setup Parser
+        let mut parser = Parser::new();
+        parser.define("EXPR", SubExpr(&[ SepBy(&Expr("ITEM"), &Token("op", ",")), Token("op", ";") ]), Eval);
+        parser.define("ITEM", ExprOr(&[
+            SubExpr(&[ Token("num", ""), Token("op", "+"), Token("num", "") ]),
+            Token("num", ""),
+        ]), Eval);
+        let ast = parser.parse_tree(&lexer, &mut LineReader::new("1,2+3;"))?;
+        let exp = TreeNode::from_expr(&SubExpr(&[
+            SubExpr(&[ Token("num", "1"), SubExpr(&[ Token("num", "2"), Token("op", "+"), Token("num", "3") ]) ]),
+            Token("op", ";"),
+        ]));
+        assert!(ast.root.eq_structure(&exp));
+        assert!(parser.parse_tree(&lexer, &mut LineReader::new(";")).is_ok());
+        // a trailing separator is an error at the missing item
+        let err = parser.parse_tree(&lexer, &mut LineReader::new("1,2,;")).expect_err("`1,2,;` should not parse");
+        assert_eq!(err.position.read_pos.0, 4);
+        Ok(())
+    }
+
+    /// assert a [`Literal`] expression matches a token by its value alone.
+    #[test]
+    fn test_get_literal() -> Result<(), String> {
+        // Setup Lexer
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("punct", "\\(|\\)|,")?;
+        // Setup Parser
+        let mut parser = Parser::new();
+        parser.define("EXPR", SubExpr(&[ Literal("("), Token("num", ""), Literal(")") ]), Eval);
+        let ast = parser.parse_tree(&lexer, &mut LineReader::new("(1)"))?;
+        let exp = TreeNode::from_expr(&SubExpr(&[ Token("punct", "("), Token("num", "1"), Token("punct", ")") ]));
+        assert!(ast.root.eq_structure(&exp));
+        // another value of the same type doesn't match
+        let err = parser.parse_tree(&lexer, &mut LineReader::new("(1,")).expect_err("`(1,` should not parse");
+        assert_eq!(err.message, "Could not find literal: \")\"");
+        assert_eq!(err.position.read_pos, (2, 2));
+        Ok(())
+    }
+
+    /// assert a [`Not`] expression excludes a match without reading anything.
+    #[test]
+    fn test_get_not() -> Result<(), String> {
+        // Setup Lexer
+        let mut lexer = Lexer::new();
+        lexer.define("kw", "end\\b")?;
+        lexer.define("ident", "[a-z]+")?;
+        // Setup Parser
+        let mut parser = Parser::new();
+        parser.define("EXPR", SubExpr(&[ Not(&Token("kw", "end")), Expr("IDENT") ]), Eval);
+        parser.define("IDENT", Token("ident", ""), Eval);
+        let ast = parser.parse_tree(&lexer, &mut LineReader::new("foo"))?;
+        let exp = TreeNode::from_expr(&SubExpr(&[ Not(&Token("kw", "end")), Token("ident", "foo") ]));
+        assert!(ast.root.eq_structure(&exp));
+        // a keyword is excluded, a word that starts like one is not
+        let err = parser.parse_tree(&lexer, &mut LineReader::new("end")).expect_err("`end` should not parse");
+        assert_eq!(err.position.read_pos, (0, 0));
+        parser.parse_tree(&lexer, &mut LineReader::new("ending"))?;
+        Ok(())
+    }
+
+    /// assert an [`End`] expression only matches when nothing is left.
+    #[test]
+    fn test_get_end() -> Result<(), String> {
+        // Setup Lexer
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define_skip("space", "\\s+")?;
+        // Setup Parser
+        let mut parser = Parser::new();
+        parser.define("EXPR", SubExpr(&[ Expr("NUM"), End ]), Eval);
+        parser.define("NUM", Token("num", ""), Eval);
+        // trailing tokens are rejected
+        let err = parser.parse_tree(&lexer, &mut LineReader::new("1 2")).expect_err("`1 2` should not parse");
+        assert_eq!(err.message, "Expected the end of the input");
+        assert_eq!(err.position.read_pos, (2, 2));
+        // trailing skipped content is still the end
+        let ast = parser.parse_tree(&lexer, &mut LineReader::new("1 "))?;
+        let exp = TreeNode::from_expr(&SubExpr(&[ Token("num", "1"), End ]));
+        assert!(ast.root.eq_structure(&exp));
+        parser.parse_tree(&lexer, &mut LineReader::new("1"))?;
+        Ok(())
+    }
+
     /// assert an [`Expr`] expression.
     #[test]
     fn test_get_expr() -> Result<(), String> {
@@ -339,7 +1165,7 @@ mod tests {
         // Define the expected AST structure
         let exp = TreeNode::from_token(Token("tok", "token").token());
         // Assert that the AST matches the expected structure
-        assert_ast!(exp, ast);
+        assert_ast!(exp, ast; ignore(positions, lambdas));
         Ok(())
     }
     
@@ -357,7 +1183,7 @@ mod tests {
         // Define the expected AST structure
         let exp = TreeNode::from_token(Token("tok:a", "abc").token());
         // Assert that the AST matches the expected structure
-        assert_ast!(exp, ast);
+        assert_ast!(exp, ast; ignore(positions, lambdas));
         Ok(())
     }
 
@@ -394,7 +1220,143 @@ mod tests {
         ]));
 
         // Assert that the AST matches the expected structure
-        assert_ast!(exp, ast);
+        assert_ast!(exp, ast; ignore(positions, lambdas));
+        Ok(())
+    }
+
+    /// a parsed tree matches a symbolic tree no matter where the tokens were read.
+    #[test]
+    fn symbolic_tree_structure() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "\\+")?;
+        let mut parser = Parser::new();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[ Expr("NUM"), Token("op", "+"), Expr("EXPR") ]),
+            Expr("NUM"),
+        ]), Eval);
+        parser.define("NUM", Token("num", ""), Eval);
+        let ast = parser.parse_tree(&lexer, &mut LineReader::new("1+23"))?;
+        // the parsed leaves have their real positions
+        let leaf = ast.root.nodes[2].leaf.as_ref().ok_or("no leaf")?;
+        assert_eq!(leaf.position, ReadPointer::from_pos((0,2, 0,4), (2,4)));
+        // the positions are ignored for either symbolic tree
+        let position = ReadPointer::from_pos((5,5, 5,6), (50, 51));
+        let exp = SubExpr(&[ Token("num", "1"), Token("op", "+"), Token("num", "23") ]);
+        assert!(TreeNode::from_expr(&exp).eq_structure(&ast.root));
+        assert!(ast.root.eq_structure(&TreeNode::from_expr_at(&exp, &position)));
+        // but the values and shape are not
+        let exp = SubExpr(&[ Token("num", "1"), Token("op", "+"), Token("num", "2") ]);
+        assert!(!TreeNode::from_expr(&exp).eq_structure(&ast.root));
+        let exp = SubExpr(&[ Token("num", "1"), Token("op", "+"), SubExpr(&[ Token("num", "23") ]) ]);
+        assert!(!TreeNode::from_expr(&exp).eq_structure(&ast.root));
+        Ok(())
+    }
+
+    /// mutually recursive rules fail the branch instead of looping forever.
+    #[test]
+    fn mutual_left_recursion() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("tok", "[a-c]")?;
+        // rules that can only reach each other
+        let mut parser = Parser::new();
+        parser.define("EXPR", Expr("A"), Eval);
+        parser.define("A", Expr("B"), Eval);
+        parser.define("B", Expr("A"), Eval);
+        let err = parser.parse_tree(&lexer, &mut LineReader::new("abc")).expect_err("the rules never read a token");
+        assert!(err.message.starts_with("Left recursion"), "got {err}");
+        // the recursive branch fails so the other alternative is used
+        let mut parser = Parser::new();
+        parser.define("EXPR", SubExpr(&[ Expr("B"), Token("tok", "a") ]), Eval);
+        parser.define("B", ExprOr(&[
+            SubExpr(&[ Expr("EXPR"), Token("tok", "b") ]),
+            Token("tok", "c"),
+        ]), Eval);
+        let ast = parser.parse_tree(&lexer, &mut LineReader::new("ca"))?;
+        let exp = TreeNode::from_expr(&SubExpr(&[ Token("tok", "c"), Token("tok", "a") ]));
+        assert!(ast.root.eq_structure(&exp), "got {ast}");
+        Ok(())
+    }
+
+    /// trees read from different places are equal.
+    #[test]
+    fn tree_eq_ignores_position() -> Result<(), String> {
+        let mut lexer = Lexer::new();
+        lexer.define("num", "[0-9]+")?;
+        lexer.define("op", "\\+")?;
+        lexer.define_skip("space", "\\s+")?;
+        let mut parser = Parser::new();
+        parser.define("EXPR", ExprOr(&[
+            SubExpr(&[ Expr("NUM"), Token("op", "+"), Expr("EXPR") ]),
+            Expr("NUM"),
+        ]), Lambda::LambdaOr(&[ Lambda::Lambda("ADD", &[1, 3]), Eval ]));
+        parser.define("NUM", Token("num", ""), Lambda::EvalAs("INTEGER"));
+        let ast_1 = parser.parse_tree(&lexer, &mut LineReader::new("1+2"))?;
+        let ast_2 = parser.parse_tree(&lexer, &mut LineReader::new("\n  1 +   2"))?;
+        assert_ne!(ast_1.root.nodes[2].leaf, None);
+        assert_eq!(ast_1, ast_2);
+        // but the tokens and lambdas are compared
+        assert_ne!(ast_1, parser.parse_tree(&lexer, &mut LineReader::new("1+3"))?);
+        let mut other = parser.parse_tree(&lexer, &mut LineReader::new("1+2"))?;
+        other.root.set_lambda(&Lambda::Lambda("SUB", &[1, 3]));
+        assert_ne!(ast_1, other);
+        Ok(())
+    }
+
+    /// every `2` becomes a `3` and the result follows.
+    #[test]
+    fn transform_tree() -> Result<(), String> {
+        use crate::exec::{Executor, StateNode};
+        let mut executor = Executor::math();
+        let mut ast = executor.parser().parse_tree(executor.lexer(), &mut LineReader::new("2 * (12 - 2) + 2"))?;
+        assert_eq!(executor.env_mut().exec_tree(&ast), StateNode::Value(NodeValue::Integer(22)));
+        let mut visited = vec![];
+        ast.transform(|mut node| {
+            if let Some(token) = node.leaf.as_mut().filter(|token| token.token_type == "int" && token.value == "2") {
+                token.value = "3".into();
+            }
+            visited.push(node.to_string());
+            node
+        });
+        // the branches are rewritten before their node
+        assert_eq!(visited.first().map(String::as_str), Some("int:3"));
+        assert_eq!(visited.last().map(String::as_str), Some("( ( int:3 op:* ( op:( ( int:12 op:- int:3 ) op:) ) ) op:+ int:3 )"));
+        assert_eq!(executor.env_mut().exec_tree(&ast), StateNode::Value(NodeValue::Integer(30)));
+        Ok(())
+    }
+
+    /// branches are only replaced where there are some.
+    #[test]
+    fn replace_child() -> Result<(), String> {
+        let mut node = TreeNode::from_expr(&SubExpr(&[ Token("num", "1"), Token("op", "+"), Token("num", "2") ]));
+        let old = node.replace_child(0, TreeNode::from_value(NodeValue::Integer(4)))?;
+        assert_eq!(old.to_string(), "num:1");
+        assert_eq!(node.to_string(), "( 4 op:+ num:2 )");
+        let err = node.replace_child(3, TreeNode::from_nodes(vec![])).expect_err("there are 3 branches");
+        assert_eq!(err, "No branch 3 to replace on node `( 4 op:+ num:2 )`, it has 3 branches");
+        let mut leaf = TreeNode::from_expr(&Token("num", "1"));
+        assert!(leaf.replace_child(0, TreeNode::from_nodes(vec![])).is_err());
+        node.map_children(|child| TreeNode::from_nodes(vec![child]));
+        assert_eq!(node.to_string(), "( ( 4 ) ( op:+ ) ( num:2 ) )");
+        Ok(())
+    }
+
+    /// leaves without branches and lambda args within the branches.
+    #[test]
+    fn check_invariants() -> Result<(), String> {
+        use crate::lang::math;
+        for src in ["x := [1, 2][0] * (3 + y)", "m.a = {a: 2}", "print(1, str(2))", "f(x) // 2 % 3"] {
+            let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new(src))?;
+            assert_eq!(ast.root.check_invariants(), Ok(()), "{src}");
+        }
+        let mut node = TreeNode::from_expr(&SubExpr(&[ Token("num", "1"), Token("num", "2") ]));
+        node.set_lambda(&Lambda::Lambda("ADD", &[1, 3]));
+        let err = node.check_invariants().expect_err("there is no third branch");
+        assert!(err.ends_with("uses arg 3, it has 2 branches"), "{err}");
+        let mut leaf = TreeNode::from_expr(&Token("num", "1"));
+        leaf.nodes.push(TreeNode::from_nodes(vec![]));
+        assert!(leaf.check_invariants().is_err());
         Ok(())
     }
 }
+
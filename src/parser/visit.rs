@@ -0,0 +1,254 @@
+//! # Tree Visiting
+//!
+//! Walk an [`AbstractSyntaxTree`] with a [`Visitor`] or iterate over its nodes, so the
+//! tree can be analyzed without matching over every [`TreeNode`] by hand.
+//!
+
+use crate::lexer::{ReadPointer, Reader, Token};
+use crate::exec::syntax::OwnedLambda;
+use super::syntax::{AbstractSyntaxTree, TreeNode};
+
+/// Callbacks for [`walk`](AbstractSyntaxTree::walk), every callback does nothing by default
+/// so only the needed ones have to be written. The depth of the root node is `0`.
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::exec::syntax::OwnedLambda;
+/// use interpreter::lang::math;
+/// use interpreter::lexer::{LineReader, Token};
+/// use interpreter::parser::Visitor;
+///
+/// /// find how deep the deepest token is.
+/// struct Depth(usize);
+/// impl Visitor for Depth {
+///     fn visit_leaf(&mut self, _: &Token, depth: usize, _: &OwnedLambda) {
+///         self.0 = self.0.max(depth);
+///     }
+/// }
+///
+/// let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("1"))?;
+/// let mut depth = Depth(0);
+/// ast.walk(&mut depth);
+/// assert_eq!(depth.0, 0);
+/// Ok::<(), String>(())
+/// ```
+pub trait Visitor {
+    /// Called for a branch node before its branches are visited.
+    fn enter_node(&mut self, _node: &TreeNode, _depth: usize, _lambda: &OwnedLambda) {}
+    /// Called for a branch node after its branches are visited.
+    fn leave_node(&mut self, _node: &TreeNode, _depth: usize, _lambda: &OwnedLambda) {}
+    /// Called for the token of a leaf node.
+    fn visit_leaf(&mut self, _token: &Token, _depth: usize, _lambda: &OwnedLambda) {}
+}
+
+/// A pre-order iterator over the nodes of a tree, see [`TreeNode::nodes`].
+pub struct Nodes<'a> {
+    stack: Vec<&'a TreeNode>,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = &'a TreeNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        // the first branch has to be on top to be next
+        self.stack.extend(node.nodes.iter().rev());
+        Some(node)
+    }
+}
+
+impl TreeNode {
+    /// Visit the node and all its branches with the `visitor`.
+    pub fn walk(&self, visitor: &mut impl Visitor, depth: usize) {
+        if let Some(token) = &self.leaf {
+            return visitor.visit_leaf(token, depth, &self.lambda);
+        }
+        visitor.enter_node(self, depth, &self.lambda);
+        self.nodes.iter().for_each(|node| node.walk(visitor, depth + 1));
+        visitor.leave_node(self, depth, &self.lambda);
+    }
+
+    /// Iterate over the node and all its branches, each node comes before its branches.
+    pub fn nodes(&self) -> Nodes<'_> {
+        Nodes { stack: vec![self] }
+    }
+
+    /// Iterate over the tokens of every leaf from left to right.
+    pub fn leaves(&self) -> impl Iterator<Item = &Token> {
+        self.nodes().filter_map(|node| node.leaf.as_ref())
+    }
+
+    /// Get the position from the first leaf to the last leaf, `None` when there are no leaves.
+    /// 
+    /// ---
+    /// 
+    /// The position is kept while parsing, so it isn't worked out again from the leaves and
+    /// stays after [folding](crate::exec::VirtualEnv::fold_constants) replaces the branches.
+    pub fn span(&self) -> Option<ReadPointer> {
+        self.position.clone()
+    }
+
+    /// Get the first node in [pre-order](TreeNode::nodes) that the `predicate` matches.
+    pub fn find(&self, predicate: impl Fn(&TreeNode) -> bool) -> Option<&TreeNode> {
+        self.nodes().find(|node| predicate(node))
+    }
+}
+
+impl AbstractSyntaxTree {
+    /// Visit every node of the tree with the `visitor`.
+    pub fn walk(&self, visitor: &mut impl Visitor) {
+        self.root.walk(visitor, 0);
+    }
+
+    /// Iterate over every node of the tree, see [`TreeNode::nodes`].
+    pub fn nodes(&self) -> Nodes<'_> {
+        self.root.nodes()
+    }
+
+    /// Iterate over the tokens of every leaf from left to right.
+    pub fn leaves(&self) -> impl Iterator<Item = &Token> {
+        self.root.leaves()
+    }
+
+    /// Get the names of the variables read by the tree - the first token under each
+    /// `GET_IDENT` lambda - in the order they are first read.
+    ///
+    /// ---
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let ast = math::parser().parse_tree(&math::lexer(), &mut LineReader::new("a * (b + a)"))?;
+    /// assert_eq!(ast.identifiers(), ["a", "b"]);
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn identifiers(&self) -> Vec<String> {
+        let mut identifiers: Vec<String> = vec![];
+        let reads = self.nodes().filter(|node| matches!(&node.lambda, OwnedLambda::Lambda(name, _) if name == "GET_IDENT"));
+        for ident in reads.filter_map(|node| node.leaves().next()) {
+            if !identifiers.contains(&ident.value) {
+                identifiers.push(ident.value.clone());
+            }
+        }
+        identifiers
+    }
+
+    /// Get the source text a `node` of the tree was read from out of the `reader` it was
+    /// parsed with, `None` for nodes made without tokens or when the reader no longer
+    /// holds the text.
+    ///
+    /// ---
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use interpreter::lang::math;
+    /// use interpreter::lexer::LineReader;
+    /// let mut reader = LineReader::new("y = (1 +  2) * 3");
+    /// let ast = math::parser().parse_tree(&math::lexer(), &mut reader)?;
+    /// let value = &ast.root.nodes[2];
+    /// assert_eq!(ast.source_of(value, &reader).as_deref(), Some("(1 +  2) * 3"));
+    /// Ok::<(), String>(())
+    /// ```
+    pub fn source_of(&self, node: &TreeNode, reader: &impl Reader) -> Option<String> {
+        reader.read_pointer(&node.span()?).map(str::to_owned)
+    }
+}
+
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::math;
+    use crate::lexer::LineReader;
+
+    fn parse(src: &str) -> Result<AbstractSyntaxTree, String> {
+        Ok(math::parser().parse_tree(&math::lexer(), &mut LineReader::new(src))?)
+    }
+
+    /// records every callback in order.
+    #[derive(Default)]
+    struct Trace(Vec<String>);
+
+    impl Visitor for Trace {
+        fn enter_node(&mut self, node: &TreeNode, depth: usize, _: &OwnedLambda) {
+            self.0.push(format!("{depth} enter {}", node.nodes.len()));
+        }
+        fn leave_node(&mut self, _: &TreeNode, depth: usize, _: &OwnedLambda) {
+            self.0.push(format!("{depth} leave"));
+        }
+        fn visit_leaf(&mut self, token: &Token, depth: usize, _: &OwnedLambda) {
+            self.0.push(format!("{depth} {}", token.value));
+        }
+    }
+
+    /// the visitor sees branches around their leaves.
+    #[test]
+    fn walk_order() -> Result<(), String> {
+        let ast = parse("1 + 2")?;
+        let mut trace = Trace::default();
+        ast.walk(&mut trace);
+        assert_eq!(trace.0, ["0 enter 3", "1 1", "1 +", "1 2", "0 leave"]);
+        Ok(())
+    }
+
+    /// the trivia and values of the leaves rebuild the source exactly.
+    #[test]
+    fn trivia_round_trip() -> Result<(), String> {
+        let src = "# totals\nx  =  1+ 2   # first\n\n\t y=( x *3)//2\ns := \"a  # b\"\nx+y  # done\n";
+        let mut lexer = math::lexer();
+        lexer.define_skip("comment", "#[^\n]*")?;
+        lexer.set_preserve_trivia(true);
+        let mut reader = LineReader::new(src);
+        let trees = math::parser().parse_all(&lexer, &mut reader)?;
+        assert_eq!(trees.len(), 4);
+        let mut rebuilt: String = trees.iter()
+            .flat_map(|ast| ast.leaves())
+            .map(|token| format!("{}{}", token.leading_trivia.as_deref().unwrap_or_default(), token.source_text()))
+            .collect();
+        rebuilt.push_str(&lexer.trivia(&reader));
+        assert_eq!(rebuilt, src);
+        // trivia is only kept when asked for
+        let ast = parse(" 1")?;
+        assert_eq!(ast.leaves().next().and_then(|token| token.leading_trivia.clone()), None);
+        Ok(())
+    }
+
+    /// nodes map back to the exact text they were read from.
+    #[test]
+    fn source_of() -> Result<(), String> {
+        let is_mult = |node: &TreeNode| matches!(&node.lambda, OwnedLambda::Lambda(name, _) if name == "MULT");
+        for (src, expected) in [("x = 2*(3+4)", "2*(3+4)"), ("  x =  2 *\t( 3 + 4 ) ", "2 *\t( 3 + 4 )")] {
+            let mut reader = LineReader::new(src);
+            let ast = math::parser().parse_tree(&math::lexer(), &mut reader)?;
+            let mult = ast.root.find(is_mult).ok_or("there should be a multiplication")?;
+            assert_eq!(ast.source_of(mult, &reader).as_deref(), Some(expected));
+            assert_eq!(ast.source_of(&ast.root, &reader).as_deref(), Some(src.trim()));
+        }
+        // made without tokens
+        let ast = AbstractSyntaxTree::new(TreeNode::from_value(crate::exec::NodeValue::Integer(1)));
+        assert_eq!(ast.source_of(&ast.root, &LineReader::new("1")), None);
+        Ok(())
+    }
+
+    /// the read identifiers are found, the assigned one is not read.
+    #[test]
+    fn identifiers() -> Result<(), String> {
+        let ast = parse("x = y + z*2")?;
+        assert_eq!(ast.identifiers(), ["y", "z"]);
+        let leaves: Vec<_> = ast.leaves().map(|token| token.value.as_str()).collect();
+        assert_eq!(leaves, ["x", "=", "y", "+", "z", "*", "2"]);
+        assert_eq!(ast.nodes().filter(|node| node.leaf.is_none()).count(), 5);
+        let set = ast.root.find(|node| matches!(&node.lambda, OwnedLambda::Lambda(name, _) if name == "ASSIGN"));
+        assert!(set.is_some());
+        assert!(ast.root.find(|node| node.leaf.as_ref().is_some_and(|token| token.value == "w")).is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,433 @@
+//! # REPL Commands
+//! 
+//! Meta-commands for the input loop started by [`run`](crate::run). Commands start
+//! with a `:` and inspect or change the [`Executor`] instead of running code.
+//! 
+//! - `:vars` lists the current variables
+//! - `:ast <expr>` displays the syntax tree of an expression without running it
+//! - `:tokens <expr>` displays the tokens of an expression
+//! - `:reset` removes all the variables
+//! - `:debug on|off` prints each step of the evaluation
+//! - `:help` lists the commands
+//! 
+//! The loop itself is [`run_with`], which reads from any [`BufRead`] and writes to any
+//! [`Write`] using the prompts of a [`ReplConfig`].
+//! 
+
+use std::io::{BufRead, Write};
+
+use crate::exec::{DebugAction, Executor};
+use crate::lexer::{LineReader, Reader, SessionReader};
+use crate::report;
+
+/// The commands with their descriptions for `:help`.
+const COMMANDS: &[(&str, &str)] = &[
+    (":vars", "list the current variables"),
+    (":ast <expr>", "display the syntax tree of an expression without running it"),
+    (":tokens <expr>", "display the tokens of an expression"),
+    (":reset", "remove all the variables"),
+    (":debug on|off", "print each step of the evaluation"),
+    (":help", "list the commands"),
+];
+
+/// The strings the input loop displays.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::repl::ReplConfig;
+/// let config = ReplConfig {
+///     prompt: "calc> ".into(),
+///     banner: Some("My Calculator".into()),
+///     ..Default::default()
+/// };
+/// assert_eq!(config.continuation, "#> ");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplConfig {
+    /// Displayed before each new input.
+    pub prompt: String,
+    /// Displayed before the next line of an input continued with a trailing `\`.
+    pub continuation: String,
+    /// Displayed on its own line before each prompt.
+    pub spacer: String,
+    /// Displayed once when the loop starts.
+    pub banner: Option<String>,
+    /// Color the error reports, see [`report::use_color`].
+    pub color: bool,
+    /// Count the lines of the error reports from the start of the session instead of the
+    /// start of each input, see [`SessionReader`].
+    pub session_lines: bool,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        Self {
+            prompt: "@> ".into(),
+            continuation: "#> ".into(),
+            spacer: "---".into(),
+            banner: None,
+            color: false,
+            session_lines: false,
+        }
+    }
+}
+
+impl ReplConfig {
+    /// Write the spacer line and the prompt before a new input.
+    pub fn write_prompt<W>(&self, out: &mut W) -> std::io::Result<()>
+    where W: Write {
+        writeln!(out, "{}", self.spacer)?;
+        write!(out, "{}", self.prompt)
+    }
+}
+
+/// Run the input loop on the [`Executor`] until `exit` or the end of the `input`,
+/// writing the prompts and results to `out`.
+/// 
+/// ---
+/// 
+/// A line ending with `\` continues on the next line, which is prompted for with
+/// the continuation prompt. Errors are written as a [report](crate::report) pointing
+/// at where in the input they happened, or where in the session with
+/// [`session_lines`](ReplConfig::session_lines).
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::exec::Executor;
+/// use interpreter::repl::{self, ReplConfig};
+/// let config = ReplConfig { spacer: "".into(), ..Default::default() };
+/// let mut out = Vec::new();
+/// repl::run_with(&mut Executor::math(), &config, "x := 2\nx * 3\n".as_bytes(), &mut out)
+///     .map_err(|err| err.to_string())?;
+/// assert_eq!(String::from_utf8_lossy(&out), "\n@> 2\n\n@> 6\n\n@> ");
+/// Ok::<(), String>(())
+/// ```
+pub fn run_with<R, W>(executor: &mut Executor, config: &ReplConfig, mut input: R, out: &mut W) -> std::io::Result<()>
+where R: BufRead, W: Write {
+    let mut session = config.session_lines.then(SessionReader::new);
+    if let Some(banner) = &config.banner {
+        writeln!(out, "{banner}")?;
+    }
+    loop {
+        config.write_prompt(out)?;
+        let Some(raw) = read_input(config, &mut input, out)? else {
+            return Ok(());
+        };
+        let line = raw.trim();
+        if line == "exit" {
+            return Ok(());
+        }
+        // a blank line does nothing
+        if line.is_empty() {
+            continue;
+        }
+        if is_command(line) {
+            command(executor, line, out)?;
+            continue;
+        }
+        let result = match session.as_mut() {
+            Some(session) => {
+                session.push_line(line);
+                executor.exec_spanned(session)
+                    .map_err(|err| report::format_error_with(&err, session.document(), config.color))
+            }
+            None => executor.exec_spanned(&mut LineReader::new(line))
+                .map_err(|err| report::format_error_with(&err, line, config.color)),
+        };
+        match result {
+            Ok((result, _)) => writeln!(out, "{result}")?,
+            // this is where you can check for ErrorEOF
+            Err(report) => write!(out, "{report}")?,
+        }
+    }
+}
+
+/// Read an input after its prompt, reading more lines while they end with a `\`. Gives
+/// `None` at the end of the input.
+fn read_input<R, W>(config: &ReplConfig, input: &mut R, out: &mut W) -> std::io::Result<Option<String>>
+where R: BufRead, W: Write {
+    let mut raw = String::new();
+    loop {
+        out.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok((!raw.is_empty()).then_some(raw));
+        }
+        let line = line.trim_end_matches(['\n', '\r']);
+        match line.strip_suffix('\\') {
+            Some(line) => {
+                raw.push_str(line);
+                raw.push('\n');
+                write!(out, "{}", config.continuation)?;
+            }
+            None => {
+                raw.push_str(line);
+                return Ok(Some(raw));
+            }
+        }
+    }
+}
+
+/// Check if the input is a meta-command.
+pub fn is_command(input: &str) -> bool {
+    input.trim_start().starts_with(':')
+}
+
+/// Run a meta-command on the [`Executor`] and write what it displays to `out`.
+/// 
+/// ---
+/// 
+/// ## Example
+/// 
+/// ```
+/// use interpreter::exec::Executor;
+/// use interpreter::repl;
+/// let mut executor = Executor::math();
+/// executor.exec_str("x := 5")?;
+/// 
+/// let mut out = Vec::new();
+/// repl::command(&mut executor, ":vars", &mut out).map_err(|err| err.to_string())?;
+/// assert!(String::from_utf8_lossy(&out).contains("x = 5\n"));
+/// Ok::<(), String>(())
+/// ```
+pub fn command<W>(executor: &mut Executor, input: &str, out: &mut W) -> std::io::Result<()>
+where W: Write {
+    let input = input.trim();
+    let (name, arg) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let arg = arg.trim();
+    match name {
+        ":vars" => vars(executor, out),
+        ":ast" => ast(executor, arg, out),
+        ":tokens" => tokens(executor, arg, out),
+        ":reset" => {
+            executor.env_mut().clear_idents();
+            writeln!(out, "Variables cleared")
+        }
+        ":debug" => debug(executor, arg, out),
+        ":help" => COMMANDS.iter().try_for_each(|(name, desc)| writeln!(out, "{name:<16}{desc}")),
+        _ => writeln!(out, "Unknown command `{name}` - use `:help` to list the commands"),
+    }
+}
+
+/// List the variables sorted by name.
+fn vars<W>(executor: &Executor, out: &mut W) -> std::io::Result<()>
+where W: Write {
+    let mut variables: Vec<_> = executor.variables().iter().collect();
+    if variables.is_empty() {
+        return writeln!(out, "No variables");
+    }
+    variables.sort_by_key(|(name, _)| *name);
+    variables.into_iter().try_for_each(|(name, value)| match value.to_string() {
+        Ok(value) => writeln!(out, "{name} = {value}"),
+        Err(err) => writeln!(out, "{name} = <{err}>"),
+    })
+}
+
+/// Turn the debugger on or off, while it is on each lambda is printed once it has run
+/// the way `print` writes its lines.
+fn debug<W>(executor: &mut Executor, arg: &str, out: &mut W) -> std::io::Result<()>
+where W: Write {
+    match arg {
+        "on" => {
+            executor.env_mut().set_debugger(Box::new(|mut event| {
+                let step = match event.span() {
+                    Some(span) => {
+                        let (line, col) = span.user_position();
+                        format!("step {} at {line}:{col}: {}", event.lambda, event.node)
+                    }
+                    None => format!("step {}: {}", event.lambda, event.node),
+                };
+                event.write_line(&step);
+                DebugAction::Continue
+            }));
+            writeln!(out, "Debugger on")
+        }
+        "off" => {
+            executor.env_mut().clear_debugger();
+            writeln!(out, "Debugger off")
+        }
+        _ => writeln!(out, "Use `:debug on` or `:debug off`"),
+    }
+}
+
+/// Display the syntax tree of the expression.
+fn ast<W>(executor: &Executor, expr: &str, out: &mut W) -> std::io::Result<()>
+where W: Write {
+    let mut reader = LineReader::new(expr);
+    match executor.parser().parse_tree(executor.lexer(), &mut reader) {
+        Ok(ast) => write!(out, "{ast:#}"),
+        Err(err) => writeln!(out, "Encountered Error: {err}"),
+    }
+}
+
+/// Display the tokens of the expression in their [compact](crate::lexer::Token::compact) form.
+fn tokens<W>(executor: &Executor, expr: &str, out: &mut W) -> std::io::Result<()>
+where W: Write {
+    let lexer = executor.lexer();
+    let mut reader = LineReader::new(expr);
+    let mut tokens = vec![];
+    while !lexer.at_end(&reader) {
+        let Some(token) = lexer.get_next_any(&reader) else {
+            let pos = lexer.next_position(&reader);
+            tokens.push(format!("<unknown at col {}>", pos.user_position().1));
+            break;
+        };
+        if reader.next(&token).is_err() {
+            break;
+        }
+        reader.commit();
+        tokens.push(token.compact());
+    }
+    writeln!(out, "{}", tokens.join(" "))
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Run the input loop over `input` and get what it wrote.
+    fn run_loop(config: &ReplConfig, input: &str) -> String {
+        let mut out = Vec::new();
+        run_with(&mut Executor::math(), config, input.as_bytes(), &mut out).expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("output should be utf-8")
+    }
+
+    /// Run a command and get what it wrote.
+    fn run(executor: &mut Executor, input: &str) -> String {
+        let mut out = Vec::new();
+        command(executor, input, &mut out).expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("output should be utf-8")
+    }
+
+    #[test]
+    fn command_vars() -> Result<(), String> {
+        let mut executor = Executor::math();
+        assert_eq!(run(&mut executor, ":vars"), "No variables\n");
+        executor.exec_str("y := 2")?;
+        executor.exec_str("x := 1.5")?;
+        assert_eq!(run(&mut executor, ":vars"), "x = 1.5\ny = 2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn command_reset() -> Result<(), String> {
+        let mut executor = Executor::math();
+        executor.exec_str("x := 1")?;
+        assert_eq!(run(&mut executor, ":reset"), "Variables cleared\n");
+        assert_eq!(run(&mut executor, ":vars"), "No variables\n");
+        assert!(executor.exec_str("x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn command_ast_does_not_run() {
+        let mut executor = Executor::math();
+        let out = run(&mut executor, ":ast x = 1 + 2");
+        assert_eq!(out, [
+            "{ ASSIGN $1 $3 }",
+            "  ident:x",
+            "  assign:=",
+            "  { ADD $1 $3 }",
+            "    int:1",
+            "    op:+",
+            "    int:2",
+            "",
+        ].join("\n"));
+        assert_eq!(run(&mut executor, ":vars"), "No variables\n");
+        assert!(run(&mut executor, ":ast )").starts_with("Encountered Error:"));
+    }
+
+    #[test]
+    fn command_tokens() {
+        let mut executor = Executor::math();
+        assert_eq!(run(&mut executor, ":tokens x := 2.5*(y)"), "ident:x@1:1 declare::=@1:3 float:2.5@1:6 op:*@1:9 op:(@1:10 ident:y@1:11 op:)@1:12\n");
+        assert_eq!(run(&mut executor, ":tokens 1 # 2"), "int:1@1:1 <unknown at col 3>\n");
+    }
+
+    #[test]
+    fn command_debug() -> Result<(), String> {
+        let mut executor = Executor::math();
+        assert_eq!(run(&mut executor, ":debug on"), "Debugger on\n");
+        assert!(executor.env().has_debugger());
+        let lines = Arc::new(Mutex::new(vec![]));
+        let sink = lines.clone();
+        executor.env_mut().set_output(Box::new(move |line| sink.lock().unwrap().push(line.to_owned())));
+        assert_eq!(executor.exec_str("1 + 2")?, "3");
+        assert_eq!(lines.lock().unwrap().last().map(String::as_str), Some("step ADD at 1:1: ( int:1 op:+ int:2 )"));
+        assert_eq!(run(&mut executor, ":debug off"), "Debugger off\n");
+        assert!(!executor.env().has_debugger());
+        assert_eq!(run(&mut executor, ":debug"), "Use `:debug on` or `:debug off`\n");
+        Ok(())
+    }
+
+    #[test]
+    fn command_help_and_unknown() {
+        let mut executor = Executor::math();
+        let help = run(&mut executor, ":help");
+        assert_eq!(help.lines().count(), COMMANDS.len());
+        assert!(help.contains(":tokens <expr>"));
+        assert!(run(&mut executor, ":nope").starts_with("Unknown command `:nope`"));
+    }
+
+    /// the prompts of the config are what gets written.
+    #[test]
+    fn prompt_config() {
+        let prompt = |config: &ReplConfig| {
+            let mut out = Vec::new();
+            config.write_prompt(&mut out).expect("writing to a Vec cannot fail");
+            String::from_utf8(out).expect("output should be utf-8")
+        };
+        let config = ReplConfig { prompt: "calc> ".into(), spacer: "===".into(), ..Default::default() };
+        assert_eq!(prompt(&config), "===\ncalc> ");
+        // the defaults are the original prompts
+        assert_eq!(prompt(&ReplConfig::default()), "---\n@> ");
+    }
+
+    /// the loop writes the prompts of the config around the results.
+    #[test]
+    fn repl_config() {
+        let config = ReplConfig {
+            prompt: "calc> ".into(),
+            continuation: "...> ".into(),
+            spacer: "===".into(),
+            banner: Some("Welcome".into()),
+            color: false,
+            session_lines: false,
+        };
+        let out = run_loop(&config, "1 + \\\n2\n:bad\n\nexit\n3\n");
+        assert_eq!(out, [
+            "Welcome",
+            "===",
+            "calc> ...> 3",
+            "===",
+            "calc> Unknown command `:bad` - use `:help` to list the commands",
+            "===",
+            "calc> ===",
+            "calc> ",
+        ].join("\n"));
+        // the defaults are the original prompts
+        let out = run_loop(&ReplConfig::default(), "1 / 0");
+        assert!(out.starts_with("---\n@> Runtime Error: "), "{out}");
+        assert!(out.ends_with("1 | 1 / 0\n  | ^^^^^\n---\n@> "), "{out}");
+    }
+
+    /// with session lines an error on the third input is reported at line 3.
+    #[test]
+    fn repl_session_lines() {
+        let config = ReplConfig { spacer: "".into(), session_lines: true, ..Default::default() };
+        let out = run_loop(&config, "x := 2\n:vars\ny := x * 3\nx / (y - 6)\n");
+        assert!(out.contains("@> 6\n"), "{out}");
+        assert!(out.contains(" --> 3:"), "{out}");
+        assert!(out.ends_with("3 | x / (y - 6)\n  | ^^^^^^^^^^^\n\n@> "), "{out}");
+        // without them every input starts at line 1
+        let out = run_loop(&ReplConfig::default(), "x := 2\ny := x * 3\nx / (y - 6)\n");
+        assert!(out.contains("1 | x / (y - 6)\n"), "{out}");
+    }
+}
@@ -0,0 +1,141 @@
+//! # Error Reports
+//!
+//! Render an [`ExecError`] for people to read: the message, the line of source it
+//! happened on and a caret under the columns it covers. The input loop started by
+//! [`run`](crate::run) shows its errors this way.
+//!
+
+use std::io::IsTerminal;
+
+use crate::exec::ExecError;
+
+// -=-=- Colors -=-=- //
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Render the error with the line of `source` it happened on, colored when stdout is a
+/// terminal and the `NO_COLOR` environment variable isn't set.
+///
+/// ---
+///
+/// The `source` is what was executed, the position of the error picks the line out of it.
+/// Use [`format_error_with`] to choose whether there is color.
+pub fn format_error(err: &ExecError, source: &str) -> String {
+    format_error_with(err, source, use_color())
+}
+
+/// Check if reports written to stdout should be colored - stdout is a terminal and the
+/// `NO_COLOR` environment variable isn't set.
+pub fn use_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+/// Render the error with the line of `source` it happened on, using ANSI colors when
+/// `color` is set.
+///
+/// ---
+///
+/// ## Example
+///
+/// ```
+/// use interpreter::exec::Executor;
+/// use interpreter::report;
+/// let src = "x := 2 +";
+/// let err = Executor::math().exec_value(src).expect_err("the expression isn't finished");
+/// let report = report::format_error_with(&err, src, false);
+/// assert!(report.starts_with("Parse Error: "));
+/// assert!(report.ends_with("1 | x := 2 +\n  |        ^\n"));
+/// ```
+pub fn format_error_with(err: &ExecError, source: &str, color: bool) -> String {
+    let paint = |style: &str, text: &str| match color {
+        true => format!("{style}{text}{RESET}"),
+        false => text.to_owned(),
+    };
+    let mut report = format!("{}: {}\n", paint(RED, &err.kind.to_string()), paint(BOLD, &err.message));
+    let Some(position) = &err.position else {
+        if let Some(file) = &err.file {
+            report.push_str(&format!(" {} {}\n", paint(BLUE, "-->"), file.display()));
+        }
+        return report;
+    };
+    let (line, col) = position.user_position();
+    let location = match &err.file {
+        Some(file) => format!("{}:{line}:{col}", file.display()),
+        None => format!("{line}:{col}"),
+    };
+    let number = line.to_string();
+    let gutter = " ".repeat(number.len());
+    let text = source.lines().nth(line as usize - 1).unwrap_or_default();
+    let start = col as usize - 1;
+    // a span over several lines is underlined to the end of its first line
+    let (end_line, end_col) = position.end_line_col();
+    let end = match end_line == position.start_line_col().0 {
+        true => end_col as usize,
+        false => text.chars().count(),
+    };
+    let underline = "^".repeat(end.saturating_sub(start).max(1));
+    report.push_str(&format!("{gutter}{} {location}\n", paint(BLUE, "-->")));
+    report.push_str(&format!("{gutter} {}\n", paint(BLUE, "|")));
+    report.push_str(&format!("{} {text}\n", paint(BLUE, &format!("{number} |"))));
+    report.push_str(&format!("{gutter} {} {}{}\n", paint(BLUE, "|"), " ".repeat(start), paint(RED, &underline)));
+    report
+}
+
+// -=-=-=-=- Unit Tests -=-=-=-=- //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::Executor;
+    use crate::lexer::LineReader;
+
+    /// Run each expression in `src` until one fails and get the plain report of its error.
+    fn report(src: &str) -> String {
+        let mut executor = Executor::math();
+        let mut reader = LineReader::new(src);
+        loop {
+            if let Err(err) = executor.exec_spanned(&mut reader) {
+                return format_error_with(&err, src, false);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_error_report() {
+        assert_eq!(report("x := 1\ny := x + 2 )"), [
+            "Parse Error: Could not find token: \"op\" with value \"(\"",
+            " --> 2:12",
+            "  |",
+            "2 | y := x + 2 )",
+            "  |            ^",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn runtime_error_report() {
+        assert_eq!(report("x := 4\nx // (x - 4)"), [
+            "Runtime Error: Cannot divide by zero",
+            " --> 2:1",
+            "  |",
+            "2 | x // (x - 4)",
+            "  | ^^^^^^^^^^^^",
+            "",
+        ].join("\n"));
+    }
+
+    /// colors wrap the parts of the report, the text stays the same.
+    #[test]
+    fn colored_report() {
+        let src = "1 / 0";
+        let err = Executor::math().exec_spanned(&mut LineReader::new(src)).expect_err("dividing by zero is an error");
+        let colored = format_error_with(&err, src, true);
+        assert!(colored.starts_with(&format!("{RED}Runtime Error{RESET}")), "{colored:?}");
+        assert!(colored.contains(&format!("{RED}^^^^^{RESET}")), "{colored:?}");
+        let plain = colored.replace(RED, "").replace(BLUE, "").replace(BOLD, "").replace(RESET, "");
+        assert_eq!(plain, format_error_with(&err, src, false));
+    }
+}